@@ -0,0 +1,99 @@
+//! This provides a way to cancel long-running operations from another thread, such as from a GUI.
+//!
+//! Operations check the token between blocks, and return a `Cancelled` error once it has been cancelled. Anything that was partially written by the operation is erased before it returns.
+
+use std::cell::RefCell;
+use std::io::{self, Read, Seek, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// A token that's shared between an operation and whatever may cancel it.
+///
+/// Clones share the same state, so cancelling one of them cancels all of them.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancels every operation that uses this token.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    #[must_use]
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+// Whether an operation's optional token has been cancelled.
+pub(crate) fn is_cancelled(cancel: Option<&CancellationToken>) -> bool {
+    cancel.is_some_and(CancellationToken::is_cancelled)
+}
+
+// Fails every read once the token has been cancelled, which stops the stream that's reading from it.
+//
+// The error is generic, so callers should check the token to find out why the stream failed.
+pub(crate) struct CancellableReader<'a, R> {
+    inner: R,
+    cancel: Option<&'a CancellationToken>,
+}
+
+impl<'a, R: Read> CancellableReader<'a, R> {
+    pub(crate) fn new(inner: R, cancel: Option<&'a CancellationToken>) -> Self {
+        Self { inner, cancel }
+    }
+}
+
+impl<R: Read> Read for CancellableReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if is_cancelled(self.cancel) {
+            return Err(io::Error::other("Cancelled"));
+        }
+
+        self.inner.read(buf)
+    }
+}
+
+// Erases everything that was written to the writer, up to its current position.
+pub(crate) fn erase_written<W: Write + Seek>(writer: &RefCell<W>) {
+    let written = writer
+        .borrow_mut()
+        .stream_position()
+        .ok()
+        .and_then(|len| usize::try_from(len).ok());
+
+    if let Some(buf_capacity) = written {
+        crate::overwrite::execute(crate::overwrite::Request {
+            writer,
+            buf_capacity,
+            scheme: crate::overwrite::Scheme::Zeros,
+            sync: None,
+            progress: None,
+            cancel: None,
+        })
+        .ok();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_stop_reading_once_cancelled() {
+        let token = CancellationToken::new();
+        let mut reader = CancellableReader::new(&[1u8; 16][..], Some(&token));
+
+        let mut buf = [0u8; 8];
+        assert_eq!(reader.read(&mut buf).unwrap(), 8);
+
+        token.clone().cancel();
+        assert!(token.is_cancelled());
+        assert!(reader.read(&mut buf).is_err());
+    }
+}
@@ -0,0 +1,305 @@
+//! This provides checksum files in the format of `sha256sum` and `b3sum`, and checks files against them.
+//!
+//! Each line is a hex hash, two spaces and a path. Like those tools, a path that contains a backslash or a newline is escaped, and its line starts with a backslash. Bytes of a path that aren't valid UTF-8 are escaped as `\xHH` in the same way, so they're found again when checking. A `*` before the path (binary mode) is accepted when parsing, as both modes read the same bytes on the platforms that we support.
+
+use std::fmt;
+use std::io::{Read, Seek, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::hasher::HashAlgorithm;
+use crate::pack::{bytes_to_path, path_to_bytes};
+use crate::progress::Progress;
+use crate::storage::Storage;
+
+#[derive(Debug)]
+pub enum Error {
+    InvalidLine(usize),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::InvalidLine(line) => write!(f, "Line {line} isn't a valid checksum"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Checksum {
+    pub hash: String,
+    pub path: PathBuf,
+}
+
+impl fmt::Display for Checksum {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Some(bytes) = path_to_bytes(&self.path) else {
+            // this platform's paths aren't bytes, so there's nothing to escape them as
+            return write!(f, "{}  {}", self.hash, self.path.to_string_lossy());
+        };
+
+        let escaped = bytes
+            .utf8_chunks()
+            .any(|chunk| !chunk.invalid().is_empty() || chunk.valid().contains(['\\', '\n']));
+        if escaped {
+            f.write_str("\\")?;
+        }
+        write!(f, "{}  ", self.hash)?;
+
+        for chunk in bytes.utf8_chunks() {
+            f.write_str(&chunk.valid().replace('\\', "\\\\").replace('\n', "\\n"))?;
+            for b in chunk.invalid() {
+                write!(f, "\\x{b:02x}")?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Parses every non-empty line of a checksum file.
+pub fn parse(checksums: &str) -> Result<Vec<Checksum>, Error> {
+    checksums
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| parse_line(line).ok_or(Error::InvalidLine(i + 1)))
+        .collect()
+}
+
+fn parse_line(line: &str) -> Option<Checksum> {
+    let (escaped, line) = match line.strip_prefix('\\') {
+        Some(line) => (true, line),
+        None => (false, line),
+    };
+
+    let (hash, path) = line.split_once(' ')?;
+    // The second separator is a space in text mode, and a `*` in binary mode
+    let path = path.strip_prefix([' ', '*'])?;
+
+    if hash.is_empty() || !hash.bytes().all(|b| b.is_ascii_hexdigit()) || path.is_empty() {
+        return None;
+    }
+
+    let path = if escaped {
+        bytes_to_path(&unescape(path)?)?
+    } else {
+        PathBuf::from(path)
+    };
+
+    Some(Checksum {
+        hash: hash.to_lowercase(),
+        path,
+    })
+}
+
+fn unescape(path: &str) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity(path.len());
+    let mut bytes = path.bytes();
+
+    while let Some(b) = bytes.next() {
+        if b == b'\\' {
+            match bytes.next()? {
+                b'\\' => out.push(b'\\'),
+                b'n' => out.push(b'\n'),
+                b'x' => {
+                    let hex = [bytes.next()?, bytes.next()?];
+                    let hex = std::str::from_utf8(&hex).ok()?;
+                    out.push(u8::from_str_radix(hex, 16).ok()?);
+                }
+                _ => return None,
+            }
+        } else {
+            out.push(b);
+        }
+    }
+
+    Some(out)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Ok,
+    Failed,
+    Missing,
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Status::Ok => f.write_str("OK"),
+            Status::Failed => f.write_str("FAILED"),
+            Status::Missing => f.write_str("MISSING"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CheckResult {
+    pub path: PathBuf,
+    pub status: Status,
+}
+
+pub struct Request<'a> {
+    pub checksums: &'a [Checksum],
+    pub algorithm: HashAlgorithm,
+    pub progress: Option<Arc<dyn Progress>>,
+}
+
+/// Hashes every file again, and compares it with its checksum.
+///
+/// Files that can't be opened are reported as missing, and files that can't be read are reported as failed.
+pub fn execute<RW>(stor: Arc<impl Storage<RW> + 'static>, req: Request<'_>) -> Vec<CheckResult>
+where
+    RW: Read + Write + Seek,
+{
+    req.checksums
+        .iter()
+        .map(|checksum| {
+            if let Some(progress) = &req.progress {
+                progress.file(&checksum.path);
+            }
+
+            let status =
+                match hash_file(&*stor, &checksum.path, req.algorithm, req.progress.as_ref()) {
+                    None => Status::Missing,
+                    Some(Ok(hash)) if hash == checksum.hash => Status::Ok,
+                    Some(_) => Status::Failed,
+                };
+
+            CheckResult {
+                path: checksum.path.clone(),
+                status,
+            }
+        })
+        .collect()
+}
+
+fn hash_file<RW>(
+    stor: &impl Storage<RW>,
+    path: &Path,
+    algorithm: HashAlgorithm,
+    progress: Option<&Arc<dyn Progress>>,
+) -> Option<Result<String, crate::hash::Error>>
+where
+    RW: Read + Write + Seek,
+{
+    let file = stor.read_file(path).ok().filter(|file| !file.is_dir())?;
+    let mut reader = file.try_reader().ok()?.borrow_mut();
+
+    Some(crate::hash::execute(
+        algorithm.hasher(),
+        crate::hash::Request {
+            reader: std::cell::RefCell::new(&mut *reader),
+            progress: progress.cloned(),
+        },
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hasher::Hasher;
+    use crate::storage::InMemoryStorage;
+
+    fn sha256(input: &[u8]) -> String {
+        let mut hasher = HashAlgorithm::Sha256.hasher();
+        hasher.write(input);
+        hasher.finish()
+    }
+
+    #[test]
+    fn should_format_and_parse_checksums() {
+        let checksums = vec![
+            Checksum {
+                hash: sha256(b"hello"),
+                path: PathBuf::from("bar/hello.txt"),
+            },
+            Checksum {
+                hash: sha256(b"world"),
+                path: PathBuf::from("bar/new\nline\\.txt"),
+            },
+        ];
+
+        let text = checksums
+            .iter()
+            .map(|checksum| checksum.to_string() + "\n")
+            .collect::<String>();
+        assert!(text.starts_with(&format!("{}  bar/hello.txt\n", sha256(b"hello"))));
+        assert!(text.ends_with("  bar/new\\nline\\\\.txt\n"));
+
+        assert_eq!(parse(&text).unwrap(), checksums);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn should_escape_paths_that_arent_utf8() {
+        use std::os::unix::ffi::OsStrExt;
+
+        let checksum = Checksum {
+            hash: sha256(b"hello"),
+            path: PathBuf::from(std::ffi::OsStr::from_bytes(b"bar/caf\xe9.txt")),
+        };
+
+        let text = checksum.to_string();
+        assert_eq!(text, format!("\\{}  bar/caf\\xe9.txt", sha256(b"hello")));
+
+        assert_eq!(parse(&text).unwrap(), vec![checksum]);
+    }
+
+    #[test]
+    fn should_parse_binary_mode_and_uppercase_hashes() {
+        let checksums = parse("ABCDEF *hello.txt\n\n").unwrap();
+
+        assert_eq!(
+            checksums,
+            vec![Checksum {
+                hash: "abcdef".to_string(),
+                path: PathBuf::from("hello.txt"),
+            }]
+        );
+    }
+
+    #[test]
+    fn should_reject_invalid_lines() {
+        match parse("abcdef  hello.txt\nnot a checksum\n") {
+            Err(Error::InvalidLine(line)) => assert_eq!(line, 2),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn should_report_ok_failed_and_missing_files() {
+        let stor = Arc::new(InMemoryStorage::default());
+        stor.add_bar_foo_folder();
+
+        let checksums = vec![
+            Checksum {
+                hash: sha256(b"hello"),
+                path: PathBuf::from("bar/hello.txt"),
+            },
+            Checksum {
+                hash: sha256(b"hello"),
+                path: PathBuf::from("bar/world.txt"),
+            },
+            Checksum {
+                hash: sha256(b"hello"),
+                path: PathBuf::from("bar/missing.txt"),
+            },
+        ];
+
+        let req = Request {
+            checksums: &checksums,
+            algorithm: HashAlgorithm::Sha256,
+            progress: None,
+        };
+
+        let statuses = execute(stor, req)
+            .into_iter()
+            .map(|result| result.status)
+            .collect::<Vec<_>>();
+        assert_eq!(statuses, vec![Status::Ok, Status::Failed, Status::Missing]);
+    }
+}
@@ -2,14 +2,18 @@
 
 use std::cell::RefCell;
 use std::io::{Read, Seek, Write};
+use std::sync::Arc;
 
 use core::cipher::Ciphers;
 use core::header::{Header, HeaderType};
 use core::key::decrypt_master_key;
-use core::primitives::Mode;
+use core::primitives::{Mode, MASTER_KEY_LEN};
 use core::protected::Protected;
 use core::stream::DecryptionStreams;
 
+use crate::cancel::{erase_written, is_cancelled, CancellableReader, CancellationToken};
+use crate::progress::{remaining_len, Phase, Progress, ProgressReader};
+
 #[derive(Debug)]
 pub enum Error {
     InitializeChiphers,
@@ -20,6 +24,7 @@ pub enum Error {
     DecryptData,
     WriteData,
     RewindDataReader,
+    Cancelled,
 }
 
 impl std::fmt::Display for Error {
@@ -33,6 +38,7 @@ impl std::fmt::Display for Error {
             Error::DecryptData => f.write_str("Unable to decrypt data"),
             Error::WriteData => f.write_str("Unable to write data"),
             Error::RewindDataReader => f.write_str("Unable to rewind the reader"),
+            Error::Cancelled => f.write_str("Decryption was cancelled"),
         }
     }
 }
@@ -51,6 +57,8 @@ where
     pub writer: &'a RefCell<W>,
     pub raw_key: Protected<Vec<u8>>,
     pub on_decrypted_header: Option<OnDecryptedHeaderFn>,
+    pub progress: Option<Arc<dyn Progress>>,
+    pub cancel: Option<CancellationToken>,
 }
 
 pub fn execute<R, W>(req: Request<'_, R, W>) -> Result<(), Error>
@@ -67,19 +75,14 @@ where
             #[allow(clippy::cast_possible_truncation)]
             let mut header_bytes = vec![0u8; header.get_size() as usize];
 
-            req.reader
-                .borrow_mut()
-                .read_exact(&mut header_bytes)
-                .or_else(|e| {
-                    if e.kind() == std::io::ErrorKind::UnexpectedEof {
-                        Ok(())
-                    } else {
-                        Err(e)
-                    }
-                })
-                .map_err(|_| Error::ReadEncryptedData)?;
+            // Content that is shorter than the header can't contain an empty header.
+            let has_empty_header = match req.reader.borrow_mut().read_exact(&mut header_bytes) {
+                Ok(()) => header_bytes.into_iter().all(|b| b == 0),
+                Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => false,
+                Err(_) => return Err(Error::ReadEncryptedData),
+            };
 
-            if !header_bytes.into_iter().all(|b| b == 0) {
+            if !has_empty_header {
                 // And return the cursor position to the start if it wasn't found
                 req.reader
                     .borrow_mut()
@@ -97,22 +100,64 @@ where
         cb(&header.header_type);
     }
 
+    let progress = req.progress.as_deref();
+    if let Some(progress) = progress {
+        progress.phase(Phase::HashingKey);
+    }
+
+    let master_key =
+        decrypt_master_key(req.raw_key, &header).map_err(|_| Error::DecryptMasterKey)?;
+
+    if let Some(progress) = progress {
+        progress.phase(Phase::Decrypting);
+        progress.total(
+            remaining_len(&mut *req.reader.borrow_mut()).map_err(|_| Error::ReadEncryptedData)?,
+        );
+    }
+
+    let cancel = req.cancel.as_ref();
+    let res = decrypt_with_master_key(
+        req.reader, req.writer, &header, &aad, master_key, progress, cancel,
+    );
+
+    if is_cancelled(cancel) {
+        // Don't leave partially decrypted data behind
+        erase_written(req.writer);
+        return Err(Error::Cancelled);
+    }
+
+    res
+}
+
+// Decrypts the data that follows a header, with a master key that has already been decrypted.
+pub(crate) fn decrypt_with_master_key<R, W>(
+    reader: &RefCell<R>,
+    writer: &RefCell<W>,
+    header: &Header,
+    aad: &[u8],
+    master_key: Protected<[u8; MASTER_KEY_LEN]>,
+    progress: Option<&dyn Progress>,
+    cancel: Option<&CancellationToken>,
+) -> Result<(), Error>
+where
+    R: Read,
+    W: Write,
+{
+    let mut reader = reader.borrow_mut();
+    let mut reader = ProgressReader::new(CancellableReader::new(&mut *reader, cancel), progress);
+
     match header.header_type.mode {
         Mode::MemoryMode => {
             let mut encrypted_data = Vec::new();
-            req.reader
-                .borrow_mut()
+            reader
                 .read_to_end(&mut encrypted_data)
                 .map_err(|_| Error::ReadEncryptedData)?;
 
-            let master_key =
-                decrypt_master_key(req.raw_key, &header).map_err(|_| Error::DecryptMasterKey)?;
-
             let ciphers = Ciphers::initialize(master_key, &header.header_type.algorithm)
                 .map_err(|_| Error::InitializeChiphers)?;
 
             let payload = core::Payload {
-                aad: &aad,
+                aad,
                 msg: &encrypted_data,
             };
 
@@ -120,15 +165,12 @@ where
                 .decrypt(&header.nonce, payload)
                 .map_err(|_| Error::DecryptData)?;
 
-            req.writer
+            writer
                 .borrow_mut()
                 .write_all(&decrypted_bytes)
                 .map_err(|_| Error::WriteData)?;
         }
         Mode::StreamMode => {
-            let master_key =
-                decrypt_master_key(req.raw_key, &header).map_err(|_| Error::DecryptMasterKey)?;
-
             let streams = DecryptionStreams::initialize(
                 master_key,
                 &header.nonce,
@@ -137,11 +179,7 @@ where
             .map_err(|_| Error::InitializeStreams)?;
 
             streams
-                .decrypt_file(
-                    &mut *req.reader.borrow_mut(),
-                    &mut *req.writer.borrow_mut(),
-                    &aad,
-                )
+                .decrypt_file(&mut reader, &mut *writer.borrow_mut(), aad)
                 .map_err(|_| Error::DecryptData)?;
         }
     }
@@ -173,10 +211,12 @@ mod tests {
             writer: &output_cur,
             raw_key: Protected::new(PASSWORD.to_vec()),
             on_decrypted_header: None,
+            progress: None,
+            cancel: None,
         };
 
         match execute(req) {
-            Ok(_) => {
+            Ok(()) => {
                 assert_eq!(output_content, "Hello world".as_bytes().to_vec());
             }
             _ => unreachable!(),
@@ -197,10 +237,12 @@ mod tests {
             writer: &output_cur,
             raw_key: Protected::new(PASSWORD.to_vec()),
             on_decrypted_header: None,
+            progress: None,
+            cancel: None,
         };
 
         match execute(req) {
-            Ok(_) => {
+            Ok(()) => {
                 assert_eq!(output_content, "Hello world".as_bytes().to_vec());
             }
             _ => unreachable!(),
@@ -224,10 +266,12 @@ mod tests {
             writer: &output_cur,
             raw_key: Protected::new(PASSWORD.to_vec()),
             on_decrypted_header: None,
+            progress: None,
+            cancel: None,
         };
 
         match execute(req) {
-            Ok(_) => {
+            Ok(()) => {
                 assert_eq!(output_content, "Hello world".as_bytes().to_vec());
             }
             _ => unreachable!(),
@@ -251,10 +295,12 @@ mod tests {
             writer: &output_cur,
             raw_key: Protected::new(PASSWORD.to_vec()),
             on_decrypted_header: None,
+            progress: None,
+            cancel: None,
         };
 
         match execute(req) {
-            Ok(_) => {
+            Ok(()) => {
                 assert_eq!(output_content, "Hello world".as_bytes().to_vec());
             }
             _ => unreachable!(),
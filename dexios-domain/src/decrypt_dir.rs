@@ -0,0 +1,249 @@
+//! This decrypts a directory tree that was created with `encrypt_dir`, and restores the original names.
+
+use std::io::{Read, Seek, Write};
+use std::path::{Path, PathBuf};
+
+use core::header::Header;
+use core::protected::Protected;
+
+pub use crate::encrypt_dir::Error;
+use crate::encrypt_dir::{DirKey, DIR_HEADER_NAME};
+use crate::storage::{Entry, Storage};
+use crate::sync::SYNC_MANIFEST_NAME;
+
+type OnFileFn = Box<dyn Fn(&Path) -> bool>;
+
+pub struct Request<'a> {
+    pub input_dir_path: PathBuf,
+    pub output_dir_path: PathBuf,
+    pub raw_key: Protected<Vec<u8>>,
+    // Called with the decrypted path of every file, and returns whether it should be decrypted
+    pub on_file: Option<&'a OnFileFn>,
+}
+
+pub fn execute<RW>(stor: &impl Storage<RW>, req: Request<'_>) -> Result<(), Error>
+where
+    RW: Read + Write + Seek,
+{
+    let input_dir = stor
+        .read_file(&req.input_dir_path)
+        .map_err(Error::Storage)?;
+    if !input_dir.is_dir() {
+        return Err(Error::InvalidDir);
+    }
+
+    let dir_key = DirKey::open(stor, &req.input_dir_path, req.raw_key.clone())?;
+
+    decrypt_tree(stor, &dir_key, &req)
+}
+
+fn decrypt_tree<RW>(
+    stor: &impl Storage<RW>,
+    dir_key: &DirKey,
+    req: &Request<'_>,
+) -> Result<(), Error>
+where
+    RW: Read + Write + Seek,
+{
+    let input_dir = stor
+        .read_file(&req.input_dir_path)
+        .map_err(Error::Storage)?;
+    let header_path = req.input_dir_path.join(DIR_HEADER_NAME);
+    let manifest_path = req.input_dir_path.join(SYNC_MANIFEST_NAME);
+
+    stor.create_dir_all(&req.output_dir_path)
+        .map_err(Error::Storage)?;
+
+    for path in stor.walk_dir(&input_dir).map_err(Error::Storage)? {
+        let path = path.map_err(Error::Storage)?;
+        if path == header_path || path == manifest_path || path == req.input_dir_path {
+            continue;
+        }
+
+        let relative_path = path
+            .strip_prefix(&req.input_dir_path)
+            .map_err(|_| Error::UnsupportedPath(path.clone()))?;
+        let decrypted_path = dir_key.decrypt_path(relative_path)?;
+        let output_path = req.output_dir_path.join(&decrypted_path);
+
+        let entry = stor.read_file(&path).map_err(Error::Storage)?;
+        if entry.is_dir() {
+            stor.create_dir_all(&output_path).map_err(Error::Storage)?;
+            continue;
+        }
+
+        if let Some(on_file) = req.on_file {
+            if !on_file(&decrypted_path) {
+                continue;
+            }
+        }
+
+        decrypt_file(stor, dir_key, &entry, &decrypted_path, &output_path)
+            .map_err(|err| Error::Decrypt(decrypted_path, err))?;
+    }
+
+    Ok(())
+}
+
+fn decrypt_file<RW>(
+    stor: &impl Storage<RW>,
+    dir_key: &DirKey,
+    entry: &Entry<RW>,
+    decrypted_path: &Path,
+    output_path: &Path,
+) -> Result<(), crate::decrypt::Error>
+where
+    RW: Read + Write + Seek,
+{
+    use crate::decrypt::Error as DecryptError;
+
+    let file_key = dir_key
+        .file_key(decrypted_path)
+        .map_err(|_| DecryptError::DecryptMasterKey)?;
+
+    let reader = entry
+        .try_reader()
+        .map_err(|_| DecryptError::ReadEncryptedData)?;
+    let (header, aad) = Header::deserialize(&mut *reader.borrow_mut())
+        .map_err(|_| DecryptError::DeserializeHeader)?;
+
+    if let Some(parent) = output_path.parent() {
+        stor.create_dir_all(parent)
+            .map_err(|_| DecryptError::WriteData)?;
+    }
+
+    let output = stor
+        .create_file(output_path)
+        .or_else(|_| stor.write_file(output_path))
+        .map_err(|_| DecryptError::WriteData)?;
+
+    let res = crate::decrypt::decrypt_with_master_key(
+        reader,
+        output.try_writer().map_err(|_| DecryptError::WriteData)?,
+        &header,
+        &aad,
+        file_key,
+        None,
+        None,
+    );
+
+    if res.is_err() {
+        // Don't leave partially decrypted files behind
+        stor.remove_file(output).ok();
+        return res;
+    }
+
+    stor.flush_file(&output)
+        .map_err(|_| DecryptError::WriteData)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encrypt_dir::tests::dir_key;
+    use crate::storage::{IMFile, InMemoryFile, InMemoryStorage};
+    use core::header::{HashingAlgorithm, HeaderType, HeaderVersion};
+    use core::primitives::{Algorithm, Mode};
+
+    fn read_file(stor: &InMemoryStorage, path: &Path) -> Option<IMFile> {
+        stor.files().get(path).cloned()
+    }
+
+    fn encrypt_tree(stor: &InMemoryStorage, dir_key: &DirKey) {
+        let req = crate::encrypt_dir::Request {
+            input_dir_path: PathBuf::from("bar"),
+            output_dir_path: PathBuf::from("enc"),
+            raw_key: Protected::new(Vec::new()),
+            header_type: HeaderType {
+                version: HeaderVersion::V5,
+                algorithm: Algorithm::XChaCha20Poly1305,
+                mode: Mode::StreamMode,
+            },
+            hashing_algorithm: HashingAlgorithm::Blake3Balloon(5),
+            on_file: None,
+        };
+
+        match crate::encrypt_dir::encrypt_tree(stor, dir_key, &req) {
+            Ok(()) => {}
+            _ => unreachable!(),
+        }
+    }
+
+    // Key hashing is slow, so the trees are encrypted and decrypted with the test's master key
+    #[test]
+    fn should_encrypt_and_decrypt_dir() {
+        let stor = InMemoryStorage::default();
+        stor.add_bar_foo_folder();
+        let dir_key = dir_key();
+
+        encrypt_tree(&stor, &dir_key);
+
+        let encrypted_path =
+            Path::new("enc").join(dir_key.encrypt_path(Path::new("foo/hello.txt")).unwrap());
+        match read_file(&stor, &encrypted_path) {
+            Some(IMFile::File(file)) => assert!(file.len > 416),
+            _ => unreachable!(),
+        }
+        assert!(read_file(&stor, Path::new("enc/foo/hello.txt")).is_none());
+
+        let req = Request {
+            input_dir_path: PathBuf::from("enc"),
+            output_dir_path: PathBuf::from("out"),
+            raw_key: Protected::new(Vec::new()),
+            on_file: None,
+        };
+
+        match decrypt_tree(&stor, &dir_key, &req) {
+            Ok(()) => {}
+            _ => unreachable!(),
+        }
+
+        for (path, content) in [
+            ("out/hello.txt", "hello"),
+            ("out/world.txt", "world"),
+            ("out/foo/hello.txt", "hello"),
+            ("out/foo/world.txt", "world"),
+        ] {
+            assert_eq!(
+                read_file(&stor, Path::new(path)),
+                Some(IMFile::File(InMemoryFile {
+                    buf: content.as_bytes().to_vec(),
+                    len: content.len(),
+                }))
+            );
+        }
+        assert_eq!(read_file(&stor, Path::new("out/foo")), Some(IMFile::Dir));
+    }
+
+    #[test]
+    fn should_refuse_files_that_were_swapped() {
+        let stor = InMemoryStorage::default();
+        stor.add_bar_foo_folder();
+        let dir_key = dir_key();
+
+        encrypt_tree(&stor, &dir_key);
+
+        let encrypted_path =
+            |path: &str| Path::new("enc").join(dir_key.encrypt_path(Path::new(path)).unwrap());
+        let hello = encrypted_path("hello.txt");
+        let world = encrypted_path("world.txt");
+        {
+            let mut files = stor.mut_files();
+            let hello_file = files.remove(&hello).unwrap();
+            let world_file = files.insert(world.clone(), hello_file).unwrap();
+            files.insert(hello, world_file);
+        }
+
+        let req = Request {
+            input_dir_path: PathBuf::from("enc"),
+            output_dir_path: PathBuf::from("out"),
+            raw_key: Protected::new(Vec::new()),
+            on_file: None,
+        };
+
+        match decrypt_tree(&stor, &dir_key, &req) {
+            Err(Error::Decrypt(_, crate::decrypt::Error::DecryptData)) => {}
+            _ => unreachable!(),
+        }
+    }
+}
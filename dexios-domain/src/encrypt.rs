@@ -2,13 +2,16 @@
 
 use std::cell::RefCell;
 use std::io::{Read, Seek, Write};
+use std::sync::Arc;
 
 use core::cipher::Ciphers;
 use core::header::{HashingAlgorithm, Header, HeaderType, Keyslot};
-use core::primitives::{Mode, ENCRYPTED_MASTER_KEY_LEN};
+use core::primitives::{Mode, ENCRYPTED_MASTER_KEY_LEN, MASTER_KEY_LEN};
 use core::protected::Protected;
 use core::stream::EncryptionStreams;
 
+use crate::cancel::{erase_written, is_cancelled, CancellableReader, CancellationToken};
+use crate::progress::{remaining_len, Phase, Progress, ProgressReader};
 use crate::utils::{gen_master_key, gen_nonce, gen_salt};
 
 #[derive(Debug)]
@@ -21,6 +24,7 @@ pub enum Error {
     InitializeStreams,
     InitializeChiphers,
     CreateAad,
+    Cancelled,
 }
 
 impl std::fmt::Display for Error {
@@ -34,6 +38,7 @@ impl std::fmt::Display for Error {
             Error::InitializeStreams => f.write_str("Cannot initialize streams"),
             Error::InitializeChiphers => f.write_str("Cannot initialize chiphers"),
             Error::CreateAad => f.write_str("Cannot create AAD"),
+            Error::Cancelled => f.write_str("Encryption was cancelled"),
         }
     }
 }
@@ -52,6 +57,8 @@ where
     // TODO: don't use external types in logic
     pub header_type: HeaderType,
     pub hashing_algorithm: HashingAlgorithm,
+    pub progress: Option<Arc<dyn Progress>>,
+    pub cancel: Option<CancellationToken>,
 }
 
 pub fn execute<R, W>(req: Request<'_, R, W>) -> Result<(), Error>
@@ -63,6 +70,9 @@ where
     let salt = gen_salt();
 
     // 2. hash key
+    if let Some(progress) = &req.progress {
+        progress.phase(Phase::HashingKey);
+    }
     let key = req
         .hashing_algorithm
         .hash(req.raw_key, &salt)
@@ -99,26 +109,52 @@ where
 
     let keyslots = vec![keyslot];
 
-    let header_nonce = gen_nonce(&req.header_type.algorithm, &req.header_type.mode);
-    let streams =
-        EncryptionStreams::initialize(master_key, &header_nonce, &req.header_type.algorithm)
-            .map_err(|_| Error::InitializeStreams)?;
-
     let header = Header {
+        nonce: gen_nonce(&req.header_type.algorithm, &req.header_type.mode),
         header_type: req.header_type,
-        nonce: header_nonce,
         salt: None,
         keyslots: Some(keyslots),
     };
 
-    req.writer
+    encrypt_with_master_key(
+        req.reader,
+        req.writer,
+        req.header_writer,
+        master_key,
+        &header,
+        req.progress.as_deref(),
+        req.cancel.as_ref(),
+    )
+}
+
+// Encrypts the reader with an existing master key, and writes the header before the encrypted data.
+//
+// The header's nonce is used for the streams, so it must be unique for every file.
+pub(crate) fn encrypt_with_master_key<R, W>(
+    reader: &RefCell<R>,
+    writer: &RefCell<W>,
+    header_writer: Option<&RefCell<W>>,
+    master_key: Protected<[u8; MASTER_KEY_LEN]>,
+    header: &Header,
+    progress: Option<&dyn Progress>,
+    cancel: Option<&CancellationToken>,
+) -> Result<(), Error>
+where
+    R: Read + Seek,
+    W: Write + Seek,
+{
+    let streams =
+        EncryptionStreams::initialize(master_key, &header.nonce, &header.header_type.algorithm)
+            .map_err(|_| Error::InitializeStreams)?;
+
+    writer
         .borrow_mut()
         .rewind()
         .map_err(|_| Error::ResetCursorPosition)?;
 
-    match req.header_writer {
+    match header_writer {
         None => {
-            req.writer
+            writer
                 .borrow_mut()
                 .write(&header.serialize().map_err(|_| Error::WriteHeader)?)
                 .map_err(|_| Error::WriteHeader)?;
@@ -138,15 +174,24 @@ where
 
     let aad = header.create_aad().map_err(|_| Error::CreateAad)?;
 
-    let mut reader = req.reader.borrow_mut();
+    let mut reader = reader.borrow_mut();
     reader.rewind().map_err(|_| Error::ResetCursorPosition)?;
 
-    let mut writer = req.writer.borrow_mut();
-    streams
-        .encrypt_file(&mut *reader, &mut *writer, &aad)
-        .map_err(|_| Error::EncryptFile)?;
+    if let Some(progress) = progress {
+        progress.phase(Phase::Encrypting);
+        progress.total(remaining_len(&mut *reader).map_err(|_| Error::ResetCursorPosition)?);
+    }
+
+    let mut reader = ProgressReader::new(CancellableReader::new(&mut *reader, cancel), progress);
+    let res = streams.encrypt_file(&mut reader, &mut *writer.borrow_mut(), &aad);
+
+    if is_cancelled(cancel) {
+        // Don't leave partially encrypted data behind
+        erase_written(writer);
+        return Err(Error::Cancelled);
+    }
 
-    Ok(())
+    res.map_err(|_| Error::EncryptFile)
 }
 
 // WARNING! Very expensive tests!
@@ -254,10 +299,12 @@ pub mod tests {
                 mode: Mode::StreamMode,
             },
             hashing_algorithm: HashingAlgorithm::Blake3Balloon(4),
+            progress: None,
+            cancel: None,
         };
 
         match execute(req) {
-            Ok(_) => {
+            Ok(()) => {
                 assert_eq!(output_content, V4_ENCRYPTED_CONTENT.to_vec());
             }
             Err(e) => {
@@ -286,10 +333,12 @@ pub mod tests {
                 mode: Mode::StreamMode,
             },
             hashing_algorithm: HashingAlgorithm::Blake3Balloon(5),
+            progress: None,
+            cancel: None,
         };
 
         match execute(req) {
-            Ok(_) => {
+            Ok(()) => {
                 assert_eq!(output_content, V5_ENCRYPTED_CONTENT.to_vec());
             }
             Err(e) => {
@@ -321,10 +370,12 @@ pub mod tests {
                 mode: Mode::StreamMode,
             },
             hashing_algorithm: HashingAlgorithm::Blake3Balloon(5),
+            progress: None,
+            cancel: None,
         };
 
         match execute(req) {
-            Ok(_) => {
+            Ok(()) => {
                 assert_eq!(output_content, V5_ENCRYPTED_FULL_DETACHED_CONTENT.to_vec());
                 assert_eq!(output_header, V5_ENCRYPTED_DETACHED_HEADER.to_vec());
             }
@@ -334,4 +385,45 @@ pub mod tests {
             }
         }
     }
+
+    // The master key is used directly, as key hashing is slow
+    #[test]
+    fn should_erase_output_once_cancelled() {
+        let mut input_content = vec![1u8; 1024];
+        let input_cur = RefCell::new(Cursor::new(&mut input_content));
+
+        let mut output_content = vec![];
+        let output_cur = RefCell::new(Cursor::new(&mut output_content));
+
+        let header = Header {
+            header_type: HeaderType {
+                version: HeaderVersion::V5,
+                algorithm: Algorithm::XChaCha20Poly1305,
+                mode: Mode::StreamMode,
+            },
+            nonce: gen_nonce(&Algorithm::XChaCha20Poly1305, &Mode::StreamMode),
+            salt: None,
+            keyslots: Some(Vec::new()),
+        };
+
+        let cancel = CancellationToken::new();
+        cancel.cancel();
+
+        match encrypt_with_master_key(
+            &input_cur,
+            &output_cur,
+            None,
+            gen_master_key(),
+            &header,
+            None,
+            Some(&cancel),
+        ) {
+            Err(Error::Cancelled) => {}
+            _ => unreachable!(),
+        }
+
+        // The header was written before the data, and has been erased
+        assert!(!output_content.is_empty());
+        assert!(output_content.iter().all(|b| *b == 0));
+    }
 }
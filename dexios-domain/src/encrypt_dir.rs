@@ -0,0 +1,503 @@
+//! This encrypts every file within a directory separately, into a parallel directory tree.
+//!
+//! Unlike packing, changing a single file only changes a single encrypted file, so the encrypted tree can be synced efficiently.
+//!
+//! All files share one master key, which is stored within the keyslots of the header at the root of the tree (`.dexios-dir`). Every file is a regular Dexios file with a unique nonce, but without any keyslots of its own. Each file is encrypted with a key that's derived from the master key and the file's path, so encrypted files can't be swapped or moved around within the tree without failing to decrypt.
+//!
+//! File and directory names are encrypted too. A name's nonce is derived from the name itself (along with its parent's path), so the same name always has the same encrypted name. Encrypted names are encoded with base32, so they're safe to use on any file system.
+
+use std::cell::RefCell;
+use std::io::{Read, Seek, Write};
+use std::path::{Component, Path, PathBuf};
+
+use core::cipher::Ciphers;
+use core::header::{HashingAlgorithm, Header, HeaderType, HEADER_VERSION};
+use core::key::decrypt_master_key;
+use core::primitives::{get_nonce_len, Algorithm, Mode, MASTER_KEY_LEN};
+use core::protected::Protected;
+use core::Payload;
+
+use crate::pack::{bytes_to_path, path_to_bytes};
+use crate::storage::Storage;
+use crate::utils::{base32_decode, base32_encode, gen_nonce};
+
+/// The name of the header that contains the keyslots, at the root of the encrypted tree.
+pub const DIR_HEADER_NAME: &str = ".dexios-dir";
+
+// Most file systems don't allow longer names
+const MAX_NAME_LEN: usize = 255;
+
+const NAME_KEY_CONTEXT: &str = "Dexios 2026-10-18 encrypted directory name encryption";
+const NAME_NONCE_CONTEXT: &str = "Dexios 2026-10-18 encrypted directory name nonces";
+const FILE_KEY_CONTEXT: &str = "Dexios 2026-10-18 encrypted directory file keys";
+
+#[derive(Debug)]
+pub enum Error {
+    InvalidDir,
+    ReadHeader,
+    WriteHeader,
+    CreateHeader(crate::key::Error),
+    DecryptMasterKey,
+    InitializeChiphers,
+    EncryptName(PathBuf),
+    DecryptName(PathBuf),
+    NameTooLong(PathBuf),
+    UnsupportedPath(PathBuf),
+    Encrypt(PathBuf, crate::encrypt::Error),
+    Decrypt(PathBuf, crate::decrypt::Error),
+    HashFile(PathBuf),
+    ReadManifest,
+    WriteManifest,
+    InvalidManifest,
+    Storage(crate::storage::Error),
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::InvalidDir => f.write_str("The input must be a directory"),
+            Error::ReadHeader => f.write_str("Unable to read the directory's header"),
+            Error::WriteHeader => f.write_str("Unable to write the directory's header"),
+            Error::CreateHeader(inner) => write!(f, "Unable to create the header: {inner}"),
+            Error::DecryptMasterKey => f.write_str("Cannot decrypt master key"),
+            Error::InitializeChiphers => f.write_str("Cannot initialize chiphers"),
+            Error::EncryptName(path) => write!(f, "Unable to encrypt name: {}", path.display()),
+            Error::DecryptName(path) => write!(f, "Unable to decrypt name: {}", path.display()),
+            Error::NameTooLong(path) => {
+                write!(f, "Name is too long once encrypted: {}", path.display())
+            }
+            Error::UnsupportedPath(path) => write!(f, "Unsupported path: {}", path.display()),
+            Error::Encrypt(path, inner) => {
+                write!(f, "Unable to encrypt {}: {inner}", path.display())
+            }
+            Error::Decrypt(path, inner) => {
+                write!(f, "Unable to decrypt {}: {inner}", path.display())
+            }
+            Error::HashFile(path) => write!(f, "Unable to hash {}", path.display()),
+            Error::ReadManifest => f.write_str("Unable to read the sync manifest"),
+            Error::WriteManifest => f.write_str("Unable to write the sync manifest"),
+            Error::InvalidManifest => f.write_str("The sync manifest is invalid or was modified"),
+            Error::Storage(inner) => write!(f, "Storage error: {inner}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+type OnFileFn = Box<dyn Fn(&Path)>;
+
+pub struct Request<'a> {
+    pub input_dir_path: PathBuf,
+    pub output_dir_path: PathBuf,
+    pub raw_key: Protected<Vec<u8>>,
+    // These are only used if the output doesn't contain a header yet
+    // TODO: don't use external types in logic
+    pub header_type: HeaderType,
+    pub hashing_algorithm: HashingAlgorithm,
+    // Called with the plaintext path of every file, before it's encrypted
+    pub on_file: Option<&'a OnFileFn>,
+}
+
+// The master key of an encrypted tree, along with the keys that are derived from it.
+pub(crate) struct DirKey {
+    master_key: Protected<[u8; MASTER_KEY_LEN]>,
+    algorithm: Algorithm,
+    name_ciphers: Ciphers,
+    name_nonce_key: Protected<[u8; 32]>,
+    file_key: Protected<[u8; 32]>,
+}
+
+impl DirKey {
+    fn new(
+        master_key: Protected<[u8; MASTER_KEY_LEN]>,
+        algorithm: Algorithm,
+    ) -> Result<Self, Error> {
+        let name_key = Protected::new(blake3::derive_key(NAME_KEY_CONTEXT, master_key.expose()));
+        let name_nonce_key =
+            Protected::new(blake3::derive_key(NAME_NONCE_CONTEXT, master_key.expose()));
+        let file_key = Protected::new(blake3::derive_key(FILE_KEY_CONTEXT, master_key.expose()));
+        let name_ciphers =
+            Ciphers::initialize(name_key, &algorithm).map_err(|_| Error::InitializeChiphers)?;
+
+        Ok(Self {
+            master_key,
+            algorithm,
+            name_ciphers,
+            name_nonce_key,
+            file_key,
+        })
+    }
+
+    // Reads the header at the root of an encrypted tree, and decrypts its master key.
+    pub(crate) fn open<RW>(
+        stor: &impl Storage<RW>,
+        dir: &Path,
+        raw_key: Protected<Vec<u8>>,
+    ) -> Result<Self, Error>
+    where
+        RW: Read + Write + Seek,
+    {
+        let file = stor
+            .read_file(dir.join(DIR_HEADER_NAME))
+            .map_err(|_| Error::ReadHeader)?;
+        let (header, _) =
+            Header::deserialize(&mut *file.try_reader().map_err(Error::Storage)?.borrow_mut())
+                .map_err(|_| Error::ReadHeader)?;
+
+        let master_key =
+            decrypt_master_key(raw_key, &header).map_err(|_| Error::DecryptMasterKey)?;
+
+        Self::new(master_key, header.header_type.algorithm)
+    }
+
+    // An existing tree keeps its master key, so it can be updated.
+    // Otherwise, a new master key is created, and its header is written to the root of the tree.
+    pub(crate) fn open_or_create<RW>(
+        stor: &impl Storage<RW>,
+        dir: &Path,
+        raw_key: Protected<Vec<u8>>,
+        header_type: &HeaderType,
+        hashing_algorithm: HashingAlgorithm,
+    ) -> Result<Self, Error>
+    where
+        RW: Read + Write + Seek,
+    {
+        if stor.read_file(dir.join(DIR_HEADER_NAME)).is_ok() {
+            return Self::open(stor, dir, raw_key);
+        }
+
+        let header_type = HeaderType {
+            version: header_type.version,
+            algorithm: header_type.algorithm,
+            mode: Mode::StreamMode,
+        };
+        let (header, master_key) =
+            crate::key::create_header(raw_key, header_type, hashing_algorithm)
+                .map_err(Error::CreateHeader)?;
+
+        stor.create_dir_all(dir).map_err(Error::Storage)?;
+        let file = stor
+            .create_file(dir.join(DIR_HEADER_NAME))
+            .map_err(Error::Storage)?;
+        header
+            .write(&mut *file.try_writer().map_err(Error::Storage)?.borrow_mut())
+            .map_err(|_| Error::WriteHeader)?;
+        stor.flush_file(&file).map_err(Error::Storage)?;
+
+        Self::new(master_key, header.header_type.algorithm)
+    }
+
+    pub(crate) fn algorithm(&self) -> Algorithm {
+        self.algorithm
+    }
+
+    pub(crate) fn master_key(&self) -> Protected<[u8; MASTER_KEY_LEN]> {
+        self.master_key.clone()
+    }
+
+    // Every file's contents are encrypted with a key that's derived from its plaintext path, so they're bound to their name.
+    pub(crate) fn file_key(&self, path: &Path) -> Result<Protected<[u8; MASTER_KEY_LEN]>, Error> {
+        let mut hasher = blake3::Hasher::new_keyed(self.file_key.expose());
+
+        for component in path.components() {
+            match component {
+                Component::Normal(name) => {
+                    let name = path_to_bytes(Path::new(name))
+                        .ok_or_else(|| Error::UnsupportedPath(path.to_path_buf()))?;
+                    hasher.update(&(name.len() as u64).to_le_bytes());
+                    hasher.update(name);
+                }
+                Component::CurDir => {}
+                _ => return Err(Error::UnsupportedPath(path.to_path_buf())),
+            }
+        }
+
+        Ok(Protected::new(*hasher.finalize().as_bytes()))
+    }
+
+    // The parent's plaintext path is used as AAD, so names can't be moved to other directories.
+    fn encrypt_name(&self, parent: &Path, name: &Path) -> Result<String, Error> {
+        let full_path = parent.join(name);
+        let aad = path_to_bytes(parent).ok_or_else(|| Error::UnsupportedPath(full_path.clone()))?;
+        let msg = path_to_bytes(name).ok_or_else(|| Error::UnsupportedPath(full_path.clone()))?;
+
+        let nonce_len = get_nonce_len(&self.algorithm, &Mode::MemoryMode);
+        let mut hasher = blake3::Hasher::new_keyed(self.name_nonce_key.expose());
+        hasher.update(&(aad.len() as u64).to_le_bytes());
+        hasher.update(aad);
+        hasher.update(msg);
+        let mut nonce = hasher.finalize().as_bytes()[..nonce_len].to_vec();
+
+        let encrypted = self
+            .name_ciphers
+            .encrypt(&nonce, Payload { aad, msg })
+            .map_err(|_| Error::EncryptName(full_path.clone()))?;
+        nonce.extend_from_slice(&encrypted);
+
+        let encoded = base32_encode(&nonce);
+        if encoded.len() > MAX_NAME_LEN {
+            return Err(Error::NameTooLong(full_path));
+        }
+
+        Ok(encoded)
+    }
+
+    fn decrypt_name(&self, parent: &Path, encrypted_name: &str) -> Result<PathBuf, Error> {
+        let invalid = || Error::DecryptName(PathBuf::from(encrypted_name));
+
+        let aad = path_to_bytes(parent).ok_or_else(invalid)?;
+        let data = base32_decode(encrypted_name).ok_or_else(invalid)?;
+        let nonce_len = get_nonce_len(&self.algorithm, &Mode::MemoryMode);
+        if data.len() < nonce_len {
+            return Err(invalid());
+        }
+
+        let (nonce, msg) = data.split_at(nonce_len);
+        let name = self
+            .name_ciphers
+            .decrypt(nonce, Payload { aad, msg })
+            .map_err(|_| invalid())?;
+        let name = bytes_to_path(&name).ok_or_else(invalid)?;
+
+        // A name must stay within its parent
+        let mut components = name.components();
+        match (components.next(), components.next()) {
+            (Some(Component::Normal(_)), None) => Ok(name),
+            _ => Err(Error::UnsupportedPath(name)),
+        }
+    }
+
+    // Encrypts every component of a relative path.
+    pub(crate) fn encrypt_path(&self, path: &Path) -> Result<PathBuf, Error> {
+        let mut parent = PathBuf::new();
+        let mut encrypted = PathBuf::new();
+
+        for component in path.components() {
+            match component {
+                Component::Normal(name) => {
+                    encrypted.push(self.encrypt_name(&parent, Path::new(name))?);
+                    parent.push(name);
+                }
+                Component::CurDir => {}
+                _ => return Err(Error::UnsupportedPath(path.to_path_buf())),
+            }
+        }
+
+        Ok(encrypted)
+    }
+
+    // Decrypts every component of a relative, encrypted path.
+    pub(crate) fn decrypt_path(&self, path: &Path) -> Result<PathBuf, Error> {
+        let mut decrypted = PathBuf::new();
+
+        for component in path.components() {
+            match component {
+                Component::Normal(name) => {
+                    let name = name
+                        .to_str()
+                        .ok_or_else(|| Error::DecryptName(path.to_path_buf()))?;
+                    let name = self.decrypt_name(&decrypted, name)?;
+                    decrypted.push(name);
+                }
+                Component::CurDir => {}
+                _ => return Err(Error::UnsupportedPath(path.to_path_buf())),
+            }
+        }
+
+        Ok(decrypted)
+    }
+}
+
+pub fn execute<RW>(stor: &impl Storage<RW>, req: Request<'_>) -> Result<(), Error>
+where
+    RW: Read + Write + Seek,
+{
+    let input_dir = stor
+        .read_file(&req.input_dir_path)
+        .map_err(Error::Storage)?;
+    if !input_dir.is_dir() {
+        return Err(Error::InvalidDir);
+    }
+
+    let dir_key = DirKey::open_or_create(
+        stor,
+        &req.output_dir_path,
+        req.raw_key.clone(),
+        &req.header_type,
+        req.hashing_algorithm,
+    )?;
+
+    encrypt_tree(stor, &dir_key, &req)
+}
+
+pub(crate) fn encrypt_tree<RW>(
+    stor: &impl Storage<RW>,
+    dir_key: &DirKey,
+    req: &Request<'_>,
+) -> Result<(), Error>
+where
+    RW: Read + Write + Seek,
+{
+    let input_dir = stor
+        .read_file(&req.input_dir_path)
+        .map_err(Error::Storage)?;
+
+    for path in stor.walk_dir(&input_dir).map_err(Error::Storage)? {
+        let path = path.map_err(Error::Storage)?;
+        let relative_path = path
+            .strip_prefix(&req.input_dir_path)
+            .map_err(|_| Error::UnsupportedPath(path.clone()))?;
+        let output_path = req
+            .output_dir_path
+            .join(dir_key.encrypt_path(relative_path)?);
+
+        let entry = stor.read_file(&path).map_err(Error::Storage)?;
+        if entry.is_dir() {
+            stor.create_dir_all(&output_path).map_err(Error::Storage)?;
+            continue;
+        }
+
+        if let Some(on_file) = req.on_file {
+            on_file(relative_path);
+        }
+
+        encrypt_file(stor, dir_key, &entry, relative_path, &output_path).map_err(
+            |err| match err {
+                Error::Encrypt(_, inner) => Error::Encrypt(path.clone(), inner),
+                err => err,
+            },
+        )?;
+    }
+
+    Ok(())
+}
+
+pub(crate) fn encrypt_file<RW>(
+    stor: &impl Storage<RW>,
+    dir_key: &DirKey,
+    entry: &crate::storage::Entry<RW>,
+    relative_path: &Path,
+    output_path: &Path,
+) -> Result<(), Error>
+where
+    RW: Read + Write + Seek,
+{
+    let file_key = dir_key.file_key(relative_path)?;
+
+    if let Some(parent) = output_path.parent() {
+        stor.create_dir_all(parent).map_err(Error::Storage)?;
+    }
+
+    let output = stor
+        .create_file(output_path)
+        .or_else(|_| stor.write_file(output_path))
+        .map_err(Error::Storage)?;
+
+    // The keyslots are stored at the root, so every file only needs a unique nonce
+    let header = Header {
+        nonce: gen_nonce(&dir_key.algorithm, &Mode::StreamMode),
+        header_type: HeaderType {
+            version: HEADER_VERSION,
+            algorithm: dir_key.algorithm,
+            mode: Mode::StreamMode,
+        },
+        salt: None,
+        keyslots: Some(Vec::new()),
+    };
+
+    let reader: &RefCell<RW> = entry.try_reader().map_err(Error::Storage)?;
+    crate::encrypt::encrypt_with_master_key(
+        reader,
+        output.try_writer().map_err(Error::Storage)?,
+        None,
+        file_key,
+        &header,
+        None,
+        None,
+    )
+    .map_err(|err| Error::Encrypt(output_path.to_path_buf(), err))?;
+
+    stor.flush_file(&output).map_err(Error::Storage)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::utils::gen_master_key;
+
+    pub fn dir_key() -> DirKey {
+        DirKey::new(gen_master_key(), Algorithm::XChaCha20Poly1305).unwrap()
+    }
+
+    #[test]
+    fn should_encrypt_and_decrypt_paths() {
+        let dir_key = dir_key();
+        let path = Path::new("bar/foo/hello world.txt");
+
+        let encrypted = dir_key.encrypt_path(path).unwrap();
+
+        assert_eq!(encrypted.components().count(), 3);
+        assert!(encrypted
+            .to_str()
+            .unwrap()
+            .bytes()
+            .all(|b| b == b'/' || b.is_ascii_lowercase() || b.is_ascii_digit()));
+        // Names are deterministic, so the tree can be updated in place
+        assert_eq!(dir_key.encrypt_path(path).unwrap(), encrypted);
+        assert_eq!(dir_key.decrypt_path(&encrypted).unwrap(), path);
+    }
+
+    #[test]
+    fn should_bind_names_to_their_parent() {
+        let dir_key = dir_key();
+
+        let bar = dir_key.encrypt_path(Path::new("bar/hello.txt")).unwrap();
+        let foo = dir_key.encrypt_path(Path::new("foo/hello.txt")).unwrap();
+        assert_ne!(bar.file_name(), foo.file_name());
+
+        let moved = Path::new(foo.iter().next().unwrap()).join(bar.file_name().unwrap());
+        match dir_key.decrypt_path(&moved) {
+            Err(Error::DecryptName(_)) => {}
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn should_derive_file_keys_from_paths() {
+        let dir_key = dir_key();
+
+        let key = dir_key.file_key(Path::new("bar/hello.txt")).unwrap();
+        assert_eq!(
+            dir_key
+                .file_key(Path::new("./bar/hello.txt"))
+                .unwrap()
+                .expose(),
+            key.expose()
+        );
+        assert_ne!(
+            dir_key
+                .file_key(Path::new("foo/hello.txt"))
+                .unwrap()
+                .expose(),
+            key.expose()
+        );
+        assert_ne!(
+            dir_key
+                .file_key(Path::new("bar/hello.tx/t"))
+                .unwrap()
+                .expose(),
+            key.expose()
+        );
+        assert_ne!(dir_key.master_key().expose(), key.expose());
+    }
+
+    #[test]
+    fn should_reject_long_names() {
+        let dir_key = dir_key();
+
+        match dir_key.encrypt_path(Path::new(&"a".repeat(200))) {
+            Err(Error::NameTooLong(_)) => {}
+            _ => unreachable!(),
+        }
+    }
+}
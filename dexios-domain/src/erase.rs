@@ -1,17 +1,28 @@
 //! This provides functionality for "shredding" a file.
 //!
+//! Once the content is overwritten, the file can also be scrubbed: it's truncated, renamed a few times to random names of the same length, and its timestamps are reset before it's removed. This keeps the original name, size and timestamps out of the directory entry that's left behind.
+//!
 //! This will not be effective on flash storage, and if you are planning to release a program that uses this function, I'd recommend putting the default number of passes to 1.
 
+use rand::distributions::{Alphanumeric, DistString};
 use std::io::{Read, Seek, Write};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
-use crate::storage::Storage;
+use crate::overwrite::{Report, Scheme};
+use crate::progress::{Phase, Progress};
+use crate::storage::{self, Entry, Storage};
+
+// The number of random names that a scrubbed file goes through
+const SCRUB_RENAMES: usize = 3;
 
 #[derive(Debug)]
 pub enum Error {
     OpenFile,
+    NotRegularFile,
     Overwrite(crate::overwrite::Error),
+    Verify(crate::overwrite::Error),
+    Scrub(storage::Error),
     RemoveFile,
 }
 
@@ -19,7 +30,12 @@ impl std::fmt::Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Error::OpenFile => f.write_str("Unable to open file"),
+            Error::NotRegularFile => f.write_str("Only regular files can be erased"),
             Error::Overwrite(inner) => write!(f, "Unable to overwrite file: {inner}"),
+            Error::Verify(inner) => {
+                write!(f, "Unable to verify that the file was overwritten: {inner}")
+            }
+            Error::Scrub(inner) => write!(f, "Unable to scrub the file's metadata: {inner}"),
             Error::RemoveFile => f.write_str("Unable to remove file"),
         }
     }
@@ -29,35 +45,144 @@ impl std::error::Error for Error {}
 
 pub struct Request<P: AsRef<Path>> {
     pub path: P,
-    pub passes: i32,
+    pub scheme: Scheme,
+    // Reads the file back once it's overwritten, before it's removed
+    pub verify: bool,
+    // Truncates and renames the file, and resets its timestamps, before it's removed
+    pub scrub: bool,
+    pub progress: Option<Arc<dyn Progress>>,
 }
 
-pub fn execute<RW, P>(stor: Arc<impl Storage<RW> + 'static>, req: Request<P>) -> Result<(), Error>
+pub fn execute<RW, P>(
+    stor: Arc<impl Storage<RW> + 'static>,
+    req: Request<P>,
+) -> Result<Report, Error>
 where
     RW: Read + Write + Seek,
     P: AsRef<Path>,
 {
-    let file = stor.write_file(req.path).map_err(|_| Error::OpenFile)?;
+    // Symlinks are never followed, and devices are erased by `erase_device` instead
+    let file = stor
+        .modify_regular_file(req.path)
+        .map_err(|err| match err {
+            storage::Error::NotRegularFile => Error::NotRegularFile,
+            _ => Error::OpenFile,
+        })?;
+
+    if let Some(progress) = &req.progress {
+        let len = stor.file_len(&file).map_err(|_| Error::OpenFile)?;
+        progress.phase(Phase::Erasing);
+        progress.total(erased_len(len, &req.scheme));
+    }
+
+    erase_file(
+        &*stor,
+        file,
+        &req.scheme,
+        req.verify,
+        req.scrub,
+        req.progress,
+    )
+}
+
+// Overwrites and removes a file that was opened with `modify_file`.
+pub(crate) fn erase_file<RW>(
+    stor: &impl Storage<RW>,
+    file: Entry<RW>,
+    scheme: &Scheme,
+    verify: bool,
+    scrub: bool,
+    progress: Option<Arc<dyn Progress>>,
+) -> Result<Report, Error>
+where
+    RW: Read + Write + Seek,
+{
+    if let Some(progress) = &progress {
+        progress.file(file.path());
+    }
+
     let buf_capacity = stor.file_len(&file).map_err(|_| Error::OpenFile)?;
 
-    crate::overwrite::execute(crate::overwrite::Request {
+    let report = crate::overwrite::execute(crate::overwrite::Request {
         writer: file
             .try_writer()
             .expect("We're confident that we're in writing mode"),
         buf_capacity,
-        passes: req.passes,
+        scheme: scheme.clone(),
+        sync: Some(Box::new(|| stor.sync_file(&file))),
+        progress,
+        cancel: None,
     })
     .map_err(Error::Overwrite)?;
 
+    if verify {
+        crate::overwrite::verify(
+            file.try_reader()
+                .expect("We're confident that we're in writing mode"),
+            &report,
+        )
+        .map_err(Error::Verify)?;
+    }
+
+    let file = if scrub {
+        scrub_file(stor, file).map_err(Error::Scrub)?
+    } else {
+        file
+    };
+
     stor.remove_file(file).map_err(|_| Error::RemoveFile)?;
 
-    Ok(())
+    Ok(report)
+}
+
+fn scrub_file<RW>(stor: &impl Storage<RW>, file: Entry<RW>) -> Result<Entry<RW>, storage::Error>
+where
+    RW: Read + Write + Seek,
+{
+    stor.truncate_file(&file)?;
+    stor.sync_file(&file)?;
+
+    let mut file = file;
+    for _ in 0..SCRUB_RENAMES {
+        let to = random_sibling(stor, file.path())?;
+        file = stor.rename_file(file, to)?;
+    }
+
+    stor.reset_file_times(&file)?;
+    stor.sync_file(&file)?;
+
+    Ok(file)
+}
+
+// A random path within the same directory, with a name that's as long as the original one.
+fn random_sibling<RW>(stor: &impl Storage<RW>, path: &Path) -> Result<PathBuf, storage::Error>
+where
+    RW: Read + Write + Seek,
+{
+    let len = path.file_name().map_or(1, std::ffi::OsStr::len);
+
+    // Names that are taken are skipped, although the rename itself also refuses to replace anything
+    for _ in 0..64 {
+        let name = Alphanumeric.sample_string(&mut rand::thread_rng(), len);
+        let candidate = path.with_file_name(name);
+        if stor.read_file(&candidate).is_err() {
+            return Ok(candidate);
+        }
+    }
+
+    Err(storage::Error::RenameFile)
+}
+
+// The number of bytes that are written to erase a file.
+pub(crate) fn erased_len(len: usize, scheme: &Scheme) -> u64 {
+    len as u64 * scheme.patterns().len() as u64
 }
 
 #[cfg(test)]
 mod tests {
     use std::path::PathBuf;
 
+    use crate::progress::tests::RecordedProgress;
     use crate::storage::InMemoryStorage;
 
     use super::*;
@@ -69,10 +194,60 @@ mod tests {
 
         let req = Request {
             path: "hello.txt",
-            passes: 2,
+            scheme: Scheme::Random(2),
+            verify: true,
+            scrub: false,
+            progress: None,
         };
         match execute(stor.clone(), req) {
-            Ok(_) => assert_eq!(stor.files().get(&PathBuf::from("hello.txt")), None),
+            Ok(report) => {
+                assert_eq!(stor.files().get(&PathBuf::from("hello.txt")), None);
+                assert_eq!(report.passes.len(), 3);
+                assert_eq!(report.bytes(), 33);
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn should_scrub_file_before_removing_it() {
+        let stor = InMemoryStorage::default();
+        stor.add_bar_foo_folder();
+
+        let file = stor.modify_file("bar/hello.txt").unwrap();
+        let scrubbed = scrub_file(&stor, file).unwrap();
+
+        let path = scrubbed.path().to_path_buf();
+        assert_ne!(path, PathBuf::from("bar/hello.txt"));
+        assert_eq!(path.parent(), Some(Path::new("bar")));
+        assert_eq!(path.file_name().unwrap().len(), "hello.txt".len());
+        assert_eq!(stor.files().get(&PathBuf::from("bar/hello.txt")), None);
+        assert_eq!(stor.file_len(&scrubbed).unwrap(), 0);
+
+        stor.remove_file(scrubbed).unwrap();
+        assert_eq!(stor.files().get(&path), None);
+        // Nothing else within the directory was touched
+        assert!(stor
+            .files()
+            .contains_key(&PathBuf::from("bar/foo/world.txt")));
+    }
+
+    #[test]
+    fn should_report_every_pass() {
+        let stor = Arc::new(InMemoryStorage::default());
+        stor.add_hello_txt();
+        let progress = Arc::new(RecordedProgress::default());
+
+        let req = Request {
+            path: "hello.txt",
+            scheme: Scheme::Random(2),
+            verify: false,
+            scrub: false,
+            progress: Some(progress.clone()),
+        };
+        match execute(stor, req) {
+            // Two random passes and a pass of zeros
+            Ok(_) => assert_eq!(progress.phases(), vec![(Phase::Erasing, Some(33), 33)]),
             _ => unreachable!(),
         }
     }
@@ -83,11 +258,30 @@ mod tests {
 
         let req = Request {
             path: "hello.txt",
-            passes: 2,
+            scheme: Scheme::Random(2),
+            verify: true,
+            scrub: false,
+            progress: None,
         };
         match execute(stor, req) {
             Err(Error::OpenFile) => {}
             _ => unreachable!(),
         }
     }
+
+    #[test]
+    #[cfg(unix)]
+    fn should_only_erase_regular_files() {
+        let req = Request {
+            path: "/dev/null",
+            scheme: Scheme::Random(2),
+            verify: false,
+            scrub: false,
+            progress: None,
+        };
+        match execute(Arc::new(storage::FileStorage), req) {
+            Err(Error::NotRegularFile) => {}
+            _ => unreachable!(),
+        }
+    }
 }
@@ -0,0 +1,308 @@
+//! This provides functionality for overwriting a block device, such as `/dev/sdb` or a loop device, or anything else whose size isn't in its metadata.
+//!
+//! The size is found by seeking to the end, as the metadata of a device reports zero bytes. The device is overwritten in place with the passes of a `Scheme`, and it's never removed.
+//!
+//! Overwriting a device that's in use would corrupt whatever is using it, so a device is refused if it (or one of its partitions) is mounted, used for swap, or held by another device (such as device-mapper or RAID). A block device is also opened exclusively, which the kernel refuses while it's in use, and that's held until the device is overwritten so nothing can mount it in the meantime. This can only be checked on Linux, so devices are refused on other platforms.
+//!
+//! Like erasing a file, this will not be effective on flash storage, as the drive may keep the old content in blocks that are out of reach.
+
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::cancel::CancellationToken;
+use crate::overwrite::{Report, Scheme};
+use crate::progress::{Phase, Progress};
+use crate::storage::Storage;
+
+#[derive(Debug)]
+pub enum Error {
+    OpenDevice,
+    DeviceLen,
+    MountTable,
+    InUse(PathBuf),
+    Unsupported,
+    Overwrite(crate::overwrite::Error),
+    Verify(crate::overwrite::Error),
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::OpenDevice => f.write_str("Unable to open the device"),
+            Error::DeviceLen => f.write_str("Unable to find the size of the device"),
+            Error::MountTable => f.write_str("Unable to read the mount table"),
+            Error::InUse(source) => write!(
+                f,
+                "The device is in use ({} is mounted, used for swap, or held by another device or program)",
+                source.display()
+            ),
+            Error::Unsupported => f.write_str(
+                "Devices can only be checked for use on Linux, so they can't be overwritten here",
+            ),
+            Error::Overwrite(inner) => write!(f, "Unable to overwrite the device: {inner}"),
+            Error::Verify(inner) => {
+                write!(
+                    f,
+                    "Unable to verify that the device was overwritten: {inner}"
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+pub struct Request<P: AsRef<Path>> {
+    pub path: P,
+    pub scheme: Scheme,
+    // Reads the device back once it's overwritten
+    pub verify: bool,
+    pub progress: Option<Arc<dyn Progress>>,
+    pub cancel: Option<CancellationToken>,
+}
+
+pub fn execute<RW, P>(
+    stor: Arc<impl Storage<RW> + 'static>,
+    req: Request<P>,
+) -> Result<Report, Error>
+where
+    RW: Read + Write + Seek,
+    P: AsRef<Path>,
+{
+    // Held until the device is overwritten
+    let _claim = claim(req.path.as_ref())?;
+
+    let device = stor
+        .modify_file(req.path.as_ref())
+        .map_err(|_| Error::OpenDevice)?;
+    let writer = device
+        .try_writer()
+        .expect("We're confident that we're in writing mode");
+
+    let len = writer
+        .borrow_mut()
+        .seek(SeekFrom::End(0))
+        .map_err(|_| Error::DeviceLen)?;
+    let len = usize::try_from(len).map_err(|_| Error::DeviceLen)?;
+
+    if let Some(progress) = &req.progress {
+        progress.phase(Phase::Erasing);
+        progress.total(len as u64 * req.scheme.patterns().len() as u64);
+        progress.file(device.path());
+    }
+
+    let report = crate::overwrite::execute(crate::overwrite::Request {
+        writer,
+        buf_capacity: len,
+        scheme: req.scheme,
+        sync: Some(Box::new(|| stor.sync_file(&device))),
+        progress: req.progress,
+        cancel: req.cancel,
+    })
+    .map_err(Error::Overwrite)?;
+
+    if req.verify {
+        crate::overwrite::verify(writer, &report).map_err(Error::Verify)?;
+    }
+
+    Ok(report)
+}
+
+// Refuses the device if it's in use, and otherwise claims a block device exclusively (until the claim is dropped).
+#[cfg(target_os = "linux")]
+fn claim(device: &Path) -> Result<Option<std::fs::File>, Error> {
+    use std::os::unix::fs::{FileTypeExt, OpenOptionsExt};
+
+    // Devices are often reached through symlinks, such as `/dev/disk/by-id/...`
+    let device = std::fs::canonicalize(device).unwrap_or_else(|_| device.to_path_buf());
+
+    let mounts = std::fs::read_to_string("/proc/self/mounts").map_err(|_| Error::MountTable)?;
+    // Swap isn't always available, e.g. within containers
+    let swaps = std::fs::read_to_string("/proc/swaps").unwrap_or_default();
+
+    if let Some(source) = find_in_table(&device, &mounts)
+        .or_else(|| find_in_table(&device, &swaps))
+        .or_else(|| find_holder(Path::new("/sys/class/block"), &device))
+    {
+        return Err(Error::InUse(source));
+    }
+
+    let is_block_device =
+        std::fs::metadata(&device).is_ok_and(|metadata| metadata.file_type().is_block_device());
+    if !is_block_device {
+        return Ok(None);
+    }
+
+    // The kernel refuses this while the device is mounted or held, even if the checks above missed it
+    std::fs::OpenOptions::new()
+        .read(true)
+        .custom_flags(libc::O_EXCL)
+        .open(&device)
+        .map(Some)
+        .map_err(|err| match err.raw_os_error() {
+            Some(libc::EBUSY) => Error::InUse(device),
+            _ => Error::OpenDevice,
+        })
+}
+
+#[cfg(not(target_os = "linux"))]
+fn claim(_device: &Path) -> Result<Option<std::fs::File>, Error> {
+    Err(Error::Unsupported)
+}
+
+// Finds a device that's built on top of this device, or one of its partitions, within sysfs (e.g. `/sys/class/block/sda/sda1/holders/dm-0`).
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn find_holder(sys_block: &Path, device: &Path) -> Option<PathBuf> {
+    let dir = sys_block.join(device.file_name()?);
+
+    let partitions = std::fs::read_dir(&dir)
+        .into_iter()
+        .flatten()
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| path.join("partition").exists());
+
+    std::iter::once(dir.clone())
+        .chain(partitions)
+        .filter_map(|path| std::fs::read_dir(path.join("holders")).ok())
+        .flatten()
+        .filter_map(Result::ok)
+        .map(|holder| Path::new("/dev").join(holder.file_name()))
+        .next()
+}
+
+// Finds the device, or one of its partitions, in the first column of a table such as `/proc/self/mounts`.
+//
+// Partitions are matched by their name starting with the device's name (e.g. `sda1` or `loop0p1`), so `loop1` is also refused while `loop10` is mounted, which errs on the side of caution.
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn find_in_table(device: &Path, table: &str) -> Option<PathBuf> {
+    let name = device.file_name()?.to_string_lossy();
+
+    table
+        .lines()
+        .filter_map(|line| line.split_whitespace().next())
+        .filter(|source| source.starts_with('/'))
+        .map(|source| {
+            let source = PathBuf::from(unescape(source));
+            std::fs::canonicalize(&source).unwrap_or(source)
+        })
+        .find(|source| {
+            source == device
+                || (source.parent() == device.parent()
+                    && source
+                        .file_name()
+                        .is_some_and(|source| source.to_string_lossy().starts_with(&*name)))
+        })
+}
+
+// The mount table escapes spaces, tabs, newlines and backslashes as octal (e.g. `\040`).
+fn unescape(field: &str) -> String {
+    let mut out = String::with_capacity(field.len());
+    let mut rest = field;
+
+    while let Some(i) = rest.find('\\') {
+        out.push_str(&rest[..i]);
+        let code = rest
+            .get(i + 1..i + 4)
+            .and_then(|octal| u8::from_str_radix(octal, 8).ok());
+        if let Some(byte) = code {
+            out.push(char::from(byte));
+            rest = &rest[i + 4..];
+        } else {
+            out.push('\\');
+            rest = &rest[i + 1..];
+        }
+    }
+
+    out.push_str(rest);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::InMemoryStorage;
+
+    const MOUNTS: &str = "\
+sysfs /sys sysfs rw,nosuid,nodev,noexec,relatime 0 0
+/dev/sda1 / ext4 rw,relatime 0 0
+/dev/loop3p2 /mnt/my\\040disk vfat rw,relatime 0 0
+";
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn should_overwrite_device_without_removing_it() {
+        let stor = Arc::new(InMemoryStorage::default());
+        stor.add_hello_txt();
+
+        let req = Request {
+            path: "hello.txt",
+            scheme: Scheme::Random(1),
+            verify: true,
+            progress: None,
+            cancel: None,
+        };
+
+        match execute(stor.clone(), req) {
+            Ok(report) => {
+                assert_eq!(report.bytes(), 22);
+
+                let file = stor.read_file("hello.txt").unwrap();
+                let mut content = Vec::new();
+                file.try_reader()
+                    .unwrap()
+                    .borrow_mut()
+                    .read_to_end(&mut content)
+                    .unwrap();
+                assert_eq!(content, vec![0u8; 11]);
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn should_find_mounted_partitions() {
+        assert_eq!(
+            find_in_table(Path::new("/dev/sda"), MOUNTS),
+            Some(PathBuf::from("/dev/sda1"))
+        );
+        assert_eq!(
+            find_in_table(Path::new("/dev/loop3"), MOUNTS),
+            Some(PathBuf::from("/dev/loop3p2"))
+        );
+        assert_eq!(find_in_table(Path::new("/dev/sdb"), MOUNTS), None);
+        assert_eq!(find_in_table(Path::new("/dev/loop0"), MOUNTS), None);
+    }
+
+    #[test]
+    fn should_find_holders_of_devices_and_partitions() {
+        let sys_block = std::env::temp_dir().join("dexios-erase-device-test");
+        let _ = std::fs::remove_dir_all(&sys_block);
+
+        std::fs::create_dir_all(sys_block.join("sda/holders")).unwrap();
+        std::fs::create_dir_all(sys_block.join("sda/sda1/holders/dm-0")).unwrap();
+        std::fs::write(sys_block.join("sda/sda1/partition"), "1").unwrap();
+        std::fs::create_dir_all(sys_block.join("sdb/holders")).unwrap();
+        std::fs::create_dir_all(sys_block.join("md0/holders/dm-1")).unwrap();
+
+        assert_eq!(
+            find_holder(&sys_block, Path::new("/dev/sda")),
+            Some(PathBuf::from("/dev/dm-0"))
+        );
+        assert_eq!(
+            find_holder(&sys_block, Path::new("/dev/md0")),
+            Some(PathBuf::from("/dev/dm-1"))
+        );
+        assert_eq!(find_holder(&sys_block, Path::new("/dev/sdb")), None);
+        assert_eq!(find_holder(&sys_block, Path::new("/dev/sdc")), None);
+
+        std::fs::remove_dir_all(&sys_block).unwrap();
+    }
+
+    #[test]
+    fn should_unescape_mount_table_fields() {
+        assert_eq!(unescape("/mnt/my\\040disk"), "/mnt/my disk");
+        assert_eq!(unescape("/mnt/back\\134slash"), "/mnt/back\\slash");
+    }
+}
@@ -1,16 +1,24 @@
 //! This provides functionality for "shredding" a directory. It first traverses the directory, and then calls `shred` on all files.
 //!
+//! Files are erased by a bounded pool of worker threads. Symlinks are never followed: the link itself is removed, and what it points to is left alone. Directories on other filesystems (and anything that isn't a regular file, such as a device) are skipped, unless `cross_filesystems` is set for the former.
+//!
+//! Every file is attempted, and the outcome of each of them is reported. The directory is only removed if every entry within it was erased.
+//!
 //! This will not be effective on flash storage, and if you are planning to release a program that uses this function, I'd recommend putting the default number of passes to 1.
 
 use std::io::{Read, Seek, Write};
-use std::sync::Arc;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
 
-use crate::storage::Storage;
+use crate::erase::{erase_file, erased_len};
+use crate::overwrite::Scheme;
+use crate::progress::{Phase, Progress};
+use crate::storage::{EntryKind, Storage, TreeEntry};
 
 #[derive(Debug)]
 pub enum Error {
     InvalidFileType,
-    EraseFile(crate::erase::Error),
     ReadDirEntries,
     RemoveDir,
 }
@@ -19,7 +27,6 @@ impl std::fmt::Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Error::InvalidFileType => f.write_str("Invalid file type"),
-            Error::EraseFile(inner) => write!(f, "Unable to erase file: {inner}"),
             Error::ReadDirEntries => f.write_str("Unable to get all dir entries"),
             Error::RemoveDir => f.write_str("Unable to remove directory recursively"),
         }
@@ -33,10 +40,57 @@ where
     RW: Read + Write + Seek,
 {
     pub entry: crate::storage::Entry<RW>,
-    pub passes: i32,
+    pub scheme: Scheme,
+    // Reads every file back once it's overwritten, before it's removed
+    pub verify: bool,
+    // Truncates and renames every file, and resets their timestamps, before they're removed
+    pub scrub: bool,
+    // Descends into directories on other filesystems, instead of skipping them
+    pub cross_filesystems: bool,
+    // The most files that are erased at once (at least one is always erased)
+    pub workers: usize,
+    pub progress: Option<Arc<dyn Progress>>,
+}
+
+#[derive(Debug)]
+pub enum Outcome {
+    Erased,
+    // A symlink, which was removed without touching what it points to
+    Unlinked,
+    // A directory on another filesystem, or something that isn't a regular file
+    Skipped,
+    Failed(crate::erase::Error),
+}
+
+#[derive(Debug)]
+pub struct FileReport {
+    pub path: PathBuf,
+    pub outcome: Outcome,
+}
+
+#[derive(Debug)]
+pub struct Report {
+    // Sorted by path
+    pub files: Vec<FileReport>,
+    // Whether the directory itself was removed, which only happens if nothing failed or was skipped
+    pub removed: bool,
 }
 
-pub fn execute<RW>(stor: Arc<impl Storage<RW> + 'static>, req: Request<RW>) -> Result<(), Error>
+impl Report {
+    pub fn failed(&self) -> impl Iterator<Item = &FileReport> {
+        self.files
+            .iter()
+            .filter(|file| matches!(file.outcome, Outcome::Failed(_)))
+    }
+
+    pub fn skipped(&self) -> impl Iterator<Item = &FileReport> {
+        self.files
+            .iter()
+            .filter(|file| matches!(file.outcome, Outcome::Skipped))
+    }
+}
+
+pub fn execute<RW>(stor: Arc<impl Storage<RW> + 'static>, req: Request<RW>) -> Result<Report, Error>
 where
     RW: Read + Write + Seek,
 {
@@ -44,43 +98,138 @@ where
         return Err(Error::InvalidFileType);
     }
 
-    let files = stor
-        .read_dir(&req.entry)
+    let entries = stor
+        .walk_tree(&req.entry, req.cross_filesystems)
+        .map_err(|_| Error::ReadDirEntries)?
+        .collect::<Result<Vec<TreeEntry>, _>>()
         .map_err(|_| Error::ReadDirEntries)?;
 
-    #[allow(clippy::needless_collect)] // 🚫 we have to collect in order to propertly join threads!
-    let handlers = files
-        .into_iter()
-        .filter(|f| !f.is_dir())
-        .map(|f| {
-            let file_path = f.path().to_path_buf();
-            let stor = stor.clone();
-            std::thread::spawn(move || -> Result<(), Error> {
-                crate::erase::execute(
-                    stor,
-                    crate::erase::Request {
-                        path: file_path,
-                        passes: req.passes,
-                    },
-                )
-                .map_err(Error::EraseFile)?;
-                Ok(())
+    // A symlink to a directory is removed like any other link, so there's nothing left to remove afterwards
+    let is_link = entries
+        .first()
+        .is_some_and(|entry| entry.kind == EntryKind::Symlink && entry.path == req.entry.path());
+
+    let mut reports = Vec::new();
+    let mut files = Vec::new();
+    for entry in entries {
+        let outcome = match entry.kind {
+            EntryKind::File => {
+                files.push(entry.path);
+                continue;
+            }
+            EntryKind::Dir => continue,
+            EntryKind::Symlink => match stor.remove_link(&entry.path) {
+                Ok(()) => Outcome::Unlinked,
+                Err(_) => Outcome::Failed(crate::erase::Error::RemoveFile),
+            },
+            EntryKind::Mount | EntryKind::Other => Outcome::Skipped,
+        };
+
+        reports.push(FileReport {
+            path: entry.path,
+            outcome,
+        });
+    }
+
+    if let Some(progress) = &req.progress {
+        // Files that can't be opened here are still attempted, and their failure is reported then
+        let total = files
+            .iter()
+            .filter_map(|path| {
+                let file = stor.read_file(path).ok()?;
+                stor.file_len(&file).ok()
             })
-        })
-        .collect::<Vec<_>>();
+            .map(|len| erased_len(len, &req.scheme))
+            .sum::<u64>();
+
+        progress.phase(Phase::Erasing);
+        progress.total(total);
+    }
+
+    reports.extend(erase_files(&*stor, &req, files));
+    reports.sort_by(|a, b| a.path.cmp(&b.path));
+
+    let removed = reports
+        .iter()
+        .all(|file| matches!(file.outcome, Outcome::Erased | Outcome::Unlinked));
+    if removed && !is_link {
+        stor.remove_dir_all(req.entry)
+            .map_err(|_| Error::RemoveDir)?;
+    }
 
-    handlers.into_iter().try_for_each(|h| h.join().unwrap())?;
+    Ok(Report {
+        files: reports,
+        removed,
+    })
+}
+
+// Erases the files with a pool of scoped threads, which take the next file from a shared index.
+fn erase_files<RW>(
+    stor: &impl Storage<RW>,
+    req: &Request<RW>,
+    files: Vec<PathBuf>,
+) -> Vec<FileReport>
+where
+    RW: Read + Write + Seek,
+{
+    let next = AtomicUsize::new(0);
+    let reports = Mutex::new(Vec::with_capacity(files.len()));
+    let (scheme, verify, scrub) = (&req.scheme, req.verify, req.scrub);
+    let progress = &req.progress;
+
+    std::thread::scope(|s| {
+        for _ in 0..req.workers.clamp(1, files.len().max(1)) {
+            s.spawn(|| {
+                while let Some(path) = files.get(next.fetch_add(1, Ordering::Relaxed)) {
+                    // The file may have been replaced by a symlink since the tree was walked
+                    let res = stor
+                        .modify_regular_file(path)
+                        .map_err(|_| crate::erase::Error::OpenFile)
+                        .and_then(|file| {
+                            erase_file(stor, file, scheme, verify, scrub, progress.clone())
+                        });
+                    let outcome = match res {
+                        Ok(_) => Outcome::Erased,
+                        Err(err) => Outcome::Failed(err),
+                    };
+
+                    reports
+                        .lock()
+                        .expect("A worker panicked while reporting")
+                        .push(FileReport {
+                            path: path.clone(),
+                            outcome,
+                        });
+                }
+            });
+        }
+    });
 
-    stor.remove_dir_all(req.entry).map_err(|_| Error::RemoveDir)
+    reports
+        .into_inner()
+        .expect("A worker panicked while reporting")
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::storage::testing::FaultyStorage;
     use crate::storage::InMemoryStorage;
 
     use std::path::PathBuf;
 
+    fn request<RW: Read + Write + Seek>(entry: crate::storage::Entry<RW>) -> Request<RW> {
+        Request {
+            entry,
+            scheme: Scheme::Random(2),
+            verify: true,
+            scrub: true,
+            cross_filesystems: false,
+            workers: 2,
+            progress: None,
+        }
+    }
+
     #[test]
     fn should_erase_dir_recursively_with_subfiles() {
         let stor = Arc::new(InMemoryStorage::default());
@@ -90,13 +239,11 @@ mod tests {
         let file = stor.read_file("bar/").unwrap();
         let file_path = file.path().to_path_buf();
 
-        let req = Request {
-            entry: file,
-            passes: 2,
-        };
-
-        match execute(stor.clone(), req) {
-            Ok(()) => {
+        match execute(stor.clone(), request(file)) {
+            Ok(report) => {
+                assert!(report.removed);
+                assert_eq!(report.files.len(), 4);
+                assert_eq!(report.failed().count(), 0);
                 assert_eq!(stor.files().get(&file_path).cloned(), None);
                 let files = stor.files();
                 let mut keys = files.keys();
@@ -106,4 +253,36 @@ mod tests {
             _ => unreachable!(),
         }
     }
+
+    #[test]
+    fn should_report_failures_and_erase_the_other_files() {
+        let inner = InMemoryStorage::default();
+        inner.add_bar_foo_folder();
+        let stor = Arc::new(FaultyStorage::new(inner).fail_removal("bar/foo/hello.txt"));
+
+        // Scrubbing renames the file before it's removed, which would avoid the fault
+        let req = Request {
+            scrub: false,
+            ..request(stor.read_file("bar/").unwrap())
+        };
+
+        match execute(stor.clone(), req) {
+            Ok(report) => {
+                assert!(!report.removed);
+
+                let failed = report.failed().map(|f| f.path.clone()).collect::<Vec<_>>();
+                assert_eq!(failed, vec![PathBuf::from("bar/foo/hello.txt")]);
+                assert_eq!(
+                    report
+                        .files
+                        .iter()
+                        .filter(|f| matches!(f.outcome, Outcome::Erased))
+                        .count(),
+                    3
+                );
+                assert!(stor.read_file("bar/world.txt").is_err());
+            }
+            _ => unreachable!(),
+        }
+    }
 }
@@ -1,16 +1,23 @@
-//! This provides functionality for hashing a file with `BLAKE3`, using a stream reader to keep memory usage low.
+//! This provides functionality for hashing a file (with `BLAKE3` or any other `Hasher`), using a stream reader to keep memory usage low.
+//!
+//! Large files on disk can also be hashed with `BLAKE3` on every core, by memory-mapping them. Anything that can't be mapped, such as a pipe, is streamed instead, and the hash is the same either way.
 
 use core::primitives::BLOCK_SIZE;
 use std::fmt;
 use std::{
     cell::RefCell,
+    fs::File,
     io::{Read, Seek},
+    path::Path,
+    sync::Arc,
 };
 
-use crate::hasher::Hasher;
+use crate::hasher::{Blake3Hasher, Hasher};
+use crate::progress::{remaining_len, Phase, Progress};
 
 #[derive(Debug)]
 pub enum Error {
+    OpenFile,
     ResetCursorPosition,
     ReadData,
 }
@@ -18,6 +25,7 @@ pub enum Error {
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
+            Error::OpenFile => f.write_str("Unable to open file"),
             Error::ResetCursorPosition => f.write_str("Unable to reset cursor position"),
             Error::ReadData => f.write_str("Unable to read data"),
         }
@@ -28,6 +36,7 @@ impl std::error::Error for Error {}
 
 pub struct Request<R: Read + Seek> {
     pub reader: RefCell<R>,
+    pub progress: Option<Arc<dyn Progress>>,
 }
 
 pub fn execute<R: Read + Seek>(mut hasher: impl Hasher, req: Request<R>) -> Result<String, Error> {
@@ -36,27 +45,83 @@ pub fn execute<R: Read + Seek>(mut hasher: impl Hasher, req: Request<R>) -> Resu
         .rewind()
         .map_err(|_| Error::ResetCursorPosition)?;
 
+    if let Some(progress) = &req.progress {
+        progress.phase(Phase::Hashing);
+        progress.total(
+            remaining_len(&mut *req.reader.borrow_mut()).map_err(|_| Error::ResetCursorPosition)?,
+        );
+    }
+
+    stream(
+        &mut hasher,
+        &mut *req.reader.borrow_mut(),
+        req.progress.as_deref(),
+    )?;
+
+    Ok(hasher.finish())
+}
+
+/// Files that are smaller than this are streamed, as they're not worth mapping and splitting between threads.
+pub const PARALLEL_THRESHOLD: u64 = 1 << 20;
+
+/// Hashes a file on disk with a `BLAKE3` hasher (which may be keyed), using every core for regular files that are at least `PARALLEL_THRESHOLD` bytes.
+///
+/// Pipes, devices and small files are streamed in the same way as `execute`, so this can be used for any path.
+pub fn execute_parallel<P: AsRef<Path>>(
+    mut hasher: Blake3Hasher,
+    path: P,
+    progress: Option<Arc<dyn Progress>>,
+) -> Result<String, Error> {
+    let mut file = File::open(path.as_ref()).map_err(|_| Error::OpenFile)?;
+    let metadata = file.metadata().map_err(|_| Error::OpenFile)?;
+    let len = metadata.is_file().then_some(metadata.len());
+
+    if let Some(progress) = &progress {
+        progress.phase(Phase::Hashing);
+        if let Some(len) = len {
+            progress.total(len);
+        }
+    }
+
+    match len {
+        Some(len) if len >= PARALLEL_THRESHOLD => {
+            hasher
+                .write_file_parallel(path.as_ref())
+                .map_err(|_| Error::ReadData)?;
+            if let Some(progress) = &progress {
+                progress.advance(len);
+            }
+        }
+        _ => stream(&mut hasher, &mut file, progress.as_deref())?,
+    }
+
+    Ok(hasher.finish())
+}
+
+// Reads until the end, as pipes may return fewer bytes than were asked for before they're done.
+fn stream(
+    hasher: &mut impl Hasher,
+    reader: &mut impl Read,
+    progress: Option<&dyn Progress>,
+) -> Result<(), Error> {
     let mut buffer = vec![0u8; BLOCK_SIZE].into_boxed_slice();
 
     loop {
-        let read_count = req
-            .reader
-            .borrow_mut()
-            .read(&mut buffer)
-            .map_err(|_| Error::ReadData)?;
+        let read_count = reader.read(&mut buffer).map_err(|_| Error::ReadData)?;
+        if read_count == 0 {
+            return Ok(());
+        }
         hasher.write(&buffer[..read_count]);
-        if read_count != BLOCK_SIZE {
-            break;
+        if let Some(progress) = progress {
+            progress.advance(read_count as u64);
         }
     }
-
-    Ok(hasher.finish())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::hasher::Blake3Hasher;
+    use crate::progress::tests::RecordedProgress;
     use rand::RngCore;
     use std::io::Cursor;
 
@@ -68,6 +133,7 @@ mod tests {
 
         let req = Request {
             reader: RefCell::new(reader),
+            progress: None,
         };
 
         match execute(Blake3Hasher::default(), req) {
@@ -94,6 +160,7 @@ mod tests {
 
         let req = Request {
             reader: RefCell::new(reader),
+            progress: None,
         };
 
         match execute(Blake3Hasher::default(), req) {
@@ -114,6 +181,7 @@ mod tests {
 
         let req = Request {
             reader: RefCell::new(reader),
+            progress: None,
         };
 
         match execute(Blake3Hasher::default(), req) {
@@ -123,4 +191,71 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn should_report_progress() {
+        let progress = Arc::new(RecordedProgress::default());
+        let mut buf = vec![0u8; BLOCK_SIZE * 2 + 10];
+        rand::thread_rng().fill_bytes(&mut buf);
+
+        let req = Request {
+            reader: RefCell::new(Cursor::new(&mut buf)),
+            progress: Some(progress.clone()),
+        };
+
+        match execute(Blake3Hasher::default(), req) {
+            Ok(_) => {
+                let len = BLOCK_SIZE as u64 * 2 + 10;
+                assert_eq!(progress.phases(), vec![(Phase::Hashing, Some(len), len)]);
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn should_hash_files_in_parallel_like_streaming() {
+        let path = std::env::temp_dir().join("dexios-hash-parallel-test");
+
+        #[allow(clippy::cast_possible_truncation)]
+        for len in [0, 11, PARALLEL_THRESHOLD as usize * 3 + 7] {
+            let mut buf = vec![0u8; len];
+            rand::thread_rng().fill_bytes(&mut buf);
+            std::fs::write(&path, &buf).unwrap();
+
+            let progress = Arc::new(RecordedProgress::default());
+            match execute_parallel(Blake3Hasher::default(), &path, Some(progress.clone())) {
+                Ok(hash) => {
+                    assert_eq!(hash, blake3::hash(&buf).to_hex().to_string());
+                    assert_eq!(
+                        progress.phases(),
+                        vec![(Phase::Hashing, Some(len as u64), len as u64)]
+                    );
+                }
+                _ => unreachable!(),
+            }
+        }
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn should_stream_pipes() {
+        use std::os::unix::io::AsRawFd;
+        use std::process::{Command, Stdio};
+
+        let mut child = Command::new("printf")
+            .arg("Hello world")
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let fd = child.stdout.as_ref().unwrap().as_raw_fd();
+
+        match execute_parallel(Blake3Hasher::default(), format!("/proc/self/fd/{fd}"), None) {
+            Ok(hash) => assert_eq!(hash, blake3::hash(b"Hello world").to_hex().to_string()),
+            _ => unreachable!(),
+        }
+
+        child.wait().unwrap();
+    }
 }
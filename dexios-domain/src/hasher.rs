@@ -1,8 +1,83 @@
+//! This contains the hash functions that files can be hashed with.
+//!
+//! `BLAKE3` is the default. The others are provided so checksums can be compared with the output of common tools, such as `sha256sum` or `b2sum`.
+
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::utils::hex_encode;
+
 pub trait Hasher {
     fn write(&mut self, input: &[u8]);
     fn finish(&mut self) -> String;
 }
 
+impl<H: Hasher + ?Sized> Hasher for Box<H> {
+    fn write(&mut self, input: &[u8]) {
+        (**self).write(input);
+    }
+
+    fn finish(&mut self) -> String {
+        (**self).finish()
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum HashAlgorithm {
+    #[default]
+    Blake3,
+    Sha256,
+    Sha512,
+    Sha3_256,
+    /// `BLAKE2b` with a 512-bit output, like `b2sum`
+    Blake2b,
+}
+
+impl HashAlgorithm {
+    pub const ALL: [HashAlgorithm; 5] = [
+        HashAlgorithm::Blake3,
+        HashAlgorithm::Sha256,
+        HashAlgorithm::Sha512,
+        HashAlgorithm::Sha3_256,
+        HashAlgorithm::Blake2b,
+    ];
+
+    #[must_use]
+    pub fn hasher(self) -> Box<dyn Hasher> {
+        match self {
+            HashAlgorithm::Blake3 => Box::<Blake3Hasher>::default(),
+            HashAlgorithm::Sha256 => Box::<Sha256Hasher>::default(),
+            HashAlgorithm::Sha512 => Box::<Sha512Hasher>::default(),
+            HashAlgorithm::Sha3_256 => Box::<Sha3_256Hasher>::default(),
+            HashAlgorithm::Blake2b => Box::<Blake2bHasher>::default(),
+        }
+    }
+}
+
+impl fmt::Display for HashAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HashAlgorithm::Blake3 => f.write_str("blake3"),
+            HashAlgorithm::Sha256 => f.write_str("sha256"),
+            HashAlgorithm::Sha512 => f.write_str("sha512"),
+            HashAlgorithm::Sha3_256 => f.write_str("sha3-256"),
+            HashAlgorithm::Blake2b => f.write_str("blake2b"),
+        }
+    }
+}
+
+impl FromStr for HashAlgorithm {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|algorithm| algorithm.to_string() == s.to_lowercase())
+            .ok_or_else(|| format!("Unknown hash algorithm: {s}"))
+    }
+}
+
 pub struct Blake3Hasher {
     inner: blake3::Hasher,
 }
@@ -15,6 +90,29 @@ impl Default for Blake3Hasher {
     }
 }
 
+impl Blake3Hasher {
+    /// A hasher for keyed MACs, rather than plain hashes (see `mac::MacKey`).
+    #[must_use]
+    pub fn keyed(key: &[u8; 32]) -> Self {
+        Self {
+            inner: blake3::Hasher::new_keyed(key),
+        }
+    }
+
+    /// A hasher for `BLAKE3`'s derive-key mode, which turns the input into a key for the given context.
+    #[must_use]
+    pub fn derive_key(context: &str) -> Self {
+        Self {
+            inner: blake3::Hasher::new_derive_key(context),
+        }
+    }
+
+    // Memory-maps the file and hashes it on every core, which gives the same hash as writing it in order.
+    pub(crate) fn write_file_parallel(&mut self, path: &Path) -> std::io::Result<()> {
+        self.inner.update_mmap_rayon(path).map(|_| ())
+    }
+}
+
 impl Hasher for Blake3Hasher {
     fn write(&mut self, input: &[u8]) {
         self.inner.update(input);
@@ -24,3 +122,83 @@ impl Hasher for Blake3Hasher {
         self.inner.finalize().to_hex().to_string()
     }
 }
+
+// The RustCrypto hashers all share the `Digest` trait, so they're implemented in the same way.
+// It's called explicitly, as `blake3::Hasher` implements it as well.
+macro_rules! digest_hasher {
+    ($name:ident, $inner:ty) => {
+        #[derive(Default)]
+        pub struct $name {
+            inner: $inner,
+        }
+
+        impl Hasher for $name {
+            fn write(&mut self, input: &[u8]) {
+                sha2::Digest::update(&mut self.inner, input);
+            }
+
+            fn finish(&mut self) -> String {
+                hex_encode(&sha2::Digest::finalize_reset(&mut self.inner))
+            }
+        }
+    };
+}
+
+digest_hasher!(Sha256Hasher, sha2::Sha256);
+digest_hasher!(Sha512Hasher, sha2::Sha512);
+digest_hasher!(Sha3_256Hasher, sha3::Sha3_256);
+digest_hasher!(Blake2bHasher, blake2::Blake2b512);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hash(algorithm: HashAlgorithm, input: &[u8]) -> String {
+        let mut hasher = algorithm.hasher();
+        hasher.write(input);
+        hasher.finish()
+    }
+
+    #[test]
+    fn should_match_known_digests() {
+        assert_eq!(
+            hash(HashAlgorithm::Sha256, b"abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(
+            hash(HashAlgorithm::Sha512, b"abc"),
+            "ddaf35a193617abacc417349ae20413112e6fa4e89a97ea20a9eeee64b55d39a2192992a274fc1a836ba3c23a3feebbd454d4423643ce80e2a9ac94fa54ca49f"
+        );
+        assert_eq!(
+            hash(HashAlgorithm::Sha3_256, b"abc"),
+            "3a985da74fe225b2045c172d6bd390bd855f086e3e9d525b46bfe24511431532"
+        );
+        assert_eq!(
+            hash(HashAlgorithm::Blake2b, b"abc"),
+            "ba80a53f981c4d0d6a2797b69f12f6e94c212f14685ac4b74b12bb6fdbffa2d17d87c5392aab792dc252d5de4533cc9518d38aa8dbf1925ab92386edd4009923"
+        );
+        assert_eq!(
+            hash(HashAlgorithm::Blake3, b"abc"),
+            blake3::hash(b"abc").to_hex().to_string()
+        );
+    }
+
+    #[test]
+    fn should_derive_keys_for_a_context() {
+        let mut hasher = Blake3Hasher::derive_key("Dexios tests");
+        hasher.write(b"key material");
+
+        assert_eq!(
+            hasher.finish(),
+            hex_encode(&blake3::derive_key("Dexios tests", b"key material"))
+        );
+    }
+
+    #[test]
+    fn should_parse_algorithm_names() {
+        for algorithm in HashAlgorithm::ALL {
+            assert_eq!(algorithm.to_string().parse(), Ok(algorithm));
+        }
+        assert!("md5".parse::<HashAlgorithm>().is_err());
+    }
+}
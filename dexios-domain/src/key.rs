@@ -1,5 +1,7 @@
+use core::header::{HashingAlgorithm, Header, HeaderType};
 use core::key::vec_to_arr;
 use core::primitives::Algorithm;
+use core::primitives::Mode;
 use core::primitives::ENCRYPTED_MASTER_KEY_LEN;
 use core::primitives::MASTER_KEY_LEN;
 use core::protected::Protected;
@@ -107,3 +109,40 @@ pub fn encrypt_master_key(
 
     Ok(vec_to_arr(master_key_encrypted))
 }
+
+// Creates a header with a new master key, that's protected by a single keyslot.
+//
+// This is used for headers that are stored on their own, rather than before encrypted data.
+pub(crate) fn create_header(
+    raw_key: Protected<Vec<u8>>,
+    header_type: HeaderType,
+    hashing_algorithm: HashingAlgorithm,
+) -> Result<(Header, Protected<[u8; MASTER_KEY_LEN]>), Error> {
+    let salt = crate::utils::gen_salt();
+    let key = hashing_algorithm
+        .hash(raw_key, &salt)
+        .map_err(|_| Error::KeyHash)?;
+
+    let master_key = crate::utils::gen_master_key();
+    let master_key_nonce = crate::utils::gen_nonce(&header_type.algorithm, &Mode::MemoryMode);
+    let encrypted_key = encrypt_master_key(
+        master_key.clone(),
+        key,
+        &master_key_nonce,
+        &header_type.algorithm,
+    )?;
+
+    let header = Header {
+        nonce: crate::utils::gen_nonce(&header_type.algorithm, &header_type.mode),
+        header_type,
+        salt: None,
+        keyslots: Some(vec![Keyslot {
+            encrypted_key,
+            nonce: master_key_nonce,
+            hash_algorithm: hashing_algorithm,
+            salt,
+        }]),
+    };
+
+    Ok((header, master_key))
+}
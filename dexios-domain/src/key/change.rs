@@ -27,8 +27,8 @@ pub fn execute<RW>(req: Request<'_, RW>) -> Result<(), Error>
 where
     RW: Read + Write + Seek,
 {
-    let (header, _) = core::header::Header::deserialize(&mut *req.handle.borrow_mut())
-        .map_err(|_| Error::HeaderDeserialize)?;
+    let (header, _) =
+        Header::deserialize(&mut *req.handle.borrow_mut()).map_err(|_| Error::HeaderDeserialize)?;
 
     if header.header_type.version < HeaderVersion::V5 {
         return Err(Error::Unsupported);
@@ -51,17 +51,31 @@
     clippy::missing_errors_doc
 )]
 
+pub mod cancel;
+pub mod checksums;
 pub mod decrypt;
+pub mod decrypt_dir;
 pub mod encrypt;
+pub mod encrypt_dir;
 pub mod erase;
+pub mod erase_device;
 pub mod erase_dir;
 pub mod hash;
 pub mod hasher;
 pub mod header;
 pub mod key;
+pub mod mac;
+pub mod manifest;
 pub mod overwrite;
 pub mod pack;
+pub mod progress;
+pub mod repo;
+pub mod signature;
+pub mod split;
 pub mod storage;
+pub mod sync;
 pub mod unpack;
+pub mod vault;
+pub mod wipe_free;
 
 pub mod utils;
@@ -0,0 +1,132 @@
+//! This provides keyed `BLAKE3` MACs of files, so tampering can be detected by anyone who holds the key. Unlike a plain hash, a MAC can't be recomputed by whoever changed the file.
+//!
+//! A `BLAKE3` key is exactly 32 bytes, but the secret may be a password or a keyfile of any length. It's hashed with `BLAKE3-Balloon` (with the parameters of V5 headers), and then turned into a key with `BLAKE3`'s derive-key mode (under `KEY_CONTEXT`). Anyone who holds a file and its MAC could otherwise guess passwords as fast as they can hash them.
+//!
+//! MACs aren't stored alongside anything, so the salt is fixed - the same secret always gives the same MAC.
+//!
+//! Files are hashed with the keyed hasher through `hash::execute` or `hash::execute_parallel`, and a MAC can then be checked against the one that was stored with `verify`.
+
+use core::header::HeaderVersion;
+use core::key::balloon_hash;
+use core::primitives::SALT_LEN;
+use core::protected::Protected;
+use std::fmt;
+
+use crate::hasher::Blake3Hasher;
+
+/// The context string for deriving MAC keys, which keeps them apart from keys that are derived from the same secret for anything else.
+pub const KEY_CONTEXT: &str = "Dexios 2026-10-18 keyed file MAC";
+
+// The salt for hashing secrets, which must stay the same for MACs to be verified
+const KEY_SALT: [u8; SALT_LEN] = *b"dexios-mac-2026!";
+
+#[derive(Debug)]
+pub enum Error {
+    DeriveKey,
+    InvalidMac,
+    Mismatch,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::DeriveKey => f.write_str("Unable to derive the MAC key"),
+            Error::InvalidMac => f.write_str("The MAC must be 64 hex characters"),
+            Error::Mismatch => f.write_str("The MAC doesn't match"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+pub struct MacKey {
+    key: Protected<[u8; 32]>,
+}
+
+impl MacKey {
+    pub fn derive(secret: Protected<Vec<u8>>) -> Result<Self, Error> {
+        let hashed =
+            balloon_hash(secret, &KEY_SALT, &HeaderVersion::V5).map_err(|_| Error::DeriveKey)?;
+
+        Ok(Self {
+            key: Protected::new(blake3::derive_key(KEY_CONTEXT, hashed.expose())),
+        })
+    }
+
+    #[must_use]
+    pub fn hasher(&self) -> Blake3Hasher {
+        Blake3Hasher::keyed(self.key.expose())
+    }
+}
+
+/// Checks a MAC against the expected one (both in hex), in constant time.
+pub fn verify(mac: &str, expected: &str) -> Result<(), Error> {
+    let mac = blake3::Hash::from_hex(mac.trim()).map_err(|_| Error::InvalidMac)?;
+    let expected = blake3::Hash::from_hex(expected.trim()).map_err(|_| Error::InvalidMac)?;
+
+    // `blake3::Hash` is compared in constant time, unlike the strings
+    if mac == expected {
+        Ok(())
+    } else {
+        Err(Error::Mismatch)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hasher::Hasher;
+
+    fn mac(key: &MacKey, input: &[u8]) -> String {
+        let mut hasher = key.hasher();
+        hasher.write(input);
+        hasher.finish()
+    }
+
+    fn derive(secret: &[u8]) -> MacKey {
+        MacKey::derive(Protected::new(secret.to_vec())).unwrap()
+    }
+
+    #[test]
+    fn should_make_keyed_mac_with_hashed_secret() {
+        let key = derive(b"secret");
+        let hashed = balloon_hash(
+            Protected::new(b"secret".to_vec()),
+            &KEY_SALT,
+            &HeaderVersion::V5,
+        )
+        .unwrap();
+        let expected_key = blake3::derive_key(KEY_CONTEXT, hashed.expose());
+
+        assert_eq!(
+            mac(&key, b"Hello world"),
+            blake3::keyed_hash(&expected_key, b"Hello world")
+                .to_hex()
+                .to_string()
+        );
+        assert_ne!(
+            mac(&key, b"Hello world"),
+            mac(&derive(b"other"), b"Hello world")
+        );
+        assert_ne!(
+            mac(&key, b"Hello world"),
+            blake3::hash(b"Hello world").to_hex().to_string()
+        );
+    }
+
+    #[test]
+    fn should_verify_mac() {
+        let key = derive(b"secret");
+        let expected = mac(&key, b"Hello world");
+
+        assert!(verify(&expected, &expected.to_uppercase()).is_ok());
+        assert!(matches!(
+            verify(&mac(&key, b"Hello World"), &expected),
+            Err(Error::Mismatch)
+        ));
+        assert!(matches!(
+            verify(&expected, "not a mac"),
+            Err(Error::InvalidMac)
+        ));
+    }
+}
@@ -0,0 +1,276 @@
+//! This contains the manifest that is stored within packed archives. It lists every packed path, along with the size, modification time and BLAKE3 hash of each file.
+//!
+//! Manifests allow incremental archives, which only contain the files that changed since a previous archive, and a list of the paths that were deleted since then.
+//!
+//! Every manifest has a random ID, and incremental manifests store the ID of the previous archive, so a chain of archives can only be restored in order.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use rand::RngCore;
+
+use crate::pack::{bytes_to_path, path_to_bytes};
+use crate::utils::{hex_decode, hex_encode};
+
+/// The name of the manifest within the archive.
+pub const MANIFEST_NAME: &str = ".dexios-manifest";
+
+const MANIFEST_VERSION: &str = "dexios-manifest 1";
+const ID_LEN: usize = 16;
+
+pub type ArchiveId = [u8; ID_LEN];
+
+#[derive(Debug)]
+pub enum Error {
+    UnsupportedVersion,
+    InvalidLine(usize),
+    MissingId,
+    UnsupportedPath(PathBuf),
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::UnsupportedVersion => f.write_str("Unsupported manifest version"),
+            Error::InvalidLine(line) => write!(f, "Invalid manifest entry on line {line}"),
+            Error::MissingId => f.write_str("The manifest doesn't contain an archive ID"),
+            Error::UnsupportedPath(path) => {
+                write!(f, "Unable to store path in manifest: {}", path.display())
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileInfo {
+    pub size: u64,
+    // Since the UNIX epoch
+    pub modified: Duration,
+    pub hash: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ManifestEntry {
+    Dir,
+    File(FileInfo),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Manifest {
+    pub id: ArchiveId,
+    // The archive that this one is based on, if it's incremental
+    pub parent: Option<ArchiveId>,
+    // Every path in the packed tree, including the ones that weren't stored in an incremental archive
+    pub entries: BTreeMap<PathBuf, ManifestEntry>,
+    // Paths that existed in the parent archive, but not in this one
+    pub deleted: Vec<PathBuf>,
+}
+
+impl Manifest {
+    /// Creates an empty manifest with a random ID, based on the given parent.
+    #[must_use]
+    pub fn new(parent: Option<&Manifest>) -> Self {
+        let mut id = [0u8; ID_LEN];
+        rand::thread_rng().fill_bytes(&mut id);
+
+        Self {
+            id,
+            parent: parent.map(|parent| parent.id),
+            entries: BTreeMap::new(),
+            deleted: Vec::new(),
+        }
+    }
+
+    /// Checks whether a file is unchanged since this manifest was created.
+    ///
+    /// Only the size and modification time are compared, so unchanged files don't need to be read.
+    #[must_use]
+    pub fn is_unchanged(&self, path: &Path, size: u64, modified: Duration) -> Option<&FileInfo> {
+        match self.entries.get(path) {
+            Some(ManifestEntry::File(info)) if info.size == size && info.modified == modified => {
+                Some(info)
+            }
+            _ => None,
+        }
+    }
+
+    pub fn serialize(&self) -> Result<String, Error> {
+        let encode_path = |path: &Path| {
+            path_to_bytes(path)
+                .map(hex_encode)
+                .ok_or_else(|| Error::UnsupportedPath(path.to_path_buf()))
+        };
+
+        let mut lines = vec![
+            MANIFEST_VERSION.to_string(),
+            format!("id {}", hex_encode(&self.id)),
+        ];
+
+        if let Some(parent) = self.parent {
+            lines.push(format!("parent {}", hex_encode(&parent)));
+        }
+
+        for (path, entry) in &self.entries {
+            let path = encode_path(path)?;
+            lines.push(match entry {
+                ManifestEntry::Dir => format!("d {path}"),
+                ManifestEntry::File(info) => format!(
+                    "f {} {} {} {} {path}",
+                    info.size,
+                    info.modified.as_secs(),
+                    info.modified.subsec_nanos(),
+                    info.hash
+                ),
+            });
+        }
+
+        for path in &self.deleted {
+            lines.push(format!("- {}", encode_path(path)?));
+        }
+
+        lines.push(String::new());
+        Ok(lines.join("\n"))
+    }
+
+    pub fn deserialize(content: &str) -> Result<Self, Error> {
+        let mut lines = content.lines();
+        if lines.next() != Some(MANIFEST_VERSION) {
+            return Err(Error::UnsupportedVersion);
+        }
+
+        let mut id = None;
+        let mut manifest = Manifest {
+            id: [0u8; ID_LEN],
+            parent: None,
+            entries: BTreeMap::new(),
+            deleted: Vec::new(),
+        };
+
+        for (i, line) in lines.enumerate().filter(|(_, line)| !line.is_empty()) {
+            // The version is on the first line
+            let invalid = || Error::InvalidLine(i + 2);
+
+            let fields = line.split(' ').collect::<Vec<_>>();
+            match fields[..] {
+                ["id", value] => id = Some(decode_id(value).ok_or_else(invalid)?),
+                ["parent", value] => manifest.parent = Some(decode_id(value).ok_or_else(invalid)?),
+                ["d", path] => {
+                    let path = decode_path(path).ok_or_else(invalid)?;
+                    manifest.entries.insert(path, ManifestEntry::Dir);
+                }
+                ["f", size, secs, nanos, hash, path] => {
+                    // `Duration::new` panics if the nanoseconds overflow the seconds
+                    let nanos = nanos
+                        .parse()
+                        .ok()
+                        .filter(|nanos| *nanos < 1_000_000_000)
+                        .ok_or_else(invalid)?;
+                    let info = FileInfo {
+                        size: size.parse().map_err(|_| invalid())?,
+                        modified: Duration::new(secs.parse().map_err(|_| invalid())?, nanos),
+                        hash: hash.to_string(),
+                    };
+                    let path = decode_path(path).ok_or_else(invalid)?;
+                    manifest.entries.insert(path, ManifestEntry::File(info));
+                }
+                ["-", path] => manifest
+                    .deleted
+                    .push(decode_path(path).ok_or_else(invalid)?),
+                _ => return Err(invalid()),
+            }
+        }
+
+        manifest.id = id.ok_or(Error::MissingId)?;
+        Ok(manifest)
+    }
+}
+
+fn decode_id(value: &str) -> Option<ArchiveId> {
+    hex_decode(value)?.try_into().ok()
+}
+
+fn decode_path(value: &str) -> Option<PathBuf> {
+    bytes_to_path(&hex_decode(value)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_serialize_and_deserialize_manifest() {
+        let parent = Manifest::new(None);
+        let mut manifest = Manifest::new(Some(&parent));
+        manifest
+            .entries
+            .insert(PathBuf::from("bar/"), ManifestEntry::Dir);
+        manifest.entries.insert(
+            PathBuf::from("bar/foo with spaces.txt"),
+            ManifestEntry::File(FileInfo {
+                size: 12,
+                modified: Duration::new(1_660_000_000, 123),
+                hash: "ab".repeat(32),
+            }),
+        );
+        manifest.deleted.push(PathBuf::from("bar/old.txt"));
+
+        let content = manifest.serialize().unwrap();
+
+        assert_eq!(Manifest::deserialize(&content).unwrap(), manifest);
+    }
+
+    #[test]
+    fn should_reject_invalid_manifest() {
+        match Manifest::deserialize("dexios-manifest 1\nid 00\n") {
+            Err(Error::InvalidLine(2)) => {}
+            _ => unreachable!(),
+        }
+
+        let hash = "ab".repeat(32);
+        let content = format!(
+            "dexios-manifest 1\nid {}\nf 12 {} 1000000000 {hash} 00\n",
+            "00".repeat(ID_LEN),
+            u64::MAX
+        );
+        match Manifest::deserialize(&content) {
+            Err(Error::InvalidLine(3)) => {}
+            _ => unreachable!(),
+        }
+
+        match Manifest::deserialize("something else") {
+            Err(Error::UnsupportedVersion) => {}
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn should_detect_unchanged_files() {
+        let mut manifest = Manifest::new(None);
+        let info = FileInfo {
+            size: 5,
+            modified: Duration::from_secs(10),
+            hash: String::new(),
+        };
+        manifest.entries.insert(
+            PathBuf::from("hello.txt"),
+            ManifestEntry::File(info.clone()),
+        );
+
+        let path = Path::new("hello.txt");
+        assert_eq!(
+            manifest.is_unchanged(path, 5, Duration::from_secs(10)),
+            Some(&info)
+        );
+        assert_eq!(
+            manifest.is_unchanged(path, 6, Duration::from_secs(10)),
+            None
+        );
+        assert_eq!(
+            manifest.is_unchanged(path, 5, Duration::from_secs(11)),
+            None
+        );
+    }
+}
@@ -1,65 +1,370 @@
 //! This contains the actual logic for "shredding" a file.
 //!
+//! The file is overwritten by every pass of a `Scheme` in turn, and each pass can be synced to the disk before the next one starts. Afterwards, `verify` reads the file back to check that it contains the last pass. That read is usually served from the OS's page cache rather than the disk, so it catches writes that went missing or were cut short, but it can't prove that the pass reached the disk itself.
+//!
 //! This will not be effective on flash storage, and if you are planning to release a program that uses this function, I'd recommend putting the default number of passes to 1.
 
-use rand::RngCore;
+use rand::rngs::StdRng;
+use rand::{RngCore, SeedableRng};
 use std::cell::RefCell;
 use std::fmt;
-use std::io::{Seek, Write};
+use std::io::{self, Read, Seek, Write};
+use std::sync::Arc;
+
+use crate::cancel::{is_cancelled, CancellationToken};
+use crate::progress::Progress;
+use crate::storage;
+use crate::utils::hex_encode;
+
+const BLOCK_SIZE: usize = 65_536;
 
-const BLOCK_SIZE: usize = 512;
+// The fixed patterns of Peter Gutmann's method, in the order that they were published
+const GUTMANN_PATTERNS: [&[u8]; 27] = [
+    &[0x55],
+    &[0xAA],
+    &[0x92, 0x49, 0x24],
+    &[0x49, 0x24, 0x92],
+    &[0x24, 0x92, 0x49],
+    &[0x00],
+    &[0x11],
+    &[0x22],
+    &[0x33],
+    &[0x44],
+    &[0x55],
+    &[0x66],
+    &[0x77],
+    &[0x88],
+    &[0x99],
+    &[0xAA],
+    &[0xBB],
+    &[0xCC],
+    &[0xDD],
+    &[0xEE],
+    &[0xFF],
+    &[0x92, 0x49, 0x24],
+    &[0x49, 0x24, 0x92],
+    &[0x24, 0x92, 0x49],
+    &[0x6D, 0xB6, 0xDB],
+    &[0xB6, 0xDB, 0x6D],
+    &[0xDB, 0x6D, 0xB6],
+];
 
 #[derive(Debug)]
 pub enum Error {
     ResetCursorPosition,
-    OverwriteWithRandomBytes,
-    OverwriteWithZeros,
+    EmptyPattern,
+    WritePass,
     FlushFile,
+    SyncFile(storage::Error),
+    ReadBack,
+    Mismatch(u64),
+    Cancelled,
 }
 
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Error::ResetCursorPosition => f.write_str("Unable to reset cursor position"),
-            Error::OverwriteWithRandomBytes => f.write_str("Unable to overwrite with random bytes"),
-            Error::OverwriteWithZeros => f.write_str("Unable to overwrite with zeros"),
+            Error::EmptyPattern => f.write_str("Patterns must contain at least one byte"),
+            Error::WritePass => f.write_str("Unable to overwrite the file"),
             Error::FlushFile => f.write_str("Unable to flush"),
+            Error::SyncFile(inner) => write!(f, "Unable to sync the file to the disk: {inner}"),
+            Error::ReadBack => f.write_str("Unable to read the file back"),
+            Error::Mismatch(offset) => write!(
+                f,
+                "The last pass wasn't written correctly (the first difference is at byte {offset})"
+            ),
+            Error::Cancelled => f.write_str("Overwriting was cancelled"),
         }
     }
 }
 
 impl std::error::Error for Error {}
 
+/// What a single pass writes over the file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Pattern {
+    /// These bytes, repeated until the end of the file
+    Bytes(Vec<u8>),
+    /// Random bytes from a CSPRNG
+    Random,
+}
+
+impl fmt::Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Pattern::Bytes(bytes) => write!(f, "0x{}", hex_encode(bytes)),
+            Pattern::Random => f.write_str("random"),
+        }
+    }
+}
+
+/// The passes that are used to overwrite a file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Scheme {
+    /// A single pass of zeros
+    Zeros,
+    /// This many passes of random bytes, followed by a pass of zeros
+    Random(u32),
+    /// `DoD` 5220.22-M: a pass of zeros, a pass of ones and a pass of random bytes
+    Dod,
+    /// Peter Gutmann's 35 passes: 4 random passes, 27 fixed patterns and 4 more random passes
+    Gutmann,
+    /// Any sequence of patterns
+    Custom(Vec<Pattern>),
+}
+
+impl Scheme {
+    #[must_use]
+    pub fn patterns(&self) -> Vec<Pattern> {
+        let zeros = Pattern::Bytes(vec![0x00]);
+
+        match self {
+            Scheme::Zeros => vec![zeros],
+            Scheme::Random(passes) => std::iter::repeat_n(Pattern::Random, *passes as usize)
+                .chain(std::iter::once(zeros))
+                .collect(),
+            Scheme::Dod => vec![zeros, Pattern::Bytes(vec![0xFF]), Pattern::Random],
+            Scheme::Gutmann => {
+                let random = std::iter::repeat_n(Pattern::Random, 4);
+                random
+                    .clone()
+                    .chain(
+                        GUTMANN_PATTERNS
+                            .iter()
+                            .map(|bytes| Pattern::Bytes(bytes.to_vec())),
+                    )
+                    .chain(random)
+                    .collect()
+            }
+            Scheme::Custom(patterns) => patterns.clone(),
+        }
+    }
+}
+
+/// How much of the file each pass overwrote.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PassReport {
+    pub pattern: Pattern,
+    pub bytes: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct Report {
+    pub passes: Vec<PassReport>,
+    len: usize,
+    // The last pass, including its seed, so it can be generated again by `verify`
+    last: Option<Fill>,
+}
+
+impl Report {
+    /// The number of bytes that were written by every pass combined.
+    #[must_use]
+    pub fn bytes(&self) -> u64 {
+        self.passes.iter().map(|pass| pass.bytes).sum()
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Fill {
+    Bytes(Vec<u8>),
+    Random([u8; 32]),
+}
+
+impl Fill {
+    fn new(pattern: &Pattern) -> Result<Self, Error> {
+        match pattern {
+            Pattern::Bytes(bytes) if bytes.is_empty() => Err(Error::EmptyPattern),
+            Pattern::Bytes(bytes) => Ok(Fill::Bytes(bytes.clone())),
+            Pattern::Random => {
+                let mut seed = [0u8; 32];
+                rand::thread_rng().fill_bytes(&mut seed);
+                Ok(Fill::Random(seed))
+            }
+        }
+    }
+
+    fn stream(&self) -> FillStream<'_> {
+        match self {
+            Fill::Bytes(bytes) => FillStream::Bytes { bytes, offset: 0 },
+            Fill::Random(seed) => FillStream::Random(Box::new(StdRng::from_seed(*seed))),
+        }
+    }
+}
+
+// Produces the content of a pass, block by block.
+enum FillStream<'a> {
+    Bytes { bytes: &'a [u8], offset: usize },
+    Random(Box<StdRng>),
+}
+
+impl FillStream<'_> {
+    fn fill(&mut self, buf: &mut [u8]) {
+        match self {
+            FillStream::Bytes { bytes, offset } => {
+                for (i, byte) in buf.iter_mut().enumerate() {
+                    *byte = bytes[(*offset + i) % bytes.len()];
+                }
+                *offset = (*offset + buf.len()) % bytes.len();
+            }
+            FillStream::Random(rng) => rng.fill_bytes(buf),
+        }
+    }
+}
+
+type SyncFn<'a> = Box<dyn Fn() -> Result<(), storage::Error> + 'a>;
+
 pub struct Request<'a, W: Write + Seek> {
     pub writer: &'a RefCell<W>,
     pub buf_capacity: usize,
-    pub passes: i32,
+    pub scheme: Scheme,
+    // Called after every pass, so each pass reaches the disk before the next one starts
+    pub sync: Option<SyncFn<'a>>,
+    pub progress: Option<Arc<dyn Progress>>,
+    pub cancel: Option<CancellationToken>,
 }
 
-pub fn execute<W: Write + Seek>(req: Request<'_, W>) -> Result<(), Error> {
-    let mut writer = req.writer.borrow_mut();
-    for _ in 0..req.passes {
-        writer.rewind().map_err(|_| Error::ResetCursorPosition)?;
+pub fn execute<W: Write + Seek>(req: Request<'_, W>) -> Result<Report, Error> {
+    let mut report = Report {
+        passes: Vec::new(),
+        len: req.buf_capacity,
+        last: None,
+    };
+    let mut block_buf = vec![0u8; BLOCK_SIZE.min(req.buf_capacity)];
+
+    for pattern in req.scheme.patterns() {
+        if is_cancelled(req.cancel.as_ref()) {
+            return Err(Error::Cancelled);
+        }
 
-        let mut blocks = vec![BLOCK_SIZE].repeat(req.buf_capacity / BLOCK_SIZE);
-        blocks.push(req.buf_capacity % BLOCK_SIZE);
+        let fill = Fill::new(&pattern)?;
+        let mut stream = fill.stream();
 
-        for block_size in blocks.into_iter().take_while(|bs| *bs > 0) {
-            let mut block_buf = Vec::with_capacity(block_size);
-            rand::thread_rng().fill_bytes(&mut block_buf);
-            writer
-                .write_all(&block_buf)
-                .map_err(|_| Error::OverwriteWithRandomBytes)?;
+        // The writer is released before syncing, as the storage needs to borrow it again
+        {
+            let mut writer = req.writer.borrow_mut();
+            writer.rewind().map_err(|_| Error::ResetCursorPosition)?;
+
+            let mut remaining = req.buf_capacity;
+            while remaining > 0 {
+                if is_cancelled(req.cancel.as_ref()) {
+                    return Err(Error::Cancelled);
+                }
+
+                let block = &mut block_buf[..remaining.min(BLOCK_SIZE)];
+                stream.fill(block);
+                writer.write_all(block).map_err(|_| Error::WritePass)?;
+
+                if let Some(progress) = &req.progress {
+                    progress.advance(block.len() as u64);
+                }
+                remaining -= block.len();
+            }
+
+            writer.flush().map_err(|_| Error::FlushFile)?;
+        }
+
+        if let Some(sync) = &req.sync {
+            sync().map_err(Error::SyncFile)?;
         }
 
-        writer.flush().map_err(|_| Error::FlushFile)?;
+        report.passes.push(PassReport {
+            pattern,
+            bytes: req.buf_capacity as u64,
+        });
+        report.last = Some(fill);
+    }
+
+    Ok(report)
+}
+
+// Appends a pattern to the writer until the storage runs out of space, or `max_len` bytes have been written.
+//
+// This returns the number of bytes that were written, so the same region can be overwritten by the next passes.
+pub(crate) fn fill<W: Write>(
+    writer: &mut W,
+    pattern: &Pattern,
+    max_len: u64,
+    progress: Option<&dyn Progress>,
+    cancel: Option<&CancellationToken>,
+) -> Result<u64, Error> {
+    let fill = Fill::new(pattern)?;
+    let mut stream = fill.stream();
+    let mut block_buf = vec![0u8; BLOCK_SIZE];
+
+    let mut written = 0u64;
+    while written < max_len {
+        if is_cancelled(cancel) {
+            return Err(Error::Cancelled);
+        }
+
+        let len = usize::try_from(max_len - written).map_or(BLOCK_SIZE, |len| len.min(BLOCK_SIZE));
+        let block = &mut block_buf[..len];
+        stream.fill(block);
+
+        // Partial writes are counted, as they're the last bytes that fit
+        let mut offset = 0;
+        while offset < block.len() {
+            match writer.write(&block[offset..]) {
+                Ok(0) => return Ok(written + offset as u64),
+                Ok(n) => offset += n,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) if is_out_of_space(&err) => {
+                    if let Some(progress) = progress {
+                        progress.advance(offset as u64);
+                    }
+                    return Ok(written + offset as u64);
+                }
+                Err(_) => return Err(Error::WritePass),
+            }
+        }
+
+        if let Some(progress) = progress {
+            progress.advance(block.len() as u64);
+        }
+        written += block.len() as u64;
+    }
+
+    Ok(written)
+}
+
+fn is_out_of_space(err: &io::Error) -> bool {
+    matches!(
+        err.kind(),
+        io::ErrorKind::StorageFull | io::ErrorKind::QuotaExceeded | io::ErrorKind::FileTooLarge
+    )
+}
+
+/// Reads the file back, and checks that it contains the last pass of the report.
+///
+/// The OS may serve this from its cache, so it doesn't show that the pass reached the disk.
+pub fn verify<R: Read + Seek>(reader: &RefCell<R>, report: &Report) -> Result<(), Error> {
+    let Some(fill) = &report.last else {
+        return Ok(());
+    };
+
+    let mut reader = reader.borrow_mut();
+    reader.rewind().map_err(|_| Error::ResetCursorPosition)?;
+
+    let mut stream = fill.stream();
+    let mut expected = vec![0u8; BLOCK_SIZE.min(report.len)];
+    let mut actual = expected.clone();
+
+    let mut offset = 0;
+    while offset < report.len {
+        let len = (report.len - offset).min(BLOCK_SIZE);
+        stream.fill(&mut expected[..len]);
+        reader
+            .read_exact(&mut actual[..len])
+            .map_err(|_| Error::ReadBack)?;
+
+        if let Some(i) = (0..len).find(|i| expected[*i] != actual[*i]) {
+            return Err(Error::Mismatch((offset + i) as u64));
+        }
+        offset += len;
     }
 
-    writer.rewind().map_err(|_| Error::ResetCursorPosition)?;
-    writer
-        .write_all(&[0].repeat(req.buf_capacity))
-        .map_err(|_| Error::OverwriteWithZeros)?;
-    writer.flush().map_err(|_| Error::FlushFile)
+    Ok(())
 }
 
 #[cfg(test)]
@@ -67,27 +372,39 @@ mod tests {
     use super::*;
     use std::io::Cursor;
 
-    fn make_test(capacity: usize, passes: i32) {
-        let mut buf = Vec::with_capacity(capacity);
-        rand::thread_rng().fill_bytes(&mut buf);
-
-        let writer = Cursor::new(&mut buf);
+    fn overwrite(buf: &mut Vec<u8>, scheme: Scheme) -> Report {
+        let capacity = buf.len();
+        let writer = RefCell::new(Cursor::new(buf));
 
         let req = Request {
-            writer: &RefCell::new(writer),
+            writer: &writer,
             buf_capacity: capacity,
-            passes,
+            scheme,
+            sync: None,
+            progress: None,
+            cancel: None,
         };
 
         match execute(req) {
-            Ok(_) => {
-                assert_eq!(buf.len(), capacity);
-                assert_eq!(buf, vec![0].repeat(capacity));
+            Ok(report) => {
+                verify(&writer, &report).unwrap();
+                report
             }
             _ => unreachable!(),
         }
     }
 
+    fn make_test(capacity: usize, passes: u32) {
+        let mut buf = vec![0xAB; capacity];
+
+        let report = overwrite(&mut buf, Scheme::Random(passes));
+
+        assert_eq!(buf.len(), capacity);
+        assert_eq!(buf, [0].repeat(capacity));
+        assert_eq!(report.passes.len(), passes as usize + 1);
+        assert_eq!(report.bytes(), capacity as u64 * (u64::from(passes) + 1));
+    }
+
     #[test]
     fn should_overwrite_empty_content() {
         make_test(0, 1);
@@ -105,12 +422,12 @@ mod tests {
 
     #[test]
     fn should_overwrite_not_perfectly_divisible_content() {
-        make_test(515, 1);
+        make_test(BLOCK_SIZE + 3, 1);
     }
 
     #[test]
     fn should_overwrite_large_content() {
-        make_test(BLOCK_SIZE * 100, 1);
+        make_test(BLOCK_SIZE * 10, 1);
     }
 
     #[test]
@@ -122,4 +439,144 @@ mod tests {
     fn should_erase_fill_random_bytes_zero_times() {
         make_test(515, 0);
     }
+
+    #[test]
+    fn should_write_random_bytes() {
+        let mut buf = vec![0u8; 515];
+
+        overwrite(&mut buf, Scheme::Custom(vec![Pattern::Random]));
+
+        assert!(buf.iter().any(|byte| *byte != 0));
+    }
+
+    #[test]
+    fn should_repeat_custom_pattern_across_blocks() {
+        let mut buf = vec![0u8; BLOCK_SIZE + 2];
+
+        overwrite(
+            &mut buf,
+            Scheme::Custom(vec![Pattern::Bytes(vec![0x92, 0x49, 0x24])]),
+        );
+
+        assert!(buf
+            .iter()
+            .enumerate()
+            .all(|(i, byte)| *byte == [0x92, 0x49, 0x24][i % 3]));
+    }
+
+    #[test]
+    fn should_list_scheme_passes() {
+        assert_eq!(Scheme::Dod.patterns().len(), 3);
+        assert_eq!(Scheme::Dod.patterns()[2], Pattern::Random);
+
+        let gutmann = Scheme::Gutmann.patterns();
+        assert_eq!(gutmann.len(), 35);
+        assert_eq!(gutmann[4], Pattern::Bytes(vec![0x55]));
+        assert_eq!(gutmann[34], Pattern::Random);
+    }
+
+    #[test]
+    fn should_sync_after_every_pass() {
+        let writer = RefCell::new(Cursor::new(vec![1u8; 10]));
+        let syncs = std::cell::Cell::new(0);
+
+        let req = Request {
+            writer: &writer,
+            buf_capacity: 10,
+            scheme: Scheme::Dod,
+            sync: Some(Box::new(|| {
+                // The writer must be available to the storage while it syncs
+                writer.borrow_mut().flush().unwrap();
+                syncs.set(syncs.get() + 1);
+                Ok(())
+            })),
+            progress: None,
+            cancel: None,
+        };
+
+        execute(req).unwrap();
+        assert_eq!(syncs.get(), 3);
+    }
+
+    #[test]
+    fn should_detect_content_that_was_not_overwritten() {
+        let writer = RefCell::new(Cursor::new(vec![1u8; 10]));
+
+        let req = Request {
+            writer: &writer,
+            buf_capacity: 10,
+            scheme: Scheme::Zeros,
+            sync: None,
+            progress: None,
+            cancel: None,
+        };
+        let report = execute(req).unwrap();
+
+        writer.borrow_mut().get_mut()[7] = 1;
+
+        match verify(&writer, &report) {
+            Err(Error::Mismatch(offset)) => assert_eq!(offset, 7),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn should_fill_until_max_len() {
+        let mut writer = Cursor::new(Vec::new());
+
+        let written = fill(&mut writer, &Pattern::Bytes(vec![0x55]), 100, None, None).unwrap();
+
+        assert_eq!(written, 100);
+        assert_eq!(writer.get_ref(), &vec![0x55; 100]);
+    }
+
+    #[test]
+    fn should_fill_until_storage_is_full() {
+        struct Full(usize);
+
+        impl Write for Full {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                match buf.len().min(self.0) {
+                    0 => Err(io::ErrorKind::StorageFull.into()),
+                    n => {
+                        self.0 -= n;
+                        Ok(n)
+                    }
+                }
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let written = fill(
+            &mut Full(BLOCK_SIZE + 7),
+            &Pattern::Random,
+            u64::MAX,
+            None,
+            None,
+        );
+
+        assert_eq!(written.unwrap(), BLOCK_SIZE as u64 + 7);
+    }
+
+    #[test]
+    fn should_reject_empty_pattern() {
+        let writer = RefCell::new(Cursor::new(vec![1u8; 10]));
+
+        let req = Request {
+            writer: &writer,
+            buf_capacity: 10,
+            scheme: Scheme::Custom(vec![Pattern::Bytes(vec![])]),
+            sync: None,
+            progress: None,
+            cancel: None,
+        };
+
+        match execute(req) {
+            Err(Error::EmptyPattern) => assert_eq!(writer.borrow().get_ref(), &vec![1u8; 10]),
+            _ => unreachable!(),
+        }
+    }
 }
@@ -6,14 +6,20 @@
 
 use std::cell::RefCell;
 use std::io::{BufWriter, Read, Seek, Write};
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::UNIX_EPOCH;
 
 use core::header::{HashingAlgorithm, HeaderType};
 use core::primitives::BLOCK_SIZE;
 use core::protected::Protected;
-use zip::write::FileOptions;
+use zip::write::FullFileOptions;
 
-use crate::storage::Storage;
+use crate::cancel::{is_cancelled, CancellationToken};
+use crate::hasher::{Blake3Hasher, Hasher};
+use crate::manifest::{FileInfo, Manifest, ManifestEntry, MANIFEST_NAME};
+use crate::progress::{Phase, Progress, ProgressReader};
+use crate::storage::{Entry, Paths, Storage};
 
 #[derive(Debug)]
 pub enum Error {
@@ -23,7 +29,11 @@ pub enum Error {
     FinishArchive,
     ReadData,
     WriteData,
+    UnsupportedPath(PathBuf),
+    Manifest(crate::manifest::Error),
+    Storage(crate::storage::Error),
     Encrypt(crate::encrypt::Error),
+    Cancelled,
 }
 
 impl std::fmt::Display for Error {
@@ -35,101 +45,463 @@ impl std::fmt::Display for Error {
             Error::FinishArchive => f.write_str("Unable to finish archive"),
             Error::ReadData => f.write_str("Unable to read data"),
             Error::WriteData => f.write_str("Unable to write data"),
+            Error::UnsupportedPath(path) => {
+                write!(f, "Unable to store path in archive: {}", path.display())
+            }
+            Error::Manifest(inner) => write!(f, "Unable to create manifest: {inner}"),
+            Error::Storage(inner) => write!(f, "Storage error: {inner}"),
             Error::Encrypt(inner) => write!(f, "Unable to encrypt archive: {inner}"),
+            Error::Cancelled => f.write_str("Packing was cancelled"),
         }
     }
 }
 
 impl std::error::Error for Error {}
 
-pub struct Request<'a, RW>
+/// Whether a manifest is stored within the archive, for incremental backups.
+pub enum Backup {
+    /// Only the files are stored.
+    None,
+    /// Every file is stored, along with a manifest.
+    Full,
+    /// Only files that changed since the previous archive are stored, along with a manifest that also lists deleted paths.
+    Incremental(Manifest),
+}
+
+/// How the files within the archive are compressed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompressionMethod {
+    /// One of the methods that the zip format defines.
+    Zip(zip::CompressionMethod),
+    /// Each file is stored as an LZ4 frame, which is much faster to compress than the other methods.
+    ///
+    /// The zip format doesn't define LZ4, so the entries are stored without compression and marked with an extra field. Only Dexios can unpack these archives, as other zip tools extract the LZ4 frames rather than the files.
+    Lz4,
+}
+
+pub struct Request<'a, W>
 where
-    RW: Read + Write + Seek,
+    W: Write + Seek,
 {
-    pub writer: &'a RefCell<RW>,
-    pub compress_files: Vec<crate::storage::Entry<RW>>,
-    pub compression_method: zip::CompressionMethod,
-    pub header_writer: Option<&'a RefCell<RW>>,
+    pub writer: &'a RefCell<W>,
+    // Paths are opened one at a time, so only a single file is held open while packing.
+    pub compress_paths: Paths<'a>,
+    pub compression_method: CompressionMethod,
+    // `None` uses the method's default level.
+    pub compression_level: Option<i32>,
+    pub backup: Backup,
+    pub header_writer: Option<&'a RefCell<W>>,
     pub raw_key: Protected<Vec<u8>>,
     // TODO: don't use external types in logic
     pub header_type: HeaderType,
     pub hashing_algorithm: HashingAlgorithm,
+    pub progress: Option<Arc<dyn Progress>>,
+    pub cancel: Option<CancellationToken>,
 }
 
-pub fn execute<RW>(stor: Arc<impl Storage<RW>>, req: Request<'_, RW>) -> Result<(), Error>
+pub fn execute<RW, W>(stor: Arc<impl Storage<RW>>, mut req: Request<'_, W>) -> Result<(), Error>
 where
     RW: Read + Write + Seek,
+    W: Write + Seek,
 {
     // 1. Create zip archive.
     let tmp_file = stor.create_temp_file().map_err(|_| Error::CreateArchive)?;
-    {
-        let mut tmp_writer = tmp_file
-            .try_writer()
-            .map_err(|_| Error::CreateArchive)?
-            .borrow_mut();
-        let mut zip_writer = zip::ZipWriter::new(BufWriter::new(&mut *tmp_writer));
-
-        let options = FileOptions::default()
-            .compression_method(req.compression_method)
+
+    // 2. Add files to the archive.
+    let res = write_archive(&*stor, &tmp_file, &mut req).and_then(|()| {
+        // 4. Encrypt zip archive
+        crate::encrypt::execute(crate::encrypt::Request {
+            reader: tmp_file.try_reader().map_err(|_| Error::FinishArchive)?,
+            writer: req.writer,
+            header_writer: req.header_writer,
+            raw_key: req.raw_key,
+            header_type: req.header_type,
+            hashing_algorithm: req.hashing_algorithm,
+            progress: req.progress.clone(),
+            cancel: req.cancel.clone(),
+        })
+        .map_err(Error::Encrypt)
+    });
+
+    // 5. Finally eraze zip archive with zeros, even if it's incomplete.
+    if let Ok(buf_capacity) = stor.file_len(&tmp_file) {
+        crate::overwrite::execute(crate::overwrite::Request {
+            buf_capacity,
+            writer: tmp_file.try_writer().map_err(|_| Error::FinishArchive)?,
+            scheme: crate::overwrite::Scheme::Random(2),
+            sync: None,
+            progress: None,
+            cancel: None,
+        })
+        .ok();
+    }
+
+    stor.remove_file(tmp_file).ok();
+
+    if is_cancelled(req.cancel.as_ref()) {
+        return Err(Error::Cancelled);
+    }
+
+    res
+}
+
+// Writes every file to the temporary zip archive, along with the manifest if there is one.
+fn write_archive<RW, W>(
+    stor: &impl Storage<RW>,
+    tmp_file: &Entry<RW>,
+    req: &mut Request<'_, W>,
+) -> Result<(), Error>
+where
+    RW: Read + Write + Seek,
+    W: Write + Seek,
+{
+    let mut tmp_writer = tmp_file
+        .try_writer()
+        .map_err(|_| Error::CreateArchive)?
+        .borrow_mut();
+    let mut zip_writer = zip::ZipWriter::new(BufWriter::new(&mut *tmp_writer));
+
+    let options = EntryOptions::new(req.compression_method, req.compression_level);
+
+    let previous = match &req.backup {
+        Backup::Incremental(previous) => Some(previous),
+        _ => None,
+    };
+    let mut manifest = match req.backup {
+        Backup::None => None,
+        _ => Some(Manifest::new(previous)),
+    };
+
+    let progress = req.progress.as_deref();
+    if let Some(progress) = progress {
+        progress.phase(Phase::Compressing);
+    }
+
+    let cancel = req.cancel.as_ref();
+    req.compress_paths.try_for_each(|path| {
+        if is_cancelled(cancel) {
+            return Err(Error::Cancelled);
+        }
+
+        let f = stor
+            .read_file(path.map_err(Error::Storage)?)
+            .map_err(Error::Storage)?;
+
+        if let Some(progress) = progress.filter(|_| !f.is_dir()) {
+            progress.file(f.path());
+        }
+
+        let Some(manifest) = manifest.as_mut() else {
+            let size = if f.is_dir() {
+                0
+            } else {
+                stor.file_len(&f).map_err(Error::Storage)? as u64
+            };
+            return add_entry(&mut zip_writer, &f, size, &options, None, progress);
+        };
+
+        let entry = backup_entry(stor, &mut zip_writer, &f, &options, previous, progress)?;
+        manifest.entries.insert(f.path().to_path_buf(), entry);
+
+        Ok(())
+    })?;
+
+    // The manifest is stored last, once every file is known
+    if let Some(mut manifest) = manifest {
+        if let Some(previous) = previous {
+            manifest.deleted = previous
+                .entries
+                .keys()
+                .filter(|path| !manifest.entries.contains_key(*path))
+                .cloned()
+                .collect();
+        }
+
+        let content = manifest.serialize().map_err(Error::Manifest)?;
+        zip_writer
+            .start_file(MANIFEST_NAME, options.stored)
+            .map_err(|_| Error::AddFileToArchive)?;
+        zip_writer
+            .write_all(content.as_bytes())
+            .map_err(|_| Error::WriteData)?;
+    }
+
+    // 3. Close archive and switch writer to reader.
+    zip_writer.finish().map_err(|_| Error::FinishArchive)?;
+
+    Ok(())
+}
+
+// The options that each entry is started with.
+struct EntryOptions {
+    compressed: FullFileOptions<'static>,
+    // Files that are already compressed won't get any smaller, so we save the CPU time.
+    stored: FullFileOptions<'static>,
+    lz4: bool,
+}
+
+impl EntryOptions {
+    fn new(method: CompressionMethod, level: Option<i32>) -> Self {
+        let stored = FullFileOptions::default()
+            .compression_method(zip::CompressionMethod::Stored)
             .large_file(true)
             .unix_permissions(0o755);
 
-        // 2. Add files to the archive.
-        req.compress_files.into_iter().try_for_each(|f| {
-            let file_path = f.path().to_str().ok_or(Error::ReadData)?;
-            if f.is_dir() {
-                zip_writer
-                    .add_directory(file_path, options)
-                    .map_err(|_| Error::AddDirToArchive)?;
-            } else {
-                zip_writer
-                    .start_file(file_path, options)
-                    .map_err(|_| Error::AddFileToArchive)?;
-
-                let mut reader = f.try_reader().map_err(|_| Error::ReadData)?.borrow_mut();
-                let mut buffer = vec![0u8; BLOCK_SIZE].into_boxed_slice();
-                loop {
-                    let read_count = reader.read(&mut buffer).map_err(|_| Error::ReadData)?;
-                    zip_writer
-                        .write_all(&buffer[..read_count])
-                        .map_err(|_| Error::WriteData)?;
-                    if read_count != BLOCK_SIZE {
-                        break;
-                    }
-                }
-            }
+        let (compressed, lz4) = match method {
+            CompressionMethod::Zip(method) => (
+                stored
+                    .clone()
+                    .compression_method(method)
+                    .compression_level(level.map(i64::from)),
+                false,
+            ),
+            // The frames are stored as they are, as they're already compressed
+            CompressionMethod::Lz4 => (stored.clone(), true),
+        };
+
+        Self {
+            compressed,
+            stored,
+            lz4,
+        }
+    }
+}
+
+/// Extensions of file types that are already compressed, and are stored without compression.
+pub const PRECOMPRESSED_EXTENSIONS: &[&str] = &[
+    "7z", "aac", "apk", "avi", "br", "bz2", "docx", "epub", "flac", "gif", "gz", "heic", "jar",
+    "jpeg", "jpg", "lz4", "m4a", "m4v", "mkv", "mov", "mp3", "mp4", "odt", "ogg", "opus", "png",
+    "pptx", "rar", "tgz", "webm", "webp", "xlsx", "xz", "zip", "zst",
+];
+
+fn is_precompressed(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| {
+            PRECOMPRESSED_EXTENSIONS
+                .iter()
+                .any(|known| known.eq_ignore_ascii_case(ext))
+        })
+}
+
+/// The ID of the extra field that holds the raw bytes of a file name that isn't valid UTF-8.
+pub(crate) const RAW_NAME_FIELD_ID: u16 = 0x6478;
+
+/// The ID of the extra field that marks an LZ4 frame, and holds the file's uncompressed size (which is only used to show progress).
+pub(crate) const LZ4_FIELD_ID: u16 = 0x6479;
 
-            Ok(())
-        })?;
+// Extra fields can't be larger than `u16::MAX` altogether. The ID and length take up 4 bytes of
+// this field, and the Zip64 field for large files takes up 28 more.
+const MAX_RAW_NAME_LEN: usize = u16::MAX as usize - 4 - 28;
 
-        // 3. Close archive and switch writer to reader.
-        zip_writer.finish().map_err(|_| Error::FinishArchive)?;
+// Adds a file or a directory to the archive if it's new or changed, and returns its manifest entry.
+fn backup_entry<RW, W>(
+    stor: &impl Storage<RW>,
+    zip_writer: &mut zip::ZipWriter<W>,
+    f: &Entry<RW>,
+    options: &EntryOptions,
+    previous: Option<&Manifest>,
+    progress: Option<&dyn Progress>,
+) -> Result<ManifestEntry, Error>
+where
+    RW: Read + Write + Seek,
+    W: Write + Seek,
+{
+    if f.is_dir() {
+        // Directories are only stored again if they're new
+        if previous.is_none_or(|previous| !previous.entries.contains_key(f.path())) {
+            add_entry(zip_writer, f, 0, options, None, None)?;
+        }
+
+        return Ok(ManifestEntry::Dir);
     }
 
-    let buf_capacity = stor.file_len(&tmp_file).map_err(|_| Error::FinishArchive)?;
+    let size = stor.file_len(f).map_err(Error::Storage)? as u64;
+    let modified = stor
+        .file_modified(f)
+        .map_err(Error::Storage)?
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
 
-    // 4. Encrypt zip archive
-    let encrypt_res = crate::encrypt::execute(crate::encrypt::Request {
-        reader: tmp_file.try_reader().map_err(|_| Error::FinishArchive)?,
-        writer: req.writer,
-        header_writer: req.header_writer,
-        raw_key: req.raw_key,
-        header_type: req.header_type,
-        hashing_algorithm: req.hashing_algorithm,
-    })
-    .map_err(Error::Encrypt);
+    if let Some(info) =
+        previous.and_then(|previous| previous.is_unchanged(f.path(), size, modified))
+    {
+        return Ok(ManifestEntry::File(info.clone()));
+    }
 
-    // 5. Finally eraze zip archive with zeros.
-    crate::overwrite::execute(crate::overwrite::Request {
-        buf_capacity,
-        writer: tmp_file.try_writer().map_err(|_| Error::FinishArchive)?,
-        passes: 2,
-    })
-    .ok();
+    let mut hasher = Blake3Hasher::default();
+    add_entry(zip_writer, f, size, options, Some(&mut hasher), progress)?;
 
-    stor.remove_file(tmp_file).ok();
+    Ok(ManifestEntry::File(FileInfo {
+        size,
+        modified,
+        hash: hasher.finish(),
+    }))
+}
 
-    encrypt_res
+// Adds a file or a directory to the archive, and copies the file's content.
+fn add_entry<RW, W>(
+    zip_writer: &mut zip::ZipWriter<W>,
+    f: &Entry<RW>,
+    size: u64,
+    options: &EntryOptions,
+    hasher: Option<&mut Blake3Hasher>,
+    progress: Option<&dyn Progress>,
+) -> Result<(), Error>
+where
+    RW: Read + Write + Seek,
+    W: Write + Seek,
+{
+    let precompressed = is_precompressed(f.path());
+    let lz4 = options.lz4 && !precompressed && !f.is_dir();
+
+    let mut entry_options = if precompressed {
+        options.stored.clone()
+    } else {
+        options.compressed.clone()
+    };
+    if lz4 {
+        entry_options
+            .add_extra_data(LZ4_FIELD_ID, Box::new(size.to_le_bytes()), false)
+            .map_err(|_| Error::AddFileToArchive)?;
+    }
+
+    start_entry(zip_writer, f.path(), f.is_dir(), entry_options)?;
+
+    if !f.is_dir() {
+        let mut reader = f.try_reader().map_err(|_| Error::ReadData)?.borrow_mut();
+        let mut reader = ProgressReader::new(&mut *reader, progress);
+
+        if lz4 {
+            let mut encoder = lz4_flex::frame::FrameEncoder::new(&mut *zip_writer);
+            copy_content(&mut reader, &mut encoder, hasher)?;
+            encoder.finish().map_err(|_| Error::WriteData)?;
+        } else {
+            copy_content(&mut reader, zip_writer, hasher)?;
+        }
+    }
+
+    Ok(())
+}
+
+// Copies a file's content to the archive, and hashes it if it's needed for the manifest.
+fn copy_content(
+    reader: &mut impl Read,
+    writer: &mut impl Write,
+    mut hasher: Option<&mut Blake3Hasher>,
+) -> Result<(), Error> {
+    let mut buffer = vec![0u8; BLOCK_SIZE].into_boxed_slice();
+    loop {
+        // A short read doesn't mean the end of the file, only a read of 0 bytes does
+        let read_count = match reader.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(err) if err.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(_) => return Err(Error::ReadData),
+        };
+        if let Some(hasher) = hasher.as_mut() {
+            hasher.write(&buffer[..read_count]);
+        }
+        writer
+            .write_all(&buffer[..read_count])
+            .map_err(|_| Error::WriteData)?;
+    }
+
+    Ok(())
+}
+
+// Starts a file or a directory entry within the archive.
+//
+// Zip entries are named with UTF-8 strings, so paths that aren't valid UTF-8 get a lossy name
+// (for other tools) and their raw bytes are kept in an extra field, which `unpack` restores from.
+fn start_entry<W: Write + Seek>(
+    zip_writer: &mut zip::ZipWriter<W>,
+    path: &Path,
+    is_dir: bool,
+    mut options: FullFileOptions<'static>,
+) -> Result<(), Error> {
+    let error = || {
+        if is_dir {
+            Error::AddDirToArchive
+        } else {
+            Error::AddFileToArchive
+        }
+    };
+
+    let name = if let Some(name) = path.to_str() {
+        name.to_string()
+    } else {
+        let raw_name = path_to_bytes(path)
+            .filter(|raw_name| raw_name.len() <= MAX_RAW_NAME_LEN)
+            .ok_or_else(|| Error::UnsupportedPath(path.to_path_buf()))?;
+
+        options
+            .add_extra_data(RAW_NAME_FIELD_ID, raw_name.into(), false)
+            .map_err(|_| error())?;
+
+        path.to_string_lossy().into_owned()
+    };
+
+    if is_dir {
+        zip_writer.add_directory(name, options)
+    } else {
+        zip_writer.start_file(name, options)
+    }
+    .map_err(|_| error())
+}
+
+// Finds the extra field with the given ID within an entry's extra data.
+fn read_extra_field(mut extra_data: &[u8], field_id: u16) -> Option<&[u8]> {
+    while extra_data.len() >= 4 {
+        let id = u16::from_le_bytes([extra_data[0], extra_data[1]]);
+        let len = usize::from(u16::from_le_bytes([extra_data[2], extra_data[3]]));
+        let field = extra_data.get(4..4 + len)?;
+
+        if id == field_id {
+            return Some(field);
+        }
+
+        extra_data = &extra_data[4 + len..];
+    }
+
+    None
+}
+
+/// Finds the raw file name within an entry's extra data, if it was stored by `pack`.
+pub(crate) fn read_raw_name(extra_data: &[u8]) -> Option<&[u8]> {
+    read_extra_field(extra_data, RAW_NAME_FIELD_ID)
+}
+
+/// Finds the uncompressed size of an entry that's stored as an LZ4 frame, if it is one.
+pub(crate) fn read_lz4_size(extra_data: &[u8]) -> Option<u64> {
+    read_extra_field(extra_data, LZ4_FIELD_ID)
+        .and_then(|field| field.try_into().ok())
+        .map(u64::from_le_bytes)
+}
+
+#[cfg(unix)]
+#[allow(clippy::unnecessary_wraps)] // other platforms can fail
+pub(crate) fn path_to_bytes(path: &Path) -> Option<&[u8]> {
+    use std::os::unix::ffi::OsStrExt;
+    Some(path.as_os_str().as_bytes())
+}
+
+#[cfg(not(unix))]
+pub(crate) fn path_to_bytes(path: &Path) -> Option<&[u8]> {
+    path.to_str().map(str::as_bytes)
+}
+
+/// Converts a raw file name back to a path. Returns `None` if the platform can't represent it.
+#[cfg(unix)]
+#[allow(clippy::unnecessary_wraps)] // other platforms can fail
+pub(crate) fn bytes_to_path(bytes: &[u8]) -> Option<PathBuf> {
+    use std::os::unix::ffi::OsStrExt;
+    Some(PathBuf::from(std::ffi::OsStr::from_bytes(bytes)))
+}
+
+#[cfg(not(unix))]
+pub(crate) fn bytes_to_path(bytes: &[u8]) -> Option<PathBuf> {
+    std::str::from_utf8(bytes).ok().map(PathBuf::from)
 }
 
 #[cfg(test)]
@@ -141,9 +513,10 @@ mod tests {
     use core::primitives::{Algorithm, Mode};
 
     use crate::encrypt::tests::PASSWORD;
+    use crate::storage::testing::{FaultyStorage, TreeBuilder};
     use crate::storage::{InMemoryStorage, Storage};
 
-    const ENCRYPTED_PACKED_BAR_DIR: [u8; 1202] = [
+    const ENCRYPTED_PACKED_BAR_DIR: [u8; 1322] = [
         222, 5, 14, 1, 12, 1, 173, 240, 60, 45, 230, 243, 58, 160, 69, 50, 217, 192, 66, 223, 124,
         190, 148, 91, 92, 129, 0, 0, 0, 0, 0, 0, 223, 181, 71, 240, 140, 106, 41, 36, 82, 150, 105,
         215, 159, 108, 234, 246, 25, 19, 65, 206, 177, 146, 15, 174, 209, 129, 82, 2, 62, 76, 129,
@@ -159,49 +532,234 @@ mod tests {
         0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
         0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
         0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-        0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 22, 64, 6, 177, 49,
+        0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 22, 64, 6, 177, 8,
         139, 218, 8, 121, 228, 19, 5, 8, 117, 33, 131, 131, 70, 76, 147, 108, 49, 191, 191, 127,
         223, 64, 127, 248, 65, 201, 130, 166, 129, 195, 245, 241, 188, 143, 148, 191, 86, 7, 102,
-        124, 253, 12, 44, 172, 79, 236, 207, 68, 229, 117, 49, 250, 55, 6, 48, 86, 48, 244, 189,
+        124, 253, 12, 44, 172, 79, 236, 207, 68, 229, 117, 49, 250, 55, 63, 48, 86, 48, 244, 189,
         137, 27, 142, 241, 44, 118, 35, 5, 138, 237, 47, 248, 108, 30, 224, 42, 91, 16, 216, 14,
         235, 132, 33, 123, 83, 188, 196, 205, 18, 71, 152, 231, 231, 127, 182, 29, 156, 157, 203,
-        178, 178, 3, 216, 51, 84, 28, 67, 91, 255, 14, 124, 180, 131, 80, 48, 27, 111, 195, 39,
+        178, 178, 3, 216, 51, 84, 28, 67, 91, 255, 14, 124, 180, 131, 105, 48, 27, 111, 195, 39,
         127, 37, 231, 111, 82, 132, 168, 253, 149, 230, 199, 161, 78, 6, 175, 98, 210, 9, 25, 145,
         199, 151, 38, 142, 199, 217, 35, 247, 168, 73, 138, 94, 175, 45, 0, 184, 252, 55, 250, 19,
         8, 79, 247, 38, 230, 133, 143, 66, 27, 69, 96, 183, 201, 238, 81, 114, 131, 123, 229, 78,
-        39, 140, 151, 4, 196, 49, 37, 58, 12, 48, 243, 83, 111, 84, 6, 82, 249, 200, 120, 238, 190,
+        39, 140, 151, 4, 196, 49, 37, 3, 12, 48, 243, 83, 111, 84, 6, 82, 249, 200, 120, 238, 190,
         136, 135, 189, 34, 237, 52, 18, 23, 43, 164, 113, 31, 111, 221, 119, 216, 110, 0, 74, 53,
         81, 86, 83, 234, 70, 69, 194, 224, 96, 26, 47, 133, 49, 147, 204, 96, 125, 165, 105, 182,
-        161, 2, 143, 225, 195, 95, 64, 24, 49, 236, 210, 124, 32, 214, 69, 201, 5, 73, 5, 7, 160,
+        161, 2, 143, 225, 195, 95, 64, 24, 49, 236, 210, 124, 32, 214, 69, 201, 5, 73, 5, 62, 160,
         233, 35, 202, 226, 40, 104, 45, 214, 0, 39, 55, 167, 203, 184, 145, 150, 233, 119, 115,
         246, 55, 162, 5, 154, 147, 144, 69, 217, 185, 39, 82, 223, 87, 132, 164, 148, 85, 234, 15,
         160, 2, 214, 133, 27, 73, 53, 27, 86, 53, 215, 96, 142, 85, 25, 127, 11, 111, 19, 1, 72,
-        74, 92, 16, 14, 98, 20, 203, 163, 227, 160, 192, 158, 223, 99, 116, 212, 137, 101, 150,
+        74, 92, 16, 14, 98, 20, 203, 154, 227, 160, 192, 158, 223, 99, 116, 212, 137, 101, 150,
         182, 125, 244, 59, 20, 157, 129, 149, 34, 21, 136, 185, 41, 242, 168, 45, 135, 100, 219,
         239, 132, 211, 238, 37, 242, 139, 218, 120, 112, 158, 75, 53, 172, 162, 136, 202, 94, 117,
-        152, 175, 205, 34, 198, 99, 49, 174, 187, 80, 151, 225, 169, 120, 192, 77, 61, 38, 2, 158,
-        45, 216, 78, 215, 134, 255, 7, 46, 144, 119, 60, 168, 202, 24, 239, 147, 122, 58, 48, 50,
-        178, 58, 153, 243, 242, 169, 238, 42, 78, 123, 37, 181, 17, 109, 175, 84, 6, 212, 122, 89,
-        60, 111, 248, 41, 156, 214, 222, 151, 212, 52, 10, 221, 69, 1, 215, 170, 76, 149, 134, 241,
-        212, 217, 131, 179, 34, 240, 124, 224, 192, 105, 34, 254, 165, 211, 100, 169, 240, 171,
-        131, 50, 80, 54, 254, 128, 179, 233, 223, 22, 39, 56, 205, 221, 76, 177, 197, 164, 140,
-        181, 42, 154, 82, 239, 240, 127, 211, 45, 146, 57, 154, 151, 153, 112, 215, 222, 199, 37,
-        44, 98, 118, 182, 189, 15, 139, 88, 227, 37, 149, 107, 13, 123, 201, 51, 61, 67, 220, 161,
-        13, 72, 176, 39, 157, 128, 105, 144, 10, 46, 29, 113, 1, 76, 162, 157, 200, 213, 175, 107,
-        128, 13, 47, 170, 216, 107, 48, 241, 149, 219, 20, 186, 74, 210, 5, 210, 18, 201, 78, 159,
-        121, 180, 195, 154, 176, 154, 255, 21, 5, 86, 212, 181, 237, 131, 116, 59, 241, 57, 24,
-        102, 126, 132, 135, 154, 99, 217, 2, 201, 139, 202, 125, 64, 165, 195, 210, 255, 165, 197,
-        172, 166, 27, 200, 226, 158, 225, 224, 10, 150, 97, 2, 77, 73, 51, 112, 201, 146, 74, 245,
-        95, 191, 244, 128, 170, 109, 227, 44, 24, 11, 216, 35, 137, 61, 120, 207, 212, 57, 229, 70,
-        152, 118, 92, 235, 187, 55, 189, 231, 126, 15, 86, 66, 78, 251, 39, 181, 191, 193, 226,
-        199, 131, 61, 145, 177, 76, 168, 0, 235, 172, 21, 213, 87, 81, 176, 135, 139, 61, 3, 91,
-        67, 84, 199, 40, 113, 140, 68, 174, 34, 199, 50, 33, 187, 208, 209, 155, 237, 140, 16, 204,
-        135, 151, 241, 28, 95, 87, 91, 169, 160, 1, 206, 18, 220, 65, 236, 52, 63, 184, 226, 237,
-        129, 19, 170, 194, 11, 154, 168, 110, 242, 19, 167, 195, 205, 68, 4, 151, 99, 196, 164, 13,
-        137, 140, 175, 134, 102, 47, 63, 0, 229, 73, 218, 226, 121, 230, 98, 31, 102, 161, 40, 233,
-        229, 39, 224, 19, 92, 220, 151, 154, 193, 191, 30,
+        152, 175, 205, 34, 198, 99, 49, 174, 187, 80, 151, 225, 169, 120, 192, 77, 61, 38, 1, 158,
+        20, 216, 78, 215, 134, 255, 7, 46, 144, 119, 60, 168, 202, 24, 239, 147, 122, 58, 48, 50,
+        178, 58, 153, 243, 230, 169, 238, 42, 78, 123, 37, 181, 17, 109, 175, 84, 6, 212, 122, 89,
+        60, 111, 248, 41, 205, 157, 207, 149, 250, 55, 30, 221, 69, 1, 215, 170, 76, 149, 167, 241,
+        212, 217, 131, 179, 114, 187, 125, 226, 237, 106, 15, 254, 172, 211, 100, 169, 240, 171,
+        162, 50, 80, 54, 254, 128, 94, 168, 233, 22, 39, 56, 175, 188, 55, 158, 255, 194, 227, 218,
+        5, 202, 25, 238, 242, 81, 61, 120, 164, 57, 154, 151, 251, 17, 165, 208, 233, 197, 229, 29,
+        111, 178, 189, 31, 139, 93, 227, 37, 149, 121, 13, 123, 201, 51, 61, 67, 220, 161, 13, 72,
+        176, 154, 87, 240, 107, 189, 9, 97, 124, 3, 46, 98, 196, 242, 167, 219, 199, 136, 74, 113,
+        118, 123, 83, 236, 187, 94, 33, 37, 233, 134, 73, 210, 5, 210, 18, 201, 78, 159, 121, 149,
+        195, 52, 32, 156, 197, 16, 5, 52, 181, 194, 194, 173, 18, 70, 158, 22, 112, 3, 18, 232,
+        232, 180, 23, 161, 118, 37, 10, 96, 125, 69, 165, 161, 179, 141, 138, 235, 202, 204, 116,
+        231, 149, 241, 147, 140, 110, 232, 94, 123, 59, 52, 123, 92, 203, 188, 73, 225, 95, 191,
+        244, 161, 170, 46, 242, 122, 34, 114, 129, 204, 64, 199, 135, 48, 43, 46, 229, 82, 152,
+        120, 92, 235, 187, 55, 189, 231, 126, 226, 215, 248, 78, 22, 166, 212, 223, 179, 205, 139,
+        132, 32, 209, 176, 83, 162, 30, 235, 167, 21, 213, 87, 81, 225, 204, 154, 63, 40, 88, 87,
+        84, 199, 40, 113, 140, 65, 174, 3, 199, 113, 48, 204, 234, 132, 208, 236, 142, 56, 207,
+        170, 151, 252, 28, 95, 87, 91, 169, 129, 1, 72, 180, 204, 119, 254, 74, 136, 70, 29, 18,
+        28, 141, 214, 237, 104, 245, 218, 2, 150, 61, 211, 187, 185, 20, 162, 19, 102, 197, 164,
+        13, 235, 235, 221, 175, 72, 39, 91, 108, 137, 170, 245, 150, 1, 146, 99, 88, 100, 131, 58,
+        23, 189, 124, 174, 19, 30, 128, 4, 188, 246, 76, 215, 45, 130, 23, 107, 137, 138, 168, 110,
+        44, 130, 148, 11, 45, 21, 159, 243, 104, 70, 92, 170, 160, 184, 224, 198, 120, 28, 67, 211,
+        24, 131, 158, 12, 143, 237, 43, 161, 42, 242, 46, 157, 32, 0, 101, 171, 240, 134, 238, 141,
+        252, 213, 86, 150, 127, 41, 203, 174, 46, 188, 213, 248, 247, 167, 252, 104, 14, 130, 223,
+        183, 73, 71, 109, 101, 89, 246, 248, 79, 125, 238, 245, 196, 246, 204, 114, 104, 137, 68,
+        192, 159, 51, 240, 115, 78, 111, 40, 185, 244, 91, 7, 95, 161, 231, 14, 138, 59, 230, 38,
+        46, 46, 147, 62, 161, 75, 118, 29, 118, 237, 18, 237, 38, 189,
     ];
 
+    #[test]
+    fn should_detect_precompressed_files() {
+        assert!(is_precompressed(Path::new("bar/photo.jpg")));
+        assert!(is_precompressed(Path::new("bar/VIDEO.MP4")));
+        assert!(is_precompressed(Path::new("backup.zip")));
+        assert!(!is_precompressed(Path::new("bar/hello.txt")));
+        assert!(!is_precompressed(Path::new("bar/zip")));
+        assert!(!is_precompressed(Path::new("bar/")));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn should_store_non_utf8_names_losslessly() {
+        use std::os::unix::ffi::OsStrExt;
+
+        let dir_path = Path::new(std::ffi::OsStr::from_bytes(b"caf\xe9/"));
+        let file_path = Path::new(std::ffi::OsStr::from_bytes(b"caf\xe9/men\xfa.txt"));
+
+        let mut zip_writer = zip::ZipWriter::new(std::io::Cursor::new(vec![]));
+        let options = FullFileOptions::default();
+        start_entry(&mut zip_writer, dir_path, true, options.clone()).unwrap();
+        start_entry(&mut zip_writer, file_path, false, options.clone()).unwrap();
+        zip_writer.write_all(b"hello").unwrap();
+        start_entry(&mut zip_writer, Path::new("hello.txt"), false, options).unwrap();
+        let archive = zip_writer.finish().unwrap();
+
+        let mut archive = zip::ZipArchive::new(archive).unwrap();
+
+        let dir = archive.by_index(0).unwrap();
+        assert!(dir.is_dir());
+        assert_eq!(
+            read_raw_name(dir.extra_data().unwrap()),
+            Some(&b"caf\xe9/"[..])
+        );
+        drop(dir);
+
+        let file = archive.by_index(1).unwrap();
+        assert!(!file.is_dir());
+        let raw_name = read_raw_name(file.extra_data().unwrap()).unwrap();
+        assert_eq!(bytes_to_path(raw_name), Some(file_path.to_path_buf()));
+        drop(file);
+
+        let file = archive.by_index(2).unwrap();
+        assert_eq!(file.name(), "hello.txt");
+        assert_eq!(read_raw_name(file.extra_data().unwrap_or_default()), None);
+    }
+
+    #[test]
+    fn should_compress_with_xz_and_lz4() {
+        let stor = InMemoryStorage::default();
+        stor.add_hello_txt();
+        let file = stor.read_file("hello.txt").unwrap();
+
+        for method in [
+            CompressionMethod::Zip(zip::CompressionMethod::Xz),
+            CompressionMethod::Lz4,
+        ] {
+            let mut zip_writer = zip::ZipWriter::new(std::io::Cursor::new(vec![]));
+            let options = EntryOptions::new(method, None);
+            file.try_reader().unwrap().borrow_mut().rewind().unwrap();
+            add_entry(&mut zip_writer, &file, 11, &options, None, None).unwrap();
+
+            let mut archive = zip::ZipArchive::new(zip_writer.finish().unwrap()).unwrap();
+            let mut zip_file = archive.by_index(0).unwrap();
+            let lz4_size = read_lz4_size(zip_file.extra_data().unwrap_or_default());
+
+            let mut content = vec![];
+            if method == CompressionMethod::Lz4 {
+                assert_eq!(lz4_size, Some(11));
+                lz4_flex::frame::FrameDecoder::new(&mut zip_file)
+                    .read_to_end(&mut content)
+                    .unwrap();
+            } else {
+                assert_eq!(lz4_size, None);
+                assert_eq!(zip_file.compression(), zip::CompressionMethod::Xz);
+                zip_file.read_to_end(&mut content).unwrap();
+            }
+            assert_eq!(content, b"hello world".to_vec());
+        }
+    }
+
+    #[test]
+    fn should_pack_whole_files_from_short_reads() {
+        let stor = FaultyStorage::new(TreeBuilder::new().file("hello.txt", "hello world").build())
+            .short_reads(4);
+        let file = stor.read_file("hello.txt").unwrap();
+
+        let mut zip_writer = zip::ZipWriter::new(std::io::Cursor::new(vec![]));
+        let options =
+            EntryOptions::new(CompressionMethod::Zip(zip::CompressionMethod::Stored), None);
+        let mut hasher = Blake3Hasher::default();
+        add_entry(
+            &mut zip_writer,
+            &file,
+            11,
+            &options,
+            Some(&mut hasher),
+            None,
+        )
+        .unwrap();
+
+        let mut archive = zip::ZipArchive::new(zip_writer.finish().unwrap()).unwrap();
+        let mut content = vec![];
+        archive
+            .by_name("hello.txt")
+            .unwrap()
+            .read_to_end(&mut content)
+            .unwrap();
+
+        assert_eq!(content, b"hello world".to_vec());
+        assert_eq!(
+            hasher.finish(),
+            blake3::hash(b"hello world").to_hex().to_string()
+        );
+    }
+
+    #[test]
+    fn should_only_store_changed_files_in_incremental_backup() {
+        let stor = InMemoryStorage::default();
+        stor.add_hello_txt();
+        stor.add_bar_foo_folder();
+
+        // In-memory files are always modified at the UNIX epoch
+        let unchanged = ManifestEntry::File(FileInfo {
+            size: 11,
+            modified: std::time::Duration::ZERO,
+            hash: String::from("unchanged"),
+        });
+
+        let mut previous = Manifest::new(None);
+        previous
+            .entries
+            .insert(PathBuf::from("hello.txt"), unchanged.clone());
+        previous
+            .entries
+            .insert(PathBuf::from("bar/"), ManifestEntry::Dir);
+        previous.entries.insert(
+            PathBuf::from("bar/hello.txt"),
+            ManifestEntry::File(FileInfo {
+                size: 4,
+                modified: std::time::Duration::ZERO,
+                hash: String::from("changed"),
+            }),
+        );
+
+        let mut zip_writer = zip::ZipWriter::new(std::io::Cursor::new(vec![]));
+        let options =
+            EntryOptions::new(CompressionMethod::Zip(zip::CompressionMethod::Stored), None);
+
+        let entries = ["hello.txt", "bar/", "bar/hello.txt", "bar/foo/"].map(|path| {
+            let file = stor.read_file(path).unwrap();
+            backup_entry(
+                &stor,
+                &mut zip_writer,
+                &file,
+                &options,
+                Some(&previous),
+                None,
+            )
+            .unwrap()
+        });
+
+        let archive = zip::ZipArchive::new(zip_writer.finish().unwrap()).unwrap();
+        let mut names = archive.file_names().collect::<Vec<_>>();
+        names.sort_unstable();
+
+        assert_eq!(names, vec!["bar/foo/", "bar/hello.txt"]);
+        assert_eq!(entries[0], unchanged);
+        assert_eq!(entries[1], ManifestEntry::Dir);
+        assert_eq!(
+            entries[2],
+            ManifestEntry::File(FileInfo {
+                size: 5,
+                modified: std::time::Duration::ZERO,
+                hash: blake3::hash(b"hello").to_hex().to_string(),
+            })
+        );
+    }
+
     #[test]
     fn should_pack_bar_directory() {
         let stor = Arc::new(InMemoryStorage::default());
@@ -209,14 +767,20 @@ mod tests {
         stor.add_bar_foo_folder_with_hidden();
 
         let file = stor.read_file("bar/").unwrap();
-        let mut compress_files = stor.read_dir(&file).unwrap();
-        compress_files.sort_by(|a, b| a.path().cmp(b.path()));
+        let mut compress_paths = stor
+            .walk_dir(&file)
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        compress_paths.sort();
 
         let output_file = stor.create_file("bar.zip.enc").unwrap();
 
         let req = Request {
-            compress_files,
-            compression_method: zip::CompressionMethod::Stored,
+            compress_paths: Box::new(compress_paths.into_iter().map(Ok)),
+            compression_method: CompressionMethod::Zip(zip::CompressionMethod::Stored),
+            compression_level: None,
+            backup: Backup::None,
             writer: output_file.try_writer().unwrap(),
             header_writer: None,
             raw_key: Protected::new(PASSWORD.to_vec()),
@@ -226,6 +790,8 @@ mod tests {
                 mode: Mode::StreamMode,
             },
             hashing_algorithm: HashingAlgorithm::Blake3Balloon(5),
+            progress: None,
+            cancel: None,
         };
 
         match execute(stor, req) {
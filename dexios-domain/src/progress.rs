@@ -0,0 +1,136 @@
+//! This provides a common way for operations to report their progress, such as to draw a progress bar.
+//!
+//! Operations go through one or more phases (e.g. `pack` compresses, hashes the key and then encrypts). The number of processed bytes starts again from zero in every phase, and the total is only reported if it's known up front.
+
+use std::fmt;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    HashingKey,
+    Encrypting,
+    Decrypting,
+    Compressing,
+    Decompressing,
+    Hashing,
+    Erasing,
+}
+
+impl fmt::Display for Phase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Phase::HashingKey => f.write_str("Hashing key"),
+            Phase::Encrypting => f.write_str("Encrypting"),
+            Phase::Decrypting => f.write_str("Decrypting"),
+            Phase::Compressing => f.write_str("Compressing"),
+            Phase::Decompressing => f.write_str("Decompressing"),
+            Phase::Hashing => f.write_str("Hashing"),
+            Phase::Erasing => f.write_str("Erasing"),
+        }
+    }
+}
+
+/// Receives progress updates from an operation.
+///
+/// Every method does nothing by default, so only the updates that are needed have to be implemented.
+///
+/// `erase_dir` erases several files at once, so updates may come from more than one thread.
+pub trait Progress: Send + Sync {
+    /// The operation has started a new phase, and no bytes of it have been processed yet.
+    fn phase(&self, _phase: Phase) {}
+
+    /// The number of bytes that the current phase will process.
+    fn total(&self, _bytes: u64) {}
+
+    /// The operation has started processing a file.
+    fn file(&self, _path: &Path) {}
+
+    /// More bytes of the current phase have been processed.
+    fn advance(&self, _bytes: u64) {}
+}
+
+// Reports every byte that's read from the inner reader.
+pub(crate) struct ProgressReader<'a, R> {
+    inner: R,
+    progress: Option<&'a dyn Progress>,
+}
+
+impl<'a, R: Read> ProgressReader<'a, R> {
+    pub(crate) fn new(inner: R, progress: Option<&'a dyn Progress>) -> Self {
+        Self { inner, progress }
+    }
+}
+
+impl<R: Read> Read for ProgressReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read_count = self.inner.read(buf)?;
+        if let Some(progress) = self.progress {
+            progress.advance(read_count as u64);
+        }
+        Ok(read_count)
+    }
+}
+
+// The number of bytes between the reader's position and its end, without moving it.
+pub(crate) fn remaining_len(reader: &mut impl Seek) -> io::Result<u64> {
+    let position = reader.stream_position()?;
+    let end = reader.seek(SeekFrom::End(0))?;
+    reader.seek(SeekFrom::Start(position))?;
+    Ok(end.saturating_sub(position))
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    // Records every update, so tests can check what an operation reported.
+    #[derive(Default)]
+    pub(crate) struct RecordedProgress {
+        pub(crate) phases: Mutex<Vec<(Phase, Option<u64>, u64)>>,
+    }
+
+    impl RecordedProgress {
+        // The phases that were reported, with their totals and the number of bytes that were processed.
+        pub(crate) fn phases(&self) -> Vec<(Phase, Option<u64>, u64)> {
+            self.phases.lock().unwrap().clone()
+        }
+    }
+
+    impl Progress for RecordedProgress {
+        fn phase(&self, phase: Phase) {
+            self.phases.lock().unwrap().push((phase, None, 0));
+        }
+
+        fn total(&self, bytes: u64) {
+            if let Some(last) = self.phases.lock().unwrap().last_mut() {
+                last.1 = Some(bytes);
+            }
+        }
+
+        fn advance(&self, bytes: u64) {
+            if let Some(last) = self.phases.lock().unwrap().last_mut() {
+                last.2 += bytes;
+            }
+        }
+    }
+
+    #[test]
+    fn should_report_read_bytes() {
+        let progress = RecordedProgress::default();
+        progress.phase(Phase::Hashing);
+
+        let mut reader = io::Cursor::new(vec![1u8; 100]);
+        reader.set_position(40);
+        assert_eq!(remaining_len(&mut reader).unwrap(), 60);
+        assert_eq!(reader.position(), 40);
+
+        let mut buf = Vec::new();
+        ProgressReader::new(&mut reader, Some(&progress))
+            .read_to_end(&mut buf)
+            .unwrap();
+
+        assert_eq!(progress.phases(), vec![(Phase::Hashing, None, 60)]);
+    }
+}
@@ -0,0 +1,537 @@
+//! This contains a deduplicating, encrypted repository for backups.
+//!
+//! Files are split into content-defined chunks, and every chunk is encrypted under the repository's master key. Chunks are identified by a keyed hash of their content, so each chunk is only stored once - no matter how many files or snapshots contain it.
+//!
+//! The master key is protected by the keyslots of a regular Dexios header, so the repository can be unlocked with any key that was added to it.
+//!
+//! A repository has the following layout:
+//!
+//! - `config` - the header that contains the keyslots
+//! - `chunks/<2 chars>/<chunk ID>` - the encrypted chunks
+//! - `snapshots/<snapshot ID>` - the encrypted snapshot manifests
+
+use std::collections::HashSet;
+use std::io::{Read, Seek, Write};
+use std::path::{Path, PathBuf};
+
+use core::cipher::Ciphers;
+use core::header::Header;
+use core::key::decrypt_master_key;
+use core::primitives::{Algorithm, Mode, MASTER_KEY_LEN};
+use core::protected::Protected;
+use core::Payload;
+
+use crate::pack::{bytes_to_path, path_to_bytes};
+use crate::storage::{self, Storage};
+use crate::utils::{gen_nonce, hex_decode, hex_encode};
+
+pub mod backup;
+pub mod chunker;
+pub mod init;
+pub mod list;
+pub mod prune;
+pub mod restore;
+
+pub const CONFIG_NAME: &str = "config";
+pub const CHUNKS_DIR: &str = "chunks";
+pub const SNAPSHOTS_DIR: &str = "snapshots";
+
+const SNAPSHOT_VERSION: &str = "dexios-snapshot 1";
+const CHUNK_ID_CONTEXT: &str = "Dexios 2026-10-18 repository chunk IDs";
+
+#[derive(Debug)]
+pub enum Error {
+    AlreadyExists,
+    ReadConfig,
+    WriteConfig,
+    HashKey,
+    EncryptMasterKey,
+    DecryptMasterKey,
+    InitializeChiphers,
+    EncryptData,
+    DecryptData,
+    ReadData,
+    WriteData,
+    MissingChunk(String),
+    CorruptChunk(String),
+    SnapshotNotFound(String),
+    InvalidSnapshot(String),
+    UnsupportedPath(PathBuf),
+    Storage(storage::Error),
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::AlreadyExists => f.write_str("A repository already exists at this location"),
+            Error::ReadConfig => f.write_str("Unable to read the repository's config"),
+            Error::WriteConfig => f.write_str("Unable to write the repository's config"),
+            Error::HashKey => f.write_str("Cannot hash raw key"),
+            Error::EncryptMasterKey => f.write_str("Cannot encrypt master key"),
+            Error::DecryptMasterKey => f.write_str("Cannot decrypt master key"),
+            Error::InitializeChiphers => f.write_str("Cannot initialize chiphers"),
+            Error::EncryptData => f.write_str("Unable to encrypt data"),
+            Error::DecryptData => f.write_str("Unable to decrypt data"),
+            Error::ReadData => f.write_str("Unable to read data"),
+            Error::WriteData => f.write_str("Unable to write data"),
+            Error::MissingChunk(id) => write!(f, "Chunk {id} is missing from the repository"),
+            Error::CorruptChunk(id) => write!(f, "Chunk {id} is corrupted"),
+            Error::SnapshotNotFound(id) => write!(f, "Unable to find snapshot {id}"),
+            Error::InvalidSnapshot(id) => write!(f, "Snapshot {id} is invalid"),
+            Error::UnsupportedPath(path) => {
+                write!(f, "Unable to store path in snapshot: {}", path.display())
+            }
+            Error::Storage(inner) => write!(f, "Storage error: {inner}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// An unlocked repository.
+pub struct Repository {
+    path: PathBuf,
+    ciphers: Ciphers,
+    algorithm: Algorithm,
+    // Chunk IDs are keyed, so they don't reveal the hashes of the chunks' content
+    id_key: Protected<[u8; 32]>,
+}
+
+impl Repository {
+    /// Unlocks the repository at the given path with one of its keys.
+    pub fn open<RW>(
+        stor: &impl Storage<RW>,
+        path: &Path,
+        raw_key: Protected<Vec<u8>>,
+    ) -> Result<Self, Error>
+    where
+        RW: Read + Write + Seek,
+    {
+        let config = stor
+            .read_file(path.join(CONFIG_NAME))
+            .map_err(|_| Error::ReadConfig)?;
+        let (header, _) =
+            Header::deserialize(&mut *config.try_reader().map_err(Error::Storage)?.borrow_mut())
+                .map_err(|_| Error::ReadConfig)?;
+
+        let master_key =
+            decrypt_master_key(raw_key, &header).map_err(|_| Error::DecryptMasterKey)?;
+
+        Self::with_master_key(path, master_key, header.header_type.algorithm)
+    }
+
+    pub(crate) fn with_master_key(
+        path: &Path,
+        master_key: Protected<[u8; MASTER_KEY_LEN]>,
+        algorithm: Algorithm,
+    ) -> Result<Self, Error> {
+        let id_key = Protected::new(blake3::derive_key(CHUNK_ID_CONTEXT, master_key.expose()));
+        let ciphers =
+            Ciphers::initialize(master_key, &algorithm).map_err(|_| Error::InitializeChiphers)?;
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            ciphers,
+            algorithm,
+            id_key,
+        })
+    }
+
+    #[must_use]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn chunk_id(&self, data: &[u8]) -> String {
+        blake3::keyed_hash(self.id_key.expose(), data)
+            .to_hex()
+            .to_string()
+    }
+
+    fn chunk_path(&self, id: &str) -> PathBuf {
+        self.path.join(CHUNKS_DIR).join(&id[..2]).join(id)
+    }
+
+    // IDs come from the user when restoring or pruning, so they're checked before they're joined
+    fn snapshot_path(&self, id: &str) -> Result<PathBuf, Error> {
+        if !is_snapshot_id(id) {
+            return Err(Error::InvalidSnapshot(id.to_string()));
+        }
+
+        Ok(self.path.join(SNAPSHOTS_DIR).join(id))
+    }
+
+    // Every object is stored as the nonce, followed by the ciphertext.
+    // The AAD binds the object to its name, so objects can't be swapped.
+    fn encrypt(&self, data: &[u8], aad: &[u8]) -> Result<Vec<u8>, Error> {
+        let mut nonce = gen_nonce(&self.algorithm, &Mode::MemoryMode);
+        let encrypted = self
+            .ciphers
+            .encrypt(&nonce, Payload { aad, msg: data })
+            .map_err(|_| Error::EncryptData)?;
+
+        nonce.extend_from_slice(&encrypted);
+        Ok(nonce)
+    }
+
+    fn decrypt(&self, data: &[u8], aad: &[u8]) -> Result<Vec<u8>, Error> {
+        let nonce_len = core::primitives::get_nonce_len(&self.algorithm, &Mode::MemoryMode);
+        if data.len() < nonce_len {
+            return Err(Error::DecryptData);
+        }
+
+        let (nonce, msg) = data.split_at(nonce_len);
+        self.ciphers
+            .decrypt(nonce, Payload { aad, msg })
+            .map_err(|_| Error::DecryptData)
+    }
+
+    fn write_chunk<RW>(&self, stor: &impl Storage<RW>, id: &str, data: &[u8]) -> Result<(), Error>
+    where
+        RW: Read + Write + Seek,
+    {
+        let encrypted = self.encrypt(data, id.as_bytes())?;
+        write_object(stor, &self.chunk_path(id), &encrypted)
+    }
+
+    fn read_chunk<RW>(&self, stor: &impl Storage<RW>, id: &str) -> Result<Vec<u8>, Error>
+    where
+        RW: Read + Write + Seek,
+    {
+        let encrypted = read_object(stor, &self.chunk_path(id))
+            .map_err(|_| Error::MissingChunk(id.to_string()))?;
+        let data = self
+            .decrypt(&encrypted, id.as_bytes())
+            .map_err(|_| Error::CorruptChunk(id.to_string()))?;
+
+        if self.chunk_id(&data) != id {
+            return Err(Error::CorruptChunk(id.to_string()));
+        }
+
+        Ok(data)
+    }
+
+    // Lists the IDs of every chunk within the repository.
+    fn chunk_ids<RW>(&self, stor: &impl Storage<RW>) -> Result<HashSet<String>, Error>
+    where
+        RW: Read + Write + Seek,
+    {
+        let chunks_dir = stor
+            .read_file(self.path.join(CHUNKS_DIR))
+            .map_err(Error::Storage)?;
+
+        stor.walk_dir(&chunks_dir)
+            .map_err(Error::Storage)?
+            .filter_map(|path| match path {
+                Ok(path) => path
+                    .file_name()
+                    .and_then(|name| name.to_str())
+                    .filter(|name| is_chunk_id(name))
+                    .map(|name| Ok(name.to_string())),
+                Err(err) => Some(Err(Error::Storage(err))),
+            })
+            .collect()
+    }
+
+    fn write_snapshot<RW>(&self, stor: &impl Storage<RW>, snapshot: &Snapshot) -> Result<(), Error>
+    where
+        RW: Read + Write + Seek,
+    {
+        let encrypted = self.encrypt(&snapshot.serialize()?, snapshot.id.as_bytes())?;
+        write_object(stor, &self.snapshot_path(&snapshot.id)?, &encrypted)
+    }
+
+    /// Reads and decrypts a snapshot.
+    pub fn read_snapshot<RW>(&self, stor: &impl Storage<RW>, id: &str) -> Result<Snapshot, Error>
+    where
+        RW: Read + Write + Seek,
+    {
+        let encrypted = read_object(stor, &self.snapshot_path(id)?)
+            .map_err(|_| Error::SnapshotNotFound(id.to_string()))?;
+        let content = self.decrypt(&encrypted, id.as_bytes())?;
+
+        Snapshot::deserialize(id, &content)
+    }
+
+    /// Lists the IDs of every snapshot, from the oldest to the newest.
+    pub fn snapshot_ids<RW>(&self, stor: &impl Storage<RW>) -> Result<Vec<String>, Error>
+    where
+        RW: Read + Write + Seek,
+    {
+        let snapshots_dir = stor
+            .read_file(self.path.join(SNAPSHOTS_DIR))
+            .map_err(Error::Storage)?;
+
+        let mut ids = stor
+            .walk_dir(&snapshots_dir)
+            .map_err(Error::Storage)?
+            .filter_map(|path| match path {
+                Ok(path) if path != snapshots_dir.path() => path
+                    .file_name()
+                    .and_then(|name| name.to_str())
+                    .filter(|name| is_snapshot_id(name))
+                    .map(|name| Ok(name.to_string())),
+                Ok(_) => None,
+                Err(err) => Some(Err(Error::Storage(err))),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        // IDs start with the time they were created at
+        ids.sort();
+        Ok(ids)
+    }
+}
+
+fn is_chunk_id(name: &str) -> bool {
+    name.len() == 64 && name.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+// Snapshot IDs are the seconds and nanoseconds they were created at, followed by a random suffix
+// (e.g. `1660000000-000000123-0a1b2c3d`), so they're always a single, plain file name.
+fn is_snapshot_id(name: &str) -> bool {
+    let digits =
+        |part: &str, len: usize| part.len() == len && part.bytes().all(|b| b.is_ascii_digit());
+    let suffix = |part: &str| {
+        part.len() == 8 && part.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'))
+    };
+
+    match name.split('-').collect::<Vec<_>>()[..] {
+        [secs, nanos, rand] => digits(secs, 10) && digits(nanos, 9) && suffix(rand),
+        // Older IDs only contain the seconds
+        [secs, rand] => digits(secs, 10) && suffix(rand),
+        _ => false,
+    }
+}
+
+fn write_object<RW>(stor: &impl Storage<RW>, path: &Path, data: &[u8]) -> Result<(), Error>
+where
+    RW: Read + Write + Seek,
+{
+    if let Some(parent) = path.parent() {
+        stor.create_dir_all(parent).map_err(Error::Storage)?;
+    }
+
+    let file = stor.create_file(path).map_err(Error::Storage)?;
+    file.try_writer()
+        .map_err(Error::Storage)?
+        .borrow_mut()
+        .write_all(data)
+        .map_err(|_| Error::WriteData)?;
+    stor.flush_file(&file).map_err(Error::Storage)
+}
+
+fn read_object<RW>(stor: &impl Storage<RW>, path: &Path) -> Result<Vec<u8>, Error>
+where
+    RW: Read + Write + Seek,
+{
+    let file = stor.read_file(path).map_err(Error::Storage)?;
+    let mut data = Vec::new();
+    file.try_reader()
+        .map_err(Error::Storage)?
+        .borrow_mut()
+        .read_to_end(&mut data)
+        .map_err(|_| Error::ReadData)?;
+
+    Ok(data)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SnapshotEntry {
+    Dir(PathBuf),
+    File {
+        path: PathBuf,
+        size: u64,
+        chunks: Vec<String>,
+    },
+}
+
+/// A snapshot lists every path that was backed up, and the chunks that make up each file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Snapshot {
+    pub id: String,
+    // Seconds since the UNIX epoch
+    pub time: u64,
+    pub entries: Vec<SnapshotEntry>,
+}
+
+impl Snapshot {
+    /// The total size of every file within the snapshot.
+    #[must_use]
+    pub fn size(&self) -> u64 {
+        self.entries
+            .iter()
+            .map(|entry| match entry {
+                SnapshotEntry::File { size, .. } => *size,
+                SnapshotEntry::Dir(_) => 0,
+            })
+            .sum()
+    }
+
+    fn serialize(&self) -> Result<Vec<u8>, Error> {
+        let encode_path = |path: &Path| {
+            path_to_bytes(path)
+                .map(hex_encode)
+                .ok_or_else(|| Error::UnsupportedPath(path.to_path_buf()))
+        };
+
+        let mut lines = vec![SNAPSHOT_VERSION.to_string(), format!("time {}", self.time)];
+
+        for entry in &self.entries {
+            lines.push(match entry {
+                SnapshotEntry::Dir(path) => format!("d {}", encode_path(path)?),
+                SnapshotEntry::File { path, size, chunks } => {
+                    let mut line = format!("f {} {size}", encode_path(path)?);
+                    for chunk in chunks {
+                        line.push(' ');
+                        line.push_str(chunk);
+                    }
+                    line
+                }
+            });
+        }
+
+        lines.push(String::new());
+        Ok(lines.join("\n").into_bytes())
+    }
+
+    fn deserialize(id: &str, content: &[u8]) -> Result<Self, Error> {
+        let invalid = || Error::InvalidSnapshot(id.to_string());
+        let decode_path = |value: &str| hex_decode(value).and_then(|bytes| bytes_to_path(&bytes));
+
+        let content = std::str::from_utf8(content).map_err(|_| invalid())?;
+        let mut lines = content.lines();
+        if lines.next() != Some(SNAPSHOT_VERSION) {
+            return Err(invalid());
+        }
+
+        let mut snapshot = Snapshot {
+            id: id.to_string(),
+            time: 0,
+            entries: Vec::new(),
+        };
+
+        for line in lines.filter(|line| !line.is_empty()) {
+            let fields = line.split(' ').collect::<Vec<_>>();
+            match fields[..] {
+                ["time", time] => snapshot.time = time.parse().map_err(|_| invalid())?,
+                ["d", path] => snapshot
+                    .entries
+                    .push(SnapshotEntry::Dir(decode_path(path).ok_or_else(invalid)?)),
+                ["f", path, size, ref chunks @ ..] => {
+                    if !chunks.iter().all(|chunk| is_chunk_id(chunk)) {
+                        return Err(invalid());
+                    }
+
+                    snapshot.entries.push(SnapshotEntry::File {
+                        path: decode_path(path).ok_or_else(invalid)?,
+                        size: size.parse().map_err(|_| invalid())?,
+                        chunks: chunks.iter().map(ToString::to_string).collect(),
+                    });
+                }
+                _ => return Err(invalid()),
+            }
+        }
+
+        Ok(snapshot)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::storage::InMemoryStorage;
+    use crate::utils::gen_master_key;
+
+    pub const REPO_PATH: &str = "repo";
+
+    // Unlocking a repository with a key is slow, so tests use the master key directly
+    pub fn create_repository(stor: &InMemoryStorage) -> Repository {
+        stor.create_dir_all(Path::new(REPO_PATH).join(CHUNKS_DIR))
+            .unwrap();
+        stor.create_dir_all(Path::new(REPO_PATH).join(SNAPSHOTS_DIR))
+            .unwrap();
+
+        Repository::with_master_key(
+            Path::new(REPO_PATH),
+            gen_master_key(),
+            Algorithm::XChaCha20Poly1305,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn should_serialize_and_deserialize_snapshot() {
+        let snapshot = Snapshot {
+            id: String::from("0000000001-abcd"),
+            time: 1,
+            entries: vec![
+                SnapshotEntry::Dir(PathBuf::from("bar/")),
+                SnapshotEntry::File {
+                    path: PathBuf::from("bar/empty.txt"),
+                    size: 0,
+                    chunks: vec![],
+                },
+                SnapshotEntry::File {
+                    path: PathBuf::from("bar/hello world.txt"),
+                    size: 11,
+                    chunks: vec!["ab".repeat(32), "cd".repeat(32)],
+                },
+            ],
+        };
+
+        let content = snapshot.serialize().unwrap();
+
+        assert_eq!(
+            Snapshot::deserialize(&snapshot.id, &content).unwrap(),
+            snapshot
+        );
+    }
+
+    #[test]
+    fn should_detect_corrupt_chunks() {
+        let stor = InMemoryStorage::default();
+        let repo = create_repository(&stor);
+
+        let data = b"hello world";
+        let id = repo.chunk_id(data);
+        repo.write_chunk(&stor, &id, data).unwrap();
+        assert_eq!(repo.read_chunk(&stor, &id).unwrap(), data.to_vec());
+
+        // Swap the chunk with another one
+        let other_id = repo.chunk_id(b"other");
+        repo.write_chunk(&stor, &other_id, b"other").unwrap();
+        let other = read_object(&stor, &repo.chunk_path(&other_id)).unwrap();
+        stor.remove_file(stor.read_file(repo.chunk_path(&id)).unwrap())
+            .unwrap();
+        write_object(&stor, &repo.chunk_path(&id), &other).unwrap();
+
+        match repo.read_chunk(&stor, &id) {
+            Err(Error::CorruptChunk(corrupt_id)) => assert_eq!(corrupt_id, id),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn should_only_accept_generated_snapshot_ids() {
+        let stor = InMemoryStorage::default();
+        let repo = create_repository(&stor);
+
+        assert!(is_snapshot_id("1660000000-000000123-0a1b2c3d"));
+        assert!(is_snapshot_id("1660000000-0a1b2c3d"));
+
+        for id in [
+            "",
+            "..",
+            "../config",
+            "1660000000-000000123-0a1b2c3d/..",
+            "/1660000000-0a1b2c3d",
+            "1660000000-0A1B2C3D",
+            "166000000-0a1b2c3d",
+        ] {
+            match repo.read_snapshot(&stor, id) {
+                Err(Error::InvalidSnapshot(invalid)) => assert_eq!(invalid, id),
+                _ => unreachable!(),
+            }
+        }
+    }
+}
@@ -0,0 +1,232 @@
+//! This stores a new snapshot within a repository.
+//!
+//! Only chunks that aren't already within the repository are encrypted and written.
+
+use std::io::{Read, Seek, Write};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use rand::RngCore;
+
+use super::chunker::{ChunkSizes, Chunker};
+use super::{Error, Repository, Snapshot, SnapshotEntry};
+use crate::storage::{Entry, Paths, Storage};
+use crate::unpack::relative_path;
+use crate::utils::hex_encode;
+
+pub struct Request<'a> {
+    pub repository: &'a Repository,
+    // Paths are stored relative to their root, without any `..` components
+    pub paths: Paths<'a>,
+    pub chunk_sizes: ChunkSizes,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Summary {
+    pub snapshot_id: String,
+    pub files: usize,
+    pub chunks: usize,
+    // Chunks that weren't already within the repository
+    pub new_chunks: usize,
+    pub new_bytes: u64,
+}
+
+pub fn execute<RW>(stor: &impl Storage<RW>, req: Request<'_>) -> Result<Summary, Error>
+where
+    RW: Read + Write + Seek,
+{
+    let repo = req.repository;
+    let mut known_chunks = repo.chunk_ids(stor)?;
+
+    let latest_id = repo.snapshot_ids(stor)?.pop();
+    let time = next_snapshot_time(latest_id.as_deref());
+    let mut suffix = [0u8; 4];
+    rand::thread_rng().fill_bytes(&mut suffix);
+
+    let mut snapshot = Snapshot {
+        id: format!(
+            "{:010}-{:09}-{}",
+            time.as_secs(),
+            time.subsec_nanos(),
+            hex_encode(&suffix)
+        ),
+        time: time.as_secs(),
+        entries: Vec::new(),
+    };
+    let mut summary = Summary::default();
+
+    for path in req.paths {
+        let path = path.map_err(Error::Storage)?;
+        let entry = stor.read_file(&path).map_err(Error::Storage)?;
+        let path = relative_path(&path);
+
+        if entry.is_dir() {
+            // The root directory has nothing left to store
+            if path.as_os_str().is_empty() {
+                continue;
+            }
+
+            snapshot.entries.push(SnapshotEntry::Dir(path));
+            continue;
+        }
+
+        let mut size = 0;
+        let mut chunks = Vec::new();
+        for chunk in chunks_of(&entry, req.chunk_sizes)? {
+            let chunk = chunk.map_err(|_| Error::ReadData)?;
+            let id = repo.chunk_id(&chunk);
+            size += chunk.len() as u64;
+
+            if !known_chunks.contains(&id) {
+                repo.write_chunk(stor, &id, &chunk)?;
+                summary.new_chunks += 1;
+                summary.new_bytes += chunk.len() as u64;
+                known_chunks.insert(id.clone());
+            }
+
+            chunks.push(id);
+        }
+
+        summary.files += 1;
+        summary.chunks += chunks.len();
+        snapshot
+            .entries
+            .push(SnapshotEntry::File { path, size, chunks });
+    }
+
+    repo.write_snapshot(stor, &snapshot)?;
+
+    summary.snapshot_id = snapshot.id;
+    Ok(summary)
+}
+
+// Snapshot IDs are sorted by the time they were created at, so a new snapshot always comes after
+// the latest one - even if they're created within the same nanosecond, or the clock goes back.
+fn next_snapshot_time(latest_id: Option<&str>) -> Duration {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+
+    match latest_id.and_then(snapshot_time) {
+        Some(latest) if latest >= now => latest + Duration::from_nanos(1),
+        _ => now,
+    }
+}
+
+// Older IDs only contain the seconds, followed by the random suffix
+fn snapshot_time(id: &str) -> Option<Duration> {
+    let mut parts = id.split('-');
+    let secs = parts.next()?.parse().ok()?;
+    let nanos = match (parts.next(), parts.next()) {
+        (Some(nanos), Some(_)) => nanos.parse().ok()?,
+        _ => 0,
+    };
+
+    Some(Duration::new(secs, nanos))
+}
+
+fn chunks_of<RW>(entry: &Entry<RW>, sizes: ChunkSizes) -> Result<Chunker<impl Read + '_>, Error>
+where
+    RW: Read + Write + Seek,
+{
+    let reader = entry.try_reader().map_err(Error::Storage)?;
+    Ok(Chunker::new(RefCellReader(reader), sizes))
+}
+
+struct RefCellReader<'a, R: Read>(&'a std::cell::RefCell<R>);
+
+impl<R: Read> Read for RefCellReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.0.borrow_mut().read(buf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repo::tests::create_repository;
+    use crate::storage::InMemoryStorage;
+
+    const SIZES: ChunkSizes = ChunkSizes {
+        min: 4,
+        avg: 8,
+        max: 16,
+    };
+
+    fn backup(stor: &InMemoryStorage, repo: &Repository, paths: &[&str]) -> Summary {
+        let paths = paths
+            .iter()
+            .map(|path| Ok(std::path::PathBuf::from(path)))
+            .collect::<Vec<_>>();
+
+        let req = Request {
+            repository: repo,
+            paths: Box::new(paths.into_iter()),
+            chunk_sizes: SIZES,
+        };
+
+        match execute(stor, req) {
+            Ok(summary) => summary,
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn should_deduplicate_chunks() {
+        let stor = InMemoryStorage::default();
+        stor.add_bar_foo_folder();
+        let repo = create_repository(&stor);
+        let paths = [
+            "bar/",
+            "bar/hello.txt",
+            "bar/world.txt",
+            "bar/foo/",
+            "bar/foo/hello.txt",
+            "bar/foo/world.txt",
+        ];
+
+        let first = backup(&stor, &repo, &paths);
+        assert_eq!(first.files, 4);
+        assert_eq!(first.chunks, 4);
+        // Both `hello.txt` and both `world.txt` files share the same content
+        assert_eq!(first.new_chunks, 2);
+        assert_eq!(first.new_bytes, 10);
+
+        let second = backup(&stor, &repo, &paths);
+        assert_eq!(second.chunks, 4);
+        assert_eq!(second.new_chunks, 0);
+
+        let snapshot = repo.read_snapshot(&stor, &first.snapshot_id).unwrap();
+        assert_eq!(snapshot.entries.len(), paths.len());
+        assert_eq!(snapshot.size(), 20);
+    }
+
+    #[test]
+    fn should_order_snapshots_by_creation() {
+        let stor = InMemoryStorage::default();
+        stor.add_hello_txt();
+        let repo = create_repository(&stor);
+
+        let ids = (0..5)
+            .map(|_| backup(&stor, &repo, &["hello.txt"]).snapshot_id)
+            .collect::<Vec<_>>();
+        assert_eq!(repo.snapshot_ids(&stor).unwrap(), ids);
+    }
+
+    #[test]
+    fn should_create_snapshots_after_the_latest_one() {
+        let future = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+            + 60;
+
+        let latest = format!("{future:010}-000000005-00000000");
+        assert_eq!(next_snapshot_time(Some(&latest)), Duration::new(future, 6));
+
+        let older_format = format!("{future:010}-00000000");
+        assert_eq!(
+            next_snapshot_time(Some(&older_format)),
+            Duration::new(future, 1)
+        );
+    }
+}
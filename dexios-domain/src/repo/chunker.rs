@@ -0,0 +1,161 @@
+//! This splits data into content-defined chunks.
+//!
+//! Chunk boundaries are found with a gear-based rolling hash (as used by `FastCDC`), so inserting or removing bytes only changes the chunks around the edit. The rest of the file still produces the same chunks, and they're deduplicated.
+
+use std::io::{self, Read};
+
+/// The minimum, average and maximum size of a chunk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChunkSizes {
+    pub min: usize,
+    // This must be a power of two
+    pub avg: usize,
+    pub max: usize,
+}
+
+impl Default for ChunkSizes {
+    fn default() -> Self {
+        Self {
+            min: 256 * 1024,
+            avg: 1024 * 1024,
+            max: 4 * 1024 * 1024,
+        }
+    }
+}
+
+// Random values for every byte, generated with splitmix64 so the table doesn't need to be stored.
+const GEAR: [u64; 256] = {
+    let mut table = [0u64; 256];
+    let mut state: u64 = 0x6465_7869_6f73_6364; // "dexioscd"
+    let mut i = 0;
+    while i < 256 {
+        state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        table[i] = z ^ (z >> 31);
+        i += 1;
+    }
+    table
+};
+
+/// An iterator over the chunks of a reader.
+pub struct Chunker<R: Read> {
+    reader: R,
+    sizes: ChunkSizes,
+    buffer: Vec<u8>,
+    eof: bool,
+}
+
+impl<R: Read> Chunker<R> {
+    pub fn new(reader: R, sizes: ChunkSizes) -> Self {
+        Self {
+            reader,
+            sizes,
+            buffer: Vec::with_capacity(sizes.max),
+            eof: false,
+        }
+    }
+
+    // Fills the buffer until it contains a full chunk, or the reader ends.
+    fn fill_buffer(&mut self) -> io::Result<()> {
+        while !self.eof && self.buffer.len() < self.sizes.max {
+            let len = self.buffer.len();
+            self.buffer.resize(self.sizes.max, 0);
+            match self.reader.read(&mut self.buffer[len..]) {
+                Ok(0) => {
+                    self.buffer.truncate(len);
+                    self.eof = true;
+                }
+                Ok(read_count) => self.buffer.truncate(len + read_count),
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => self.buffer.truncate(len),
+                Err(err) => {
+                    self.buffer.truncate(len);
+                    return Err(err);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn boundary(&self) -> usize {
+        let data = &self.buffer;
+        if data.len() <= self.sizes.min {
+            return data.len();
+        }
+
+        let mask = (self.sizes.avg - 1) as u64;
+        let mut hash: u64 = 0;
+        for (i, byte) in data.iter().enumerate().skip(self.sizes.min) {
+            hash = (hash << 1).wrapping_add(GEAR[usize::from(*byte)]);
+            if hash & mask == 0 {
+                return i + 1;
+            }
+        }
+
+        data.len()
+    }
+}
+
+impl<R: Read> Iterator for Chunker<R> {
+    type Item = io::Result<Vec<u8>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Err(err) = self.fill_buffer() {
+            return Some(Err(err));
+        }
+
+        if self.buffer.is_empty() {
+            return None;
+        }
+
+        let boundary = self.boundary();
+        let rest = self.buffer.split_off(boundary);
+        Some(Ok(std::mem::replace(&mut self.buffer, rest)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{prelude::StdRng, RngCore, SeedableRng};
+
+    const SIZES: ChunkSizes = ChunkSizes {
+        min: 64,
+        avg: 256,
+        max: 1024,
+    };
+
+    fn chunks(data: &[u8]) -> Vec<Vec<u8>> {
+        Chunker::new(data, SIZES).map(Result::unwrap).collect()
+    }
+
+    #[test]
+    fn should_split_data_into_chunks() {
+        let mut data = vec![0u8; 64 * 1024];
+        StdRng::seed_from_u64(1).fill_bytes(&mut data);
+
+        let chunks = chunks(&data);
+
+        assert!(chunks.len() > 1);
+        assert_eq!(chunks.concat(), data);
+        for chunk in &chunks[..chunks.len() - 1] {
+            assert!(chunk.len() > SIZES.min && chunk.len() <= SIZES.max);
+        }
+        assert!(Chunker::new(&[][..], SIZES).next().is_none());
+    }
+
+    #[test]
+    fn should_keep_chunks_after_insertion() {
+        let mut data = vec![0u8; 64 * 1024];
+        StdRng::seed_from_u64(2).fill_bytes(&mut data);
+        let before = chunks(&data);
+
+        data.splice(100..100, b"inserted".iter().copied());
+        let after = chunks(&data);
+
+        let shared = after.iter().filter(|chunk| before.contains(chunk)).count();
+        assert!(shared >= after.len() - 2);
+    }
+}
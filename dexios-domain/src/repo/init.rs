@@ -0,0 +1,49 @@
+//! This creates a new, empty repository.
+//!
+//! The master key is stored within the keyslots of a regular Dexios header, so the usual `key` operations can be used on the repository's config.
+
+use std::io::{Read, Seek, Write};
+use std::path::PathBuf;
+
+use core::header::{HashingAlgorithm, HeaderType};
+use core::protected::Protected;
+
+use super::{Error, CHUNKS_DIR, CONFIG_NAME, SNAPSHOTS_DIR};
+use crate::storage::Storage;
+
+pub struct Request {
+    pub path: PathBuf,
+    pub raw_key: Protected<Vec<u8>>,
+    // TODO: don't use external types in logic
+    pub header_type: HeaderType,
+    pub hashing_algorithm: HashingAlgorithm,
+}
+
+pub fn execute<RW>(stor: &impl Storage<RW>, req: Request) -> Result<(), Error>
+where
+    RW: Read + Write + Seek,
+{
+    let config_path = req.path.join(CONFIG_NAME);
+    if stor.read_file(&config_path).is_ok() {
+        return Err(Error::AlreadyExists);
+    }
+
+    let (header, _) =
+        crate::key::create_header(req.raw_key, req.header_type, req.hashing_algorithm).map_err(
+            |err| match err {
+                crate::key::Error::KeyHash => Error::HashKey,
+                _ => Error::EncryptMasterKey,
+            },
+        )?;
+
+    stor.create_dir_all(req.path.join(CHUNKS_DIR))
+        .map_err(Error::Storage)?;
+    stor.create_dir_all(req.path.join(SNAPSHOTS_DIR))
+        .map_err(Error::Storage)?;
+
+    let config = stor.create_file(&config_path).map_err(Error::Storage)?;
+    header
+        .write(&mut *config.try_writer().map_err(Error::Storage)?.borrow_mut())
+        .map_err(|_| Error::WriteConfig)?;
+    stor.flush_file(&config).map_err(Error::Storage)
+}
@@ -0,0 +1,22 @@
+//! This lists every snapshot within a repository, from the oldest to the newest.
+
+use std::io::{Read, Seek, Write};
+
+use super::{Error, Repository, Snapshot};
+use crate::storage::Storage;
+
+pub struct Request<'a> {
+    pub repository: &'a Repository,
+}
+
+pub fn execute<RW>(stor: &impl Storage<RW>, req: Request<'_>) -> Result<Vec<Snapshot>, Error>
+where
+    RW: Read + Write + Seek,
+{
+    let repo = req.repository;
+
+    repo.snapshot_ids(stor)?
+        .iter()
+        .map(|id| repo.read_snapshot(stor, id))
+        .collect()
+}
@@ -0,0 +1,115 @@
+//! This removes old snapshots from a repository, along with any chunks that are no longer referenced.
+
+use std::collections::HashSet;
+use std::io::{Read, Seek, Write};
+use std::path::Path;
+
+use super::{Error, Repository, SnapshotEntry};
+use crate::storage::Storage;
+
+pub struct Request<'a> {
+    pub repository: &'a Repository,
+    // The number of recent snapshots to keep
+    pub keep: usize,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Summary {
+    pub removed_snapshots: Vec<String>,
+    pub removed_chunks: usize,
+}
+
+pub fn execute<RW>(stor: &impl Storage<RW>, req: Request<'_>) -> Result<Summary, Error>
+where
+    RW: Read + Write + Seek,
+{
+    let repo = req.repository;
+
+    let mut snapshot_ids = repo.snapshot_ids(stor)?;
+    let kept_ids = snapshot_ids.split_off(snapshot_ids.len().saturating_sub(req.keep));
+
+    // Every kept snapshot is read first, so nothing is removed if one of them is invalid
+    let mut referenced = HashSet::new();
+    for id in &kept_ids {
+        for entry in repo.read_snapshot(stor, id)?.entries {
+            if let SnapshotEntry::File { chunks, .. } = entry {
+                referenced.extend(chunks);
+            }
+        }
+    }
+
+    for id in &snapshot_ids {
+        remove_object(stor, &repo.snapshot_path(id)?)?;
+    }
+
+    let mut removed_chunks = 0;
+    for id in repo.chunk_ids(stor)? {
+        if !referenced.contains(&id) {
+            remove_object(stor, &repo.chunk_path(&id))?;
+            removed_chunks += 1;
+        }
+    }
+
+    Ok(Summary {
+        removed_snapshots: snapshot_ids,
+        removed_chunks,
+    })
+}
+
+fn remove_object<RW>(stor: &impl Storage<RW>, path: &Path) -> Result<(), Error>
+where
+    RW: Read + Write + Seek,
+{
+    let file = stor.write_file(path).map_err(Error::Storage)?;
+    stor.remove_file(file).map_err(Error::Storage)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repo::backup;
+    use crate::repo::chunker::ChunkSizes;
+    use crate::repo::tests::create_repository;
+    use crate::storage::InMemoryStorage;
+    use std::path::PathBuf;
+
+    fn backup(stor: &InMemoryStorage, repo: &Repository, path: &str) -> String {
+        backup::execute(
+            stor,
+            backup::Request {
+                repository: repo,
+                paths: Box::new(vec![Ok(PathBuf::from(path))].into_iter()),
+                chunk_sizes: ChunkSizes::default(),
+            },
+        )
+        .unwrap()
+        .snapshot_id
+    }
+
+    #[test]
+    fn should_remove_old_snapshots_and_unreferenced_chunks() {
+        let stor = InMemoryStorage::default();
+        stor.add_hello_txt();
+        stor.add_bar_foo_folder();
+        let repo = create_repository(&stor);
+
+        let old_id = backup(&stor, &repo, "bar/world.txt");
+        let new_id = backup(&stor, &repo, "hello.txt");
+
+        let req = Request {
+            repository: &repo,
+            keep: 1,
+        };
+
+        match execute(&stor, req) {
+            Ok(summary) => {
+                assert_eq!(summary.removed_snapshots, vec![old_id]);
+                assert_eq!(summary.removed_chunks, 1);
+            }
+            _ => unreachable!(),
+        }
+
+        assert_eq!(repo.snapshot_ids(&stor).unwrap(), vec![new_id]);
+        assert_eq!(repo.chunk_ids(&stor).unwrap().len(), 1);
+    }
+}
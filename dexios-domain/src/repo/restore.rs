@@ -0,0 +1,223 @@
+//! This restores a snapshot from a repository to a directory.
+//!
+//! Every chunk is verified against its ID before it's written.
+
+use std::io::{Read, Seek, Write};
+use std::path::PathBuf;
+
+use super::{Error, Repository, Snapshot, SnapshotEntry};
+use crate::storage::Storage;
+use crate::unpack::enclosed_path;
+
+/// Restores the most recent snapshot.
+pub const LATEST: &str = "latest";
+
+type OnFileFn = Box<dyn Fn(PathBuf) -> bool>;
+
+pub struct Request<'a> {
+    pub repository: &'a Repository,
+    // A snapshot ID, or `LATEST`
+    pub snapshot: &'a str,
+    pub output_dir_path: PathBuf,
+    // Returns whether the file should be restored
+    pub on_file: Option<OnFileFn>,
+}
+
+pub fn execute<RW>(stor: &impl Storage<RW>, req: Request<'_>) -> Result<Snapshot, Error>
+where
+    RW: Read + Write + Seek,
+{
+    let repo = req.repository;
+
+    let id = if req.snapshot == LATEST {
+        repo.snapshot_ids(stor)?
+            .pop()
+            .ok_or_else(|| Error::SnapshotNotFound(LATEST.to_string()))?
+    } else {
+        req.snapshot.to_string()
+    };
+    let snapshot = repo.read_snapshot(stor, &id)?;
+
+    stor.create_dir_all(&req.output_dir_path)
+        .map_err(Error::Storage)?;
+
+    for entry in &snapshot.entries {
+        let path = match entry {
+            SnapshotEntry::Dir(path) | SnapshotEntry::File { path, .. } => path,
+        };
+        // Snapshots could contain any path, so they're restricted to the output directory
+        let full_path = enclosed_path(path.clone())
+            .map(|path| req.output_dir_path.join(path))
+            .ok_or_else(|| Error::UnsupportedPath(path.clone()))?;
+
+        match entry {
+            SnapshotEntry::Dir(_) => stor.create_dir_all(&full_path).map_err(Error::Storage)?,
+            SnapshotEntry::File { chunks, .. } => {
+                if let Some(on_file) = req.on_file.as_ref() {
+                    if !on_file(full_path.clone()) {
+                        continue;
+                    }
+                }
+
+                if let Some(parent) = full_path.parent() {
+                    stor.create_dir_all(parent).map_err(Error::Storage)?;
+                }
+
+                let file = stor
+                    .create_file(&full_path)
+                    .or_else(|_| stor.write_file(&full_path))
+                    .map_err(Error::Storage)?;
+
+                for id in chunks {
+                    let data = repo.read_chunk(stor, id)?;
+                    file.try_writer()
+                        .map_err(Error::Storage)?
+                        .borrow_mut()
+                        .write_all(&data)
+                        .map_err(|_| Error::WriteData)?;
+                }
+
+                stor.flush_file(&file).map_err(Error::Storage)?;
+            }
+        }
+    }
+
+    Ok(snapshot)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repo::backup;
+    use crate::repo::chunker::ChunkSizes;
+    use crate::repo::tests::create_repository;
+    use crate::storage::{IMFile, InMemoryFile, InMemoryStorage};
+
+    fn backup(stor: &InMemoryStorage, repo: &Repository, paths: &[&str]) {
+        let paths = paths
+            .iter()
+            .map(|path| Ok(PathBuf::from(path)))
+            .collect::<Vec<_>>();
+
+        backup::execute(
+            stor,
+            backup::Request {
+                repository: repo,
+                paths: Box::new(paths.into_iter()),
+                chunk_sizes: ChunkSizes::default(),
+            },
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn should_restore_latest_snapshot() {
+        let stor = InMemoryStorage::default();
+        stor.add_bar_foo_folder();
+        let repo = create_repository(&stor);
+
+        backup(
+            &stor,
+            &repo,
+            &["bar/", "bar/hello.txt", "bar/foo/", "bar/foo/world.txt"],
+        );
+
+        let req = Request {
+            repository: &repo,
+            snapshot: LATEST,
+            output_dir_path: PathBuf::from("out"),
+            on_file: None,
+        };
+
+        match execute(&stor, req) {
+            Ok(snapshot) => assert_eq!(snapshot.entries.len(), 4),
+            _ => unreachable!(),
+        }
+
+        let files = stor.files();
+        assert_eq!(
+            files.get(&PathBuf::from("out/bar/hello.txt")),
+            Some(&IMFile::File(InMemoryFile {
+                buf: b"hello".to_vec(),
+                len: 5,
+            }))
+        );
+        assert_eq!(
+            files.get(&PathBuf::from("out/bar/foo/world.txt")),
+            Some(&IMFile::File(InMemoryFile {
+                buf: b"world".to_vec(),
+                len: 5,
+            }))
+        );
+        assert_eq!(files.get(&PathBuf::from("out/bar/foo")), Some(&IMFile::Dir));
+    }
+
+    #[test]
+    fn should_restore_absolute_and_parent_paths() {
+        let stor = InMemoryStorage::default();
+        stor.save_file("/", IMFile::Dir);
+        stor.save_file("/bar/", IMFile::Dir);
+        stor.save_text_file("/bar/hello.txt", "hello");
+        stor.save_text_file("../world.txt", "world");
+        let repo = create_repository(&stor);
+
+        backup(
+            &stor,
+            &repo,
+            &["/", "/bar/", "/bar/hello.txt", "../world.txt"],
+        );
+
+        let req = Request {
+            repository: &repo,
+            snapshot: LATEST,
+            output_dir_path: PathBuf::from("out"),
+            on_file: None,
+        };
+
+        match execute(&stor, req) {
+            Ok(snapshot) => assert_eq!(snapshot.entries.len(), 3),
+            _ => unreachable!(),
+        }
+
+        let files = stor.files();
+        assert_eq!(
+            files.get(&PathBuf::from("out/bar/hello.txt")),
+            Some(&IMFile::File(InMemoryFile {
+                buf: b"hello".to_vec(),
+                len: 5,
+            }))
+        );
+        assert_eq!(
+            files.get(&PathBuf::from("out/world.txt")),
+            Some(&IMFile::File(InMemoryFile {
+                buf: b"world".to_vec(),
+                len: 5,
+            }))
+        );
+    }
+
+    #[test]
+    fn should_reject_paths_outside_of_output_dir() {
+        let stor = InMemoryStorage::default();
+        let repo = create_repository(&stor);
+
+        let snapshot = Snapshot {
+            id: String::from("0000000001-00000000"),
+            time: 1,
+            entries: vec![SnapshotEntry::Dir(PathBuf::from("../escape"))],
+        };
+        repo.write_snapshot(&stor, &snapshot).unwrap();
+
+        let req = Request {
+            repository: &repo,
+            snapshot: &snapshot.id,
+            output_dir_path: PathBuf::from("out"),
+            on_file: None,
+        };
+
+        match execute(&stor, req) {
+            Err(Error::UnsupportedPath(path)) => assert_eq!(path, PathBuf::from("../escape")),
+            _ => unreachable!(),
+        }
+    }
+}
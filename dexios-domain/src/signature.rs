@@ -0,0 +1,511 @@
+//! This module contains Ed25519 signatures for encrypted files. The AEAD only proves that a file was made by someone who holds the key, and a shared password may be held by a whole team, so a signature proves which of them made it.
+//!
+//! The signature covers the header and the ciphertext. They're hashed with `BLAKE3` (in its derive-key mode, under `SIGNATURE_CONTEXT`), and the hash is signed, so a file of any size is read only once. A `DigestReader` hashes a file while something else reads it, so a file can be decrypted and verified from the same read.
+//!
+//! A signature may be detached (e.g. stored in a `.sig` file), or embedded within the header. An embedded signature is stored in the last keyslot of a V5 header, which must be free. Older versions skip it while they read the keyslots, so they're still able to decrypt the file. The signature's slot is hashed as zeros, as a signature can't cover itself.
+//!
+//! The last keyslot is used because a V5 header has a fixed size, with nowhere else to put it. Storing it anywhere else would need a new header version, which no released version of Dexios could decrypt. So a file with an embedded signature may only hold three keys, and a file with four keys needs a detached signature.
+//!
+//! Anything that rewrites the header (such as adding or changing a key) removes an embedded signature, and invalidates a detached one, so the file needs to be signed again.
+
+use std::cell::RefCell;
+use std::io::{self, Cursor, Read, Seek, SeekFrom};
+
+use core::header::{Header, HeaderVersion};
+use core::primitives::BLOCK_SIZE;
+
+use crate::progress::{remaining_len, Phase, Progress, ProgressReader};
+use crate::utils::{hex_decode, hex_encode};
+
+pub use ed25519_dalek::{Signature, SigningKey, VerifyingKey};
+
+pub mod sign;
+pub mod verify;
+
+/// The context string for hashing signed files, which keeps these hashes apart from any others.
+pub const SIGNATURE_CONTEXT: &str = "Dexios 2026-10-18 Ed25519 file signature";
+
+// The signature is stored in the last of the four keyslots, which are 96 bytes each
+const SLOT_START: usize = 320;
+const SLOT_END: usize = 416;
+const SLOT_IDENTIFIER: [u8; 2] = [0xD5, 0x01];
+
+#[derive(Debug)]
+pub enum Error {
+    InvalidFile,
+    Read,
+    Write,
+    Seek,
+    Unsupported,
+    NoFreeKeyslot,
+    NotSigned,
+    InvalidKey,
+    InvalidSignature,
+    BadSignature,
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::InvalidFile => f.write_str("The file does not contain a valid Dexios header"),
+            Error::Read => f.write_str("Unable to read the data"),
+            Error::Write => f.write_str("Unable to write the signature"),
+            Error::Seek => f.write_str("Unable to seek the data's cursor"),
+            Error::Unsupported => f.write_str(
+                "Signatures can only be embedded within V5 headers (use a detached signature instead)",
+            ),
+            Error::NoFreeKeyslot => f.write_str(
+                "Every keyslot is populated, so the signature can't be embedded (use a detached signature instead)",
+            ),
+            Error::NotSigned => f.write_str("The file isn't signed"),
+            Error::InvalidKey => f.write_str("The key isn't a valid Ed25519 key"),
+            Error::InvalidSignature => f.write_str("The signature isn't a valid Ed25519 signature"),
+            Error::BadSignature => {
+                f.write_str("The signature doesn't match the file or the signer's key")
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+#[must_use]
+pub fn generate_key() -> SigningKey {
+    SigningKey::generate(&mut rand::rngs::OsRng)
+}
+
+/// Keys and signatures are stored as hex, so they can be copied around easily.
+#[must_use]
+pub fn encode(bytes: &[u8]) -> String {
+    hex_encode(bytes)
+}
+
+pub fn decode_signing_key(hex: &str) -> Result<SigningKey, Error> {
+    let bytes = decode_array(hex).ok_or(Error::InvalidKey)?;
+    Ok(SigningKey::from_bytes(&bytes))
+}
+
+pub fn decode_verifying_key(hex: &str) -> Result<VerifyingKey, Error> {
+    let bytes = decode_array(hex).ok_or(Error::InvalidKey)?;
+    VerifyingKey::from_bytes(&bytes).map_err(|_| Error::InvalidKey)
+}
+
+pub fn decode_signature(hex: &str) -> Result<Signature, Error> {
+    let bytes = decode_array(hex).ok_or(Error::InvalidSignature)?;
+    Ok(Signature::from_bytes(&bytes))
+}
+
+fn decode_array<const N: usize>(hex: &str) -> Option<[u8; N]> {
+    hex_decode(hex.trim())?.try_into().ok()
+}
+
+/// Reads the signature that's embedded within the header, if there is one.
+pub fn embedded<R: Read + Seek>(reader: &RefCell<R>) -> Result<Option<Signature>, Error> {
+    let header = read_header(reader)?;
+
+    Ok(embedded_signature(&header).map(|bytes| Signature::from_bytes(&bytes)))
+}
+
+struct RawHeader {
+    bytes: Vec<u8>,
+    version: HeaderVersion,
+    keyslots: usize,
+}
+
+impl RawHeader {
+    // Whether the last keyslot may hold a signature, rather than a key
+    fn slot_free(&self) -> bool {
+        self.version >= HeaderVersion::V5 && self.keyslots < 4
+    }
+}
+
+fn read_header<R: Read + Seek>(reader: &RefCell<R>) -> Result<RawHeader, Error> {
+    let mut reader = reader.borrow_mut();
+    reader.rewind().map_err(|_| Error::Seek)?;
+
+    let (header, _) = Header::deserialize(&mut *reader).map_err(|_| Error::InvalidFile)?;
+    let len = usize::try_from(header.get_size()).map_err(|_| Error::InvalidFile)?;
+
+    let mut bytes = vec![0u8; len];
+    reader.rewind().map_err(|_| Error::Seek)?;
+    reader.read_exact(&mut bytes).map_err(|_| Error::Read)?;
+
+    Ok(RawHeader {
+        bytes,
+        version: header.header_type.version,
+        keyslots: header.keyslots.map_or(0, |keyslots| keyslots.len()),
+    })
+}
+
+// Splits the start of a file into its header and whatever follows it.
+fn parse_header(mut bytes: Vec<u8>) -> Result<(RawHeader, Vec<u8>), Error> {
+    let (header, _) =
+        Header::deserialize(&mut Cursor::new(&bytes)).map_err(|_| Error::InvalidFile)?;
+    let len = usize::try_from(header.get_size()).map_err(|_| Error::InvalidFile)?;
+    if bytes.len() < len {
+        return Err(Error::InvalidFile);
+    }
+
+    let rest = bytes.split_off(len);
+    Ok((
+        RawHeader {
+            bytes,
+            version: header.header_type.version,
+            keyslots: header.keyslots.map_or(0, |keyslots| keyslots.len()),
+        },
+        rest,
+    ))
+}
+
+fn embedded_signature(header: &RawHeader) -> Option<[u8; 64]> {
+    if !header.slot_free() || header.bytes[SLOT_START..SLOT_START + 2] != SLOT_IDENTIFIER {
+        return None;
+    }
+
+    header.bytes[SLOT_START + 2..SLOT_START + 66]
+        .try_into()
+        .ok()
+}
+
+// Hashes the header and the ciphertext, which is what gets signed. This leaves the reader at the end.
+fn digest<R: Read + Seek>(
+    reader: &RefCell<R>,
+    progress: Option<&dyn Progress>,
+) -> Result<[u8; 32], Error> {
+    let mut reader = reader.borrow_mut();
+    reader.rewind().map_err(|_| Error::Seek)?;
+    if let Some(progress) = progress {
+        progress.phase(Phase::Hashing);
+        progress.total(remaining_len(&mut *reader).map_err(|_| Error::Seek)?);
+    }
+
+    let mut digest_reader = DigestReader::new(ProgressReader::new(&mut *reader, progress));
+    digest_reader.finish()
+}
+
+/// Hashes a file while it's read by something else, such as `decrypt`, so the signature is checked against the bytes that were actually used. Reading the file a second time to verify it would let it be swapped in between.
+///
+/// The reader has to start at the beginning of the file. The start of the file (which holds the header) is kept, so it may be read again. Anything after it is only read once, in order, although it may be skipped over (in which case it's still read and hashed).
+pub struct DigestReader<R> {
+    inner: R,
+    hasher: blake3::Hasher,
+    // Every header fits within the first `SLOT_END` bytes
+    head: Vec<u8>,
+    head_hashed: bool,
+    signature: Option<[u8; 64]>,
+    // Where the next read starts, and how much has been read from `inner` (which is where it's left)
+    position: u64,
+    hashed: u64,
+}
+
+impl<R> DigestReader<R> {
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            hasher: blake3::Hasher::new_derive_key(SIGNATURE_CONTEXT),
+            head: Vec::with_capacity(SLOT_END),
+            head_hashed: false,
+            signature: None,
+            position: 0,
+            hashed: 0,
+        }
+    }
+
+    /// The signature that's embedded within the header, once the header has been read.
+    #[must_use]
+    pub fn embedded(&self) -> Option<Signature> {
+        self.signature.map(|bytes| Signature::from_bytes(&bytes))
+    }
+
+    fn update(&mut self, mut data: &[u8]) -> Result<(), Error> {
+        if !self.head_hashed {
+            let len = data.len().min(SLOT_END - self.head.len());
+            self.head.extend_from_slice(&data[..len]);
+            data = &data[len..];
+
+            if self.head.len() < SLOT_END {
+                return Ok(());
+            }
+            self.hash_head()?;
+        }
+
+        self.hasher.update(data);
+        Ok(())
+    }
+
+    // The signature's slot is hashed as zeros, so the header is only hashed once it can be parsed.
+    fn hash_head(&mut self) -> Result<(), Error> {
+        if self.head_hashed {
+            return Ok(());
+        }
+
+        let (mut header, rest) = parse_header(self.head.clone())?;
+        self.signature = embedded_signature(&header);
+        if self.signature.is_some() {
+            header.bytes[SLOT_START..SLOT_END].fill(0);
+        }
+
+        self.hasher.update(&header.bytes);
+        self.hasher.update(&rest);
+        self.head_hashed = true;
+        Ok(())
+    }
+}
+
+impl<R: Read> DigestReader<R> {
+    // Reads and hashes the next bytes from `inner`.
+    fn read_inner(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read_count = self.inner.read(buf)?;
+        self.update(&buf[..read_count]).map_err(io::Error::other)?;
+        self.hashed += read_count as u64;
+        Ok(read_count)
+    }
+
+    /// Reads whatever is left of the file, and returns its digest.
+    pub(crate) fn finish(&mut self) -> Result<[u8; 32], Error> {
+        self.position = self.position.max(self.hashed);
+        io::copy(self, &mut io::sink()).map_err(|_| Error::Read)?;
+        self.hash_head()?;
+
+        Ok(*self.hasher.finalize().as_bytes())
+    }
+}
+
+impl<R: Read> Read for DigestReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        // The start of the file is read again from what was kept
+        if let Some(head) = usize::try_from(self.position)
+            .ok()
+            .and_then(|position| self.head.get(position..))
+            .filter(|head| !head.is_empty())
+        {
+            let read_count = head.len().min(buf.len());
+            buf[..read_count].copy_from_slice(&head[..read_count]);
+            self.position += read_count as u64;
+            return Ok(read_count);
+        }
+
+        if self.position < self.hashed {
+            return Err(io::Error::other(Error::Read));
+        }
+
+        // Anything that was skipped over is still hashed
+        let mut skipped = vec![0u8; BLOCK_SIZE];
+        while self.hashed < self.position {
+            let len = usize::try_from(self.position - self.hashed)
+                .unwrap_or(usize::MAX)
+                .min(skipped.len());
+            if self.read_inner(&mut skipped[..len])? == 0 {
+                return Ok(0);
+            }
+        }
+
+        let read_count = self.read_inner(buf)?;
+        self.position += read_count as u64;
+        Ok(read_count)
+    }
+}
+
+impl<R: Seek> Seek for DigestReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let position = match pos {
+            SeekFrom::Start(position) => Some(position),
+            SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+            SeekFrom::End(_) => {
+                // `inner` is left where it was, as it's only read in order
+                let position = self.inner.seek(pos)?;
+                self.inner.seek(SeekFrom::Start(self.hashed))?;
+                Some(position)
+            }
+        };
+
+        self.position = position.ok_or_else(|| io::Error::from(io::ErrorKind::InvalidInput))?;
+        Ok(self.position)
+    }
+}
+
+fn seek_to_slot<R: Seek>(reader: &mut R) -> Result<(), Error> {
+    reader
+        .seek(SeekFrom::Start(SLOT_START as u64))
+        .map(|_| ())
+        .map_err(|_| Error::Seek)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encrypt::tests::{PASSWORD, V4_ENCRYPTED_CONTENT, V5_ENCRYPTED_CONTENT};
+    use core::protected::Protected;
+    use std::io::Cursor;
+
+    fn sign(content: &mut Vec<u8>, key: &SigningKey, embed: bool) -> Result<Signature, Error> {
+        sign::execute(sign::Request {
+            handle: &RefCell::new(Cursor::new(content)),
+            key,
+            embed,
+            progress: None,
+        })
+    }
+
+    fn verify(
+        content: &[u8],
+        key: &VerifyingKey,
+        signature: Option<Signature>,
+    ) -> Result<(), Error> {
+        verify::execute(verify::Request {
+            reader: &RefCell::new(Cursor::new(content)),
+            key,
+            signature,
+            progress: None,
+        })
+    }
+
+    #[test]
+    fn should_encode_and_decode_keys() {
+        let key = generate_key();
+
+        let decoded = decode_signing_key(&encode(&key.to_bytes())).unwrap();
+        assert_eq!(decoded.to_bytes(), key.to_bytes());
+
+        let public = decode_verifying_key(&encode(key.verifying_key().as_bytes())).unwrap();
+        assert_eq!(public, key.verifying_key());
+
+        assert!(matches!(
+            decode_verifying_key("abcdef"),
+            Err(Error::InvalidKey)
+        ));
+        assert!(matches!(
+            decode_signature("not hex"),
+            Err(Error::InvalidSignature)
+        ));
+    }
+
+    #[test]
+    fn should_embed_signature_and_still_decrypt() {
+        let key = generate_key();
+        let mut content = V5_ENCRYPTED_CONTENT.to_vec();
+
+        let signature = sign(&mut content, &key, true).unwrap();
+        assert_eq!(
+            embedded(&RefCell::new(Cursor::new(&content))).unwrap(),
+            Some(signature)
+        );
+        assert!(verify(&content, &key.verifying_key(), None).is_ok());
+
+        let mut output = vec![];
+        crate::decrypt::execute(crate::decrypt::Request {
+            header_reader: None,
+            reader: &RefCell::new(Cursor::new(&content)),
+            writer: &RefCell::new(Cursor::new(&mut output)),
+            raw_key: Protected::new(PASSWORD.to_vec()),
+            on_decrypted_header: None,
+            progress: None,
+            cancel: None,
+        })
+        .unwrap();
+        assert_eq!(output, b"Hello world".to_vec());
+    }
+
+    #[test]
+    fn should_verify_the_bytes_that_were_decrypted() {
+        let key = generate_key();
+        let mut content = V5_ENCRYPTED_CONTENT.to_vec();
+        sign(&mut content, &key, true).unwrap();
+
+        let decrypt = |content: &[u8], key: &VerifyingKey| {
+            let reader = RefCell::new(DigestReader::new(Cursor::new(content)));
+            let mut output = vec![];
+            crate::decrypt::execute(crate::decrypt::Request {
+                header_reader: None,
+                reader: &reader,
+                writer: &RefCell::new(Cursor::new(&mut output)),
+                raw_key: Protected::new(PASSWORD.to_vec()),
+                on_decrypted_header: None,
+                progress: None,
+                cancel: None,
+            })
+            .unwrap();
+            assert_eq!(output, b"Hello world".to_vec());
+
+            let reader = reader.into_inner();
+            assert!(reader.embedded().is_some());
+            verify::finish(reader, key, None)
+        };
+
+        assert!(decrypt(&content, &key.verifying_key()).is_ok());
+        assert!(matches!(
+            decrypt(&content, &generate_key().verifying_key()),
+            Err(Error::BadSignature)
+        ));
+    }
+
+    #[test]
+    fn should_only_read_files_once_while_digesting() {
+        let content = V5_ENCRYPTED_CONTENT.to_vec();
+        let mut reader = DigestReader::new(Cursor::new(&content));
+
+        // The header may be read again, and skipped bytes are still hashed
+        let mut buf = [0u8; 4];
+        reader.seek(SeekFrom::Start(SLOT_END as u64 + 4)).unwrap();
+        reader.read_exact(&mut buf).unwrap();
+        reader.seek(SeekFrom::Start(2)).unwrap();
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, content[2..6]);
+        assert_eq!(
+            reader.finish().unwrap(),
+            digest(&RefCell::new(Cursor::new(&content)), None).unwrap()
+        );
+
+        let mut reader = DigestReader::new(Cursor::new(&content));
+        reader.seek(SeekFrom::Start(SLOT_END as u64 + 4)).unwrap();
+        reader.read_exact(&mut buf).unwrap();
+        reader.seek(SeekFrom::Start(SLOT_END as u64)).unwrap();
+        assert!(reader.read_exact(&mut buf).is_err());
+    }
+
+    #[test]
+    fn should_verify_detached_signature_of_any_header_version() {
+        let key = generate_key();
+
+        for content in [V4_ENCRYPTED_CONTENT.to_vec(), V5_ENCRYPTED_CONTENT.to_vec()] {
+            let mut signed = content.clone();
+            let signature = sign(&mut signed, &key, false).unwrap();
+            assert_eq!(signed, content);
+
+            assert!(verify(&content, &key.verifying_key(), Some(signature)).is_ok());
+            assert!(matches!(
+                verify(&content, &key.verifying_key(), None),
+                Err(Error::NotSigned)
+            ));
+        }
+    }
+
+    #[test]
+    fn should_detect_tampering_and_other_signers() {
+        let key = generate_key();
+        let mut content = V5_ENCRYPTED_CONTENT.to_vec();
+        sign(&mut content, &key, true).unwrap();
+
+        assert!(matches!(
+            verify(&content, &generate_key().verifying_key(), None),
+            Err(Error::BadSignature)
+        ));
+
+        let last = content.len() - 1;
+        content[last] ^= 1;
+        assert!(matches!(
+            verify(&content, &key.verifying_key(), None),
+            Err(Error::BadSignature)
+        ));
+    }
+
+    #[test]
+    fn should_only_embed_within_v5_headers() {
+        let mut content = V4_ENCRYPTED_CONTENT.to_vec();
+
+        assert!(matches!(
+            sign(&mut content, &generate_key(), true),
+            Err(Error::Unsupported)
+        ));
+    }
+}
@@ -0,0 +1,53 @@
+//! This provides functionality for signing an encrypted file, and embedding the signature within its header if requested.
+
+use std::cell::RefCell;
+use std::io::{Read, Seek, Write};
+use std::sync::Arc;
+
+use core::header::HeaderVersion;
+use ed25519_dalek::Signer;
+
+use super::{Error, Signature, SigningKey, SLOT_END, SLOT_IDENTIFIER, SLOT_START};
+use crate::progress::Progress;
+
+pub struct Request<'a, RW>
+where
+    RW: Read + Write + Seek,
+{
+    pub handle: &'a RefCell<RW>,
+    pub key: &'a SigningKey,
+    // Stores the signature within the header, rather than only returning it
+    pub embed: bool,
+    pub progress: Option<Arc<dyn Progress>>,
+}
+
+pub fn execute<RW>(req: Request<'_, RW>) -> Result<Signature, Error>
+where
+    RW: Read + Write + Seek,
+{
+    if req.embed {
+        let header = super::read_header(req.handle)?;
+        if header.version < HeaderVersion::V5 {
+            return Err(Error::Unsupported);
+        }
+        if !header.slot_free() {
+            return Err(Error::NoFreeKeyslot);
+        }
+    }
+
+    let digest = super::digest(req.handle, req.progress.as_deref())?;
+    let signature = req.key.sign(&digest);
+
+    if req.embed {
+        let mut slot = [0u8; SLOT_END - SLOT_START];
+        slot[..2].copy_from_slice(&SLOT_IDENTIFIER);
+        slot[2..66].copy_from_slice(&signature.to_bytes());
+
+        let mut handle = req.handle.borrow_mut();
+        super::seek_to_slot(&mut *handle)?;
+        handle.write_all(&slot).map_err(|_| Error::Write)?;
+        handle.flush().map_err(|_| Error::Write)?;
+    }
+
+    Ok(signature)
+}
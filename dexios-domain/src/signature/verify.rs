@@ -0,0 +1,55 @@
+//! This provides functionality for verifying the signature of an encrypted file, whether it's detached or embedded within the header.
+
+use std::cell::RefCell;
+use std::io::{Read, Seek};
+use std::sync::Arc;
+
+use super::{DigestReader, Error, Signature, VerifyingKey};
+use crate::progress::Progress;
+
+pub struct Request<'a, R>
+where
+    R: Read + Seek,
+{
+    pub reader: &'a RefCell<R>,
+    pub key: &'a VerifyingKey,
+    // A detached signature, otherwise the one that's embedded within the header is used
+    pub signature: Option<Signature>,
+    pub progress: Option<Arc<dyn Progress>>,
+}
+
+pub fn execute<R>(req: Request<'_, R>) -> Result<(), Error>
+where
+    R: Read + Seek,
+{
+    let signature = match req.signature {
+        Some(signature) => signature,
+        None => super::embedded(req.reader)?.ok_or(Error::NotSigned)?,
+    };
+
+    let digest = super::digest(req.reader, req.progress.as_deref())?;
+
+    req.key
+        .verify_strict(&digest, &signature)
+        .map_err(|_| Error::BadSignature)
+}
+
+/// Verifies a file that was read through a `DigestReader` (e.g. while it was decrypted), and reads whatever was left of it first.
+pub fn finish<R>(
+    mut reader: DigestReader<R>,
+    key: &VerifyingKey,
+    // A detached signature, otherwise the one that's embedded within the header is used
+    signature: Option<Signature>,
+) -> Result<(), Error>
+where
+    R: Read,
+{
+    let digest = reader.finish()?;
+
+    let signature = signature
+        .or_else(|| reader.embedded())
+        .ok_or(Error::NotSigned)?;
+
+    key.verify_strict(&digest, &signature)
+        .map_err(|_| Error::BadSignature)
+}
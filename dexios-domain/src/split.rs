@@ -0,0 +1,613 @@
+//! This provides a writer and a reader for splitting an encrypted file into multiple volumes of a fixed size.
+//!
+//! Every volume starts with a small header that contains an ID shared by all volumes of the set, the volume's index and whether it's the last volume.
+//! The reader checks every volume before anything is read, so missing, reordered or foreign volumes are detected before decryption starts.
+//!
+//! The volumes are opened with a callback, so the caller decides how they are named and stored.
+
+use rand::RngCore;
+use std::cell::RefCell;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+pub const VOLUME_MAGIC: [u8; 4] = *b"DXVL";
+pub const VOLUME_HEADER_LEN: usize = 25;
+// For arithmetic with file offsets
+const HEADER_LEN: u64 = VOLUME_HEADER_LEN as u64;
+
+const SET_ID_LEN: usize = 16;
+const LAST_VOLUME_FLAG: u8 = 1;
+
+#[derive(Debug)]
+pub enum Error {
+    VolumeSize,
+    OpenVolume(u32),
+    EraseVolume(u32),
+    MissingVolume(u32),
+    InvalidVolume(u32),
+    ForeignVolume(u32),
+    VolumeOrder { expected: u32, found: u32 },
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::VolumeSize => write!(
+                f,
+                "The volume size must be larger than the volume header ({VOLUME_HEADER_LEN} bytes)"
+            ),
+            Error::OpenVolume(index) => write!(f, "Unable to open volume {index}"),
+            Error::EraseVolume(index) => write!(f, "Unable to erase volume {index}"),
+            Error::MissingVolume(index) => write!(f, "Volume {index} is missing"),
+            Error::InvalidVolume(index) => write!(f, "Volume {index} is not a valid volume"),
+            Error::ForeignVolume(index) => {
+                write!(f, "Volume {index} belongs to a different set of volumes")
+            }
+            Error::VolumeOrder { expected, found } => {
+                write!(f, "Expected volume {expected}, but found volume {found}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Opens the volume with the given index. Indices start at 1.
+pub type OpenVolumeFn<T> = Box<dyn FnMut(u32) -> io::Result<T>>;
+
+struct VolumeHeader {
+    set_id: [u8; SET_ID_LEN],
+    index: u32,
+    is_last: bool,
+}
+
+impl VolumeHeader {
+    fn serialize(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(VOLUME_HEADER_LEN);
+        bytes.extend_from_slice(&VOLUME_MAGIC);
+        bytes.extend_from_slice(&self.set_id);
+        bytes.extend_from_slice(&self.index.to_le_bytes());
+        bytes.push(if self.is_last { LAST_VOLUME_FLAG } else { 0 });
+        bytes
+    }
+
+    fn deserialize(reader: &mut impl Read) -> Option<Self> {
+        let mut bytes = [0u8; VOLUME_HEADER_LEN];
+        reader.read_exact(&mut bytes).ok()?;
+
+        if bytes[..4] != VOLUME_MAGIC {
+            return None;
+        }
+
+        let mut set_id = [0u8; SET_ID_LEN];
+        set_id.copy_from_slice(&bytes[4..20]);
+
+        Some(Self {
+            set_id,
+            index: u32::from_le_bytes([bytes[20], bytes[21], bytes[22], bytes[23]]),
+            is_last: bytes[24] == LAST_VOLUME_FLAG,
+        })
+    }
+}
+
+/// Checks whether the reader starts with a volume header.
+///
+/// The reader's position is left after the bytes that were read.
+pub fn is_volume(reader: &mut impl Read) -> bool {
+    let mut magic = [0u8; 4];
+    reader.read_exact(&mut magic).is_ok() && magic == VOLUME_MAGIC
+}
+
+/// A writer that starts a new volume whenever the current one is full.
+///
+/// [`SplitWriter::finish`] must be called once everything has been written, as it marks the last volume.
+pub struct SplitWriter<W: Write + Seek> {
+    open_volume: OpenVolumeFn<W>,
+    set_id: [u8; SET_ID_LEN],
+    payload_size: u64,
+    volume: Option<W>,
+    index: u32,
+    volume_written: u64,
+    position: u64,
+}
+
+impl<W: Write + Seek> SplitWriter<W> {
+    /// `volume_size` is the maximum size of every volume, including its header.
+    pub fn new(volume_size: u64, open_volume: OpenVolumeFn<W>) -> Result<Self, Error> {
+        if volume_size <= HEADER_LEN {
+            return Err(Error::VolumeSize);
+        }
+
+        let mut set_id = [0u8; SET_ID_LEN];
+        rand::thread_rng().fill_bytes(&mut set_id);
+
+        Ok(Self {
+            open_volume,
+            set_id,
+            payload_size: volume_size - HEADER_LEN,
+            volume: None,
+            index: 0,
+            volume_written: 0,
+            position: 0,
+        })
+    }
+
+    fn next_volume(&mut self) -> io::Result<()> {
+        if let Some(mut volume) = self.volume.take() {
+            volume.flush()?;
+        }
+
+        self.index += 1;
+        let mut volume = (self.open_volume)(self.index)?;
+
+        let header = VolumeHeader {
+            set_id: self.set_id,
+            index: self.index,
+            is_last: false,
+        };
+        volume.write_all(&header.serialize())?;
+
+        self.volume = Some(volume);
+        self.volume_written = 0;
+
+        Ok(())
+    }
+
+    /// Marks the current volume as the last one, and flushes it.
+    ///
+    /// Returns the number of volumes that were written.
+    pub fn finish(&mut self) -> io::Result<u32> {
+        if self.volume.is_none() {
+            // Even empty content needs one volume
+            self.next_volume()?;
+        }
+
+        let volume = self.volume.as_mut().expect("We've just opened a volume");
+        volume.seek(SeekFrom::Start(HEADER_LEN - 1))?;
+        volume.write_all(&[LAST_VOLUME_FLAG])?;
+        volume.seek(SeekFrom::End(0))?;
+        volume.flush()?;
+
+        Ok(self.index)
+    }
+
+    /// The number of volumes that have been opened so far.
+    #[must_use]
+    pub fn volumes(&self) -> u32 {
+        self.index
+    }
+}
+
+/// Overwrites every volume with zeros, for when writing was cancelled.
+///
+/// The writer only keeps the last volume open, so each volume is opened again with `open_volume` (which mustn't truncate it). The volumes still need to be removed afterwards.
+pub fn erase_volumes<W: Write + Seek>(
+    volumes: u32,
+    mut open_volume: OpenVolumeFn<W>,
+) -> Result<(), Error> {
+    for index in 1..=volumes {
+        let mut volume = open_volume(index).map_err(|_| Error::OpenVolume(index))?;
+        let len = volume
+            .seek(SeekFrom::End(0))
+            .ok()
+            .and_then(|len| usize::try_from(len).ok())
+            .ok_or(Error::EraseVolume(index))?;
+
+        crate::overwrite::execute(crate::overwrite::Request {
+            writer: &RefCell::new(volume),
+            buf_capacity: len,
+            scheme: crate::overwrite::Scheme::Zeros,
+            sync: None,
+            progress: None,
+            cancel: None,
+        })
+        .map_err(|_| Error::EraseVolume(index))?;
+    }
+
+    Ok(())
+}
+
+impl<W: Write + Seek> Write for SplitWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        if self.volume.is_none() || self.volume_written == self.payload_size {
+            self.next_volume()?;
+        }
+
+        let remaining = usize::try_from(self.payload_size - self.volume_written)
+            .unwrap_or(usize::MAX)
+            .min(buf.len());
+
+        let volume = self.volume.as_mut().expect("We've just opened a volume");
+        volume.write_all(&buf[..remaining])?;
+
+        self.volume_written += remaining as u64;
+        self.position += remaining as u64;
+
+        Ok(remaining)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self.volume.as_mut() {
+            Some(volume) => volume.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+// Volumes are written sequentially, so we can only "seek" to the current position.
+impl<W: Write + Seek> Seek for SplitWriter<W> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let target = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            // The end is always the current position
+            SeekFrom::Current(offset) | SeekFrom::End(offset) => {
+                self.position.checked_add_signed(offset)
+            }
+        };
+
+        match target {
+            Some(target) if target == self.position => Ok(self.position),
+            _ => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "Split volumes can only be written sequentially",
+            )),
+        }
+    }
+}
+
+/// A reader that reads all volumes in sequence, as if they were a single file.
+pub struct SplitReader<R: Read + Seek> {
+    open_volume: OpenVolumeFn<R>,
+    // The payload length of every volume
+    lengths: Vec<u64>,
+    volume: R,
+    index: usize,
+    offset: u64,
+}
+
+impl<R: Read + Seek> SplitReader<R> {
+    /// Opens and checks every volume of the set, starting with the first one.
+    pub fn new(mut open_volume: OpenVolumeFn<R>) -> Result<Self, Error> {
+        let mut set_id = None;
+        let mut lengths = vec![];
+
+        for index in 1.. {
+            let mut volume = open_volume(index).map_err(|_| {
+                if index == 1 {
+                    Error::OpenVolume(index)
+                } else {
+                    Error::MissingVolume(index)
+                }
+            })?;
+
+            let header =
+                VolumeHeader::deserialize(&mut volume).ok_or(Error::InvalidVolume(index))?;
+
+            if *set_id.get_or_insert(header.set_id) != header.set_id {
+                return Err(Error::ForeignVolume(index));
+            }
+
+            if header.index != index {
+                return Err(Error::VolumeOrder {
+                    expected: index,
+                    found: header.index,
+                });
+            }
+
+            let len = volume
+                .seek(SeekFrom::End(0))
+                .map_err(|_| Error::InvalidVolume(index))?;
+            lengths.push(len - HEADER_LEN);
+
+            if header.is_last {
+                break;
+            }
+        }
+
+        let mut volume = open_volume(1).map_err(|_| Error::OpenVolume(1))?;
+        volume
+            .seek(SeekFrom::Start(HEADER_LEN))
+            .map_err(|_| Error::InvalidVolume(1))?;
+
+        Ok(Self {
+            open_volume,
+            lengths,
+            volume,
+            index: 0,
+            offset: 0,
+        })
+    }
+
+    /// The number of volumes within the set.
+    #[must_use]
+    pub fn volumes(&self) -> usize {
+        self.lengths.len()
+    }
+
+    fn position(&self) -> u64 {
+        self.lengths[..self.index].iter().sum::<u64>() + self.offset
+    }
+
+    fn open(&mut self, index: usize, offset: u64) -> io::Result<()> {
+        if index != self.index {
+            #[allow(clippy::cast_possible_truncation)]
+            let volume_index = index as u32 + 1;
+            self.volume = (self.open_volume)(volume_index)?;
+            self.index = index;
+        }
+
+        self.volume.seek(SeekFrom::Start(HEADER_LEN + offset))?;
+        self.offset = offset;
+
+        Ok(())
+    }
+}
+
+// This fills the buffer across volume boundaries, as short reads are treated as the end of a stream.
+impl<R: Read + Seek> Read for SplitReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut read = 0;
+
+        while read < buf.len() {
+            let remaining = self.lengths[self.index] - self.offset;
+
+            if remaining == 0 {
+                if self.index + 1 == self.lengths.len() {
+                    break;
+                }
+
+                self.open(self.index + 1, 0)?;
+                continue;
+            }
+
+            let len = usize::try_from(remaining)
+                .unwrap_or(usize::MAX)
+                .min(buf.len() - read);
+
+            let read_count = self.volume.read(&mut buf[read..read + len])?;
+            if read_count == 0 {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "The volume is shorter than expected",
+                ));
+            }
+
+            read += read_count;
+            self.offset += read_count as u64;
+        }
+
+        Ok(read)
+    }
+}
+
+impl<R: Read + Seek> Seek for SplitReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let total: u64 = self.lengths.iter().sum();
+
+        let target = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::Current(offset) => self.position().checked_add_signed(offset),
+            SeekFrom::End(offset) => total.checked_add_signed(offset),
+        }
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Invalid seek position"))?
+        .min(total);
+
+        let mut start = 0;
+        for (index, len) in self.lengths.iter().copied().enumerate() {
+            if target < start + len || index + 1 == self.lengths.len() {
+                self.open(index, target - start)?;
+                break;
+            }
+
+            start += len;
+        }
+
+        Ok(target)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::io::Cursor;
+    use std::rc::Rc;
+
+    // Volumes are stored in memory, so the test can inspect and reorder them
+    type Volumes = Rc<RefCell<Vec<Vec<u8>>>>;
+
+    struct VolumeCursor {
+        volumes: Volumes,
+        index: usize,
+        cursor: Cursor<Vec<u8>>,
+    }
+
+    impl Read for VolumeCursor {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.cursor.read(buf)
+        }
+    }
+
+    impl Write for VolumeCursor {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.cursor.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            self.volumes.borrow_mut()[self.index] = self.cursor.get_ref().clone();
+            Ok(())
+        }
+    }
+
+    impl Seek for VolumeCursor {
+        fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+            self.cursor.seek(pos)
+        }
+    }
+
+    fn write_volumes(content: &[u8], volume_size: u64) -> Volumes {
+        let volumes = Volumes::default();
+
+        let writer_volumes = volumes.clone();
+        let mut writer = SplitWriter::new(
+            volume_size,
+            Box::new(move |index| {
+                writer_volumes.borrow_mut().push(vec![]);
+                Ok(VolumeCursor {
+                    volumes: writer_volumes.clone(),
+                    index: index as usize - 1,
+                    cursor: Cursor::new(vec![]),
+                })
+            }),
+        )
+        .unwrap();
+
+        writer.write_all(content).unwrap();
+        writer.finish().unwrap();
+
+        volumes
+    }
+
+    fn open_volumes(volumes: &Volumes) -> Result<SplitReader<VolumeCursor>, Error> {
+        SplitReader::new(reopen(volumes))
+    }
+
+    // Opens the volumes that were already written, without truncating them
+    fn reopen(volumes: &Volumes) -> OpenVolumeFn<VolumeCursor> {
+        let volumes = volumes.clone();
+        Box::new(move |index| {
+            let index = index as usize - 1;
+            let content = volumes
+                .borrow()
+                .get(index)
+                .cloned()
+                .ok_or_else(|| io::Error::from(io::ErrorKind::NotFound))?;
+            Ok(VolumeCursor {
+                volumes: volumes.clone(),
+                index,
+                cursor: Cursor::new(content),
+            })
+        })
+    }
+
+    #[test]
+    fn should_split_content_into_volumes() {
+        let content = (0..100).collect::<Vec<u8>>();
+        let volumes = write_volumes(&content, HEADER_LEN + 30);
+
+        let volumes = volumes.borrow();
+        assert_eq!(volumes.len(), 4);
+        assert_eq!(volumes[0].len(), 25 + 30);
+        assert_eq!(volumes[3].len(), 25 + 10);
+        assert_eq!(volumes[2][24], 0);
+        assert_eq!(volumes[3][24], LAST_VOLUME_FLAG);
+    }
+
+    #[test]
+    fn should_erase_every_volume_that_was_written() {
+        let volumes = Volumes::default();
+
+        let writer_volumes = volumes.clone();
+        let mut writer = SplitWriter::new(
+            HEADER_LEN + 30,
+            Box::new(move |index| {
+                writer_volumes.borrow_mut().push(vec![]);
+                Ok(VolumeCursor {
+                    volumes: writer_volumes.clone(),
+                    index: index as usize - 1,
+                    cursor: Cursor::new(vec![]),
+                })
+            }),
+        )
+        .unwrap();
+
+        // The writer is dropped without being finished, as it is when writing is cancelled
+        writer.write_all(&[1u8; 100]).unwrap();
+        writer.flush().unwrap();
+        assert_eq!(writer.volumes(), 4);
+        drop(writer);
+
+        erase_volumes(4, reopen(&volumes)).unwrap();
+
+        let volumes = volumes.borrow();
+        assert_eq!(
+            volumes.iter().map(Vec::len).collect::<Vec<_>>(),
+            vec![25 + 30, 25 + 30, 25 + 30, 25 + 10]
+        );
+        assert!(volumes.iter().flatten().all(|byte| *byte == 0));
+    }
+
+    #[test]
+    fn should_read_volumes_in_sequence() {
+        let content = (0..100).collect::<Vec<u8>>();
+        let volumes = write_volumes(&content, HEADER_LEN + 30);
+
+        let mut reader = open_volumes(&volumes).unwrap();
+        let mut output = vec![];
+        reader.read_to_end(&mut output).unwrap();
+
+        assert_eq!(reader.volumes(), 4);
+        assert_eq!(output, content);
+    }
+
+    #[test]
+    fn should_seek_across_volumes() {
+        let content = (0..100).collect::<Vec<u8>>();
+        let volumes = write_volumes(&content, HEADER_LEN + 30);
+
+        let mut reader = open_volumes(&volumes).unwrap();
+        let mut buf = [0u8; 10];
+
+        reader.seek(SeekFrom::Start(55)).unwrap();
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(buf.to_vec(), (55..65).collect::<Vec<u8>>());
+
+        reader.seek(SeekFrom::Current(-40)).unwrap();
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(buf.to_vec(), (25..35).collect::<Vec<u8>>());
+
+        reader.rewind().unwrap();
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(buf.to_vec(), (0..10).collect::<Vec<u8>>());
+    }
+
+    #[test]
+    fn should_detect_missing_volume() {
+        let volumes = write_volumes(&[1u8; 100], HEADER_LEN + 30);
+        volumes.borrow_mut().truncate(3);
+
+        match open_volumes(&volumes) {
+            Err(Error::MissingVolume(4)) => {}
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn should_detect_reordered_volumes() {
+        let volumes = write_volumes(&[1u8; 100], HEADER_LEN + 30);
+        volumes.borrow_mut().swap(1, 2);
+
+        match open_volumes(&volumes) {
+            Err(Error::VolumeOrder {
+                expected: 2,
+                found: 3,
+            }) => {}
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn should_detect_foreign_volume() {
+        let volumes = write_volumes(&[1u8; 100], HEADER_LEN + 30);
+        let other_volumes = write_volumes(&[2u8; 100], HEADER_LEN + 30);
+        volumes.borrow_mut()[1] = other_volumes.borrow()[1].clone();
+
+        match open_volumes(&volumes) {
+            Err(Error::ForeignVolume(2)) => {}
+            _ => unreachable!(),
+        }
+    }
+}
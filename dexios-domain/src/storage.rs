@@ -1,18 +1,25 @@
 use rand::distributions::{Alphanumeric, DistString};
 use std::cell::RefCell;
 use std::fs;
-use std::io::{Read, Seek, Write};
+use std::io::{self, Read, Seek, Write};
 use std::path::{Path, PathBuf};
+use std::time::SystemTime;
 
-#[cfg(test)]
+#[cfg(any(test, feature = "testing"))]
 use std::collections::HashMap;
-#[cfg(test)]
-use std::io;
-#[cfg(test)]
+#[cfg(any(test, feature = "testing"))]
 use std::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
-#[cfg(test)]
+#[cfg(any(test, feature = "testing"))]
 use std::thread;
 
+mod dynamic;
+#[cfg(feature = "s3")]
+pub mod s3;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+
+pub use dynamic::{BoxedStream, DynStorage, Erased, Stream};
+
 #[derive(Debug)]
 pub enum FileMode {
     Read,
@@ -30,6 +37,12 @@ pub enum Error {
     FlushFile,
     FileAccess,
     FileLen,
+    FileModified,
+    RenameFile,
+    AlreadyExists,
+    NotRegularFile,
+    TruncateFile,
+    FileTimes,
 }
 
 impl std::fmt::Display for Error {
@@ -44,12 +57,42 @@ impl std::fmt::Display for Error {
             Error::DirEntries => f.write_str("Unable to read directory"),
             Error::FileAccess => f.write_str("Permission denied"),
             Error::FileLen => f.write_str("Unable to get file length"),
+            Error::FileModified => f.write_str("Unable to get file modification time"),
+            Error::RenameFile => f.write_str("Unable to rename the file"),
+            Error::AlreadyExists => f.write_str("A file already exists at the new path"),
+            Error::NotRegularFile => f.write_str("The path isn't a regular file"),
+            Error::TruncateFile => f.write_str("Unable to truncate the file"),
+            Error::FileTimes => f.write_str("Unable to set the file's timestamps"),
         }
     }
 }
 
 impl std::error::Error for Error {}
 
+/// A lazy iterator over the paths within a directory.
+pub type Paths<'a> = Box<dyn Iterator<Item = Result<PathBuf, Error>> + 'a>;
+
+/// What an entry of a directory tree is, without following symlinks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntryKind {
+    File,
+    Dir,
+    Symlink,
+    /// A directory on another filesystem, which isn't descended into
+    Mount,
+    /// Anything else, such as a device, a socket or a named pipe
+    Other,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TreeEntry {
+    pub path: PathBuf,
+    pub kind: EntryKind,
+}
+
+/// A lazy iterator over the entries of a directory tree.
+pub type TreeEntries<'a> = Box<dyn Iterator<Item = Result<TreeEntry, Error>> + 'a>;
+
 pub trait Storage<RW>: Send + Sync
 where
     RW: Read + Write + Seek,
@@ -67,16 +110,69 @@ where
     fn create_file<P: AsRef<Path>>(&self, path: P) -> Result<Entry<RW>, Error>;
     fn read_file<P: AsRef<Path>>(&self, path: P) -> Result<Entry<RW>, Error>;
     fn write_file<P: AsRef<Path>>(&self, path: P) -> Result<Entry<RW>, Error>;
+    // Unlike `write_file`, this keeps the file's content, so it can be overwritten in place.
+    fn modify_file<P: AsRef<Path>>(&self, path: P) -> Result<Entry<RW>, Error>;
+    // Like `modify_file`, but anything other than a regular file (such as a symlink) is refused rather than followed.
+    // Backends that can't tell them apart keep the default, so nothing is erased through them.
+    fn modify_regular_file<P: AsRef<Path>>(&self, _path: P) -> Result<Entry<RW>, Error> {
+        Err(Error::OpenFile(FileMode::Write))
+    }
     fn flush_file(&self, file: &Entry<RW>) -> Result<(), Error>;
+    // Makes sure that everything written so far has reached the disk, not just the OS.
+    fn sync_file(&self, file: &Entry<RW>) -> Result<(), Error> {
+        self.flush_file(file)
+    }
     fn file_len(&self, file: &Entry<RW>) -> Result<usize, Error>;
+    fn file_modified(&self, file: &Entry<RW>) -> Result<SystemTime, Error>;
     fn remove_file(&self, file: Entry<RW>) -> Result<(), Error>;
+    // The file stays open, and the returned entry points to its new path.
+    // Anything that already exists at the new path is never replaced.
+    // Scrubbing needs this and the next two methods, and backends that can't support them keep these defaults.
+    fn rename_file<P: AsRef<Path>>(&self, _file: Entry<RW>, _to: P) -> Result<Entry<RW>, Error> {
+        Err(Error::RenameFile)
+    }
+    fn truncate_file(&self, _file: &Entry<RW>) -> Result<(), Error> {
+        Err(Error::TruncateFile)
+    }
+    // Sets the access and modification times to the Unix epoch.
+    fn reset_file_times(&self, _file: &Entry<RW>) -> Result<(), Error> {
+        Err(Error::FileTimes)
+    }
     fn remove_dir_all(&self, file: Entry<RW>) -> Result<(), Error>;
     // TODO(pleshevskiy): return iterator instead of Vector
     fn read_dir(&self, file: &Entry<RW>) -> Result<Vec<Entry<RW>>, Error>;
+    // Unlike `read_dir`, this doesn't open anything, so it's suitable for large trees.
+    fn walk_dir(&self, file: &Entry<RW>) -> Result<Paths<'_>, Error>;
+    // Unlike `walk_dir`, symlinks (including the directory itself) are reported rather than followed.
+    // Directories on other filesystems are reported as mounts and skipped, unless `cross_filesystems` is set.
+    // Backends that can't tell symlinks apart keep the default, so directories can't be erased through them.
+    fn walk_tree(
+        &self,
+        _file: &Entry<RW>,
+        _cross_filesystems: bool,
+    ) -> Result<TreeEntries<'_>, Error> {
+        Err(Error::DirEntries)
+    }
+    // Removes a symlink, without touching what it points to.
+    fn remove_link<P: AsRef<Path>>(&self, _path: P) -> Result<(), Error> {
+        Err(Error::RemoveFile)
+    }
 }
 
 pub struct FileStorage;
 
+#[cfg(unix)]
+fn same_file(a: &fs::Metadata, b: &fs::Metadata) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    a.dev() == b.dev() && a.ino() == b.ino()
+}
+
+// Other platforms don't expose a file's identity, so only the file types are compared
+#[cfg(not(unix))]
+fn same_file(_a: &fs::Metadata, _b: &fs::Metadata) -> bool {
+    true
+}
+
 impl Storage<fs::File> for FileStorage {
     fn create_dir_all<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
         fs::create_dir_all(&path).map_err(|_| Error::CreateDir)
@@ -124,6 +220,44 @@ impl Storage<fs::File> for FileStorage {
         }))
     }
 
+    fn modify_file<P: AsRef<Path>>(&self, path: P) -> Result<Entry<fs::File>, Error> {
+        let path = path.as_ref().to_path_buf();
+        let file = fs::File::options()
+            .write(true)
+            .read(true)
+            .open(&path)
+            .map_err(|_| Error::OpenFile(FileMode::Write))?;
+
+        Ok(Entry::File(FileData {
+            path,
+            stream: RefCell::new(file),
+        }))
+    }
+
+    fn modify_regular_file<P: AsRef<Path>>(&self, path: P) -> Result<Entry<fs::File>, Error> {
+        // Devices, FIFOs and the like aren't opened at all, as opening them may block
+        let linked = fs::symlink_metadata(&path).map_err(|_| Error::OpenFile(FileMode::Write))?;
+        if !linked.is_file() {
+            return Err(Error::NotRegularFile);
+        }
+
+        let entry = self.modify_file(&path)?;
+
+        // The path may be replaced by a symlink just before it's opened, which would be followed,
+        // so the opened file must still be the regular file at the path itself
+        let opened = entry
+            .try_reader()?
+            .borrow()
+            .metadata()
+            .map_err(|_| Error::OpenFile(FileMode::Write))?;
+        let linked = fs::symlink_metadata(&path).map_err(|_| Error::OpenFile(FileMode::Write))?;
+        if !opened.is_file() || !linked.is_file() || !same_file(&opened, &linked) {
+            return Err(Error::OpenFile(FileMode::Write));
+        }
+
+        Ok(entry)
+    }
+
     fn flush_file(&self, file: &Entry<fs::File>) -> Result<(), Error> {
         file.try_writer()?
             .borrow_mut()
@@ -131,6 +265,12 @@ impl Storage<fs::File> for FileStorage {
             .map_err(|_| Error::FlushFile)
     }
 
+    fn sync_file(&self, file: &Entry<fs::File>) -> Result<(), Error> {
+        let mut stream = file.try_writer()?.borrow_mut();
+        stream.flush().map_err(|_| Error::FlushFile)?;
+        stream.sync_data().map_err(|_| Error::FlushFile)
+    }
+
     fn file_len(&self, file: &Entry<fs::File>) -> Result<usize, Error> {
         let fs_file = match file {
             Entry::File(FileData { stream, .. }) => stream.borrow(),
@@ -140,6 +280,12 @@ impl Storage<fs::File> for FileStorage {
         file_meta.len().try_into().map_err(|_| Error::FileLen)
     }
 
+    fn file_modified(&self, file: &Entry<fs::File>) -> Result<SystemTime, Error> {
+        fs::metadata(file.path())
+            .and_then(|meta| meta.modified())
+            .map_err(|_| Error::FileModified)
+    }
+
     fn remove_file(&self, file: Entry<fs::File>) -> Result<(), Error> {
         if let Entry::File(FileData { stream, .. }) = &file {
             let mut stream = stream.borrow_mut();
@@ -150,6 +296,52 @@ impl Storage<fs::File> for FileStorage {
         fs::remove_file(file.path()).map_err(|_| Error::RemoveFile)
     }
 
+    fn rename_file<P: AsRef<Path>>(
+        &self,
+        file: Entry<fs::File>,
+        to: P,
+    ) -> Result<Entry<fs::File>, Error> {
+        let Entry::File(FileData { path, stream }) = file else {
+            return Err(Error::FileAccess);
+        };
+
+        let to = to.as_ref().to_path_buf();
+
+        // Unlike a rename, a hard link fails if the new path exists, so nothing can be replaced
+        match fs::hard_link(&path, &to) {
+            Ok(()) => fs::remove_file(&path).map_err(|_| Error::RenameFile)?,
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {
+                return Err(Error::AlreadyExists)
+            }
+            // Some file systems (such as FAT) don't support hard links
+            Err(_) => {
+                if fs::symlink_metadata(&to).is_ok() {
+                    return Err(Error::AlreadyExists);
+                }
+                fs::rename(&path, &to).map_err(|_| Error::RenameFile)?;
+            }
+        }
+
+        Ok(Entry::File(FileData { path: to, stream }))
+    }
+
+    fn truncate_file(&self, file: &Entry<fs::File>) -> Result<(), Error> {
+        let mut stream = file.try_writer()?.borrow_mut();
+        stream.set_len(0).map_err(|_| Error::TruncateFile)?;
+        stream.rewind().map_err(|_| Error::TruncateFile)
+    }
+
+    fn reset_file_times(&self, file: &Entry<fs::File>) -> Result<(), Error> {
+        let times = fs::FileTimes::new()
+            .set_accessed(SystemTime::UNIX_EPOCH)
+            .set_modified(SystemTime::UNIX_EPOCH);
+
+        file.try_writer()?
+            .borrow()
+            .set_times(times)
+            .map_err(|_| Error::FileTimes)
+    }
+
     fn remove_dir_all(&self, file: Entry<fs::File>) -> Result<(), Error> {
         if !file.is_dir() {
             return Err(Error::RemoveDir);
@@ -159,41 +351,110 @@ impl Storage<fs::File> for FileStorage {
     }
 
     fn read_dir(&self, file: &Entry<fs::File>) -> Result<Vec<Entry<fs::File>>, Error> {
+        self.walk_dir(file)?
+            .map(|path| path.and_then(|path| self.read_file(path)))
+            .collect()
+    }
+
+    fn walk_dir(&self, file: &Entry<fs::File>) -> Result<Paths<'_>, Error> {
         if !file.is_dir() {
             return Err(Error::FileAccess);
         }
 
-        walkdir::WalkDir::new(file.path())
-            .into_iter()
-            .map(|res| {
-                res.map(|e| e.path().to_owned())
+        Ok(Box::new(
+            walkdir::WalkDir::new(file.path()).into_iter().map(|res| {
+                res.map(walkdir::DirEntry::into_path)
                     .map_err(|_| Error::DirEntries)
-            })
-            .map(|path| path.and_then(|path| self.read_file(path)))
-            .collect()
+            }),
+        ))
+    }
+
+    fn walk_tree(
+        &self,
+        file: &Entry<fs::File>,
+        cross_filesystems: bool,
+    ) -> Result<TreeEntries<'_>, Error> {
+        let root = fs::symlink_metadata(file.path()).map_err(|_| Error::DirEntries)?;
+
+        // walkdir always follows a symlink at the root, so it's reported before walkdir gets to it
+        if root.file_type().is_symlink() {
+            let entry = TreeEntry {
+                path: file.path().to_path_buf(),
+                kind: EntryKind::Symlink,
+            };
+            return Ok(Box::new(std::iter::once(Ok(entry))));
+        }
+
+        if !root.is_dir() {
+            return Err(Error::FileAccess);
+        }
+
+        let root_device = device(&root);
+        let entries = walkdir::WalkDir::new(file.path())
+            .same_file_system(!cross_filesystems)
+            .into_iter()
+            .map(move |res| {
+                let entry = res.map_err(|_| Error::DirEntries)?;
+                let file_type = entry.file_type();
+
+                let kind = if file_type.is_symlink() {
+                    EntryKind::Symlink
+                } else if file_type.is_dir() {
+                    let metadata = entry.metadata().map_err(|_| Error::DirEntries)?;
+                    if !cross_filesystems && device(&metadata) != root_device {
+                        EntryKind::Mount
+                    } else {
+                        EntryKind::Dir
+                    }
+                } else if file_type.is_file() {
+                    EntryKind::File
+                } else {
+                    EntryKind::Other
+                };
+
+                Ok(TreeEntry {
+                    path: entry.into_path(),
+                    kind,
+                })
+            });
+
+        Ok(Box::new(entries))
+    }
+
+    fn remove_link<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        let metadata = fs::symlink_metadata(&path).map_err(|_| Error::RemoveFile)?;
+        if !metadata.file_type().is_symlink() {
+            return Err(Error::RemoveFile);
+        }
+
+        fs::remove_file(path).map_err(|_| Error::RemoveFile)
     }
 }
 
-#[cfg(test)]
+// The device that a file is on, if the platform tells us.
+//
+// Where it doesn't, walkdir still stays on the same filesystem, but mounts are reported as directories.
+#[cfg(unix)]
+#[allow(clippy::unnecessary_wraps)]
+fn device(metadata: &fs::Metadata) -> Option<u64> {
+    use std::os::unix::fs::MetadataExt;
+    Some(metadata.dev())
+}
+
+#[cfg(not(unix))]
+fn device(_metadata: &fs::Metadata) -> Option<u64> {
+    None
+}
+
+#[cfg(any(test, feature = "testing"))]
 #[derive(Default)]
 pub struct InMemoryStorage {
     pub files: RwLock<HashMap<PathBuf, IMFile>>,
 }
 
-#[cfg(test)]
+#[cfg(any(test, feature = "testing"))]
 impl InMemoryStorage {
-    fn save_text_file<P: AsRef<Path>>(&self, path: P, content: &str) {
-        let buf = content.bytes().collect::<Vec<_>>();
-        self.save_file(
-            path,
-            IMFile::File(InMemoryFile {
-                len: buf.len(),
-                buf,
-            }),
-        );
-    }
-
-    fn save_file<P: AsRef<Path>>(&self, path: P, im_file: IMFile) {
+    pub(crate) fn save_file<P: AsRef<Path>>(&self, path: P, im_file: IMFile) {
         self.mut_files().insert(path.as_ref().to_owned(), im_file);
     }
 
@@ -214,6 +475,20 @@ impl InMemoryStorage {
             }
         }
     }
+}
+
+#[cfg(test)]
+impl InMemoryStorage {
+    pub(crate) fn save_text_file<P: AsRef<Path>>(&self, path: P, content: &str) {
+        let buf = content.bytes().collect::<Vec<_>>();
+        self.save_file(
+            path,
+            IMFile::File(InMemoryFile {
+                len: buf.len(),
+                buf,
+            }),
+        );
+    }
 
     // --------------------------------
     // TEST DATA
@@ -242,10 +517,25 @@ impl InMemoryStorage {
     }
 }
 
-#[cfg(test)]
+#[cfg(any(test, feature = "testing"))]
 impl Storage<io::Cursor<Vec<u8>>> for InMemoryStorage {
-    fn create_dir_all<P: AsRef<Path>>(&self, _path: P) -> Result<(), Error> {
-        todo!();
+    fn create_dir_all<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        let mut files = self.mut_files();
+        for dir in path
+            .as_ref()
+            .ancestors()
+            .filter(|dir| !dir.as_os_str().is_empty())
+        {
+            match files.get(dir) {
+                Some(IMFile::File(_)) => return Err(Error::CreateDir),
+                Some(IMFile::Dir) => {}
+                None => {
+                    files.insert(dir.to_path_buf(), IMFile::Dir);
+                }
+            }
+        }
+
+        Ok(())
     }
 
     fn create_file<P: AsRef<Path>>(&self, path: P) -> Result<Entry<io::Cursor<Vec<u8>>>, Error> {
@@ -308,6 +598,19 @@ impl Storage<io::Cursor<Vec<u8>>> for InMemoryStorage {
         }))
     }
 
+    // in-memory files are never truncated when they're opened
+    fn modify_file<P: AsRef<Path>>(&self, path: P) -> Result<Entry<io::Cursor<Vec<u8>>>, Error> {
+        self.write_file(path)
+    }
+
+    // There are no symlinks in memory
+    fn modify_regular_file<P: AsRef<Path>>(
+        &self,
+        path: P,
+    ) -> Result<Entry<io::Cursor<Vec<u8>>>, Error> {
+        self.modify_file(path)
+    }
+
     fn flush_file(&self, file: &Entry<io::Cursor<Vec<u8>>>) -> Result<(), Error> {
         if file.is_dir() {
             return Err(Error::FileAccess);
@@ -335,6 +638,11 @@ impl Storage<io::Cursor<Vec<u8>>> for InMemoryStorage {
         Ok(cur.get_ref().len())
     }
 
+    // in-memory files don't keep track of modification times
+    fn file_modified(&self, _file: &Entry<io::Cursor<Vec<u8>>>) -> Result<SystemTime, Error> {
+        Ok(SystemTime::UNIX_EPOCH)
+    }
+
     fn remove_file(&self, file: Entry<io::Cursor<Vec<u8>>>) -> Result<(), Error> {
         self.mut_files()
             .remove(file.path())
@@ -342,6 +650,41 @@ impl Storage<io::Cursor<Vec<u8>>> for InMemoryStorage {
         Ok(())
     }
 
+    fn rename_file<P: AsRef<Path>>(
+        &self,
+        file: Entry<io::Cursor<Vec<u8>>>,
+        to: P,
+    ) -> Result<Entry<io::Cursor<Vec<u8>>>, Error> {
+        let Entry::File(FileData { path, stream }) = file else {
+            return Err(Error::FileAccess);
+        };
+
+        let to = to.as_ref().to_path_buf();
+        let mut files = self.mut_files();
+        if files.contains_key(&to) {
+            return Err(Error::AlreadyExists);
+        }
+        let im_file = files.remove(&path).ok_or(Error::RenameFile)?;
+        files.insert(to.clone(), im_file);
+
+        Ok(Entry::File(FileData { path: to, stream }))
+    }
+
+    fn truncate_file(&self, file: &Entry<io::Cursor<Vec<u8>>>) -> Result<(), Error> {
+        {
+            let mut cursor = file.try_writer()?.borrow_mut();
+            cursor.get_mut().clear();
+            cursor.set_position(0);
+        }
+
+        self.flush_file(file)
+    }
+
+    // in-memory files don't keep track of timestamps
+    fn reset_file_times(&self, _file: &Entry<io::Cursor<Vec<u8>>>) -> Result<(), Error> {
+        Ok(())
+    }
+
     fn remove_dir_all(&self, file: Entry<io::Cursor<Vec<u8>>>) -> Result<(), Error> {
         if !file.is_dir() {
             return Err(Error::FileAccess);
@@ -382,23 +725,74 @@ impl Storage<io::Cursor<Vec<u8>>> for InMemoryStorage {
             .map(|(k, _)| self.read_file(k))
             .collect()
     }
+
+    fn walk_dir(&self, file: &Entry<io::Cursor<Vec<u8>>>) -> Result<Paths<'_>, Error> {
+        if !file.is_dir() {
+            return Err(Error::FileAccess);
+        }
+
+        let file_path = file.path();
+
+        #[allow(clippy::needless_collect)] // 🚫 we have to collect to close read lock guard!
+        let file_paths = self
+            .files()
+            .keys()
+            .filter(|k| k.starts_with(file_path))
+            .cloned()
+            .collect::<Vec<_>>();
+
+        Ok(Box::new(file_paths.into_iter().map(Ok)))
+    }
+
+    // There are no symlinks or mounts in memory, so every entry is a file or a directory.
+    fn walk_tree(
+        &self,
+        file: &Entry<io::Cursor<Vec<u8>>>,
+        _cross_filesystems: bool,
+    ) -> Result<TreeEntries<'_>, Error> {
+        if !file.is_dir() {
+            return Err(Error::FileAccess);
+        }
+
+        let file_path = file.path();
+
+        #[allow(clippy::needless_collect)] // 🚫 we have to collect to close read lock guard!
+        let entries = self
+            .files()
+            .iter()
+            .filter(|(k, _)| k.starts_with(file_path))
+            .map(|(k, v)| TreeEntry {
+                path: k.clone(),
+                kind: match v {
+                    IMFile::File(_) => EntryKind::File,
+                    IMFile::Dir => EntryKind::Dir,
+                },
+            })
+            .collect::<Vec<_>>();
+
+        Ok(Box::new(entries.into_iter().map(Ok)))
+    }
+
+    fn remove_link<P: AsRef<Path>>(&self, _path: P) -> Result<(), Error> {
+        Err(Error::RemoveFile)
+    }
 }
 
-#[cfg(test)]
+#[cfg(any(test, feature = "testing"))]
 #[derive(Debug, Clone, Default, PartialEq, Eq)]
 pub struct InMemoryFile {
     pub buf: Vec<u8>,
     pub len: usize,
 }
 
-#[cfg(test)]
+#[cfg(any(test, feature = "testing"))]
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum IMFile {
     File(InMemoryFile),
     Dir,
 }
 
-#[cfg(test)]
+#[cfg(any(test, feature = "testing"))]
 impl IMFile {
     fn inner(&self) -> &InMemoryFile {
         match self {
@@ -563,7 +957,7 @@ mod tests {
             .unwrap();
 
         match stor.flush_file(&file) {
-            Ok(_) => {
+            Ok(()) => {
                 let im_file = stor.files().get(file.path()).cloned();
                 assert_eq!(
                     im_file,
@@ -586,7 +980,7 @@ mod tests {
         let file_path = file.path().to_path_buf();
 
         match stor.remove_file(file) {
-            Ok(_) => {
+            Ok(()) => {
                 let im_file = stor.files().get(&file_path).cloned();
                 assert_eq!(im_file, None);
             }
@@ -603,7 +997,7 @@ mod tests {
         let file_path = file.path().to_path_buf();
 
         match stor.remove_file(file) {
-            Ok(_) => {
+            Ok(()) => {
                 let im_file = stor.files().get(&file_path).cloned();
                 assert_eq!(im_file, None);
             }
@@ -711,6 +1105,34 @@ mod tests {
         }
     }
 
+    #[test]
+    fn should_walk_dir_paths() {
+        let stor = InMemoryStorage::default();
+        stor.add_hello_txt();
+        stor.add_bar_foo_folder();
+
+        let file = stor.read_file("bar/").unwrap();
+        let paths = stor.walk_dir(&file);
+
+        match paths {
+            Ok(paths) => {
+                let file_names = paths.collect::<Result<Vec<_>, _>>().unwrap();
+                assert_eq!(
+                    sorted_file_names(&file_names),
+                    vec![
+                        "bar/",
+                        "bar/foo/",
+                        "bar/foo/hello.txt",
+                        "bar/foo/world.txt",
+                        "bar/hello.txt",
+                        "bar/world.txt",
+                    ]
+                );
+            }
+            _ => unreachable!(),
+        }
+    }
+
     #[test]
     fn should_include_hidden_files_names() {
         let stor = InMemoryStorage::default();
@@ -740,4 +1162,67 @@ mod tests {
             _ => unreachable!(),
         }
     }
+
+    #[test]
+    fn should_not_rename_over_an_existing_file() {
+        let stor = InMemoryStorage::default();
+        stor.add_hello_txt();
+        stor.add_bar_foo_folder();
+
+        let file = stor.read_file("hello.txt").unwrap();
+        match stor.rename_file(file, "bar/hello.txt") {
+            Err(Error::AlreadyExists) => {}
+            _ => unreachable!(),
+        }
+        assert!(stor.read_file("hello.txt").is_ok());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn should_not_rename_over_an_existing_link() {
+        let dir = std::env::temp_dir().join("dexios-rename-test");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("from"), "from").unwrap();
+        std::os::unix::fs::symlink(dir.join("missing"), dir.join("to")).unwrap();
+
+        let stor = FileStorage;
+        let file = stor.read_file(dir.join("from")).unwrap();
+        match stor.rename_file(file, dir.join("to")) {
+            Err(Error::AlreadyExists) => {}
+            _ => unreachable!(),
+        }
+        assert!(fs::symlink_metadata(dir.join("to")).unwrap().is_symlink());
+
+        let file = stor.read_file(dir.join("from")).unwrap();
+        match stor.rename_file(file, dir.join("renamed")) {
+            Ok(file) => assert_eq!(file.path(), dir.join("renamed")),
+            _ => unreachable!(),
+        }
+        assert_eq!(fs::read_to_string(dir.join("renamed")).unwrap(), "from");
+        assert!(!dir.join("from").exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn should_not_follow_links_to_modify_regular_files() {
+        let dir = std::env::temp_dir().join("dexios-modify-regular-test");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("target"), "target").unwrap();
+        std::os::unix::fs::symlink(dir.join("target"), dir.join("link")).unwrap();
+
+        let stor = FileStorage;
+        assert!(stor.modify_regular_file(dir.join("target")).is_ok());
+        for path in [dir.join("link"), dir.clone(), PathBuf::from("/dev/null")] {
+            match stor.modify_regular_file(path) {
+                Err(Error::NotRegularFile) => {}
+                _ => unreachable!(),
+            }
+        }
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
 }
@@ -0,0 +1,406 @@
+//! This allows a storage to be chosen at runtime.
+//!
+//! `Storage` is generic over its stream type, so it can't be used as a trait object. `DynStorage` is object safe instead: it takes `&Path` arguments and works with boxed streams. Any `Storage` can be turned into one with `Erased`, and a `Box<dyn DynStorage>` implements `Storage` again, so it can be passed to every domain function.
+//!
+//! ```
+//! use dexios_domain::storage::{DynStorage, Erased, FileStorage, Storage};
+//! use std::sync::Arc;
+//!
+//! let stor: Box<dyn DynStorage> = Box::new(Erased::new(FileStorage));
+//! let stor = Arc::new(stor);
+//! assert!(stor.read_file("file/that/does/not/exist").is_err());
+//! ```
+
+use std::any::Any;
+use std::cell::RefCell;
+use std::io::{Cursor, Read, Seek, Write};
+use std::marker::PhantomData;
+use std::path::Path;
+use std::time::SystemTime;
+
+use super::{Entry, Error, FileData, Paths, Storage, TreeEntries};
+
+/// A stream that can be boxed, and unboxed again by the storage that opened it.
+pub trait Stream: Read + Write + Seek + Any {
+    fn as_any(&self) -> &dyn Any;
+    fn into_any(self: Box<Self>) -> Box<dyn Any>;
+}
+
+impl<T: Read + Write + Seek + Any> Stream for T {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn into_any(self: Box<Self>) -> Box<dyn Any> {
+        self
+    }
+}
+
+pub type BoxedStream = Box<dyn Stream>;
+
+/// An object safe version of `Storage`.
+pub trait DynStorage: Send + Sync {
+    fn create_dir_all(&self, path: &Path) -> Result<(), Error>;
+    fn create_file(&self, path: &Path) -> Result<Entry<BoxedStream>, Error>;
+    fn read_file(&self, path: &Path) -> Result<Entry<BoxedStream>, Error>;
+    fn write_file(&self, path: &Path) -> Result<Entry<BoxedStream>, Error>;
+    fn modify_file(&self, path: &Path) -> Result<Entry<BoxedStream>, Error>;
+    fn modify_regular_file(&self, path: &Path) -> Result<Entry<BoxedStream>, Error>;
+    fn flush_file(&self, file: &Entry<BoxedStream>) -> Result<(), Error>;
+    fn sync_file(&self, file: &Entry<BoxedStream>) -> Result<(), Error>;
+    fn file_len(&self, file: &Entry<BoxedStream>) -> Result<usize, Error>;
+    fn file_modified(&self, file: &Entry<BoxedStream>) -> Result<SystemTime, Error>;
+    fn remove_file(&self, file: Entry<BoxedStream>) -> Result<(), Error>;
+    fn rename_file(&self, file: Entry<BoxedStream>, to: &Path)
+        -> Result<Entry<BoxedStream>, Error>;
+    fn truncate_file(&self, file: &Entry<BoxedStream>) -> Result<(), Error>;
+    fn reset_file_times(&self, file: &Entry<BoxedStream>) -> Result<(), Error>;
+    fn remove_dir_all(&self, file: Entry<BoxedStream>) -> Result<(), Error>;
+    fn read_dir(&self, file: &Entry<BoxedStream>) -> Result<Vec<Entry<BoxedStream>>, Error>;
+    fn walk_dir(&self, file: &Entry<BoxedStream>) -> Result<Paths<'_>, Error>;
+    fn walk_tree(
+        &self,
+        file: &Entry<BoxedStream>,
+        cross_filesystems: bool,
+    ) -> Result<TreeEntries<'_>, Error>;
+    fn remove_link(&self, path: &Path) -> Result<(), Error>;
+}
+
+// `(**self)` is needed everywhere below, as `self.create_file` would resolve to this impl again.
+impl Storage<BoxedStream> for Box<dyn DynStorage> {
+    fn create_dir_all<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        (**self).create_dir_all(path.as_ref())
+    }
+
+    fn create_file<P: AsRef<Path>>(&self, path: P) -> Result<Entry<BoxedStream>, Error> {
+        (**self).create_file(path.as_ref())
+    }
+
+    fn read_file<P: AsRef<Path>>(&self, path: P) -> Result<Entry<BoxedStream>, Error> {
+        (**self).read_file(path.as_ref())
+    }
+
+    fn write_file<P: AsRef<Path>>(&self, path: P) -> Result<Entry<BoxedStream>, Error> {
+        (**self).write_file(path.as_ref())
+    }
+
+    fn modify_file<P: AsRef<Path>>(&self, path: P) -> Result<Entry<BoxedStream>, Error> {
+        (**self).modify_file(path.as_ref())
+    }
+
+    fn modify_regular_file<P: AsRef<Path>>(&self, path: P) -> Result<Entry<BoxedStream>, Error> {
+        (**self).modify_regular_file(path.as_ref())
+    }
+
+    fn flush_file(&self, file: &Entry<BoxedStream>) -> Result<(), Error> {
+        (**self).flush_file(file)
+    }
+
+    fn sync_file(&self, file: &Entry<BoxedStream>) -> Result<(), Error> {
+        (**self).sync_file(file)
+    }
+
+    fn file_len(&self, file: &Entry<BoxedStream>) -> Result<usize, Error> {
+        (**self).file_len(file)
+    }
+
+    fn file_modified(&self, file: &Entry<BoxedStream>) -> Result<SystemTime, Error> {
+        (**self).file_modified(file)
+    }
+
+    fn remove_file(&self, file: Entry<BoxedStream>) -> Result<(), Error> {
+        (**self).remove_file(file)
+    }
+
+    fn rename_file<P: AsRef<Path>>(
+        &self,
+        file: Entry<BoxedStream>,
+        to: P,
+    ) -> Result<Entry<BoxedStream>, Error> {
+        (**self).rename_file(file, to.as_ref())
+    }
+
+    fn truncate_file(&self, file: &Entry<BoxedStream>) -> Result<(), Error> {
+        (**self).truncate_file(file)
+    }
+
+    fn reset_file_times(&self, file: &Entry<BoxedStream>) -> Result<(), Error> {
+        (**self).reset_file_times(file)
+    }
+
+    fn remove_dir_all(&self, file: Entry<BoxedStream>) -> Result<(), Error> {
+        (**self).remove_dir_all(file)
+    }
+
+    fn read_dir(&self, file: &Entry<BoxedStream>) -> Result<Vec<Entry<BoxedStream>>, Error> {
+        (**self).read_dir(file)
+    }
+
+    fn walk_dir(&self, file: &Entry<BoxedStream>) -> Result<Paths<'_>, Error> {
+        (**self).walk_dir(file)
+    }
+
+    fn walk_tree(
+        &self,
+        file: &Entry<BoxedStream>,
+        cross_filesystems: bool,
+    ) -> Result<TreeEntries<'_>, Error> {
+        (**self).walk_tree(file, cross_filesystems)
+    }
+
+    fn remove_link<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        (**self).remove_link(path.as_ref())
+    }
+}
+
+/// Erases the stream type of a `Storage`, so it can be used as a `DynStorage`.
+///
+/// Entries can only be passed back to the storage that opened them, otherwise `Error::FileAccess` is returned.
+pub struct Erased<S, RW> {
+    inner: S,
+    stream: PhantomData<fn() -> RW>,
+}
+
+impl<S, RW> Erased<S, RW>
+where
+    S: Storage<RW>,
+    RW: Read + Write + Seek + 'static,
+{
+    pub fn new(inner: S) -> Self {
+        Self {
+            inner,
+            stream: PhantomData,
+        }
+    }
+}
+
+fn erase<RW: Stream>(entry: Entry<RW>) -> Entry<BoxedStream> {
+    match entry {
+        Entry::Dir(path) => Entry::Dir(path),
+        Entry::File(FileData { path, stream }) => Entry::File(FileData {
+            path,
+            stream: RefCell::new(Box::new(stream.into_inner())),
+        }),
+    }
+}
+
+fn restore<RW: Stream>(entry: Entry<BoxedStream>) -> Result<Entry<RW>, Error> {
+    match entry {
+        Entry::Dir(path) => Ok(Entry::Dir(path)),
+        Entry::File(FileData { path, stream }) => {
+            let stream = Stream::into_any(stream.into_inner())
+                .downcast::<RW>()
+                .map_err(|_| Error::FileAccess)?;
+            Ok(Entry::File(FileData {
+                path,
+                stream: RefCell::new(*stream),
+            }))
+        }
+    }
+}
+
+// Lends the original entry to `f`, and puts its stream back afterwards.
+fn with_restored<RW, T, F>(entry: &Entry<BoxedStream>, f: F) -> Result<T, Error>
+where
+    RW: Stream,
+    F: FnOnce(&Entry<RW>) -> Result<T, Error>,
+{
+    let data = match entry {
+        Entry::Dir(path) => return f(&Entry::Dir(path.clone())),
+        Entry::File(data) => data,
+    };
+
+    if !Stream::as_any(&**data.stream.borrow()).is::<RW>() {
+        return Err(Error::FileAccess);
+    }
+
+    let stream = data.stream.replace(Box::new(Cursor::new(Vec::new())));
+    let restored = restore(Entry::File(FileData {
+        path: data.path.clone(),
+        stream: RefCell::new(stream),
+    }))?;
+
+    let res = f(&restored);
+
+    if let Entry::File(FileData { stream, .. }) = restored {
+        data.stream.replace(Box::new(stream.into_inner()));
+    }
+
+    res
+}
+
+impl<S, RW> DynStorage for Erased<S, RW>
+where
+    S: Storage<RW>,
+    RW: Read + Write + Seek + 'static,
+{
+    fn create_dir_all(&self, path: &Path) -> Result<(), Error> {
+        self.inner.create_dir_all(path)
+    }
+
+    fn create_file(&self, path: &Path) -> Result<Entry<BoxedStream>, Error> {
+        self.inner.create_file(path).map(erase)
+    }
+
+    fn read_file(&self, path: &Path) -> Result<Entry<BoxedStream>, Error> {
+        self.inner.read_file(path).map(erase)
+    }
+
+    fn write_file(&self, path: &Path) -> Result<Entry<BoxedStream>, Error> {
+        self.inner.write_file(path).map(erase)
+    }
+
+    fn modify_file(&self, path: &Path) -> Result<Entry<BoxedStream>, Error> {
+        self.inner.modify_file(path).map(erase)
+    }
+
+    fn modify_regular_file(&self, path: &Path) -> Result<Entry<BoxedStream>, Error> {
+        self.inner.modify_regular_file(path).map(erase)
+    }
+
+    fn flush_file(&self, file: &Entry<BoxedStream>) -> Result<(), Error> {
+        with_restored(file, |file| self.inner.flush_file(file))
+    }
+
+    fn sync_file(&self, file: &Entry<BoxedStream>) -> Result<(), Error> {
+        with_restored(file, |file| self.inner.sync_file(file))
+    }
+
+    fn file_len(&self, file: &Entry<BoxedStream>) -> Result<usize, Error> {
+        with_restored(file, |file| self.inner.file_len(file))
+    }
+
+    fn file_modified(&self, file: &Entry<BoxedStream>) -> Result<SystemTime, Error> {
+        with_restored(file, |file| self.inner.file_modified(file))
+    }
+
+    fn remove_file(&self, file: Entry<BoxedStream>) -> Result<(), Error> {
+        self.inner.remove_file(restore(file)?)
+    }
+
+    fn rename_file(
+        &self,
+        file: Entry<BoxedStream>,
+        to: &Path,
+    ) -> Result<Entry<BoxedStream>, Error> {
+        self.inner.rename_file(restore(file)?, to).map(erase)
+    }
+
+    fn truncate_file(&self, file: &Entry<BoxedStream>) -> Result<(), Error> {
+        with_restored(file, |file| self.inner.truncate_file(file))
+    }
+
+    fn reset_file_times(&self, file: &Entry<BoxedStream>) -> Result<(), Error> {
+        with_restored(file, |file| self.inner.reset_file_times(file))
+    }
+
+    fn remove_dir_all(&self, file: Entry<BoxedStream>) -> Result<(), Error> {
+        self.inner.remove_dir_all(restore(file)?)
+    }
+
+    fn read_dir(&self, file: &Entry<BoxedStream>) -> Result<Vec<Entry<BoxedStream>>, Error> {
+        let entries = with_restored(file, |file| self.inner.read_dir(file))?;
+        Ok(entries.into_iter().map(erase).collect())
+    }
+
+    fn walk_dir(&self, file: &Entry<BoxedStream>) -> Result<Paths<'_>, Error> {
+        with_restored(file, |file| self.inner.walk_dir(file))
+    }
+
+    fn walk_tree(
+        &self,
+        file: &Entry<BoxedStream>,
+        cross_filesystems: bool,
+    ) -> Result<TreeEntries<'_>, Error> {
+        with_restored(file, |file| self.inner.walk_tree(file, cross_filesystems))
+    }
+
+    fn remove_link(&self, path: &Path) -> Result<(), Error> {
+        self.inner.remove_link(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::InMemoryStorage;
+    use std::sync::Arc;
+
+    fn in_memory() -> Arc<Box<dyn DynStorage>> {
+        let stor = InMemoryStorage::default();
+        stor.add_hello_txt();
+        stor.add_bar_foo_folder();
+        Arc::new(Box::new(Erased::new(stor)))
+    }
+
+    fn read_to_string(stor: &dyn DynStorage, path: &str) -> String {
+        let file = stor.read_file(Path::new(path)).unwrap();
+        let mut content = String::new();
+        file.try_reader()
+            .unwrap()
+            .borrow_mut()
+            .read_to_string(&mut content)
+            .unwrap();
+        content
+    }
+
+    #[test]
+    fn should_keep_stream_after_flush() {
+        let stor = in_memory();
+
+        let file = stor.create_file("world.txt").unwrap();
+        file.try_writer()
+            .unwrap()
+            .borrow_mut()
+            .write_all(b"hello")
+            .unwrap();
+        stor.flush_file(&file).unwrap();
+        file.try_writer()
+            .unwrap()
+            .borrow_mut()
+            .write_all(b" world")
+            .unwrap();
+        stor.flush_file(&file).unwrap();
+
+        assert_eq!(stor.file_len(&file).unwrap(), 11);
+        assert_eq!(read_to_string(&**stor, "world.txt"), "hello world");
+    }
+
+    #[test]
+    fn should_reject_entry_from_another_storage() {
+        let stor = in_memory();
+
+        let file = erase(Entry::File(FileData {
+            path: "hello.txt".into(),
+            stream: RefCell::new(Cursor::new([0u8; 4])),
+        }));
+
+        match stor.file_len(&file) {
+            Err(Error::FileAccess) => {}
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn should_erase_dir_through_dyn_storage() {
+        let stor = in_memory();
+
+        let req = crate::erase_dir::Request {
+            entry: stor.read_file("bar/").unwrap(),
+            scheme: crate::overwrite::Scheme::Random(1),
+            verify: true,
+            scrub: true,
+            cross_filesystems: false,
+            workers: 2,
+            progress: None,
+        };
+
+        match crate::erase_dir::execute(stor.clone(), req) {
+            Ok(report) if report.removed => {
+                assert!(stor.read_file("bar/hello.txt").is_err());
+                assert_eq!(read_to_string(&**stor, "hello.txt"), "hello world");
+            }
+            _ => unreachable!(),
+        }
+    }
+}
@@ -0,0 +1,440 @@
+//! This contains storages for testing code that's built on top of this crate, and it's only available with the `testing` feature.
+//!
+//! `TreeBuilder` seeds an `InMemoryStorage` with files and directories, and `FaultyStorage` wraps it to inject failures, so error paths can be tested without touching the disk.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::io::{self, Cursor, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::SystemTime;
+
+use super::{
+    Entry, Error, FileData, IMFile, InMemoryFile, InMemoryStorage, Paths, Storage, TreeEntries,
+};
+
+/// Seeds an `InMemoryStorage` with a tree of files and directories.
+///
+/// Parent directories are created for every entry, just like `create_dir_all`.
+#[derive(Default)]
+pub struct TreeBuilder {
+    files: HashMap<PathBuf, IMFile>,
+}
+
+impl TreeBuilder {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[must_use]
+    pub fn dir<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.add_dirs(path.as_ref());
+        self
+    }
+
+    #[must_use]
+    pub fn file<P: AsRef<Path>, C: AsRef<[u8]>>(mut self, path: P, content: C) -> Self {
+        let path = path.as_ref();
+        if let Some(parent) = path.parent() {
+            self.add_dirs(parent);
+        }
+
+        let buf = content.as_ref().to_vec();
+        self.files.insert(
+            path.to_path_buf(),
+            IMFile::File(InMemoryFile {
+                len: buf.len(),
+                buf,
+            }),
+        );
+        self
+    }
+
+    #[must_use]
+    pub fn build(self) -> InMemoryStorage {
+        InMemoryStorage {
+            files: RwLock::new(self.files),
+        }
+    }
+
+    fn add_dirs(&mut self, path: &Path) {
+        for dir in path.ancestors().filter(|dir| !dir.as_os_str().is_empty()) {
+            self.files.insert(dir.to_path_buf(), IMFile::Dir);
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct Faults {
+    full_at_write: Option<usize>,
+    failed_reads: Vec<PathBuf>,
+    failed_removals: Vec<PathBuf>,
+    max_read_len: Option<usize>,
+}
+
+/// A storage that behaves like the `InMemoryStorage` it wraps, apart from the failures that it's told to inject.
+///
+/// ```
+/// use dexios_domain::storage::testing::{FaultyStorage, TreeBuilder};
+///
+/// let stor = FaultyStorage::new(TreeBuilder::new().file("hello.txt", "hello world").build())
+///     .full_at_write(3)
+///     .fail_removal("hello.txt");
+/// ```
+pub struct FaultyStorage {
+    inner: InMemoryStorage,
+    faults: Arc<Faults>,
+    writes: Arc<AtomicUsize>,
+}
+
+impl FaultyStorage {
+    #[must_use]
+    pub fn new(inner: InMemoryStorage) -> Self {
+        Self {
+            inner,
+            faults: Arc::default(),
+            writes: Arc::default(),
+        }
+    }
+
+    /// Fails the nth write (counting from 1) and every write after it with `ENOSPC`, as if the disk filled up.
+    #[must_use]
+    pub fn full_at_write(mut self, n: usize) -> Self {
+        Arc::make_mut(&mut self.faults).full_at_write = Some(n);
+        self
+    }
+
+    /// Fails every read from the file at this path.
+    #[must_use]
+    pub fn fail_reads<P: AsRef<Path>>(mut self, path: P) -> Self {
+        Arc::make_mut(&mut self.faults)
+            .failed_reads
+            .push(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Fails to remove the file or directory at this path.
+    #[must_use]
+    pub fn fail_removal<P: AsRef<Path>>(mut self, path: P) -> Self {
+        Arc::make_mut(&mut self.faults)
+            .failed_removals
+            .push(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Limits every read to at most `max_len` bytes, which callers must handle by reading again.
+    #[must_use]
+    pub fn short_reads(mut self, max_len: usize) -> Self {
+        assert!(max_len > 0, "A read of 0 bytes means the end of the file");
+        Arc::make_mut(&mut self.faults).max_read_len = Some(max_len);
+        self
+    }
+
+    /// The number of writes that have been attempted, including the ones that failed.
+    pub fn writes(&self) -> usize {
+        self.writes.load(Ordering::SeqCst)
+    }
+
+    pub fn inner(&self) -> &InMemoryStorage {
+        &self.inner
+    }
+
+    fn wrap(&self, entry: Entry<Cursor<Vec<u8>>>) -> Entry<FaultyStream> {
+        match entry {
+            Entry::Dir(path) => Entry::Dir(path),
+            Entry::File(FileData { path, stream }) => Entry::File(FileData {
+                stream: RefCell::new(FaultyStream {
+                    inner: stream.into_inner(),
+                    path: path.clone(),
+                    faults: self.faults.clone(),
+                    writes: self.writes.clone(),
+                }),
+                path,
+            }),
+        }
+    }
+
+    fn check_removal(&self, path: &Path, err: Error) -> Result<(), Error> {
+        if self.faults.failed_removals.iter().any(|p| p == path) {
+            return Err(err);
+        }
+
+        Ok(())
+    }
+}
+
+fn unwrap(entry: Entry<FaultyStream>) -> Entry<Cursor<Vec<u8>>> {
+    match entry {
+        Entry::Dir(path) => Entry::Dir(path),
+        Entry::File(FileData { path, stream }) => Entry::File(FileData {
+            path,
+            stream: RefCell::new(stream.into_inner().inner),
+        }),
+    }
+}
+
+/// The stream of a file that was opened by a `FaultyStorage`.
+pub struct FaultyStream {
+    inner: Cursor<Vec<u8>>,
+    path: PathBuf,
+    faults: Arc<Faults>,
+    writes: Arc<AtomicUsize>,
+}
+
+impl Read for FaultyStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.faults.failed_reads.contains(&self.path) {
+            return Err(io::Error::other("Injected read error"));
+        }
+
+        let len = self
+            .faults
+            .max_read_len
+            .map_or(buf.len(), |max| max.min(buf.len()));
+        self.inner.read(&mut buf[..len])
+    }
+}
+
+impl Write for FaultyStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.writes.fetch_add(1, Ordering::SeqCst) + 1;
+        if self
+            .faults
+            .full_at_write
+            .is_some_and(|full_at| n >= full_at)
+        {
+            return Err(io::ErrorKind::StorageFull.into());
+        }
+
+        self.inner.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl Seek for FaultyStream {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.inner.seek(pos)
+    }
+}
+
+impl Storage<FaultyStream> for FaultyStorage {
+    fn create_dir_all<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        self.inner.create_dir_all(path)
+    }
+
+    fn create_file<P: AsRef<Path>>(&self, path: P) -> Result<Entry<FaultyStream>, Error> {
+        self.inner.create_file(path).map(|file| self.wrap(file))
+    }
+
+    fn read_file<P: AsRef<Path>>(&self, path: P) -> Result<Entry<FaultyStream>, Error> {
+        self.inner.read_file(path).map(|file| self.wrap(file))
+    }
+
+    fn write_file<P: AsRef<Path>>(&self, path: P) -> Result<Entry<FaultyStream>, Error> {
+        self.inner.write_file(path).map(|file| self.wrap(file))
+    }
+
+    fn modify_file<P: AsRef<Path>>(&self, path: P) -> Result<Entry<FaultyStream>, Error> {
+        self.inner.modify_file(path).map(|file| self.wrap(file))
+    }
+
+    fn modify_regular_file<P: AsRef<Path>>(&self, path: P) -> Result<Entry<FaultyStream>, Error> {
+        self.inner
+            .modify_regular_file(path)
+            .map(|file| self.wrap(file))
+    }
+
+    fn flush_file(&self, file: &Entry<FaultyStream>) -> Result<(), Error> {
+        let writer = file.try_writer()?;
+        writer.borrow_mut().flush().map_err(|_| Error::FlushFile)?;
+
+        let buf = writer.borrow().inner.get_ref().clone();
+        let len = buf.len();
+        self.inner
+            .save_file(file.path(), IMFile::File(InMemoryFile { buf, len }));
+
+        Ok(())
+    }
+
+    fn file_len(&self, file: &Entry<FaultyStream>) -> Result<usize, Error> {
+        Ok(file.try_reader()?.borrow().inner.get_ref().len())
+    }
+
+    fn file_modified(&self, _file: &Entry<FaultyStream>) -> Result<SystemTime, Error> {
+        Ok(SystemTime::UNIX_EPOCH)
+    }
+
+    fn remove_file(&self, file: Entry<FaultyStream>) -> Result<(), Error> {
+        self.check_removal(file.path(), Error::RemoveFile)?;
+        self.inner.remove_file(unwrap(file))
+    }
+
+    fn rename_file<P: AsRef<Path>>(
+        &self,
+        file: Entry<FaultyStream>,
+        to: P,
+    ) -> Result<Entry<FaultyStream>, Error> {
+        self.inner
+            .rename_file(unwrap(file), to)
+            .map(|file| self.wrap(file))
+    }
+
+    fn truncate_file(&self, file: &Entry<FaultyStream>) -> Result<(), Error> {
+        {
+            let mut stream = file.try_writer()?.borrow_mut();
+            stream.inner.get_mut().clear();
+            stream.inner.set_position(0);
+        }
+
+        self.flush_file(file)
+    }
+
+    fn reset_file_times(&self, _file: &Entry<FaultyStream>) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn remove_dir_all(&self, file: Entry<FaultyStream>) -> Result<(), Error> {
+        self.check_removal(file.path(), Error::RemoveDir)?;
+        self.inner.remove_dir_all(unwrap(file))
+    }
+
+    fn read_dir(&self, file: &Entry<FaultyStream>) -> Result<Vec<Entry<FaultyStream>>, Error> {
+        if !file.is_dir() {
+            return Err(Error::FileAccess);
+        }
+
+        let entries = self
+            .inner
+            .read_dir(&Entry::Dir(file.path().to_path_buf()))?;
+        Ok(entries.into_iter().map(|entry| self.wrap(entry)).collect())
+    }
+
+    fn walk_dir(&self, file: &Entry<FaultyStream>) -> Result<Paths<'_>, Error> {
+        if !file.is_dir() {
+            return Err(Error::FileAccess);
+        }
+
+        self.inner.walk_dir(&Entry::Dir(file.path().to_path_buf()))
+    }
+
+    fn walk_tree(
+        &self,
+        file: &Entry<FaultyStream>,
+        cross_filesystems: bool,
+    ) -> Result<TreeEntries<'_>, Error> {
+        if !file.is_dir() {
+            return Err(Error::FileAccess);
+        }
+
+        self.inner
+            .walk_tree(&Entry::Dir(file.path().to_path_buf()), cross_filesystems)
+    }
+
+    fn remove_link<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        self.check_removal(path.as_ref(), Error::RemoveFile)?;
+        self.inner.remove_link(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn faulty_hello_txt() -> FaultyStorage {
+        FaultyStorage::new(
+            TreeBuilder::new()
+                .file("bar/hello.txt", "hello world")
+                .build(),
+        )
+    }
+
+    #[test]
+    fn should_build_tree_with_parent_dirs() {
+        let stor = TreeBuilder::new()
+            .dir("foo/")
+            .file("bar/baz/hello.txt", "hello")
+            .build();
+
+        let files = stor.files();
+        assert_eq!(files.get(Path::new("foo")), Some(&IMFile::Dir));
+        assert_eq!(files.get(Path::new("bar")), Some(&IMFile::Dir));
+        assert_eq!(files.get(Path::new("bar/baz")), Some(&IMFile::Dir));
+        assert_eq!(
+            files.get(Path::new("bar/baz/hello.txt")),
+            Some(&IMFile::File(InMemoryFile {
+                buf: b"hello".to_vec(),
+                len: 5
+            }))
+        );
+    }
+
+    #[test]
+    fn should_fail_writes_once_full() {
+        let stor = faulty_hello_txt().full_at_write(2);
+        let file = stor.create_file("world.txt").unwrap();
+        let mut writer = file.try_writer().unwrap().borrow_mut();
+
+        assert!(writer.write(b"world").is_ok());
+        match writer.write(b"world") {
+            Err(err) => assert_eq!(err.kind(), io::ErrorKind::StorageFull),
+            _ => unreachable!(),
+        }
+        assert!(writer.write(b"world").is_err());
+        assert_eq!(stor.writes(), 3);
+    }
+
+    #[test]
+    fn should_fail_reads() {
+        let stor = faulty_hello_txt().fail_reads("bar/hello.txt");
+        let file = stor.read_file("bar/hello.txt").unwrap();
+
+        let mut buf = Vec::new();
+        assert!(file
+            .try_reader()
+            .unwrap()
+            .borrow_mut()
+            .read_to_end(&mut buf)
+            .is_err());
+    }
+
+    #[test]
+    fn should_return_short_reads() {
+        let stor = faulty_hello_txt().short_reads(4);
+        let file = stor.read_file("bar/hello.txt").unwrap();
+        let mut reader = file.try_reader().unwrap().borrow_mut();
+
+        let mut buf = [0u8; 16];
+        assert_eq!(reader.read(&mut buf).unwrap(), 4);
+
+        let mut rest = Vec::new();
+        reader.read_to_end(&mut rest).unwrap();
+        assert_eq!(rest, b"o world");
+    }
+
+    #[test]
+    fn should_fail_to_erase_file_that_cant_be_removed() {
+        let stor = Arc::new(faulty_hello_txt().fail_removal("bar/hello.txt"));
+
+        let req = crate::erase::Request {
+            path: "bar/hello.txt",
+            scheme: crate::overwrite::Scheme::Random(1),
+            verify: false,
+            scrub: false,
+            progress: None,
+        };
+        match crate::erase::execute(stor.clone(), req) {
+            Err(crate::erase::Error::RemoveFile) => {
+                assert!(stor
+                    .inner()
+                    .files()
+                    .contains_key(Path::new("bar/hello.txt")));
+            }
+            _ => unreachable!(),
+        }
+    }
+}
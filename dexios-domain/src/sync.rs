@@ -0,0 +1,437 @@
+//! This keeps an encrypted directory tree (see `encrypt_dir`) up to date with its source directory.
+//!
+//! An encrypted manifest is stored at the root of the tree, and it lists the size, modification time and `BLAKE3` hash of every file. Files are only hashed if their size or modification time changed, and only encrypted if their hash changed. Paths that no longer exist within the source directory are removed from the tree.
+
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::io::{Read, Seek, Write};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use core::cipher::Ciphers;
+use core::header::{HashingAlgorithm, HeaderType};
+use core::primitives::Mode;
+use core::protected::Protected;
+use core::Payload;
+
+pub use crate::encrypt_dir::Error;
+use crate::encrypt_dir::{encrypt_file, DirKey};
+use crate::hasher::Blake3Hasher;
+use crate::manifest::{FileInfo, Manifest, ManifestEntry};
+use crate::storage::{Entry, Storage};
+use crate::utils::gen_nonce;
+
+/// The name of the encrypted manifest, at the root of the encrypted tree.
+pub const SYNC_MANIFEST_NAME: &str = ".dexios-sync";
+
+const MANIFEST_KEY_CONTEXT: &str = "Dexios 2026-10-18 encrypted directory sync manifest";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Change {
+    Added,
+    Updated,
+    Removed,
+}
+
+pub type OnChangeFn = Box<dyn Fn(&Path, Change)>;
+
+pub struct Request<'a> {
+    pub input_dir_path: PathBuf,
+    pub output_dir_path: PathBuf,
+    pub raw_key: Protected<Vec<u8>>,
+    // These are only used if the output doesn't contain a header yet
+    // TODO: don't use external types in logic
+    pub header_type: HeaderType,
+    pub hashing_algorithm: HashingAlgorithm,
+    // Called with the plaintext path of every file that changes
+    pub on_change: Option<&'a OnChangeFn>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Summary {
+    pub added: usize,
+    pub updated: usize,
+    pub removed: usize,
+    pub unchanged: usize,
+}
+
+pub fn execute<RW>(stor: &impl Storage<RW>, req: Request<'_>) -> Result<Summary, Error>
+where
+    RW: Read + Write + Seek,
+{
+    let input_dir = stor
+        .read_file(&req.input_dir_path)
+        .map_err(Error::Storage)?;
+    if !input_dir.is_dir() {
+        return Err(Error::InvalidDir);
+    }
+
+    let dir_key = DirKey::open_or_create(
+        stor,
+        &req.output_dir_path,
+        req.raw_key.clone(),
+        &req.header_type,
+        req.hashing_algorithm,
+    )?;
+
+    sync_tree(stor, &dir_key, &req)
+}
+
+fn sync_tree<RW>(
+    stor: &impl Storage<RW>,
+    dir_key: &DirKey,
+    req: &Request<'_>,
+) -> Result<Summary, Error>
+where
+    RW: Read + Write + Seek,
+{
+    let input_dir = stor
+        .read_file(&req.input_dir_path)
+        .map_err(Error::Storage)?;
+
+    // Without a manifest, every file is treated as new
+    let previous = read_manifest(stor, dir_key, &req.output_dir_path)?
+        .map_or_else(BTreeMap::new, |manifest| manifest.entries);
+    let mut manifest = Manifest::new(None);
+    let mut summary = Summary::default();
+
+    for path in stor.walk_dir(&input_dir).map_err(Error::Storage)? {
+        let path = path.map_err(Error::Storage)?;
+        let relative_path = path
+            .strip_prefix(&req.input_dir_path)
+            .map_err(|_| Error::UnsupportedPath(path.clone()))?
+            .to_path_buf();
+        if relative_path.as_os_str().is_empty() {
+            continue;
+        }
+
+        let output_path = req
+            .output_dir_path
+            .join(dir_key.encrypt_path(&relative_path)?);
+        let entry = stor.read_file(&path).map_err(Error::Storage)?;
+        let previous_entry = previous.get(&relative_path);
+
+        // A path that changed between a file and a directory is removed first
+        if let Some(previous_entry) = previous_entry {
+            if matches!(previous_entry, ManifestEntry::Dir) != entry.is_dir() {
+                remove_path(stor, &output_path)?;
+            }
+        }
+
+        if entry.is_dir() {
+            stor.create_dir_all(&output_path).map_err(Error::Storage)?;
+            manifest.entries.insert(relative_path, ManifestEntry::Dir);
+            continue;
+        }
+
+        let (info, change) = sync_file(
+            stor,
+            dir_key,
+            &entry,
+            &relative_path,
+            &output_path,
+            previous_entry,
+        )
+        .map_err(|err| match err {
+            Error::Encrypt(_, inner) => Error::Encrypt(path.clone(), inner),
+            err => err,
+        })?;
+
+        match change {
+            Some(change) => {
+                if change == Change::Added {
+                    summary.added += 1;
+                } else {
+                    summary.updated += 1;
+                }
+
+                if let Some(on_change) = req.on_change {
+                    on_change(&relative_path, change);
+                }
+            }
+            None => summary.unchanged += 1,
+        }
+
+        manifest
+            .entries
+            .insert(relative_path, ManifestEntry::File(info));
+    }
+
+    // Children are removed before their parents
+    let mut deleted = previous
+        .iter()
+        .filter(|(path, _)| !manifest.entries.contains_key(*path))
+        .collect::<Vec<_>>();
+    deleted.sort_by_key(|(path, _)| std::cmp::Reverse(path.components().count()));
+
+    for (path, entry) in deleted {
+        let output_path = req.output_dir_path.join(dir_key.encrypt_path(path)?);
+        remove_path(stor, &output_path)?;
+
+        if let ManifestEntry::File(_) = entry {
+            summary.removed += 1;
+            if let Some(on_change) = req.on_change {
+                on_change(path, Change::Removed);
+            }
+        }
+    }
+
+    write_manifest(stor, dir_key, &req.output_dir_path, &manifest)?;
+
+    Ok(summary)
+}
+
+// Encrypts a file if it's new or changed, and returns its info for the manifest.
+fn sync_file<RW>(
+    stor: &impl Storage<RW>,
+    dir_key: &DirKey,
+    entry: &Entry<RW>,
+    relative_path: &Path,
+    output_path: &Path,
+    previous: Option<&ManifestEntry>,
+) -> Result<(FileInfo, Option<Change>), Error>
+where
+    RW: Read + Write + Seek,
+{
+    let size = stor.file_len(entry).map_err(Error::Storage)? as u64;
+    let modified = stor
+        .file_modified(entry)
+        .map_err(Error::Storage)?
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+
+    let previous = match previous {
+        Some(ManifestEntry::File(info)) => Some(info),
+        _ => None,
+    };
+    let exists = stor.read_file(output_path).is_ok();
+
+    if let Some(info) = previous {
+        if exists && info.size == size && info.modified == modified {
+            return Ok((info.clone(), None));
+        }
+    }
+
+    let reader = entry.try_reader().map_err(Error::Storage)?;
+    let hash = crate::hash::execute(
+        Blake3Hasher::default(),
+        crate::hash::Request {
+            reader: RefCell::new(&mut *reader.borrow_mut()),
+            progress: None,
+        },
+    )
+    .map_err(|_| Error::HashFile(entry.path().to_path_buf()))?;
+
+    let info = FileInfo {
+        size,
+        modified,
+        hash,
+    };
+
+    // Only the modification time changed
+    if let Some(previous) = previous {
+        if exists && previous.hash == info.hash {
+            return Ok((info, None));
+        }
+    }
+
+    if exists {
+        remove_path(stor, output_path)?;
+    }
+    encrypt_file(stor, dir_key, entry, relative_path, output_path)?;
+
+    let change = if previous.is_some() && exists {
+        Change::Updated
+    } else {
+        Change::Added
+    };
+
+    Ok((info, Some(change)))
+}
+
+fn remove_path<RW>(stor: &impl Storage<RW>, path: &Path) -> Result<(), Error>
+where
+    RW: Read + Write + Seek,
+{
+    match stor.read_file(path) {
+        Ok(entry) if entry.is_dir() => stor.remove_dir_all(entry).map_err(Error::Storage),
+        // Files need to be opened for writing before they can be removed
+        Ok(_) => {
+            let file = stor.write_file(path).map_err(Error::Storage)?;
+            stor.remove_file(file).map_err(Error::Storage)
+        }
+        Err(_) => Ok(()),
+    }
+}
+
+// The manifest is encrypted with its own key, that's derived from the master key.
+fn manifest_ciphers(dir_key: &DirKey) -> Result<Ciphers, Error> {
+    let key = Protected::new(blake3::derive_key(
+        MANIFEST_KEY_CONTEXT,
+        dir_key.master_key().expose(),
+    ));
+    Ciphers::initialize(key, &dir_key.algorithm()).map_err(|_| Error::InitializeChiphers)
+}
+
+fn read_manifest<RW>(
+    stor: &impl Storage<RW>,
+    dir_key: &DirKey,
+    dir: &Path,
+) -> Result<Option<Manifest>, Error>
+where
+    RW: Read + Write + Seek,
+{
+    let manifest_path = dir.join(SYNC_MANIFEST_NAME);
+    let Ok(file) = stor.read_file(&manifest_path) else {
+        return Ok(None);
+    };
+
+    let mut data = Vec::new();
+    file.try_reader()
+        .map_err(Error::Storage)?
+        .borrow_mut()
+        .read_to_end(&mut data)
+        .map_err(|_| Error::ReadManifest)?;
+
+    let nonce_len = core::primitives::get_nonce_len(&dir_key.algorithm(), &Mode::MemoryMode);
+    if data.len() < nonce_len {
+        return Err(Error::InvalidManifest);
+    }
+
+    let (nonce, msg) = data.split_at(nonce_len);
+    let content = manifest_ciphers(dir_key)?
+        .decrypt(
+            nonce,
+            Payload {
+                aad: SYNC_MANIFEST_NAME.as_bytes(),
+                msg,
+            },
+        )
+        .map_err(|_| Error::InvalidManifest)?;
+
+    let content = String::from_utf8(content).map_err(|_| Error::InvalidManifest)?;
+    Manifest::deserialize(&content)
+        .map(Some)
+        .map_err(|_| Error::InvalidManifest)
+}
+
+fn write_manifest<RW>(
+    stor: &impl Storage<RW>,
+    dir_key: &DirKey,
+    dir: &Path,
+    manifest: &Manifest,
+) -> Result<(), Error>
+where
+    RW: Read + Write + Seek,
+{
+    let content = manifest.serialize().map_err(|_| Error::WriteManifest)?;
+
+    let mut data = gen_nonce(&dir_key.algorithm(), &Mode::MemoryMode);
+    let encrypted = manifest_ciphers(dir_key)?
+        .encrypt(
+            &data,
+            Payload {
+                aad: SYNC_MANIFEST_NAME.as_bytes(),
+                msg: content.as_bytes(),
+            },
+        )
+        .map_err(|_| Error::WriteManifest)?;
+    data.extend_from_slice(&encrypted);
+
+    let manifest_path = dir.join(SYNC_MANIFEST_NAME);
+    remove_path(stor, &manifest_path)?;
+    let file = stor.create_file(&manifest_path).map_err(Error::Storage)?;
+    file.try_writer()
+        .map_err(Error::Storage)?
+        .borrow_mut()
+        .write_all(&data)
+        .map_err(|_| Error::WriteManifest)?;
+    stor.flush_file(&file).map_err(Error::Storage)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encrypt_dir::tests::dir_key;
+    use crate::storage::{IMFile, InMemoryFile, InMemoryStorage};
+    use core::header::HeaderVersion;
+    use core::primitives::Algorithm;
+
+    fn sync(stor: &InMemoryStorage, dir_key: &DirKey) -> Summary {
+        let req = Request {
+            input_dir_path: PathBuf::from("bar"),
+            output_dir_path: PathBuf::from("enc"),
+            raw_key: Protected::new(Vec::new()),
+            header_type: HeaderType {
+                version: HeaderVersion::V5,
+                algorithm: Algorithm::XChaCha20Poly1305,
+                mode: Mode::StreamMode,
+            },
+            hashing_algorithm: HashingAlgorithm::Blake3Balloon(5),
+            on_change: None,
+        };
+
+        match sync_tree(stor, dir_key, &req) {
+            Ok(summary) => summary,
+            _ => unreachable!(),
+        }
+    }
+
+    fn encrypted_path(dir_key: &DirKey, path: &str) -> PathBuf {
+        Path::new("enc").join(dir_key.encrypt_path(Path::new(path)).unwrap())
+    }
+
+    // Key hashing is slow, so the tree is synced with the test's master key
+    #[test]
+    fn should_only_sync_changes() {
+        let stor = InMemoryStorage::default();
+        stor.add_bar_foo_folder();
+        let dir_key = dir_key();
+
+        let first = sync(&stor, &dir_key);
+        assert_eq!(
+            first,
+            Summary {
+                added: 4,
+                ..Summary::default()
+            }
+        );
+        assert!(stor
+            .files()
+            .contains_key(&Path::new("enc").join(SYNC_MANIFEST_NAME)));
+
+        let unchanged_file = stor
+            .files()
+            .get(&encrypted_path(&dir_key, "hello.txt"))
+            .cloned();
+
+        // In-memory files don't have modification times, so only the size changes
+        stor.mut_files().insert(
+            PathBuf::from("bar/world.txt"),
+            IMFile::File(InMemoryFile {
+                buf: b"world!".to_vec(),
+                len: 6,
+            }),
+        );
+        stor.mut_files().remove(&PathBuf::from("bar/foo/hello.txt"));
+
+        let second = sync(&stor, &dir_key);
+        assert_eq!(
+            second,
+            Summary {
+                updated: 1,
+                removed: 1,
+                unchanged: 2,
+                ..Summary::default()
+            }
+        );
+
+        let files = stor.files();
+        assert_eq!(
+            files.get(&encrypted_path(&dir_key, "hello.txt")).cloned(),
+            unchanged_file
+        );
+        assert!(!files.contains_key(&encrypted_path(&dir_key, "foo/hello.txt")));
+        assert!(files.contains_key(&encrypted_path(&dir_key, "foo/world.txt")));
+    }
+}
@@ -4,38 +4,96 @@
 
 use std::cell::RefCell;
 use std::io::{Read, Seek, Write};
-use std::path::PathBuf;
+use std::path::{Component, Path, PathBuf};
 use std::sync::Arc;
 
+use crate::cancel::{is_cancelled, CancellableReader, CancellationToken};
+use crate::manifest::{self, Manifest, MANIFEST_NAME};
+use crate::overwrite::Scheme;
+use crate::progress::{Phase, Progress, ProgressReader};
 use crate::storage::{self, Storage};
-use crate::{decrypt, overwrite};
+use crate::{decrypt, erase, overwrite, pack};
+use core::primitives::BLOCK_SIZE;
 use core::protected::Protected;
 
 #[derive(Debug)]
 pub enum Error {
     WriteData,
+    ReadData,
     OpenArchive,
     OpenArchivedFile,
     ResetCursorPosition,
+    LimitExceeded(Limit),
+    UnsupportedPath(String),
+    ReadManifest(manifest::Error),
+    BrokenChain,
     Storage(storage::Error),
     Decrypt(decrypt::Error),
+    Cancelled,
 }
 
 impl std::fmt::Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Error::WriteData => f.write_str("Unable to write data"),
+            Error::ReadData => f.write_str("Unable to read data"),
             Error::OpenArchive => f.write_str("Unable to open archive"),
             Error::OpenArchivedFile => f.write_str("Unable to open archived file"),
             Error::ResetCursorPosition => f.write_str("Unable to reset cursor position"),
+            Error::LimitExceeded(limit) => write!(f, "Archive exceeds the limit: {limit}"),
+            Error::UnsupportedPath(path) => {
+                write!(
+                    f,
+                    "Unable to represent archived path on this platform: {path}"
+                )
+            }
+            Error::ReadManifest(inner) => write!(f, "Unable to read manifest: {inner}"),
+            Error::BrokenChain => f.write_str(
+                "This archive is incremental, and its previous archive must be restored first",
+            ),
             Error::Storage(inner) => write!(f, "Storage error: {inner}"),
             Error::Decrypt(inner) => write!(f, "Decrypt error: {inner}"),
+            Error::Cancelled => f.write_str("Unpacking was cancelled"),
         }
     }
 }
 
 impl std::error::Error for Error {}
 
+/// The limit that was hit while extracting the archive.
+#[derive(Debug)]
+pub enum Limit {
+    TotalSize(u64),
+    Entries(usize),
+    EntrySize(u64),
+    CompressionRatio(u64),
+}
+
+impl std::fmt::Display for Limit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Limit::TotalSize(max) => write!(f, "more than {max} bytes in total"),
+            Limit::Entries(max) => write!(f, "more than {max} entries"),
+            Limit::EntrySize(max) => write!(f, "a file larger than {max} bytes"),
+            Limit::CompressionRatio(max) => {
+                write!(f, "a file with a compression ratio higher than {max}:1")
+            }
+        }
+    }
+}
+
+/// Resource limits for archives that come from an untrusted source.
+///
+/// Sizes are checked against the bytes that are actually extracted, not the sizes declared in the archive.
+/// `None` disables the corresponding check.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Limits {
+    pub max_total_size: Option<u64>,
+    pub max_entries: Option<usize>,
+    pub max_entry_size: Option<u64>,
+    pub max_compression_ratio: Option<u64>,
+}
+
 type OnArchiveInfo = Box<dyn FnOnce(usize)>;
 type OnZipFileFn = Box<dyn Fn(PathBuf) -> bool>;
 
@@ -50,39 +108,44 @@ where
     pub on_decrypted_header: Option<decrypt::OnDecryptedHeaderFn>,
     pub on_archive_info: Option<OnArchiveInfo>,
     pub on_zip_file: Option<OnZipFileFn>,
+    pub limits: Limits,
+    // The manifest of the previously restored archive, when restoring a chain of incremental archives
+    pub base: Option<&'a Manifest>,
+    pub progress: Option<Arc<dyn Progress>>,
+    pub cancel: Option<CancellationToken>,
 }
 
-pub fn execute<RW: Read + Write + Seek>(
+/// Returns the archive's manifest, if it has one, so the next archive of a chain can be restored on top.
+pub fn execute<RW, R>(
     stor: Arc<impl Storage<RW> + 'static>,
-    req: Request<'_, RW>,
-) -> Result<(), Error> {
-    // 1. Create temp zip archive.
-    let tmp_file = stor.create_temp_file().map_err(Error::Storage)?;
-
-    // 2. Decrypt input file to temp zip archive.
-    decrypt::execute(decrypt::Request {
+    req: Request<'_, R>,
+) -> Result<Option<Manifest>, Error>
+where
+    RW: Read + Write + Seek,
+    R: Read + Seek,
+{
+    let decrypt_parts = DecryptParts {
         header_reader: req.header_reader,
         reader: req.reader,
-        writer: tmp_file
-            .try_writer()
-            .expect("We sure that file in write mode"),
         raw_key: req.raw_key,
         on_decrypted_header: req.on_decrypted_header,
-    })
-    .map_err(Error::Decrypt)?;
-
-    let buf_capacity = stor.file_len(&tmp_file).map_err(Error::Storage)?;
-
-    // 3. Recover files from temp archive.
-    {
-        let mut reader = tmp_file
-            .try_reader()
-            .expect("We sure that file in read mode")
-            .borrow_mut();
+        progress: req.progress.clone(),
+        cancel: req.cancel.clone(),
+    };
 
-        reader.rewind().map_err(|_| Error::ResetCursorPosition)?;
+    let res = with_archive(&stor, decrypt_parts, |archive| {
+        if let Some(max_entries) = req.limits.max_entries {
+            if archive.len() > max_entries {
+                return Err(Error::LimitExceeded(Limit::Entries(max_entries)));
+            }
+        }
 
-        let mut archive = zip::ZipArchive::new(&mut *reader).map_err(|_| Error::OpenArchive)?;
+        let manifest = read_archived_manifest(archive, &req.limits)?;
+        if let Some(parent) = manifest.as_ref().and_then(|manifest| manifest.parent) {
+            if req.base.map(|base| base.id) != Some(parent) {
+                return Err(Error::BrokenChain);
+            }
+        }
 
         let output_dir = req.output_dir_path.clone();
 
@@ -90,25 +153,25 @@ pub fn execute<RW: Read + Write + Seek>(
         let entities = (0..archive.len())
             .filter_map(|i| {
                 let zip_file = archive.by_index(i).ok()?;
+                if zip_file.name() == MANIFEST_NAME {
+                    return None;
+                }
+
                 let mut full_path = output_dir.clone();
 
-                // Prevent zip slip attack
-                //
-                // Source: https://snyk.io/research/zip-slip-vulnerability
-                zip_file.enclosed_name().map(|path| {
-                    full_path.push(path);
+                archived_path(&zip_file).map(|path| {
+                    path.map(|path| {
+                        full_path.push(path);
 
-                    (full_path, i, zip_file.is_dir())
+                        (full_path, i, zip_file.is_dir())
+                    })
                 })
             })
-            .filter(|(full_path, ..)| {
-                if let Some(on_zip_file) = req.on_zip_file.as_ref() {
-                    on_zip_file(full_path.clone())
-                } else {
-                    true
-                }
+            .filter(|entity| match (entity, req.on_zip_file.as_ref()) {
+                (Ok((full_path, ..)), Some(on_zip_file)) => on_zip_file(full_path.clone()),
+                _ => true,
             })
-            .collect::<Vec<_>>();
+            .collect::<Result<Vec<_>, _>>()?;
 
         let files_count = entities.len();
         if let Some(on_archive_info) = req.on_archive_info {
@@ -134,44 +197,591 @@ pub fn execute<RW: Read + Write + Seek>(
             .try_for_each(|th| th.join().unwrap())?;
 
         // 6. create files
-        entities
-            .iter()
-            .filter(|(_, _, is_dir)| !*is_dir)
-            .try_for_each(|(full_path, i, _)| {
-                let mut zip_file = archive.by_index(*i).map_err(|_| Error::OpenArchivedFile)?;
-                let file = stor
-                    .create_file(full_path)
-                    .or_else(|_| stor.write_file(full_path))
-                    .map_err(Error::Storage)?;
-                std::io::copy(
-                    &mut zip_file,
-                    &mut *file.try_writer().map_err(Error::Storage)?.borrow_mut(),
-                )
-                .map_err(|_| Error::WriteData)?;
-                Ok(())
-            })?;
+        extract_files(
+            &*stor,
+            archive,
+            &entities,
+            &req.limits,
+            req.progress.as_deref(),
+            req.cancel.as_ref(),
+        )?;
+
+        // 7. remove paths that were deleted since the previous archive
+        if let Some(manifest) = manifest.as_ref() {
+            remove_deleted(&*stor, &output_dir, &manifest.deleted)?;
+        }
+
+        Ok(manifest)
+    });
+
+    if res.is_err() && is_cancelled(req.cancel.as_ref()) {
+        return Err(Error::Cancelled);
+    }
+
+    res
+}
+
+// Copies every file out of the archive.
+//
+// If the operation is cancelled, every file that was extracted is erased.
+fn extract_files<RW, R>(
+    stor: &impl Storage<RW>,
+    archive: &mut zip::ZipArchive<R>,
+    entities: &[(PathBuf, usize, bool)],
+    limits: &Limits,
+    progress: Option<&dyn Progress>,
+    cancel: Option<&CancellationToken>,
+) -> Result<(), Error>
+where
+    RW: Read + Write + Seek,
+    R: Read + Seek,
+{
+    if let Some(progress) = progress {
+        progress.phase(Phase::Decompressing);
+        progress.total(uncompressed_len(archive, entities));
+    }
+
+    let mut total_size = 0;
+    let mut extracted = Vec::new();
+    let res = entities
+        .iter()
+        .filter(|(_, _, is_dir)| !*is_dir)
+        .try_for_each(|(full_path, i, _)| {
+            let mut zip_file = archive.by_index(*i).map_err(|_| Error::OpenArchivedFile)?;
+            let compressed_size = zip_file.compressed_size();
+            let lz4 = pack::read_lz4_size(zip_file.extra_data().unwrap_or_default()).is_some();
+            if let Some(progress) = progress {
+                progress.file(full_path);
+            }
+
+            let file = stor
+                .create_file(full_path)
+                .or_else(|_| stor.write_file(full_path))
+                .map_err(Error::Storage)?;
+
+            // The limits are checked against the decompressed frames, as they're copied
+            let mut reader: Box<dyn Read> = if lz4 {
+                Box::new(lz4_flex::frame::FrameDecoder::new(&mut zip_file))
+            } else {
+                Box::new(&mut zip_file)
+            };
+
+            let copy_res = copy_with_limits(
+                &mut ProgressReader::new(CancellableReader::new(&mut reader, cancel), progress),
+                &mut *file.try_writer().map_err(Error::Storage)?.borrow_mut(),
+                compressed_size,
+                limits,
+                &mut total_size,
+            );
+
+            if copy_res.is_err() {
+                // Don't leave truncated files behind
+                if is_cancelled(cancel) {
+                    erase::erase_file(stor, file, &Scheme::Zeros, false, false, None).ok();
+                } else {
+                    stor.remove_file(file).ok();
+                }
+            } else {
+                extracted.push(full_path);
+            }
+
+            copy_res
+        });
+
+    if res.is_err() && is_cancelled(cancel) {
+        for path in extracted {
+            if let Ok(file) = stor.modify_file(path) {
+                erase::erase_file(stor, file, &Scheme::Zeros, false, false, None).ok();
+            }
+        }
     }
 
-    // 7. Finally eraze temp zip archive with zeros.
+    res
+}
+
+/// Decrypts an archive, and reads the manifest that's stored within it.
+///
+/// Returns `None` if the archive doesn't contain a manifest.
+pub fn read_manifest<RW, R>(
+    stor: Arc<impl Storage<RW>>,
+    reader: &RefCell<R>,
+    header_reader: Option<&RefCell<R>>,
+    raw_key: Protected<Vec<u8>>,
+) -> Result<Option<Manifest>, Error>
+where
+    RW: Read + Write + Seek,
+    R: Read + Seek,
+{
+    let decrypt_parts = DecryptParts {
+        header_reader,
+        reader,
+        raw_key,
+        on_decrypted_header: None,
+        progress: None,
+        cancel: None,
+    };
+    // The previous archive is one of the user's own, so its manifest isn't limited
+    with_archive(&stor, decrypt_parts, |archive| {
+        read_archived_manifest(archive, &Limits::default())
+    })
+}
+
+// Everything that's needed to decrypt the archive.
+struct DecryptParts<'a, R> {
+    header_reader: Option<&'a RefCell<R>>,
+    reader: &'a RefCell<R>,
+    raw_key: Protected<Vec<u8>>,
+    on_decrypted_header: Option<decrypt::OnDecryptedHeaderFn>,
+    progress: Option<Arc<dyn Progress>>,
+    cancel: Option<CancellationToken>,
+}
+
+// Decrypts the input to a temporary zip archive, and erases the archive once `f` is done with it.
+fn with_archive<RW, R, T>(
+    stor: &Arc<impl Storage<RW>>,
+    parts: DecryptParts<'_, R>,
+    f: impl FnOnce(&mut zip::ZipArchive<&mut RW>) -> Result<T, Error>,
+) -> Result<T, Error>
+where
+    RW: Read + Write + Seek,
+    R: Read + Seek,
+{
+    // 1. Create temp zip archive.
+    let tmp_file = stor.create_temp_file().map_err(Error::Storage)?;
+
+    // 2. Decrypt input file to temp zip archive.
+    let decrypt_res = decrypt::execute(decrypt::Request {
+        header_reader: parts.header_reader,
+        reader: parts.reader,
+        writer: tmp_file
+            .try_writer()
+            .expect("We sure that file in write mode"),
+        raw_key: parts.raw_key,
+        on_decrypted_header: parts.on_decrypted_header,
+        progress: parts.progress,
+        cancel: parts.cancel,
+    })
+    .map_err(Error::Decrypt);
+
+    let buf_capacity = stor.file_len(&tmp_file).map_err(Error::Storage)?;
+
+    // 3. Recover files from temp archive.
+    let res = decrypt_res.and_then(|()| {
+        let mut reader = tmp_file
+            .try_reader()
+            .expect("We sure that file in read mode")
+            .borrow_mut();
+
+        reader.rewind().map_err(|_| Error::ResetCursorPosition)?;
+
+        let mut archive = zip::ZipArchive::new(&mut *reader).map_err(|_| Error::OpenArchive)?;
+
+        f(&mut archive)
+    });
+
+    // Finally eraze temp zip archive with zeros.
     overwrite::execute(overwrite::Request {
         buf_capacity,
         writer: tmp_file
             .try_writer()
             .expect("We sure that file in write mode"),
-        passes: 1,
+        scheme: Scheme::Random(1),
+        sync: None,
+        progress: None,
+        cancel: None,
     })
     .ok();
 
     stor.remove_file(tmp_file).ok();
 
+    res
+}
+
+// The number of bytes that the archived files take up once they're decompressed.
+fn uncompressed_len<R: Read + Seek>(
+    archive: &mut zip::ZipArchive<R>,
+    entities: &[(PathBuf, usize, bool)],
+) -> u64 {
+    entities
+        .iter()
+        .filter(|(_, _, is_dir)| !*is_dir)
+        .filter_map(|(_, i, _)| {
+            let zip_file = archive.by_index(*i).ok()?;
+            pack::read_lz4_size(zip_file.extra_data().unwrap_or_default()).or(Some(zip_file.size()))
+        })
+        .sum()
+}
+
+fn read_archived_manifest<R: Read + Seek>(
+    archive: &mut zip::ZipArchive<R>,
+    limits: &Limits,
+) -> Result<Option<Manifest>, Error> {
+    let Ok(mut zip_file) = archive.by_name(MANIFEST_NAME) else {
+        return Ok(None);
+    };
+
+    // The manifest is read before anything is extracted, so it's limited like any other entry
+    let compressed_size = zip_file.compressed_size();
+    let mut content = Vec::new();
+    copy_with_limits(&mut zip_file, &mut content, compressed_size, limits, &mut 0)?;
+    let content = String::from_utf8(content).map_err(|_| Error::ReadData)?;
+
+    Manifest::deserialize(&content)
+        .map(Some)
+        .map_err(Error::ReadManifest)
+}
+
+// Removes deleted paths from the output directory, if they exist.
+fn remove_deleted<RW: Read + Write + Seek>(
+    stor: &impl Storage<RW>,
+    output_dir: &Path,
+    deleted: &[PathBuf],
+) -> Result<(), Error> {
+    deleted
+        .iter()
+        .filter_map(|path| enclosed_path(path.clone()))
+        .try_for_each(|path| {
+            let full_path = output_dir.join(path);
+
+            // Paths within deleted directories are already gone
+            let Ok(file) = stor.read_file(&full_path) else {
+                return Ok(());
+            };
+
+            if file.is_dir() {
+                stor.remove_dir_all(file)
+            } else {
+                // Files are truncated before they're removed, so they need to be writable
+                drop(file);
+                stor.write_file(&full_path)
+                    .and_then(|file| stor.remove_file(file))
+            }
+            .map_err(Error::Storage)
+        })
+}
+
+// Gets the path of an archived entry, relative to the output directory.
+//
+// Returns `None` if the path would escape the output directory.
+fn archived_path<R: Read>(zip_file: &zip::read::ZipFile<'_, R>) -> Option<Result<PathBuf, Error>> {
+    // Names that aren't valid UTF-8 are stored as raw bytes by `pack`
+    match pack::read_raw_name(zip_file.extra_data().unwrap_or_default()) {
+        Some(raw_name) => {
+            if let Some(path) = pack::bytes_to_path(raw_name) {
+                enclosed_path(path).map(Ok)
+            } else {
+                let name = String::from_utf8_lossy(raw_name).into_owned();
+                Some(Err(Error::UnsupportedPath(name)))
+            }
+        }
+        // Prevent zip slip attack
+        //
+        // Source: https://snyk.io/research/zip-slip-vulnerability
+        None => zip_file.enclosed_name().map(Ok),
+    }
+}
+
+// Only allows relative paths that stay within the output directory.
+pub(crate) fn enclosed_path(path: PathBuf) -> Option<PathBuf> {
+    path.components()
+        .all(|c| matches!(c, Component::Normal(_) | Component::CurDir))
+        .then_some(path)
+}
+
+// Drops the root, prefix and `..` components, so a path that's stored can always be restored
+// within an output directory.
+pub(crate) fn relative_path(path: &Path) -> PathBuf {
+    path.components()
+        .filter_map(|c| match c {
+            Component::Normal(name) => Some(name),
+            _ => None,
+        })
+        .collect()
+}
+
+// Copies an archived file to the writer while keeping track of the extracted bytes.
+//
+// Limits are checked before each block is written, so the output never grows beyond them.
+fn copy_with_limits<R: Read, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    compressed_size: u64,
+    limits: &Limits,
+    total_size: &mut u64,
+) -> Result<(), Error> {
+    let mut entry_size: u64 = 0;
+    let mut buffer = vec![0u8; BLOCK_SIZE].into_boxed_slice();
+
+    loop {
+        let read_count = reader.read(&mut buffer).map_err(|_| Error::ReadData)?;
+        if read_count == 0 {
+            break;
+        }
+
+        entry_size += read_count as u64;
+        *total_size += read_count as u64;
+
+        if let Some(max) = limits.max_entry_size {
+            if entry_size > max {
+                return Err(Error::LimitExceeded(Limit::EntrySize(max)));
+            }
+        }
+
+        if let Some(max) = limits.max_total_size {
+            if *total_size > max {
+                return Err(Error::LimitExceeded(Limit::TotalSize(max)));
+            }
+        }
+
+        if let Some(max) = limits.max_compression_ratio {
+            if entry_size > compressed_size.saturating_mul(max) {
+                return Err(Error::LimitExceeded(Limit::CompressionRatio(max)));
+            }
+        }
+
+        writer
+            .write_all(&buffer[..read_count])
+            .map_err(|_| Error::WriteData)?;
+    }
+
     Ok(())
 }
 
 #[cfg(test)]
 mod tests {
+    use super::*;
+    use std::io::Cursor;
+    use zip::write::SimpleFileOptions;
+
+    use crate::storage::InMemoryStorage;
+
+    fn make_archive(files: &[(&str, Vec<u8>)]) -> Vec<u8> {
+        let mut zip_writer = zip::ZipWriter::new(Cursor::new(vec![]));
+        let options = SimpleFileOptions::default().compression_method(zip::CompressionMethod::Zstd);
+
+        for (name, content) in files {
+            zip_writer.start_file(*name, options).unwrap();
+            zip_writer.write_all(content).unwrap();
+        }
+
+        zip_writer.finish().unwrap().into_inner()
+    }
+
+    fn extract_all(archive: Vec<u8>, limits: &Limits) -> Result<Vec<Vec<u8>>, Error> {
+        let mut archive = zip::ZipArchive::new(Cursor::new(archive)).unwrap();
+        let mut total_size = 0;
+
+        (0..archive.len())
+            .map(|i| {
+                let mut zip_file = archive.by_index(i).unwrap();
+                let compressed_size = zip_file.compressed_size();
+                let mut output = vec![];
+                copy_with_limits(
+                    &mut zip_file,
+                    &mut output,
+                    compressed_size,
+                    limits,
+                    &mut total_size,
+                )?;
+                Ok(output)
+            })
+            .collect()
+    }
+
     #[test]
     #[ignore = "not yet implemented"]
     fn should_unpack_encrypted_archive() {
         todo!()
     }
+
+    #[test]
+    fn should_only_allow_enclosed_raw_paths() {
+        assert_eq!(
+            enclosed_path(PathBuf::from("bar/./hello.txt")),
+            Some(PathBuf::from("bar/./hello.txt"))
+        );
+        assert_eq!(enclosed_path(PathBuf::from("../hello.txt")), None);
+        assert_eq!(enclosed_path(PathBuf::from("bar/../../hello.txt")), None);
+        assert_eq!(enclosed_path(PathBuf::from("/etc/shadow")), None);
+    }
+
+    #[test]
+    fn should_extract_files_within_limits() {
+        let archive = make_archive(&[
+            ("hello.txt", b"hello".to_vec()),
+            ("world.txt", b"world".to_vec()),
+        ]);
+        let limits = Limits {
+            max_total_size: Some(10),
+            max_entries: Some(2),
+            max_entry_size: Some(5),
+            max_compression_ratio: Some(100),
+        };
+
+        match extract_all(archive, &limits) {
+            Ok(files) => assert_eq!(files, vec![b"hello".to_vec(), b"world".to_vec()]),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn should_stop_on_compression_ratio() {
+        let archive = make_archive(&[("zeros.bin", vec![0u8; BLOCK_SIZE * 16])]);
+        let limits = Limits {
+            max_compression_ratio: Some(100),
+            ..Limits::default()
+        };
+
+        match extract_all(archive, &limits) {
+            Err(Error::LimitExceeded(Limit::CompressionRatio(100))) => {}
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn should_stop_on_total_size() {
+        let archive = make_archive(&[
+            ("hello.txt", b"hello".to_vec()),
+            ("world.txt", b"world".to_vec()),
+        ]);
+        let limits = Limits {
+            max_total_size: Some(8),
+            ..Limits::default()
+        };
+
+        match extract_all(archive, &limits) {
+            Err(Error::LimitExceeded(Limit::TotalSize(8))) => {}
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn should_stop_on_entry_size() {
+        let archive = make_archive(&[("hello.txt", b"hello world".to_vec())]);
+        let limits = Limits {
+            max_entry_size: Some(5),
+            ..Limits::default()
+        };
+
+        match extract_all(archive, &limits) {
+            Err(Error::LimitExceeded(Limit::EntrySize(5))) => {}
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn should_extract_lz4_frames_within_limits() {
+        let content = vec![0u8; BLOCK_SIZE * 16];
+
+        let mut zip_writer = zip::ZipWriter::new(Cursor::new(vec![]));
+        let mut options = zip::write::FullFileOptions::default()
+            .compression_method(zip::CompressionMethod::Stored);
+        options
+            .add_extra_data(
+                pack::LZ4_FIELD_ID,
+                Box::new((content.len() as u64).to_le_bytes()),
+                false,
+            )
+            .unwrap();
+        zip_writer.start_file("zeros.bin", options).unwrap();
+        let mut encoder = lz4_flex::frame::FrameEncoder::new(&mut zip_writer);
+        encoder.write_all(&content).unwrap();
+        encoder.finish().unwrap();
+        let archive = zip_writer.finish().unwrap();
+
+        let mut archive = zip::ZipArchive::new(archive).unwrap();
+        let entities = [(PathBuf::from("zeros.bin"), 0, false)];
+        assert_eq!(
+            uncompressed_len(&mut archive, &entities),
+            content.len() as u64
+        );
+
+        let stor = InMemoryStorage::default();
+        let limits = Limits {
+            max_compression_ratio: Some(100),
+            ..Limits::default()
+        };
+        match extract_files(&stor, &mut archive, &entities, &limits, None, None) {
+            Err(Error::LimitExceeded(Limit::CompressionRatio(100))) => {}
+            _ => unreachable!(),
+        }
+
+        assert!(extract_files(
+            &stor,
+            &mut archive,
+            &entities,
+            &Limits::default(),
+            None,
+            None
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn should_read_archived_manifest() {
+        let manifest = Manifest::new(None);
+        let content = manifest.serialize().unwrap().into_bytes();
+        let archive = make_archive(&[("hello.txt", vec![1]), (MANIFEST_NAME, content)]);
+
+        let mut archive = zip::ZipArchive::new(Cursor::new(archive)).unwrap();
+        assert_eq!(
+            read_archived_manifest(&mut archive, &Limits::default()).unwrap(),
+            Some(manifest)
+        );
+
+        let archive = make_archive(&[("hello.txt", vec![1])]);
+        let mut archive = zip::ZipArchive::new(Cursor::new(archive)).unwrap();
+        assert_eq!(
+            read_archived_manifest(&mut archive, &Limits::default()).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn should_limit_archived_manifest() {
+        let archive = make_archive(&[(MANIFEST_NAME, vec![b' '; BLOCK_SIZE * 16])]);
+        let mut archive = zip::ZipArchive::new(Cursor::new(archive)).unwrap();
+
+        let limits = Limits {
+            max_entry_size: Some(BLOCK_SIZE as u64),
+            ..Limits::default()
+        };
+        match read_archived_manifest(&mut archive, &limits) {
+            Err(Error::LimitExceeded(Limit::EntrySize(_))) => {}
+            _ => unreachable!(),
+        }
+
+        let limits = Limits {
+            max_compression_ratio: Some(10),
+            ..Limits::default()
+        };
+        match read_archived_manifest(&mut archive, &limits) {
+            Err(Error::LimitExceeded(Limit::CompressionRatio(10))) => {}
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn should_remove_deleted_paths() {
+        let stor = InMemoryStorage::default();
+        stor.add_hello_txt();
+        stor.add_bar_foo_folder();
+
+        let deleted = [
+            "bar/foo/",
+            "bar/foo/hello.txt",
+            "bar/hello.txt",
+            "../hello.txt",
+        ]
+        .map(PathBuf::from);
+        remove_deleted(&stor, Path::new(""), &deleted).unwrap();
+
+        let mut remaining = stor.files().keys().cloned().collect::<Vec<_>>();
+        remaining.sort();
+
+        assert_eq!(
+            remaining,
+            ["bar/", "bar/world.txt", "hello.txt"].map(PathBuf::from)
+        );
+    }
 }
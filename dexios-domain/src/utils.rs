@@ -1,3 +1,89 @@
+#[must_use]
+pub fn hex_encode(bytes: &[u8]) -> String {
+    use std::fmt::Write;
+
+    bytes.iter().fold(String::new(), |mut acc, b| {
+        let _ = write!(acc, "{b:02x}");
+        acc
+    })
+}
+
+#[must_use]
+pub fn hex_decode(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+// RFC 4648 base32, without padding. It only uses lowercase letters and digits, so it's safe for file names on case-insensitive file systems.
+const BASE32_ALPHABET: &[u8; 32] = b"abcdefghijklmnopqrstuvwxyz234567";
+
+#[must_use]
+pub fn base32_encode(bytes: &[u8]) -> String {
+    let mut encoded = String::with_capacity((bytes.len() * 8).div_ceil(5));
+    let mut buffer: u16 = 0;
+    let mut bits = 0;
+
+    for byte in bytes {
+        buffer = (buffer << 8) | u16::from(*byte);
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            encoded.push(char::from(
+                BASE32_ALPHABET[usize::from((buffer >> bits) & 0x1f)],
+            ));
+        }
+    }
+
+    if bits > 0 {
+        encoded.push(char::from(
+            BASE32_ALPHABET[usize::from((buffer << (5 - bits)) & 0x1f)],
+        ));
+    }
+
+    encoded
+}
+
+#[must_use]
+pub fn base32_decode(encoded: &str) -> Option<Vec<u8>> {
+    let mut decoded = Vec::with_capacity(encoded.len() * 5 / 8);
+    let mut buffer: u16 = 0;
+    let mut bits = 0;
+
+    for c in encoded.bytes() {
+        let value = BASE32_ALPHABET
+            .iter()
+            .position(|a| *a == c.to_ascii_lowercase())?;
+        buffer = (buffer << 5) | u16::try_from(value).ok()?;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            decoded.push(u8::try_from((buffer >> bits) & 0xff).ok()?);
+        }
+    }
+
+    Some(decoded)
+}
+
+#[cfg(test)]
+pub use test::gen_master_key;
+#[cfg(test)]
+pub use test::gen_nonce;
+#[cfg(test)]
+pub use test::gen_salt;
+
+#[cfg(not(test))]
+pub use core::primitives::gen_master_key;
+#[cfg(not(test))]
+pub use core::primitives::gen_nonce;
+#[cfg(not(test))]
+pub use core::primitives::gen_salt;
+
 // TODO(pleshevskiy): dedup these utils
 
 #[cfg(test)]
@@ -32,25 +118,3 @@ mod test {
         Protected::new(master_key)
     }
 }
-
-#[must_use]
-pub fn hex_encode(bytes: &[u8]) -> String {
-    bytes
-        .iter()
-        .map(|b| format!("{b:02x}"))
-        .collect::<String>()
-}
-
-#[cfg(test)]
-pub use test::gen_master_key;
-#[cfg(test)]
-pub use test::gen_nonce;
-#[cfg(test)]
-pub use test::gen_salt;
-
-#[cfg(not(test))]
-pub use core::primitives::gen_master_key;
-#[cfg(not(test))]
-pub use core::primitives::gen_nonce;
-#[cfg(not(test))]
-pub use core::primitives::gen_salt;
@@ -0,0 +1,461 @@
+//! This contains an encrypted vault - a single file that files can be added to, extracted from and removed from, without rewriting the whole file.
+//!
+//! A vault starts with a regular Dexios header, and the master key is protected by the header's keyslots, so the usual `key` operations work on vaults too.
+//!
+//! The header is followed by encrypted segments, which are only ever appended:
+//!
+//! - chunk segments contain up to `BLOCK_SIZE` bytes of a file's content
+//! - index segments list every file, along with where its chunks are
+//!
+//! Every change appends a new index, and the last complete index is the vault's current state. This means that an interrupted write leaves the previous state intact, while an index that was tampered with stops the vault from opening. Files that are removed or replaced stay within the vault until it's compacted.
+//!
+//! Each segment is stored as its kind (1 byte), the length of its content (8 bytes, little-endian), the nonce and the ciphertext. The AAD binds every segment to its kind and offset, so segments can't be moved or swapped.
+
+use std::cell::RefCell;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use core::cipher::Ciphers;
+use core::header::Header;
+use core::key::decrypt_master_key;
+use core::primitives::{Algorithm, Mode, MASTER_KEY_LEN};
+use core::protected::Protected;
+use core::Payload;
+
+use crate::pack::{bytes_to_path, path_to_bytes};
+use crate::storage;
+use crate::utils::{gen_nonce, hex_decode, hex_encode};
+
+pub mod add;
+pub mod compact;
+pub mod create;
+pub mod get;
+pub mod remove;
+
+const INDEX_VERSION: &str = "dexios-vault-index 1";
+
+// The kind and the length of the content
+const SEGMENT_HEADER_LEN: u64 = 9;
+
+const CHUNK_SEGMENT: u8 = 1;
+const INDEX_SEGMENT: u8 = 2;
+
+#[derive(Debug)]
+pub enum Error {
+    ReadHeader,
+    WriteHeader,
+    HashKey,
+    EncryptMasterKey,
+    DecryptMasterKey,
+    InitializeChiphers,
+    EncryptData,
+    DecryptData,
+    ReadData,
+    WriteData,
+    Seek,
+    MissingIndex,
+    InvalidIndex,
+    CorruptSegment(u64),
+    FileNotFound(PathBuf),
+    UnsupportedPath(PathBuf),
+    Storage(storage::Error),
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::ReadHeader => f.write_str("Unable to read the vault's header"),
+            Error::WriteHeader => f.write_str("Unable to write the vault's header"),
+            Error::HashKey => f.write_str("Cannot hash raw key"),
+            Error::EncryptMasterKey => f.write_str("Cannot encrypt master key"),
+            Error::DecryptMasterKey => f.write_str("Cannot decrypt master key"),
+            Error::InitializeChiphers => f.write_str("Cannot initialize chiphers"),
+            Error::EncryptData => f.write_str("Unable to encrypt data"),
+            Error::DecryptData => f.write_str("Unable to decrypt data"),
+            Error::ReadData => f.write_str("Unable to read data"),
+            Error::WriteData => f.write_str("Unable to write data"),
+            Error::Seek => f.write_str("Unable to seek within the vault"),
+            Error::MissingIndex => f.write_str("Unable to find the vault's index"),
+            Error::InvalidIndex => f.write_str("The vault's index is invalid"),
+            Error::CorruptSegment(offset) => {
+                write!(f, "The segment at offset {offset} is corrupted")
+            }
+            Error::FileNotFound(path) => {
+                write!(f, "Unable to find {} within the vault", path.display())
+            }
+            Error::UnsupportedPath(path) => {
+                write!(f, "Unable to store path in vault: {}", path.display())
+            }
+            Error::Storage(inner) => write!(f, "Storage error: {inner}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// A file within the vault.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VaultEntry {
+    pub path: PathBuf,
+    pub size: u64,
+    // The offsets of the file's chunk segments, in order
+    chunks: Vec<u64>,
+}
+
+/// An unlocked vault.
+pub struct Vault {
+    ciphers: Ciphers,
+    algorithm: Algorithm,
+    header_len: u64,
+    // Sorted by path
+    entries: Vec<VaultEntry>,
+    // Where the next segment is appended
+    end: u64,
+}
+
+impl Vault {
+    /// Unlocks the vault with one of its keys, and reads its latest index.
+    pub fn open<R>(reader: &RefCell<R>, raw_key: Protected<Vec<u8>>) -> Result<Self, Error>
+    where
+        R: Read + Seek,
+    {
+        let mut reader = reader.borrow_mut();
+        reader.rewind().map_err(|_| Error::Seek)?;
+        let (header, _) = Header::deserialize(&mut *reader).map_err(|_| Error::ReadHeader)?;
+
+        let master_key =
+            decrypt_master_key(raw_key, &header).map_err(|_| Error::DecryptMasterKey)?;
+        let mut vault =
+            Self::with_master_key(master_key, header.header_type.algorithm, header.get_size())?;
+
+        vault.load(&mut *reader)?;
+        Ok(vault)
+    }
+
+    pub(crate) fn with_master_key(
+        master_key: Protected<[u8; MASTER_KEY_LEN]>,
+        algorithm: Algorithm,
+        header_len: u64,
+    ) -> Result<Self, Error> {
+        let ciphers =
+            Ciphers::initialize(master_key, &algorithm).map_err(|_| Error::InitializeChiphers)?;
+
+        Ok(Self {
+            ciphers,
+            algorithm,
+            header_len,
+            entries: Vec::new(),
+            end: header_len,
+        })
+    }
+
+    /// Every file within the vault, sorted by path.
+    #[must_use]
+    pub fn entries(&self) -> &[VaultEntry] {
+        &self.entries
+    }
+
+    fn entry(&self, path: &Path) -> Option<&VaultEntry> {
+        self.entries
+            .binary_search_by(|entry| entry.path.as_path().cmp(path))
+            .ok()
+            .map(|i| &self.entries[i])
+    }
+
+    // Adds the entry, or replaces the one with the same path.
+    fn insert(&mut self, entry: VaultEntry) {
+        match self
+            .entries
+            .binary_search_by(|other| other.path.cmp(&entry.path))
+        {
+            Ok(i) => self.entries[i] = entry,
+            Err(i) => self.entries.insert(i, entry),
+        }
+    }
+
+    fn remove(&mut self, path: &Path) -> Option<VaultEntry> {
+        self.entries
+            .binary_search_by(|entry| entry.path.as_path().cmp(path))
+            .ok()
+            .map(|i| self.entries.remove(i))
+    }
+
+    // Scans every segment, and reads the last complete index.
+    fn load<R: Read + Seek>(&mut self, reader: &mut R) -> Result<(), Error> {
+        let len = reader.seek(SeekFrom::End(0)).map_err(|_| Error::Seek)?;
+
+        let mut offset = self.header_len;
+        let mut index = None;
+        while offset + SEGMENT_HEADER_LEN <= len {
+            reader
+                .seek(SeekFrom::Start(offset))
+                .map_err(|_| Error::Seek)?;
+            let (kind, content_len) = read_segment_header(reader)?;
+
+            // The last segment may be incomplete if a write was interrupted
+            let next = match (offset + SEGMENT_HEADER_LEN).checked_add(content_len) {
+                Some(next) if next <= len => next,
+                _ => break,
+            };
+
+            if kind == INDEX_SEGMENT {
+                index = Some((offset, next));
+            }
+            offset = next;
+        }
+
+        // A complete index that can't be decrypted has been tampered with, so we don't fall back to an older state
+        let (offset, next) = index.ok_or(Error::MissingIndex)?;
+        let content = self.read_segment(reader, INDEX_SEGMENT, offset)?;
+        self.entries = deserialize_index(&content)?;
+
+        // Anything after the current index is left over from an interrupted write, so it's overwritten by the next change
+        self.end = next;
+        Ok(())
+    }
+
+    // Encrypts and appends a segment, and returns its offset.
+    fn write_segment<W: Write + Seek>(
+        &mut self,
+        writer: &mut W,
+        kind: u8,
+        data: &[u8],
+    ) -> Result<u64, Error> {
+        let offset = self.end;
+
+        let mut nonce = gen_nonce(&self.algorithm, &Mode::MemoryMode);
+        let encrypted = self
+            .ciphers
+            .encrypt(
+                &nonce,
+                Payload {
+                    aad: &segment_aad(kind, offset),
+                    msg: data,
+                },
+            )
+            .map_err(|_| Error::EncryptData)?;
+        nonce.extend_from_slice(&encrypted);
+
+        writer
+            .seek(SeekFrom::Start(offset))
+            .map_err(|_| Error::Seek)?;
+        writer.write_all(&[kind]).map_err(|_| Error::WriteData)?;
+        writer
+            .write_all(&(nonce.len() as u64).to_le_bytes())
+            .map_err(|_| Error::WriteData)?;
+        writer.write_all(&nonce).map_err(|_| Error::WriteData)?;
+
+        self.end = offset + SEGMENT_HEADER_LEN + nonce.len() as u64;
+        Ok(offset)
+    }
+
+    fn read_segment<R: Read + Seek>(
+        &self,
+        reader: &mut R,
+        kind: u8,
+        offset: u64,
+    ) -> Result<Vec<u8>, Error> {
+        reader
+            .seek(SeekFrom::Start(offset))
+            .map_err(|_| Error::Seek)?;
+        let (actual_kind, len) = read_segment_header(reader)?;
+        if actual_kind != kind {
+            return Err(Error::CorruptSegment(offset));
+        }
+
+        let mut data = vec![0u8; usize::try_from(len).map_err(|_| Error::CorruptSegment(offset))?];
+        reader
+            .read_exact(&mut data)
+            .map_err(|_| Error::CorruptSegment(offset))?;
+
+        let nonce_len = core::primitives::get_nonce_len(&self.algorithm, &Mode::MemoryMode);
+        if data.len() < nonce_len {
+            return Err(Error::CorruptSegment(offset));
+        }
+
+        let (nonce, msg) = data.split_at(nonce_len);
+        self.ciphers
+            .decrypt(
+                nonce,
+                Payload {
+                    aad: &segment_aad(kind, offset),
+                    msg,
+                },
+            )
+            .map_err(|_| Error::CorruptSegment(offset))
+    }
+
+    // Appends the current list of files, which makes it the vault's state.
+    fn write_index<W: Write + Seek>(&mut self, writer: &mut W) -> Result<(), Error> {
+        let content = serialize_index(&self.entries)?;
+        self.write_segment(writer, INDEX_SEGMENT, &content)?;
+        writer.flush().map_err(|_| Error::WriteData)
+    }
+}
+
+fn read_segment_header<R: Read>(reader: &mut R) -> Result<(u8, u64), Error> {
+    let mut buf = [0u8; 9];
+    reader.read_exact(&mut buf).map_err(|_| Error::ReadData)?;
+
+    let mut len = [0u8; 8];
+    len.copy_from_slice(&buf[1..]);
+    Ok((buf[0], u64::from_le_bytes(len)))
+}
+
+fn segment_aad(kind: u8, offset: u64) -> Vec<u8> {
+    let mut aad = vec![kind];
+    aad.extend_from_slice(&offset.to_le_bytes());
+    aad
+}
+
+fn serialize_index(entries: &[VaultEntry]) -> Result<Vec<u8>, Error> {
+    let mut lines = vec![INDEX_VERSION.to_string()];
+
+    for entry in entries {
+        let path = path_to_bytes(&entry.path)
+            .map(hex_encode)
+            .ok_or_else(|| Error::UnsupportedPath(entry.path.clone()))?;
+
+        let mut line = format!("f {path} {}", entry.size);
+        for offset in &entry.chunks {
+            line.push(' ');
+            line.push_str(&offset.to_string());
+        }
+        lines.push(line);
+    }
+
+    lines.push(String::new());
+    Ok(lines.join("\n").into_bytes())
+}
+
+fn deserialize_index(content: &[u8]) -> Result<Vec<VaultEntry>, Error> {
+    let content = std::str::from_utf8(content).map_err(|_| Error::InvalidIndex)?;
+    let mut lines = content.lines();
+    if lines.next() != Some(INDEX_VERSION) {
+        return Err(Error::InvalidIndex);
+    }
+
+    let mut entries = lines
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            let fields = line.split(' ').collect::<Vec<_>>();
+            let ["f", path, size, ref chunks @ ..] = fields[..] else {
+                return Err(Error::InvalidIndex);
+            };
+
+            Ok(VaultEntry {
+                path: hex_decode(path)
+                    .and_then(|bytes| bytes_to_path(&bytes))
+                    .ok_or(Error::InvalidIndex)?,
+                size: size.parse().map_err(|_| Error::InvalidIndex)?,
+                chunks: chunks
+                    .iter()
+                    .map(|offset| offset.parse().map_err(|_| Error::InvalidIndex))
+                    .collect::<Result<_, _>>()?,
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    entries.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(entries)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::utils::gen_master_key;
+    use std::io::Cursor;
+
+    // Unlocking a vault with a key is slow, so tests use the master key directly
+    pub fn create_vault() -> (Vault, RefCell<Cursor<Vec<u8>>>) {
+        // The header's content doesn't matter, as long as the segments start after it
+        let header_len = 416;
+        let mut vault = Vault::with_master_key(
+            gen_master_key(),
+            Algorithm::XChaCha20Poly1305,
+            header_len as u64,
+        )
+        .unwrap();
+
+        let mut cursor = Cursor::new(vec![0u8; header_len]);
+        vault.write_index(&mut cursor).unwrap();
+
+        (vault, RefCell::new(cursor))
+    }
+
+    // Reloads the vault from the handle, just like `Vault::open`.
+    pub fn reload(vault: &Vault, handle: &RefCell<Cursor<Vec<u8>>>) -> Vec<VaultEntry> {
+        let mut reloaded =
+            Vault::with_master_key(gen_master_key(), vault.algorithm, vault.header_len).unwrap();
+        reloaded.load(&mut *handle.borrow_mut()).unwrap();
+        assert_eq!(reloaded.end, vault.end);
+        reloaded.entries
+    }
+
+    #[test]
+    fn should_serialize_and_deserialize_index() {
+        let entries = vec![
+            VaultEntry {
+                path: PathBuf::from("bar/empty.txt"),
+                size: 0,
+                chunks: vec![],
+            },
+            VaultEntry {
+                path: PathBuf::from("bar/hello world.txt"),
+                size: 11,
+                chunks: vec![416, 1024],
+            },
+        ];
+
+        let content = serialize_index(&entries).unwrap();
+
+        assert_eq!(deserialize_index(&content).unwrap(), entries);
+    }
+
+    #[test]
+    fn should_ignore_incomplete_segment() {
+        let (mut vault, handle) = create_vault();
+        vault.insert(VaultEntry {
+            path: PathBuf::from("hello.txt"),
+            size: 0,
+            chunks: vec![],
+        });
+        vault.write_index(&mut *handle.borrow_mut()).unwrap();
+
+        // An interrupted write leaves part of a segment behind
+        let end = vault.end;
+        vault.write_index(&mut *handle.borrow_mut()).unwrap();
+        handle
+            .borrow_mut()
+            .get_mut()
+            .truncate(usize::try_from(end).unwrap() + 20);
+        vault.end = end;
+
+        assert_eq!(reload(&vault, &handle), vault.entries);
+    }
+
+    #[test]
+    fn should_detect_moved_segments() {
+        let (mut vault, handle) = create_vault();
+        let offset = vault
+            .write_segment(&mut *handle.borrow_mut(), CHUNK_SEGMENT, b"hello world")
+            .unwrap();
+
+        // Copy the chunk to the end of the vault
+        let segment = handle.borrow().get_ref()[usize::try_from(offset).unwrap()..].to_vec();
+        let moved = handle.borrow().get_ref().len() as u64;
+        handle.borrow_mut().get_mut().extend_from_slice(&segment);
+
+        let mut reader = handle.borrow_mut();
+        assert_eq!(
+            vault
+                .read_segment(&mut *reader, CHUNK_SEGMENT, offset)
+                .unwrap(),
+            b"hello world"
+        );
+        match vault.read_segment(&mut *reader, CHUNK_SEGMENT, moved) {
+            Err(Error::CorruptSegment(corrupt)) => assert_eq!(corrupt, moved),
+            _ => unreachable!(),
+        }
+    }
+}
@@ -0,0 +1,148 @@
+//! This adds files to a vault.
+//!
+//! Files that are already within the vault are replaced, and the old content stays within the vault until it's compacted.
+
+use std::cell::RefCell;
+use std::io::{Read, Seek, Write};
+
+use core::primitives::BLOCK_SIZE;
+
+use super::{Error, Vault, VaultEntry, CHUNK_SEGMENT};
+use crate::pack::path_to_bytes;
+use crate::storage::{Paths, Storage};
+use crate::unpack::relative_path;
+
+pub struct Request<'a, W>
+where
+    W: Write + Seek,
+{
+    pub handle: &'a RefCell<W>,
+    pub vault: &'a mut Vault,
+    // Paths are stored relative to their root (without any `..`), and directories are skipped
+    pub paths: Paths<'a>,
+}
+
+pub fn execute<RW, W>(stor: &impl Storage<RW>, req: Request<'_, W>) -> Result<usize, Error>
+where
+    RW: Read + Write + Seek,
+    W: Write + Seek,
+{
+    let vault = req.vault;
+    let mut handle = req.handle.borrow_mut();
+    let mut added = 0;
+
+    for path in req.paths {
+        let path = path.map_err(Error::Storage)?;
+        if path_to_bytes(&path).is_none() {
+            return Err(Error::UnsupportedPath(path));
+        }
+
+        let file = stor.read_file(&path).map_err(Error::Storage)?;
+        if file.is_dir() {
+            continue;
+        }
+        let path = relative_path(&path);
+
+        let mut reader = file.try_reader().map_err(Error::Storage)?.borrow_mut();
+        let mut buffer = vec![0u8; BLOCK_SIZE].into_boxed_slice();
+        let mut size = 0;
+        let mut chunks = Vec::new();
+
+        loop {
+            let read_count = read_block(&mut *reader, &mut buffer)?;
+            if read_count == 0 {
+                break;
+            }
+
+            chunks.push(vault.write_segment(&mut *handle, CHUNK_SEGMENT, &buffer[..read_count])?);
+            size += read_count as u64;
+        }
+
+        vault.insert(VaultEntry { path, size, chunks });
+        added += 1;
+    }
+
+    // The files only become part of the vault once the new index is written
+    vault.write_index(&mut *handle)?;
+
+    Ok(added)
+}
+
+// Fills the buffer, unless the reader runs out of data first.
+fn read_block(reader: &mut impl Read, buffer: &mut [u8]) -> Result<usize, Error> {
+    let mut read_count = 0;
+    while read_count < buffer.len() {
+        match reader.read(&mut buffer[read_count..]) {
+            Ok(0) => break,
+            Ok(n) => read_count += n,
+            Err(err) if err.kind() == std::io::ErrorKind::Interrupted => {}
+            Err(_) => return Err(Error::ReadData),
+        }
+    }
+    Ok(read_count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    use crate::storage::InMemoryStorage;
+    use crate::vault::tests::{create_vault, reload};
+
+    fn paths(paths: &[&str]) -> Paths<'static> {
+        let paths = paths
+            .iter()
+            .map(|path| Ok(PathBuf::from(path)))
+            .collect::<Vec<_>>();
+        Box::new(paths.into_iter())
+    }
+
+    #[test]
+    fn should_add_files() {
+        let stor = InMemoryStorage::default();
+        stor.add_bar_foo_folder();
+        let (mut vault, handle) = create_vault();
+
+        let req = Request {
+            handle: &handle,
+            vault: &mut vault,
+            paths: paths(&["bar/", "bar/hello.txt", "bar/foo/world.txt"]),
+        };
+
+        assert_eq!(execute(&stor, req).unwrap(), 2);
+
+        let entries = reload(&vault, &handle);
+        assert_eq!(entries, vault.entries());
+        assert_eq!(
+            entries
+                .iter()
+                .map(|entry| (entry.path.clone(), entry.size))
+                .collect::<Vec<_>>(),
+            vec![
+                (PathBuf::from("bar/foo/world.txt"), 5),
+                (PathBuf::from("bar/hello.txt"), 5),
+            ]
+        );
+    }
+
+    #[test]
+    fn should_replace_existing_files() {
+        let stor = InMemoryStorage::default();
+        stor.add_hello_txt();
+        let (mut vault, handle) = create_vault();
+
+        for _ in 0..2 {
+            let req = Request {
+                handle: &handle,
+                vault: &mut vault,
+                paths: paths(&["hello.txt"]),
+            };
+            execute(&stor, req).unwrap();
+        }
+
+        let entries = reload(&vault, &handle);
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries, vault.entries());
+    }
+}
@@ -0,0 +1,119 @@
+//! This rewrites a vault without the content of removed and replaced files.
+//!
+//! The header is copied as-is, so the vault keeps its keys.
+
+use std::cell::RefCell;
+use std::io::{Read, Seek, Write};
+
+use super::{Error, Vault, CHUNK_SEGMENT};
+
+pub struct Request<'a, R, W>
+where
+    R: Read + Seek,
+    W: Write + Seek,
+{
+    pub reader: &'a RefCell<R>,
+    // This should be a new file - it's only a valid vault once the compaction finishes
+    pub writer: &'a RefCell<W>,
+    pub vault: &'a mut Vault,
+}
+
+pub fn execute<R, W>(req: Request<'_, R, W>) -> Result<(), Error>
+where
+    R: Read + Seek,
+    W: Write + Seek,
+{
+    let vault = req.vault;
+    let mut reader = req.reader.borrow_mut();
+    let mut writer = req.writer.borrow_mut();
+
+    let mut header = vec![0u8; usize::try_from(vault.header_len).map_err(|_| Error::ReadHeader)?];
+    reader.rewind().map_err(|_| Error::Seek)?;
+    reader
+        .read_exact(&mut header)
+        .map_err(|_| Error::ReadHeader)?;
+    writer.rewind().map_err(|_| Error::Seek)?;
+    writer.write_all(&header).map_err(|_| Error::WriteHeader)?;
+
+    let mut entries = vault.entries.clone();
+    vault.end = vault.header_len;
+
+    for entry in &mut entries {
+        for offset in &mut entry.chunks {
+            let data = vault.read_segment(&mut *reader, CHUNK_SEGMENT, *offset)?;
+            *offset = vault.write_segment(&mut *writer, CHUNK_SEGMENT, &data)?;
+        }
+    }
+
+    vault.entries = entries;
+    vault.write_index(&mut *writer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+    use std::path::PathBuf;
+
+    use crate::storage::InMemoryStorage;
+    use crate::vault::tests::{create_vault, reload};
+    use crate::vault::{add, get, remove};
+
+    #[test]
+    fn should_drop_removed_content() {
+        let stor = InMemoryStorage::default();
+        stor.add_bar_foo_folder();
+        let (mut vault, handle) = create_vault();
+
+        let paths = ["bar/hello.txt", "bar/foo/world.txt"]
+            .iter()
+            .map(|path| Ok(PathBuf::from(path)))
+            .collect::<Vec<_>>();
+        add::execute(
+            &stor,
+            add::Request {
+                handle: &handle,
+                vault: &mut vault,
+                paths: Box::new(paths.into_iter()),
+            },
+        )
+        .unwrap();
+        remove::execute(remove::Request {
+            handle: &handle,
+            vault: &mut vault,
+            paths: vec![PathBuf::from("bar/hello.txt")],
+        })
+        .unwrap();
+
+        let compacted = RefCell::new(Cursor::new(Vec::new()));
+        execute(Request {
+            reader: &handle,
+            writer: &compacted,
+            vault: &mut vault,
+        })
+        .unwrap();
+
+        assert!(compacted.borrow().get_ref().len() < handle.borrow().get_ref().len());
+        let header_len = usize::try_from(vault.header_len).unwrap();
+        assert_eq!(
+            compacted.borrow().get_ref()[..header_len],
+            handle.borrow().get_ref()[..header_len]
+        );
+        assert_eq!(reload(&vault, &compacted), vault.entries());
+
+        get::execute(
+            &stor,
+            get::Request {
+                handle: &compacted,
+                vault: &vault,
+                paths: vec![],
+                output_dir_path: PathBuf::from("out"),
+                on_file: None,
+            },
+        )
+        .unwrap();
+        assert!(stor
+            .files()
+            .contains_key(&PathBuf::from("out/bar/foo/world.txt")));
+    }
+}
@@ -0,0 +1,108 @@
+//! This creates an empty vault.
+
+use std::cell::RefCell;
+use std::io::{Seek, Write};
+
+use core::header::{HashingAlgorithm, HeaderType};
+use core::protected::Protected;
+
+use super::{Error, Vault};
+
+pub struct Request<'a, W>
+where
+    W: Write + Seek,
+{
+    pub handle: &'a RefCell<W>,
+    pub raw_key: Protected<Vec<u8>>,
+    // TODO: don't use external types in logic
+    pub header_type: HeaderType,
+    pub hashing_algorithm: HashingAlgorithm,
+}
+
+pub fn execute<W>(req: Request<'_, W>) -> Result<Vault, Error>
+where
+    W: Write + Seek,
+{
+    let (header, master_key) =
+        crate::key::create_header(req.raw_key, req.header_type, req.hashing_algorithm).map_err(
+            |err| match err {
+                crate::key::Error::KeyHash => Error::HashKey,
+                _ => Error::EncryptMasterKey,
+            },
+        )?;
+
+    let mut handle = req.handle.borrow_mut();
+    handle.rewind().map_err(|_| Error::Seek)?;
+    header.write(&mut *handle).map_err(|_| Error::WriteHeader)?;
+
+    let mut vault =
+        Vault::with_master_key(master_key, header.header_type.algorithm, header.get_size())?;
+    vault.write_index(&mut *handle)?;
+
+    Ok(vault)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    use core::header::HeaderVersion;
+    use core::primitives::{Algorithm, Mode};
+
+    #[test]
+    fn should_create_and_open_vault() {
+        let handle = RefCell::new(Cursor::new(Vec::new()));
+
+        let req = Request {
+            handle: &handle,
+            raw_key: Protected::new(b"12345678".to_vec()),
+            header_type: HeaderType {
+                version: HeaderVersion::V5,
+                mode: Mode::MemoryMode,
+                algorithm: Algorithm::XChaCha20Poly1305,
+            },
+            hashing_algorithm: HashingAlgorithm::Blake3Balloon(5),
+        };
+
+        let vault = execute(req).unwrap();
+        assert!(vault.entries().is_empty());
+
+        let opened = Vault::open(&handle, Protected::new(b"12345678".to_vec())).unwrap();
+        assert!(opened.entries().is_empty());
+        assert_eq!(opened.end, vault.end);
+
+        assert!(matches!(
+            Vault::open(&handle, Protected::new(b"87654321".to_vec())),
+            Err(Error::DecryptMasterKey)
+        ));
+    }
+
+    #[test]
+    fn should_not_open_vault_with_corrupted_index() {
+        let handle = RefCell::new(Cursor::new(Vec::new()));
+
+        let req = Request {
+            handle: &handle,
+            raw_key: Protected::new(b"12345678".to_vec()),
+            header_type: HeaderType {
+                version: HeaderVersion::V5,
+                mode: Mode::MemoryMode,
+                algorithm: Algorithm::XChaCha20Poly1305,
+            },
+            hashing_algorithm: HashingAlgorithm::Blake3Balloon(5),
+        };
+
+        let mut vault = execute(req).unwrap();
+        let offset = vault.end;
+        vault.write_index(&mut *handle.borrow_mut()).unwrap();
+
+        // Flip a bit within the last index, so it's complete but can't be decrypted
+        *handle.borrow_mut().get_mut().last_mut().unwrap() ^= 1;
+
+        match Vault::open(&handle, Protected::new(b"12345678".to_vec())) {
+            Err(Error::CorruptSegment(corrupt)) => assert_eq!(corrupt, offset),
+            _ => unreachable!(),
+        }
+    }
+}
@@ -0,0 +1,196 @@
+//! This extracts files from a vault to a directory.
+
+use std::cell::RefCell;
+use std::io::{Read, Seek, Write};
+use std::path::PathBuf;
+
+use super::{Error, Vault, CHUNK_SEGMENT};
+use crate::storage::Storage;
+use crate::unpack::enclosed_path;
+
+type OnFileFn = Box<dyn Fn(PathBuf) -> bool>;
+
+pub struct Request<'a, R>
+where
+    R: Read + Seek,
+{
+    pub handle: &'a RefCell<R>,
+    pub vault: &'a Vault,
+    // Every file is extracted if this is empty
+    pub paths: Vec<PathBuf>,
+    pub output_dir_path: PathBuf,
+    // Returns whether the file should be extracted
+    pub on_file: Option<OnFileFn>,
+}
+
+pub fn execute<RW, R>(stor: &impl Storage<RW>, req: Request<'_, R>) -> Result<usize, Error>
+where
+    RW: Read + Write + Seek,
+    R: Read + Seek,
+{
+    let vault = req.vault;
+
+    let entries = if req.paths.is_empty() {
+        vault.entries().iter().collect::<Vec<_>>()
+    } else {
+        req.paths
+            .into_iter()
+            .map(|path| vault.entry(&path).ok_or(Error::FileNotFound(path)))
+            .collect::<Result<Vec<_>, _>>()?
+    };
+
+    let mut handle = req.handle.borrow_mut();
+    let mut extracted = 0;
+
+    for entry in entries {
+        // Vaults could contain any path, so they're restricted to the output directory
+        let full_path = enclosed_path(entry.path.clone())
+            .map(|path| req.output_dir_path.join(path))
+            .ok_or_else(|| Error::UnsupportedPath(entry.path.clone()))?;
+
+        if let Some(on_file) = req.on_file.as_ref() {
+            if !on_file(full_path.clone()) {
+                continue;
+            }
+        }
+
+        if let Some(parent) = full_path.parent() {
+            stor.create_dir_all(parent).map_err(Error::Storage)?;
+        }
+
+        let file = stor
+            .create_file(&full_path)
+            .or_else(|_| stor.write_file(&full_path))
+            .map_err(Error::Storage)?;
+
+        for offset in &entry.chunks {
+            let data = vault.read_segment(&mut *handle, CHUNK_SEGMENT, *offset)?;
+            file.try_writer()
+                .map_err(Error::Storage)?
+                .borrow_mut()
+                .write_all(&data)
+                .map_err(|_| Error::WriteData)?;
+        }
+
+        stor.flush_file(&file).map_err(Error::Storage)?;
+        extracted += 1;
+    }
+
+    Ok(extracted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::{IMFile, InMemoryFile, InMemoryStorage};
+    use crate::vault::add;
+    use crate::vault::tests::create_vault;
+
+    #[test]
+    fn should_extract_files() {
+        let stor = InMemoryStorage::default();
+        stor.add_bar_foo_folder();
+        let (mut vault, handle) = create_vault();
+
+        let paths = ["bar/hello.txt", "bar/foo/world.txt"]
+            .iter()
+            .map(|path| Ok(PathBuf::from(path)))
+            .collect::<Vec<_>>();
+        add::execute(
+            &stor,
+            add::Request {
+                handle: &handle,
+                vault: &mut vault,
+                paths: Box::new(paths.into_iter()),
+            },
+        )
+        .unwrap();
+
+        let req = Request {
+            handle: &handle,
+            vault: &vault,
+            paths: vec![PathBuf::from("bar/foo/world.txt")],
+            output_dir_path: PathBuf::from("out"),
+            on_file: None,
+        };
+
+        assert_eq!(execute(&stor, req).unwrap(), 1);
+
+        let files = stor.files();
+        assert_eq!(
+            files.get(&PathBuf::from("out/bar/foo/world.txt")),
+            Some(&IMFile::File(InMemoryFile {
+                buf: b"world".to_vec(),
+                len: 5,
+            }))
+        );
+        assert_eq!(files.get(&PathBuf::from("out/bar/hello.txt")), None);
+    }
+
+    #[test]
+    fn should_extract_absolute_and_parent_paths() {
+        let stor = InMemoryStorage::default();
+        stor.save_text_file("/bar/hello.txt", "hello");
+        stor.save_text_file("../world.txt", "world");
+        let (mut vault, handle) = create_vault();
+
+        let paths = ["/bar/hello.txt", "../world.txt"]
+            .iter()
+            .map(|path| Ok(PathBuf::from(path)))
+            .collect::<Vec<_>>();
+        add::execute(
+            &stor,
+            add::Request {
+                handle: &handle,
+                vault: &mut vault,
+                paths: Box::new(paths.into_iter()),
+            },
+        )
+        .unwrap();
+
+        let req = Request {
+            handle: &handle,
+            vault: &vault,
+            paths: vec![],
+            output_dir_path: PathBuf::from("out"),
+            on_file: None,
+        };
+
+        assert_eq!(execute(&stor, req).unwrap(), 2);
+
+        let files = stor.files();
+        assert_eq!(
+            files.get(&PathBuf::from("out/bar/hello.txt")),
+            Some(&IMFile::File(InMemoryFile {
+                buf: b"hello".to_vec(),
+                len: 5,
+            }))
+        );
+        assert_eq!(
+            files.get(&PathBuf::from("out/world.txt")),
+            Some(&IMFile::File(InMemoryFile {
+                buf: b"world".to_vec(),
+                len: 5,
+            }))
+        );
+    }
+
+    #[test]
+    fn should_reject_missing_files() {
+        let stor = InMemoryStorage::default();
+        let (vault, handle) = create_vault();
+
+        let req = Request {
+            handle: &handle,
+            vault: &vault,
+            paths: vec![PathBuf::from("missing.txt")],
+            output_dir_path: PathBuf::from("out"),
+            on_file: None,
+        };
+
+        match execute(&stor, req) {
+            Err(Error::FileNotFound(path)) => assert_eq!(path, PathBuf::from("missing.txt")),
+            _ => unreachable!(),
+        }
+    }
+}
@@ -0,0 +1,79 @@
+//! This removes files from a vault.
+//!
+//! Only the index is rewritten, so the removed content stays within the vault until it's compacted.
+
+use std::cell::RefCell;
+use std::io::{Seek, Write};
+use std::path::PathBuf;
+
+use super::{Error, Vault};
+
+pub struct Request<'a, W>
+where
+    W: Write + Seek,
+{
+    pub handle: &'a RefCell<W>,
+    pub vault: &'a mut Vault,
+    pub paths: Vec<PathBuf>,
+}
+
+pub fn execute<W>(req: Request<'_, W>) -> Result<(), Error>
+where
+    W: Write + Seek,
+{
+    // Nothing is removed unless every file exists
+    if let Some(path) = req
+        .paths
+        .iter()
+        .find(|path| req.vault.entry(path).is_none())
+    {
+        return Err(Error::FileNotFound(path.clone()));
+    }
+
+    for path in &req.paths {
+        req.vault.remove(path);
+    }
+
+    req.vault.write_index(&mut *req.handle.borrow_mut())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vault::tests::{create_vault, reload};
+    use crate::vault::VaultEntry;
+
+    #[test]
+    fn should_remove_files() {
+        let (mut vault, handle) = create_vault();
+        for path in ["hello.txt", "world.txt"] {
+            vault.insert(VaultEntry {
+                path: PathBuf::from(path),
+                size: 0,
+                chunks: vec![],
+            });
+        }
+
+        let req = Request {
+            handle: &handle,
+            vault: &mut vault,
+            paths: vec![PathBuf::from("hello.txt")],
+        };
+        execute(req).unwrap();
+
+        let entries = reload(&vault, &handle);
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].path, PathBuf::from("world.txt"));
+
+        let req = Request {
+            handle: &handle,
+            vault: &mut vault,
+            paths: vec![PathBuf::from("world.txt"), PathBuf::from("hello.txt")],
+        };
+        match execute(req) {
+            Err(Error::FileNotFound(path)) => assert_eq!(path, PathBuf::from("hello.txt")),
+            _ => unreachable!(),
+        }
+        assert_eq!(vault.entries().len(), 1);
+    }
+}
@@ -0,0 +1,281 @@
+//! This provides functionality for wiping the free space of a filesystem, so the remnants of files that were removed without being erased are overwritten.
+//!
+//! Temporary files are created within the given directory, and they're grown until the filesystem runs out of space (or until the limit is reached). Each of them is written with the first pass of a `Scheme` as it grows, and then overwritten with the rest of its passes. Every temporary file is removed once the free space is filled, and also if anything fails or the operation is cancelled.
+//!
+//! Like erasing, this will not be effective on flash storage, and space that's reserved by the filesystem (e.g. for the root user, or for snapshots) isn't reached.
+
+use rand::distributions::{Alphanumeric, DistString};
+use std::io::{Read, Seek, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::cancel::CancellationToken;
+use crate::overwrite::{self, Pattern, Scheme};
+use crate::progress::{Phase, Progress};
+use crate::storage::{self, Entry, Storage};
+
+/// The size that temporary files are capped at by default, as some filesystems limit the size of a single file.
+pub const DEFAULT_FILE_SIZE: u64 = 1 << 30;
+
+const FILE_PREFIX: &str = ".dexios-wipe-";
+
+#[derive(Debug)]
+pub enum Error {
+    EmptyScheme,
+    CreateFile(storage::Error),
+    Overwrite(overwrite::Error),
+    SyncFile(storage::Error),
+    RemoveFile(storage::Error),
+    Cancelled,
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::EmptyScheme => f.write_str("The scheme doesn't contain any passes"),
+            Error::CreateFile(inner) => write!(f, "Unable to create a temporary file: {inner}"),
+            Error::Overwrite(inner) => write!(f, "Unable to fill the free space: {inner}"),
+            Error::SyncFile(inner) => write!(f, "Unable to sync a temporary file: {inner}"),
+            Error::RemoveFile(inner) => write!(f, "Unable to remove a temporary file: {inner}"),
+            Error::Cancelled => f.write_str("Wiping the free space was cancelled"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+pub struct Request<P: AsRef<Path>> {
+    // A directory on the filesystem whose free space should be wiped
+    pub dir: P,
+    pub scheme: Scheme,
+    // The most free space to fill, or all of it if there's no limit
+    pub limit: Option<u64>,
+    pub file_size: u64,
+    pub progress: Option<Arc<dyn Progress>>,
+    pub cancel: Option<CancellationToken>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Report {
+    pub files: usize,
+    // The free space that was filled, which every pass was written over
+    pub bytes: u64,
+    // Whether the filesystem ran out of space, rather than the limit being reached
+    pub full: bool,
+}
+
+pub fn execute<RW, P>(
+    stor: Arc<impl Storage<RW> + 'static>,
+    req: Request<P>,
+) -> Result<Report, Error>
+where
+    RW: Read + Write + Seek,
+    P: AsRef<Path>,
+{
+    let patterns = req.scheme.patterns();
+    let Some((first, rest)) = patterns.split_first() else {
+        return Err(Error::EmptyScheme);
+    };
+
+    if let Some(progress) = &req.progress {
+        progress.phase(Phase::Erasing);
+        if let Some(limit) = req.limit {
+            progress.total(limit.saturating_mul(patterns.len() as u64));
+        }
+    }
+
+    let mut files = Vec::new();
+    let res = fill_free_space(&*stor, &req, first, rest, &mut files);
+
+    // The temporary files are removed whatever happened, so the free space is given back
+    let mut removed = Ok(());
+    for file in files {
+        if let Err(err) = stor.remove_file(file) {
+            removed = removed.and(Err(Error::RemoveFile(err)));
+        }
+    }
+
+    let report = res?;
+    removed?;
+
+    Ok(report)
+}
+
+fn fill_free_space<RW, P>(
+    stor: &impl Storage<RW>,
+    req: &Request<P>,
+    first: &Pattern,
+    rest: &[Pattern],
+    files: &mut Vec<Entry<RW>>,
+) -> Result<Report, Error>
+where
+    RW: Read + Write + Seek,
+    P: AsRef<Path>,
+{
+    let mut report = Report {
+        files: 0,
+        bytes: 0,
+        full: false,
+    };
+
+    loop {
+        let remaining = req
+            .limit
+            .map_or(u64::MAX, |limit| limit.saturating_sub(report.bytes));
+        if remaining == 0 {
+            break;
+        }
+
+        let file = stor
+            .create_file(temp_path(req.dir.as_ref()))
+            .map_err(Error::CreateFile)?;
+        files.push(file);
+        let file = files.last().expect("The file was just pushed");
+
+        if let Some(progress) = &req.progress {
+            progress.file(file.path());
+        }
+
+        let max_len = remaining.min(req.file_size).min(usize::MAX as u64);
+        let len = overwrite::fill(
+            &mut *file
+                .try_writer()
+                .expect("We're confident that we're in writing mode")
+                .borrow_mut(),
+            first,
+            max_len,
+            req.progress.as_deref(),
+            req.cancel.as_ref(),
+        )
+        .map_err(overwrite_error)?;
+        stor.sync_file(file).map_err(Error::SyncFile)?;
+
+        if len > 0 && !rest.is_empty() {
+            overwrite::execute(overwrite::Request {
+                writer: file
+                    .try_writer()
+                    .expect("We're confident that we're in writing mode"),
+                buf_capacity: usize::try_from(len).expect("The length is capped at usize::MAX"),
+                scheme: Scheme::Custom(rest.to_vec()),
+                sync: Some(Box::new(|| stor.sync_file(file))),
+                progress: req.progress.clone(),
+                cancel: req.cancel.clone(),
+            })
+            .map_err(overwrite_error)?;
+        }
+
+        if len > 0 {
+            report.files += 1;
+            report.bytes += len;
+        }
+
+        if len < max_len {
+            report.full = true;
+            break;
+        }
+    }
+
+    Ok(report)
+}
+
+fn overwrite_error(err: overwrite::Error) -> Error {
+    match err {
+        overwrite::Error::Cancelled => Error::Cancelled,
+        err => Error::Overwrite(err),
+    }
+}
+
+fn temp_path(dir: &Path) -> PathBuf {
+    let name = Alphanumeric.sample_string(&mut rand::thread_rng(), 16);
+    dir.join(format!("{FILE_PREFIX}{name}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::testing::FaultyStorage;
+    use crate::storage::InMemoryStorage;
+
+    fn request(scheme: Scheme, limit: Option<u64>) -> Request<&'static str> {
+        Request {
+            dir: "mnt",
+            scheme,
+            limit,
+            file_size: 4,
+            progress: None,
+            cancel: None,
+        }
+    }
+
+    fn temp_files(stor: &InMemoryStorage) -> usize {
+        stor.files()
+            .keys()
+            .filter(|path| path.to_string_lossy().contains(FILE_PREFIX))
+            .count()
+    }
+
+    #[test]
+    fn should_fill_up_to_the_limit() {
+        let stor = Arc::new(InMemoryStorage::default());
+
+        match execute(stor.clone(), request(Scheme::Random(1), Some(10))) {
+            Ok(report) => {
+                assert_eq!(
+                    report,
+                    Report {
+                        files: 3,
+                        bytes: 10,
+                        full: false,
+                    }
+                );
+                assert_eq!(temp_files(&stor), 0);
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn should_stop_once_the_storage_is_full() {
+        let inner = InMemoryStorage::default();
+        let stor = Arc::new(FaultyStorage::new(inner).full_at_write(3));
+
+        match execute(stor, request(Scheme::Zeros, None)) {
+            Ok(report) => assert_eq!(
+                report,
+                Report {
+                    files: 2,
+                    bytes: 8,
+                    full: true,
+                }
+            ),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn should_remove_temporary_files_when_cancelled() {
+        let stor = Arc::new(InMemoryStorage::default());
+        let cancel = CancellationToken::new();
+        cancel.cancel();
+
+        let req = Request {
+            cancel: Some(cancel),
+            ..request(Scheme::Zeros, Some(10))
+        };
+
+        match execute(stor.clone(), req) {
+            Err(Error::Cancelled) => assert_eq!(temp_files(&stor), 0),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn should_reject_empty_scheme() {
+        let stor = Arc::new(InMemoryStorage::default());
+
+        match execute(stor, request(Scheme::Custom(vec![]), Some(10))) {
+            Err(Error::EmptyScheme) => {}
+            _ => unreachable!(),
+        }
+    }
+}
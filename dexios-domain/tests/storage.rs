@@ -275,3 +275,31 @@ fn should_include_hidden_files_names() {
         _ => unreachable!(),
     }
 }
+
+#[test]
+fn should_walk_dir_paths() {
+    let stor = TestFileStorage::new(16);
+    add_hello_txt(&stor).unwrap();
+    add_bar_foo_folder(&stor).unwrap();
+
+    let file = stor.read_file("bar_16/").unwrap();
+    let paths = stor.walk_dir(&file);
+
+    match paths {
+        Ok(paths) => {
+            let file_names = paths.collect::<Result<Vec<_>, _>>().unwrap();
+            assert_eq!(
+                sorted_file_names(file_names.iter().collect()),
+                vec![
+                    "bar_16/",
+                    "bar_16/foo",
+                    "bar_16/foo/hello.txt",
+                    "bar_16/foo/world.txt",
+                    "bar_16/hello.txt",
+                    "bar_16/world.txt",
+                ]
+            )
+        }
+        _ => unreachable!(),
+    }
+}
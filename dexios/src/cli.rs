@@ -1,5 +1,7 @@
 use clap::{Arg, Command};
 
+pub mod cancel;
+pub mod progress;
 pub mod prompt;
 
 // this defines all of the clap subcommands and arguments
@@ -73,6 +75,14 @@ pub fn get_matches() -> clap::ArgMatches {
                 .takes_value(true)
                 .help("Store the header separately from the file"),
         )
+        .arg(
+            Arg::new("split")
+                .long("split")
+                .value_name("size")
+                .takes_value(true)
+                .conflicts_with("header")
+                .help("Split the output into volumes of this size (e.g. 4G), named output.001, output.002, ..."),
+        )
         .arg(
             Arg::new("force")
                 .short('f')
@@ -119,6 +129,13 @@ pub fn get_matches() -> clap::ArgMatches {
                 .takes_value(true)
                 .help("Use a header file that was dumped"),
         )
+        .arg(
+            Arg::new("require-signer")
+                .long("require-signer")
+                .value_name("public key")
+                .takes_value(true)
+                .help("Refuse to decrypt the file unless it's signed by this key (in hex, or a .pub file)"),
+        )
         .arg(
             Arg::new("erase")
                 .long("erase")
@@ -175,21 +192,237 @@ pub fn get_matches() -> clap::ArgMatches {
                         .value_name("# of passes")
                         .takes_value(true)
                         .require_equals(true)
-                        .help("Specify the number of passes (default is 1)")
+                        .help("Specify the number of random passes (default is 1)")
                         .min_values(0)
                         .default_missing_value("1"),
+                )
+                .arg(
+                    Arg::new("scheme")
+                        .long("scheme")
+                        .value_name("scheme")
+                        .takes_value(true)
+                        .possible_values(["zeros", "random", "dod", "gutmann", "custom"])
+                        .default_value("random")
+                        .help("The passes to overwrite the file with (random passes are followed by a pass of zeros)"),
+                )
+                .arg(
+                    Arg::new("pattern")
+                        .long("pattern")
+                        .value_name("hex bytes/random")
+                        .takes_value(true)
+                        .multiple_occurrences(true)
+                        .required_if_eq("scheme", "custom")
+                        .help("A pass of the custom scheme, such as 0x55 or random (can be repeated)"),
+                )
+                .arg(
+                    Arg::new("verify")
+                        .long("verify")
+                        .takes_value(false)
+                        .help("Read the file back to check that it contains the last pass (this may come from the OS's cache rather than the disk)"),
+                )
+                .arg(
+                    Arg::new("scrub")
+                        .long("scrub")
+                        .takes_value(false)
+                        .help("Truncate and rename the file, and reset its timestamps, before removing it"),
+                )
+                .arg(
+                    Arg::new("cross-filesystems")
+                        .long("cross-filesystems")
+                        .takes_value(false)
+                        .help("Erase within other filesystems that are mounted inside a directory (they're skipped by default)"),
+                )
+                .arg(
+                    Arg::new("device")
+                        .long("device")
+                        .takes_value(false)
+                        .conflicts_with_all(&["scrub", "cross-filesystems"])
+                        .help("Overwrite a block device (e.g. /dev/sdb) in place, instead of erasing a file (devices that are in use are refused, and this is only supported on Linux)"),
                 ),
         )
         .subcommand(
-            Command::new("hash").about("Hash files with BLAKE3").arg(
-                Arg::new("input")
-                    .value_name("input")
-                    .takes_value(true)
-                    .required(true)
-                    .help("The file(s) to hash")
-                    .min_values(1)
-                    .multiple_occurrences(true),
-            ),
+            Command::new("wipe-free")
+                .about("Overwrite the free space of a filesystem")
+                .arg(
+                    Arg::new("mountpoint")
+                        .value_name("mountpoint")
+                        .takes_value(true)
+                        .required(true)
+                        .help("A directory on the filesystem to wipe (temporary files are created within it)"),
+                )
+                .arg(
+                    Arg::new("force")
+                        .short('f')
+                        .long("force")
+                        .takes_value(false)
+                        .help("Force all actions"),
+                )
+                .arg(
+                    Arg::new("passes")
+                        .long("passes")
+                        .value_name("# of passes")
+                        .takes_value(true)
+                        .require_equals(true)
+                        .help("Specify the number of random passes (default is 1)")
+                        .min_values(0)
+                        .default_missing_value("1"),
+                )
+                .arg(
+                    Arg::new("scheme")
+                        .long("scheme")
+                        .value_name("scheme")
+                        .takes_value(true)
+                        .possible_values(["zeros", "random", "dod", "gutmann", "custom"])
+                        .default_value("random")
+                        .help("The passes to overwrite the free space with (random passes are followed by a pass of zeros)"),
+                )
+                .arg(
+                    Arg::new("pattern")
+                        .long("pattern")
+                        .value_name("hex bytes/random")
+                        .takes_value(true)
+                        .multiple_occurrences(true)
+                        .required_if_eq("scheme", "custom")
+                        .help("A pass of the custom scheme, such as 0x55 or random (can be repeated)"),
+                )
+                .arg(
+                    Arg::new("limit")
+                        .long("limit")
+                        .value_name("size")
+                        .takes_value(true)
+                        .help("Stop once this much free space has been filled (e.g. 10G)"),
+                ),
+        )
+        .subcommand(
+            Command::new("hash")
+                .about("Hash files with BLAKE3 (or another hash function)")
+                .arg(
+                    Arg::new("input")
+                        .value_name("input")
+                        .takes_value(true)
+                        .required_unless_present("check")
+                        .help("The file(s) to hash")
+                        .min_values(1)
+                        .multiple_occurrences(true),
+                )
+                .arg(
+                    Arg::new("recursive")
+                        .short('r')
+                        .long("recursive")
+                        .takes_value(false)
+                        .help("Hash every file within directories, and print a checksum file (like sha256sum or b3sum)"),
+                )
+                .arg(
+                    Arg::new("check")
+                        .short('c')
+                        .long("check")
+                        .value_name("checksum file")
+                        .takes_value(true)
+                        .conflicts_with_all(&["input", "recursive"])
+                        .help("Hash the files within a checksum file again, and report any that changed"),
+                )
+                .arg(
+                    Arg::new("algo")
+                        .long("algo")
+                        .value_name("algorithm")
+                        .takes_value(true)
+                        .possible_values(["blake3", "sha256", "sha512", "sha3-256", "blake2b"])
+                        .default_value("blake3")
+                        .help("The hash function to use"),
+                )
+                .arg(
+                    Arg::new("key")
+                        .long("key")
+                        .value_name("keyfile")
+                        .takes_value(true)
+                        .min_values(0)
+                        .max_values(1)
+                        .conflicts_with_all(&["check", "recursive"])
+                        .help("Make a keyed BLAKE3 MAC, with the key from a keyfile (or from DEXIOS_KEY/a password if no keyfile is given)"),
+                )
+                .arg(
+                    Arg::new("verify")
+                        .long("verify")
+                        .value_name("mac")
+                        .takes_value(true)
+                        .requires("key")
+                        .help("Check the file's MAC against this one, and fail if they don't match"),
+                )
+                .arg(
+                    Arg::new("derive-key")
+                        .long("derive-key")
+                        .value_name("context")
+                        .takes_value(true)
+                        .conflicts_with_all(&["check", "recursive", "key"])
+                        .help("Derive a key from each file with BLAKE3's derive-key mode, under this context string"),
+                ),
+        )
+        .subcommand(
+            Command::new("sign")
+                .about("Sign an encrypted file with an Ed25519 key")
+                .arg(
+                    Arg::new("input")
+                        .value_name("input")
+                        .takes_value(true)
+                        .required_unless_present("generate")
+                        .help("The encrypted file to sign"),
+                )
+                .arg(
+                    Arg::new("key")
+                        .short('k')
+                        .long("key")
+                        .value_name("file")
+                        .takes_value(true)
+                        .required_unless_present("generate")
+                        .help("The signing key (made with --generate)"),
+                )
+                .arg(
+                    Arg::new("detached")
+                        .long("detached")
+                        .takes_value(false)
+                        .help("Store the signature in input.sig, rather than within the header"),
+                )
+                .arg(
+                    Arg::new("generate")
+                        .long("generate")
+                        .value_name("file")
+                        .takes_value(true)
+                        .conflicts_with_all(&["input", "key", "detached"])
+                        .help("Generate a new signing key, and store its public key in file.pub"),
+                )
+                .arg(
+                    Arg::new("force")
+                        .short('f')
+                        .long("force")
+                        .takes_value(false)
+                        .help("Force all actions"),
+                ),
+        )
+        .subcommand(
+            Command::new("verify-sig")
+                .about("Verify the Ed25519 signature of an encrypted file")
+                .arg(
+                    Arg::new("input")
+                        .value_name("input")
+                        .takes_value(true)
+                        .required(true)
+                        .help("The encrypted file to verify"),
+                )
+                .arg(
+                    Arg::new("signer")
+                        .long("signer")
+                        .value_name("public key")
+                        .takes_value(true)
+                        .required(true)
+                        .help("The signer's public key (in hex, or a .pub file)"),
+                )
+                .arg(
+                    Arg::new("sig")
+                        .long("sig")
+                        .value_name("file")
+                        .takes_value(true)
+                        .help("A detached signature (by default, the embedded signature or input.sig is used)"),
+                ),
         )
         .subcommand(
             Command::new("pack")
@@ -247,6 +480,28 @@ pub fn get_matches() -> clap::ArgMatches {
                     .takes_value(true)
                     .help("Store the header separately from the file"),
             )
+            .arg(
+                Arg::new("split")
+                    .long("split")
+                    .value_name("size")
+                    .takes_value(true)
+                    .conflicts_with("header")
+                    .help("Split the output into volumes of this size (e.g. 4G), named output.001, output.002, ..."),
+            )
+            .arg(
+                Arg::new("since")
+                    .long("since")
+                    .value_name("archive")
+                    .takes_value(true)
+                    .help("Create an incremental archive, that only contains files that changed since a previous archive"),
+            )
+            .arg(
+                Arg::new("full")
+                    .long("full")
+                    .takes_value(false)
+                    .conflicts_with("since")
+                    .help("Create a full archive, with a manifest so that incremental archives can be based on it"),
+            )
             .arg(
                 Arg::new("zstd")
                     .short('z')
@@ -254,12 +509,21 @@ pub fn get_matches() -> clap::ArgMatches {
                     .takes_value(false)
                     .help("Use ZSTD compression"),
             )
+            .arg(
+                Arg::new("compression")
+                    .long("compression")
+                    .value_name("method[:level]")
+                    .takes_value(true)
+                    .conflicts_with("zstd")
+                    .help("Use none, deflate, bzip2, zstd, xz or lz4 compression, with an optional level (e.g. zstd:19). Archives that use lz4 can only be unpacked by Dexios"),
+            )
+            // pack always indexes recursively, but this is still accepted so existing scripts keep working
             .arg(
                 Arg::new("recursive")
                     .short('r')
                     .long("recursive")
                     .takes_value(false)
-                    .help("Index files and folders within other folders (index recursively)"),
+                    .help("Index files and folders within other folders (this is always done)"),
             )
             .arg(
                 Arg::new("keyfile")
@@ -298,8 +562,9 @@ pub fn get_matches() -> clap::ArgMatches {
                     Arg::new("input")
                         .value_name("input")
                         .takes_value(true)
+                        .multiple_values(true)
                         .required(true)
-                        .help("The file to decrypt"),
+                        .help("The file(s) to decrypt - a chain of incremental archives is restored in order, starting with the full archive"),
                 )
                 .arg(
                     Arg::new("output")
@@ -354,122 +619,305 @@ pub fn get_matches() -> clap::ArgMatches {
                         .takes_value(false)
                         .help("Force all actions"),
                 )
-        )
-        .subcommand(Command::new("key")
-                .about("Manipulate keys within the header (for advanced users")
-                .subcommand_required(true)
-                .subcommand(
-                    Command::new("change")
-                        .about("Change an encrypted file's key")
-                        .arg_required_else_help(true)
-                        .arg(
-                            Arg::new("input")
-                                .value_name("input")
-                                .takes_value(true)
-                                .required(true)
-                                .help("The encrypted file/header file"),
-                        )
-                        .arg(
-                            Arg::new("autogenerate")
-                                .long("auto")
-                                .value_name("# of words")
-                                .min_values(0)
-                                .default_missing_value("7")
-                                .takes_value(true)
-                                .require_equals(true)
-                                .help("Autogenerate a passphrase (default is 7 words)")
-                                .conflicts_with("keyfile"),
-                        )
-                        .arg(
-                            Arg::new("argon")
-                                .long("argon")
-                                .takes_value(false)
-                                .help("Use argon2id for password hashing"),
-                        )
-                        .arg(
-                            Arg::new("keyfile-old")
-                                .short('k')
-                                .long("keyfile-old")
-                                .value_name("file")
-                                .takes_value(true)
-                                .help("Use an old keyfile to decrypt the master key"),
-                        )
-                        .arg(
-                            Arg::new("keyfile-new")
-                                .short('n')
-                                .long("keyfile-new")
-                                .value_name("file")
-                                .takes_value(true)
-                                .help("Use a keyfile as the new key"),
-                        ),
+                .arg(
+                    Arg::new("max-size")
+                        .long("max-size")
+                        .value_name("size")
+                        .takes_value(true)
+                        .help("Abort if the extracted files exceed this size in total (e.g. 10G)"),
                 )
-                .subcommand(
-                    Command::new("add")
-                        .about("Add a key to an encrypted file (for advanced users)")
-                        .arg_required_else_help(true)
-                        .arg(
-                            Arg::new("input")
-                                .value_name("input")
-                                .takes_value(true)
-                                .required(true)
-                                .help("The encrypted file/header file"),
-                        )
-                        .arg(
-                            Arg::new("argon")
-                                .long("argon")
-                                .takes_value(false)
-                                .help("Use argon2id for password hashing"),
-                        )
-                        .arg(
-                            Arg::new("autogenerate")
-                                .long("auto")
-                                .value_name("# of words")
-                                .min_values(0)
-                                .default_missing_value("7")
-                                .takes_value(true)
-                                .require_equals(true)
-                                .help("Autogenerate a passphrase (default is 7 words)")
-                                .conflicts_with("keyfile"),
-                        )
-                        .arg(
-                            Arg::new("keyfile-old")
-                                .short('k')
-                                .long("keyfile-old")
-                                .value_name("file")
-                                .takes_value(true)
-                                .help("Use an old keyfile to decrypt the master key"),
-                        )
-                        .arg(
-                            Arg::new("keyfile-new")
-                                .short('n')
-                                .long("keyfile-new")
-                                .value_name("file")
-                                .takes_value(true)
-                                .help("Use a keyfile as the new key"),
-                        ),
+                .arg(
+                    Arg::new("max-files")
+                        .long("max-files")
+                        .value_name("# of files")
+                        .takes_value(true)
+                        .help("Abort if the archive contains more entries than this"),
                 )
-                .subcommand(
-                    Command::new("del")
-                        .about("Delete a key from an encrypted file (for advanced users)")
-                        .arg_required_else_help(true)
-                        .arg(
-                            Arg::new("input")
-                                .value_name("input")
-                                .takes_value(true)
-                                .required(true)
-                                .help("The encrypted file/header file"),
-                        )
-                        .arg(
-                            Arg::new("keyfile")
-                                .short('k')
-                                .long("keyfile")
-                                .value_name("file")
-                                .takes_value(true)
-                                .help("Use a keyfile to identify the key you want to delete"),
-                        ),
+                .arg(
+                    Arg::new("max-file-size")
+                        .long("max-file-size")
+                        .value_name("size")
+                        .takes_value(true)
+                        .help("Abort if a single extracted file exceeds this size (e.g. 512M)"),
                 )
-                .subcommand(
-                    Command::new("verify")
+                .arg(
+                    Arg::new("max-ratio")
+                        .long("max-ratio")
+                        .value_name("ratio")
+                        .takes_value(true)
+                        .help("Abort if a file's compression ratio exceeds this (e.g. 100 for 100:1)"),
+                )
+        )
+        .subcommand(
+            Command::new("encrypt-dir")
+                .about("Encrypt every file within a directory separately, into a directory with encrypted names")
+                .arg_required_else_help(true)
+                .arg(
+                    Arg::new("input")
+                        .value_name("input")
+                        .takes_value(true)
+                        .required(true)
+                        .help("The directory to encrypt"),
+                )
+                .arg(
+                    Arg::new("output")
+                        .value_name("output")
+                        .takes_value(true)
+                        .required(true)
+                        .help("The output directory (existing encrypted directories are updated)"),
+                )
+                .arg(
+                    Arg::new("keyfile")
+                        .short('k')
+                        .long("keyfile")
+                        .value_name("file")
+                        .takes_value(true)
+                        .help("Use a keyfile instead of a password"),
+                )
+                .arg(
+                    Arg::new("autogenerate")
+                        .long("auto")
+                        .value_name("# of words")
+                        .min_values(0)
+                        .default_missing_value("7")
+                        .takes_value(true)
+                        .require_equals(true)
+                        .help("Autogenerate a passphrase (default is 7 words)")
+                        .conflicts_with("keyfile"),
+                )
+                .arg(
+                    Arg::new("argon")
+                        .long("argon")
+                        .takes_value(false)
+                        .help("Use argon2id for password hashing"),
+                )
+                .arg(
+                    Arg::new("aes")
+                        .long("aes")
+                        .takes_value(false)
+                        .help("Use AES-256-GCM for encryption"),
+                )
+                .arg(
+                    Arg::new("verbose")
+                        .short('v')
+                        .long("verbose")
+                        .takes_value(false)
+                        .help("Show a detailed output"),
+                )
+        )
+        .subcommand(
+            Command::new("decrypt-dir")
+                .about("Decrypt a directory that was encrypted with encrypt-dir")
+                .arg_required_else_help(true)
+                .arg(
+                    Arg::new("input")
+                        .value_name("input")
+                        .takes_value(true)
+                        .required(true)
+                        .help("The encrypted directory"),
+                )
+                .arg(
+                    Arg::new("output")
+                        .value_name("output")
+                        .takes_value(true)
+                        .required(true)
+                        .help("The output directory"),
+                )
+                .arg(
+                    Arg::new("keyfile")
+                        .short('k')
+                        .long("keyfile")
+                        .value_name("file")
+                        .takes_value(true)
+                        .help("Use a keyfile instead of a password"),
+                )
+                .arg(
+                    Arg::new("verbose")
+                        .short('v')
+                        .long("verbose")
+                        .takes_value(false)
+                        .help("Show a detailed output"),
+                )
+                .arg(
+                    Arg::new("force")
+                        .short('f')
+                        .long("force")
+                        .takes_value(false)
+                        .help("Force all actions"),
+                )
+        )
+        .subcommand(
+            Command::new("sync")
+                .about("Keep an encrypted directory up to date, only encrypting new or changed files")
+                .arg_required_else_help(true)
+                .arg(
+                    Arg::new("input")
+                        .value_name("input")
+                        .takes_value(true)
+                        .required(true)
+                        .help("The directory to synchronise"),
+                )
+                .arg(
+                    Arg::new("output")
+                        .value_name("output")
+                        .takes_value(true)
+                        .required(true)
+                        .help("The encrypted directory (it's created if it doesn't exist)"),
+                )
+                .arg(
+                    Arg::new("keyfile")
+                        .short('k')
+                        .long("keyfile")
+                        .value_name("file")
+                        .takes_value(true)
+                        .help("Use a keyfile instead of a password"),
+                )
+                .arg(
+                    Arg::new("autogenerate")
+                        .long("auto")
+                        .value_name("# of words")
+                        .min_values(0)
+                        .default_missing_value("7")
+                        .takes_value(true)
+                        .require_equals(true)
+                        .help("Autogenerate a passphrase (default is 7 words)")
+                        .conflicts_with("keyfile"),
+                )
+                .arg(
+                    Arg::new("argon")
+                        .long("argon")
+                        .takes_value(false)
+                        .help("Use argon2id for password hashing"),
+                )
+                .arg(
+                    Arg::new("aes")
+                        .long("aes")
+                        .takes_value(false)
+                        .help("Use AES-256-GCM for encryption"),
+                )
+                .arg(
+                    Arg::new("verbose")
+                        .short('v')
+                        .long("verbose")
+                        .takes_value(false)
+                        .help("Show a detailed output"),
+                )
+        )
+        .subcommand(Command::new("key")
+                .about("Manipulate keys within the header (for advanced users")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("change")
+                        .about("Change an encrypted file's key")
+                        .arg_required_else_help(true)
+                        .arg(
+                            Arg::new("input")
+                                .value_name("input")
+                                .takes_value(true)
+                                .required(true)
+                                .help("The encrypted file/header file"),
+                        )
+                        .arg(
+                            Arg::new("autogenerate")
+                                .long("auto")
+                                .value_name("# of words")
+                                .min_values(0)
+                                .default_missing_value("7")
+                                .takes_value(true)
+                                .require_equals(true)
+                                .help("Autogenerate a passphrase (default is 7 words)")
+                                .conflicts_with("keyfile"),
+                        )
+                        .arg(
+                            Arg::new("argon")
+                                .long("argon")
+                                .takes_value(false)
+                                .help("Use argon2id for password hashing"),
+                        )
+                        .arg(
+                            Arg::new("keyfile-old")
+                                .short('k')
+                                .long("keyfile-old")
+                                .value_name("file")
+                                .takes_value(true)
+                                .help("Use an old keyfile to decrypt the master key"),
+                        )
+                        .arg(
+                            Arg::new("keyfile-new")
+                                .short('n')
+                                .long("keyfile-new")
+                                .value_name("file")
+                                .takes_value(true)
+                                .help("Use a keyfile as the new key"),
+                        ),
+                )
+                .subcommand(
+                    Command::new("add")
+                        .about("Add a key to an encrypted file (for advanced users)")
+                        .arg_required_else_help(true)
+                        .arg(
+                            Arg::new("input")
+                                .value_name("input")
+                                .takes_value(true)
+                                .required(true)
+                                .help("The encrypted file/header file"),
+                        )
+                        .arg(
+                            Arg::new("argon")
+                                .long("argon")
+                                .takes_value(false)
+                                .help("Use argon2id for password hashing"),
+                        )
+                        .arg(
+                            Arg::new("autogenerate")
+                                .long("auto")
+                                .value_name("# of words")
+                                .min_values(0)
+                                .default_missing_value("7")
+                                .takes_value(true)
+                                .require_equals(true)
+                                .help("Autogenerate a passphrase (default is 7 words)")
+                                .conflicts_with("keyfile"),
+                        )
+                        .arg(
+                            Arg::new("keyfile-old")
+                                .short('k')
+                                .long("keyfile-old")
+                                .value_name("file")
+                                .takes_value(true)
+                                .help("Use an old keyfile to decrypt the master key"),
+                        )
+                        .arg(
+                            Arg::new("keyfile-new")
+                                .short('n')
+                                .long("keyfile-new")
+                                .value_name("file")
+                                .takes_value(true)
+                                .help("Use a keyfile as the new key"),
+                        ),
+                )
+                .subcommand(
+                    Command::new("del")
+                        .about("Delete a key from an encrypted file (for advanced users)")
+                        .arg_required_else_help(true)
+                        .arg(
+                            Arg::new("input")
+                                .value_name("input")
+                                .takes_value(true)
+                                .required(true)
+                                .help("The encrypted file/header file"),
+                        )
+                        .arg(
+                            Arg::new("keyfile")
+                                .short('k')
+                                .long("keyfile")
+                                .value_name("file")
+                                .takes_value(true)
+                                .help("Use a keyfile to identify the key you want to delete"),
+                        ),
+                )
+                .subcommand(
+                    Command::new("verify")
                         .about("Verify that a key is correct")
                         .arg_required_else_help(true)
                         .arg(
@@ -563,5 +1011,390 @@ pub fn get_matches() -> clap::ArgMatches {
                         ),
                 ),
         )
+        .subcommand(
+            Command::new("repo")
+                .about("Manage a deduplicating, encrypted backup repository")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("init")
+                        .about("Create a new repository")
+                        .arg_required_else_help(true)
+                        .arg(
+                            Arg::new("repo")
+                                .value_name("repo")
+                                .takes_value(true)
+                                .required(true)
+                                .help("The repository's directory"),
+                        )
+                        .arg(
+                            Arg::new("keyfile")
+                                .short('k')
+                                .long("keyfile")
+                                .value_name("file")
+                                .takes_value(true)
+                                .help("Use a keyfile instead of a password"),
+                        )
+                        .arg(
+                            Arg::new("autogenerate")
+                                .long("auto")
+                                .value_name("# of words")
+                                .min_values(0)
+                                .default_missing_value("7")
+                                .takes_value(true)
+                                .require_equals(true)
+                                .help("Autogenerate a passphrase (default is 7 words)")
+                                .conflicts_with("keyfile"),
+                        )
+                        .arg(
+                            Arg::new("argon")
+                                .long("argon")
+                                .takes_value(false)
+                                .help("Use argon2id for password hashing"),
+                        )
+                        .arg(
+                            Arg::new("aes")
+                                .long("aes")
+                                .takes_value(false)
+                                .help("Use AES-256-GCM for encryption"),
+                        ),
+                )
+                .subcommand(
+                    Command::new("backup")
+                        .about("Store a new snapshot of files and directories")
+                        .arg_required_else_help(true)
+                        .arg(
+                            Arg::new("repo")
+                                .value_name("repo")
+                                .takes_value(true)
+                                .required(true)
+                                .help("The repository's directory"),
+                        )
+                        .arg(
+                            Arg::new("input")
+                                .value_name("input")
+                                .takes_value(true)
+                                .multiple_values(true)
+                                .required(true)
+                                .help("The files/directories to back up"),
+                        )
+                        .arg(
+                            Arg::new("keyfile")
+                                .short('k')
+                                .long("keyfile")
+                                .value_name("file")
+                                .takes_value(true)
+                                .help("Use a keyfile instead of a password"),
+                        )
+                        .arg(
+                            Arg::new("verbose")
+                                .short('v')
+                                .long("verbose")
+                                .takes_value(false)
+                                .help("Show a detailed output"),
+                        ),
+                )
+                .subcommand(
+                    Command::new("list")
+                        .about("List every snapshot within the repository")
+                        .arg_required_else_help(true)
+                        .arg(
+                            Arg::new("repo")
+                                .value_name("repo")
+                                .takes_value(true)
+                                .required(true)
+                                .help("The repository's directory"),
+                        )
+                        .arg(
+                            Arg::new("keyfile")
+                                .short('k')
+                                .long("keyfile")
+                                .value_name("file")
+                                .takes_value(true)
+                                .help("Use a keyfile instead of a password"),
+                        ),
+                )
+                .subcommand(
+                    Command::new("restore")
+                        .about("Restore a snapshot to a directory")
+                        .arg_required_else_help(true)
+                        .arg(
+                            Arg::new("repo")
+                                .value_name("repo")
+                                .takes_value(true)
+                                .required(true)
+                                .help("The repository's directory"),
+                        )
+                        .arg(
+                            Arg::new("snapshot")
+                                .value_name("snapshot")
+                                .takes_value(true)
+                                .required(true)
+                                .help("The snapshot's ID, or \"latest\""),
+                        )
+                        .arg(
+                            Arg::new("output")
+                                .value_name("output")
+                                .takes_value(true)
+                                .required(true)
+                                .help("The output directory"),
+                        )
+                        .arg(
+                            Arg::new("keyfile")
+                                .short('k')
+                                .long("keyfile")
+                                .value_name("file")
+                                .takes_value(true)
+                                .help("Use a keyfile instead of a password"),
+                        )
+                        .arg(
+                            Arg::new("verbose")
+                                .short('v')
+                                .long("verbose")
+                                .takes_value(false)
+                                .help("Show a detailed output"),
+                        )
+                        .arg(
+                            Arg::new("force")
+                                .short('f')
+                                .long("force")
+                                .takes_value(false)
+                                .help("Force all actions"),
+                        ),
+                )
+                .subcommand(
+                    Command::new("prune")
+                        .about("Remove old snapshots, and any chunks that are no longer used")
+                        .arg_required_else_help(true)
+                        .arg(
+                            Arg::new("repo")
+                                .value_name("repo")
+                                .takes_value(true)
+                                .required(true)
+                                .help("The repository's directory"),
+                        )
+                        .arg(
+                            Arg::new("keep")
+                                .long("keep")
+                                .value_name("# of snapshots")
+                                .takes_value(true)
+                                .required(true)
+                                .help("The number of recent snapshots to keep"),
+                        )
+                        .arg(
+                            Arg::new("keyfile")
+                                .short('k')
+                                .long("keyfile")
+                                .value_name("file")
+                                .takes_value(true)
+                                .help("Use a keyfile instead of a password"),
+                        ),
+                ),
+        )
+        .subcommand(
+            Command::new("vault")
+                .about("Manage an encrypted vault that files can be added to and removed from")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("create")
+                        .about("Create a new, empty vault")
+                        .arg_required_else_help(true)
+                        .arg(
+                            Arg::new("vault")
+                                .value_name("vault")
+                                .takes_value(true)
+                                .required(true)
+                                .help("The vault file"),
+                        )
+                        .arg(
+                            Arg::new("keyfile")
+                                .short('k')
+                                .long("keyfile")
+                                .value_name("file")
+                                .takes_value(true)
+                                .help("Use a keyfile instead of a password"),
+                        )
+                        .arg(
+                            Arg::new("autogenerate")
+                                .long("auto")
+                                .value_name("# of words")
+                                .min_values(0)
+                                .default_missing_value("7")
+                                .takes_value(true)
+                                .require_equals(true)
+                                .help("Autogenerate a passphrase (default is 7 words)")
+                                .conflicts_with("keyfile"),
+                        )
+                        .arg(
+                            Arg::new("argon")
+                                .long("argon")
+                                .takes_value(false)
+                                .help("Use argon2id for password hashing"),
+                        )
+                        .arg(
+                            Arg::new("aes")
+                                .long("aes")
+                                .takes_value(false)
+                                .help("Use AES-256-GCM for encryption"),
+                        )
+                        .arg(
+                            Arg::new("force")
+                                .short('f')
+                                .long("force")
+                                .takes_value(false)
+                                .help("Force all actions"),
+                        ),
+                )
+                .subcommand(
+                    Command::new("add")
+                        .about("Add files and directories to a vault, replacing any with the same path")
+                        .arg_required_else_help(true)
+                        .arg(
+                            Arg::new("vault")
+                                .value_name("vault")
+                                .takes_value(true)
+                                .required(true)
+                                .help("The vault file"),
+                        )
+                        .arg(
+                            Arg::new("input")
+                                .value_name("input")
+                                .takes_value(true)
+                                .multiple_values(true)
+                                .required(true)
+                                .help("The files/directories to add"),
+                        )
+                        .arg(
+                            Arg::new("keyfile")
+                                .short('k')
+                                .long("keyfile")
+                                .value_name("file")
+                                .takes_value(true)
+                                .help("Use a keyfile instead of a password"),
+                        )
+                        .arg(
+                            Arg::new("verbose")
+                                .short('v')
+                                .long("verbose")
+                                .takes_value(false)
+                                .help("Show a detailed output"),
+                        ),
+                )
+                .subcommand(
+                    Command::new("ls")
+                        .about("List every file within a vault")
+                        .arg_required_else_help(true)
+                        .arg(
+                            Arg::new("vault")
+                                .value_name("vault")
+                                .takes_value(true)
+                                .required(true)
+                                .help("The vault file"),
+                        )
+                        .arg(
+                            Arg::new("keyfile")
+                                .short('k')
+                                .long("keyfile")
+                                .value_name("file")
+                                .takes_value(true)
+                                .help("Use a keyfile instead of a password"),
+                        ),
+                )
+                .subcommand(
+                    Command::new("get")
+                        .about("Extract files from a vault to a directory")
+                        .arg_required_else_help(true)
+                        .arg(
+                            Arg::new("vault")
+                                .value_name("vault")
+                                .takes_value(true)
+                                .required(true)
+                                .help("The vault file"),
+                        )
+                        .arg(
+                            Arg::new("output")
+                                .value_name("output")
+                                .takes_value(true)
+                                .required(true)
+                                .help("The output directory"),
+                        )
+                        .arg(
+                            Arg::new("file")
+                                .value_name("file")
+                                .takes_value(true)
+                                .multiple_values(true)
+                                .help("The files to extract (default is every file)"),
+                        )
+                        .arg(
+                            Arg::new("keyfile")
+                                .short('k')
+                                .long("keyfile")
+                                .value_name("file")
+                                .takes_value(true)
+                                .help("Use a keyfile instead of a password"),
+                        )
+                        .arg(
+                            Arg::new("verbose")
+                                .short('v')
+                                .long("verbose")
+                                .takes_value(false)
+                                .help("Show a detailed output"),
+                        )
+                        .arg(
+                            Arg::new("force")
+                                .short('f')
+                                .long("force")
+                                .takes_value(false)
+                                .help("Force all actions"),
+                        ),
+                )
+                .subcommand(
+                    Command::new("rm")
+                        .about("Remove files from a vault")
+                        .arg_required_else_help(true)
+                        .arg(
+                            Arg::new("vault")
+                                .value_name("vault")
+                                .takes_value(true)
+                                .required(true)
+                                .help("The vault file"),
+                        )
+                        .arg(
+                            Arg::new("file")
+                                .value_name("file")
+                                .takes_value(true)
+                                .multiple_values(true)
+                                .required(true)
+                                .help("The files to remove"),
+                        )
+                        .arg(
+                            Arg::new("keyfile")
+                                .short('k')
+                                .long("keyfile")
+                                .value_name("file")
+                                .takes_value(true)
+                                .help("Use a keyfile instead of a password"),
+                        ),
+                )
+                .subcommand(
+                    Command::new("compact")
+                        .about("Reclaim the space used by removed and replaced files")
+                        .arg_required_else_help(true)
+                        .arg(
+                            Arg::new("vault")
+                                .value_name("vault")
+                                .takes_value(true)
+                                .required(true)
+                                .help("The vault file"),
+                        )
+                        .arg(
+                            Arg::new("keyfile")
+                                .short('k')
+                                .long("keyfile")
+                                .value_name("file")
+                                .takes_value(true)
+                                .help("Use a keyfile instead of a password"),
+                        ),
+                ),
+        )
         .get_matches()
 }
@@ -0,0 +1,28 @@
+use domain::cancel::CancellationToken;
+
+// every operation runs on the main thread, so the token only needs to be created (and the handler installed) once
+thread_local! {
+    static TOKEN: CancellationToken = install_handler();
+}
+
+// this returns a token that's cancelled by ctrl-c, so the running operation can erase its partial output
+// the handler is only installed once an operation starts, so ctrl-c still exits while prompting for a password
+// pressing ctrl-c a second time exits straight away
+pub fn ctrl_c_token() -> CancellationToken {
+    TOKEN.with(CancellationToken::clone)
+}
+
+fn install_handler() -> CancellationToken {
+    let token = CancellationToken::new();
+    let handler_token = token.clone();
+
+    ctrlc::set_handler(move || {
+        if handler_token.is_cancelled() {
+            std::process::exit(130);
+        }
+        handler_token.cancel();
+    })
+    .ok();
+
+    token
+}
@@ -0,0 +1,59 @@
+use std::path::Path;
+use std::sync::Arc;
+
+use domain::progress::{Phase, Progress};
+use indicatif::ProgressStyle;
+
+// this draws the progress of an operation to stderr
+// nothing is drawn if stderr isn't a terminal, so piped output stays clean
+pub struct ProgressBar(indicatif::ProgressBar);
+
+impl ProgressBar {
+    // the bar is cleared once every reference to it is dropped
+    pub fn create() -> Arc<Self> {
+        let pb = indicatif::ProgressBar::new_spinner();
+        pb.set_style(spinner_style());
+
+        Arc::new(Self(pb))
+    }
+}
+
+fn spinner_style() -> ProgressStyle {
+    ProgressStyle::default_spinner().template("{spinner:.cyan} {prefix} {bytes} {wide_msg}")
+}
+
+fn bar_style() -> ProgressStyle {
+    ProgressStyle::default_bar()
+        .template(
+            "{prefix} [{bar:30.cyan/blue}] {bytes}/{total_bytes} ({bytes_per_sec}, {eta}) {wide_msg}",
+        )
+        .progress_chars("=> ")
+}
+
+impl Progress for ProgressBar {
+    fn phase(&self, phase: Phase) {
+        self.0.set_style(spinner_style());
+        self.0.reset();
+        self.0.set_prefix(phase.to_string());
+        self.0.set_message("");
+    }
+
+    fn total(&self, bytes: u64) {
+        self.0.set_length(bytes);
+        self.0.set_style(bar_style());
+    }
+
+    fn file(&self, path: &Path) {
+        self.0.set_message(path.display().to_string());
+    }
+
+    fn advance(&self, bytes: u64) {
+        self.0.inc(bytes);
+    }
+}
+
+impl Drop for ProgressBar {
+    fn drop(&mut self) {
+        self.0.finish_and_clear();
+    }
+}
@@ -3,7 +3,9 @@ use std::io::{self, stdin, Write};
 
 use crate::{
     global::states::{ForceMode, PasswordState},
-    question, warn,
+    question,
+    subcommands::remote,
+    warn,
 };
 
 use core::protected::Protected;
@@ -51,7 +53,13 @@ pub fn get_answer(prompt: &str, default: bool, force: ForceMode) -> Result<bool>
 // if they have the force argument supplied, this will just assume true
 // if force mode is true, avoid prompts at all
 pub fn overwrite_check(name: &str, force: ForceMode) -> Result<bool> {
-    let answer = if std::fs::metadata(name).is_ok() {
+    let exists = if remote::is_remote(name) {
+        remote::exists(name)?
+    } else {
+        std::fs::metadata(name).is_ok()
+    };
+
+    let answer = if exists {
         let prompt = format!("{} already exists, would you like to overwrite?", name);
         get_answer(&prompt, true, force)?
     } else {
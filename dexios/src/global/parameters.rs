@@ -3,14 +3,18 @@
 
 use crate::global::states::{EraseMode, EraseSourceDir, ForceMode, HashMode, HeaderLocation};
 use crate::global::structs::CryptoParams;
+use crate::global::structs::EraseParams;
 use crate::global::structs::PackParams;
 use crate::warn;
 use anyhow::{Context, Result};
 use clap::ArgMatches;
 use core::header::{HashingAlgorithm, ARGON2ID_LATEST, BLAKE3BALLOON_LATEST};
 use core::primitives::Algorithm;
+use domain::hasher::HashAlgorithm;
+use domain::overwrite::{Pattern, Scheme};
+use domain::unpack::Limits;
 
-use super::states::{Compression, DirectoryMode, Key, KeyParams, PrintMode};
+use super::states::{Compression, Key, KeyParams, PrintMode};
 use super::structs::KeyManipulationParams;
 
 pub fn get_params(name: &str, sub_matches: &ArgMatches) -> Result<Vec<String>> {
@@ -83,6 +87,30 @@ pub fn parameter_handler(sub_matches: &ArgMatches) -> Result<CryptoParams> {
     })
 }
 
+// the hash function for `dexios hash`, which is BLAKE3 unless another one is chosen
+pub fn hash_algorithm(sub_matches: &ArgMatches) -> Result<HashAlgorithm> {
+    sub_matches
+        .value_of("algo")
+        .map_or(Ok(HashAlgorithm::Blake3), str::parse)
+        .map_err(|err| anyhow::anyhow!(err))
+}
+
+// the key for `dexios hash --key`, which is read from the keyfile if one is given
+// otherwise it's read from DEXIOS_KEY, or the user is asked for a password
+pub fn mac_key(sub_matches: &ArgMatches) -> Result<Key> {
+    if let Some(keyfile) = sub_matches.value_of("key") {
+        return Ok(Key::Keyfile(keyfile.to_string()));
+    }
+
+    let params = KeyParams {
+        user: true,
+        env: true,
+        autogenerate: false,
+        keyfile: false,
+    };
+    Key::init(sub_matches, &params, "key")
+}
+
 pub fn hashing_algorithm(sub_matches: &ArgMatches) -> HashingAlgorithm {
     if sub_matches.is_present("argon") {
         HashingAlgorithm::Argon2id(ARGON2ID_LATEST)
@@ -100,12 +128,42 @@ pub fn algorithm(sub_matches: &ArgMatches) -> Algorithm {
     }
 }
 
-pub fn erase_params(sub_matches: &ArgMatches) -> Result<(i32, ForceMode)> {
+pub fn erase_params(sub_matches: &ArgMatches) -> Result<(EraseParams, ForceMode)> {
+    let params = EraseParams {
+        scheme: erase_scheme(sub_matches)?,
+        verify: sub_matches.is_present("verify"),
+        scrub: sub_matches.is_present("scrub"),
+        cross_filesystems: sub_matches.is_present("cross-filesystems"),
+    };
+    let force = forcemode(sub_matches);
+
+    Ok((params, force))
+}
+
+// gets the passes to overwrite with, for both `erase` and `wipe-free`
+pub fn erase_scheme(sub_matches: &ArgMatches) -> Result<Scheme> {
+    let scheme = match sub_matches.value_of("scheme") {
+        Some("zeros") => Scheme::Zeros,
+        Some("dod") => Scheme::Dod,
+        Some("gutmann") => Scheme::Gutmann,
+        Some("custom") => Scheme::Custom(
+            get_params("pattern", sub_matches)?
+                .iter()
+                .map(|pattern| parse_pattern(pattern))
+                .collect::<Result<_>>()?,
+        ),
+        _ => Scheme::Random(erase_passes(sub_matches)?),
+    };
+
+    Ok(scheme)
+}
+
+fn erase_passes(sub_matches: &ArgMatches) -> Result<u32> {
     let passes = if sub_matches.is_present("passes") {
         let result = sub_matches
             .value_of("passes")
             .context("No amount of passes specified")?
-            .parse::<i32>();
+            .parse::<u32>();
         if let Ok(value) = result {
             value
         } else {
@@ -117,9 +175,25 @@ pub fn erase_params(sub_matches: &ArgMatches) -> Result<(i32, ForceMode)> {
         1
     };
 
-    let force = forcemode(sub_matches);
+    Ok(passes)
+}
 
-    Ok((passes, force))
+// patterns are either "random", or hex bytes that are repeated (e.g. "55" or "0x924924")
+fn parse_pattern(pattern: &str) -> Result<Pattern> {
+    if pattern == "random" {
+        return Ok(Pattern::Random);
+    }
+
+    let hex = pattern.strip_prefix("0x").unwrap_or(pattern);
+    domain::utils::hex_decode(hex)
+        .filter(|bytes| !bytes.is_empty())
+        .map(Pattern::Bytes)
+        .with_context(|| {
+            format!(
+                "Invalid pattern: {} (expected hex bytes or \"random\")",
+                pattern
+            )
+        })
 }
 
 pub fn pack_params(sub_matches: &ArgMatches) -> Result<(CryptoParams, PackParams)> {
@@ -167,36 +241,121 @@ pub fn pack_params(sub_matches: &ArgMatches) -> Result<(CryptoParams, PackParams
         PrintMode::Quiet
     };
 
-    let dir_mode = if sub_matches.is_present("recursive") {
-        //specify to emit hash after operation
-        DirectoryMode::Recursive
-    } else {
-        // default
-        DirectoryMode::Singular
-    };
-
     let erase_source = if sub_matches.is_present("erase") {
         EraseSourceDir::Erase
     } else {
         EraseSourceDir::Retain
     };
 
-    let compression = if sub_matches.is_present("zstd") {
-        Compression::Zstd
+    let compression = if let Some(value) = sub_matches.value_of("compression") {
+        parse_compression(value)?
+    } else if sub_matches.is_present("zstd") {
+        Compression::Zstd(None)
     } else {
         Compression::None
     };
 
     let pack_params = PackParams {
-        dir_mode,
         print_mode,
         erase_source,
         compression,
+        split: split_size(sub_matches)?,
+        since: sub_matches.value_of("since").map(str::to_string),
+        full: sub_matches.is_present("full"),
     };
 
     Ok((crypto_params, pack_params))
 }
 
+// parses a human-readable size, such as `512`, `64K`, `10M` or `4G`
+// suffixes are binary (1K = 1024 bytes)
+pub fn parse_size(value: &str) -> Result<u64> {
+    let value = value.trim();
+    let (number, multiplier) = match value.chars().last().map(|c| c.to_ascii_uppercase()) {
+        Some('K') => (&value[..value.len() - 1], 1 << 10),
+        Some('M') => (&value[..value.len() - 1], 1 << 20),
+        Some('G') => (&value[..value.len() - 1], 1 << 30),
+        Some('T') => (&value[..value.len() - 1], 1 << 40),
+        _ => (value, 1),
+    };
+
+    number
+        .parse::<u64>()
+        .ok()
+        .and_then(|n| n.checked_mul(multiplier))
+        .with_context(|| format!("Invalid size: {value}"))
+}
+
+// parses a compression method with an optional level, such as `zstd` or `deflate:9`
+pub fn parse_compression(value: &str) -> Result<Compression> {
+    let (method, level) = match value.split_once(':') {
+        Some((method, level)) => {
+            let level = level
+                .parse::<i32>()
+                .with_context(|| format!("Invalid compression level: {level}"))?;
+            (method, Some(level))
+        }
+        None => (value, None),
+    };
+
+    let (compression, levels) = match method.to_lowercase().as_str() {
+        "none" | "store" if level.is_none() => return Ok(Compression::None),
+        "none" | "store" => return Err(anyhow::anyhow!("No compression can't have a level")),
+        "deflate" => (Compression::Deflate(level), 0..=9),
+        "bzip2" => (Compression::Bzip2(level), 1..=9),
+        "zstd" => (Compression::Zstd(level), -7..=22),
+        "xz" => (Compression::Xz(level), 0..=9),
+        "lz4" if level.is_none() => return Ok(Compression::Lz4),
+        "lz4" => return Err(anyhow::anyhow!("LZ4 compression can't have a level")),
+        _ => return Err(anyhow::anyhow!("Unknown compression method: {method}")),
+    };
+
+    match level {
+        Some(level) if !levels.contains(&level) => Err(anyhow::anyhow!(
+            "The {method} compression level must be between {} and {}",
+            levels.start(),
+            levels.end()
+        )),
+        _ => Ok(compression),
+    }
+}
+
+// the size of each volume, if the output should be split
+pub fn split_size(sub_matches: &ArgMatches) -> Result<Option<u64>> {
+    sub_matches.value_of("split").map(parse_size).transpose()
+}
+
+// the most free space that `wipe-free` should fill
+pub fn wipe_limit(sub_matches: &ArgMatches) -> Result<Option<u64>> {
+    sub_matches.value_of("limit").map(parse_size).transpose()
+}
+
+// gets the limits that are enforced while extracting an archive
+pub fn unpack_limits(sub_matches: &ArgMatches) -> Result<Limits> {
+    let size_of = |name: &str| -> Result<Option<u64>> {
+        sub_matches.value_of(name).map(parse_size).transpose()
+    };
+
+    let max_entries = sub_matches
+        .value_of("max-files")
+        .map(str::parse)
+        .transpose()
+        .context("Invalid maximum number of files")?;
+
+    let max_compression_ratio = sub_matches
+        .value_of("max-ratio")
+        .map(str::parse)
+        .transpose()
+        .context("Invalid maximum compression ratio")?;
+
+    Ok(Limits {
+        max_total_size: size_of("max-size")?,
+        max_entries,
+        max_entry_size: size_of("max-file-size")?,
+        max_compression_ratio,
+    })
+}
+
 pub fn forcemode(sub_matches: &ArgMatches) -> ForceMode {
     if sub_matches.is_present("force") {
         ForceMode::Force
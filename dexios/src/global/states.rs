@@ -10,15 +10,15 @@ use crate::cli::prompt::get_password;
 use crate::warn;
 use core::key::generate_passphrase;
 
-#[derive(PartialEq, Eq, Clone, Copy)]
-pub enum DirectoryMode {
-    Singular,
-    Recursive,
-}
-
+// each method can have an optional level, otherwise the method's default is used
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
 pub enum Compression {
     None,
-    Zstd,
+    Deflate(Option<i32>),
+    Bzip2(Option<i32>),
+    Zstd(Option<i32>),
+    Xz(Option<i32>),
+    Lz4,
 }
 
 #[derive(PartialEq, Eq)]
@@ -27,7 +27,7 @@ pub enum EraseSourceDir {
     Retain,
 }
 
-#[derive(PartialEq, Eq)]
+#[derive(PartialEq, Eq, Clone, Copy)]
 pub enum PrintMode {
     Verbose,
     Quiet,
@@ -40,7 +40,7 @@ pub enum HeaderLocation {
 
 #[derive(PartialEq, Eq, Clone, Copy)]
 pub enum EraseMode {
-    EraseFile(i32),
+    EraseFile(u32),
     IgnoreFile,
 }
 
@@ -1,10 +1,9 @@
 use core::header::HashingAlgorithm;
+use domain::overwrite::Scheme;
 
 use crate::global::states::{ForceMode, HashMode};
 
-use super::states::{
-    Compression, DirectoryMode, EraseMode, EraseSourceDir, HeaderLocation, Key, PrintMode,
-};
+use super::states::{Compression, EraseMode, EraseSourceDir, HeaderLocation, Key, PrintMode};
 
 pub struct CryptoParams {
     pub hash_mode: HashMode,
@@ -16,10 +15,14 @@ pub struct CryptoParams {
 }
 
 pub struct PackParams {
-    pub dir_mode: DirectoryMode,
     pub print_mode: PrintMode,
     pub erase_source: EraseSourceDir,
     pub compression: Compression,
+    pub split: Option<u64>,
+    // the previous archive, for incremental archives
+    pub since: Option<String>,
+    // stores a manifest, so the archive can be the base of incremental ones
+    pub full: bool,
 }
 
 pub struct KeyManipulationParams {
@@ -27,3 +30,13 @@ pub struct KeyManipulationParams {
     pub key_new: Key,
     pub hashing_algorithm: HashingAlgorithm,
 }
+
+pub struct EraseParams {
+    pub scheme: Scheme,
+    // read the last pass back before the file is removed
+    pub verify: bool,
+    // truncate, rename and reset the timestamps of the file before it's removed
+    pub scrub: bool,
+    // when erasing a directory, descend into other filesystems that are mounted within it
+    pub cross_filesystems: bool,
+}
@@ -24,15 +24,33 @@ fn main() -> Result<()> {
         Some(("erase", sub_matches)) => {
             subcommands::erase(sub_matches)?;
         }
+        Some(("wipe-free", sub_matches)) => {
+            subcommands::wipe_free(sub_matches)?;
+        }
         Some(("pack", sub_matches)) => {
             subcommands::pack(sub_matches)?;
         }
         Some(("unpack", sub_matches)) => {
             subcommands::unpack(sub_matches)?;
         }
+        Some(("encrypt-dir", sub_matches)) => {
+            subcommands::encrypt_dir(sub_matches)?;
+        }
+        Some(("decrypt-dir", sub_matches)) => {
+            subcommands::decrypt_dir(sub_matches)?;
+        }
+        Some(("sync", sub_matches)) => {
+            subcommands::sync(sub_matches)?;
+        }
         Some(("hash", sub_matches)) => {
             subcommands::hash_stream(sub_matches)?;
         }
+        Some(("sign", sub_matches)) => {
+            subcommands::sign(sub_matches)?;
+        }
+        Some(("verify-sig", sub_matches)) => {
+            subcommands::verify_sig(sub_matches)?;
+        }
         Some(("header", sub_matches)) => match sub_matches.subcommand_name() {
             Some("dump") => {
                 subcommands::header_dump(sub_matches)?;
@@ -63,6 +81,45 @@ fn main() -> Result<()> {
             }
             _ => (),
         },
+        Some(("repo", sub_matches)) => match sub_matches.subcommand_name() {
+            Some("init") => {
+                subcommands::repo_init(sub_matches)?;
+            }
+            Some("backup") => {
+                subcommands::repo_backup(sub_matches)?;
+            }
+            Some("list") => {
+                subcommands::repo_list(sub_matches)?;
+            }
+            Some("restore") => {
+                subcommands::repo_restore(sub_matches)?;
+            }
+            Some("prune") => {
+                subcommands::repo_prune(sub_matches)?;
+            }
+            _ => (),
+        },
+        Some(("vault", sub_matches)) => match sub_matches.subcommand_name() {
+            Some("create") => {
+                subcommands::vault_create(sub_matches)?;
+            }
+            Some("add") => {
+                subcommands::vault_add(sub_matches)?;
+            }
+            Some("ls") => {
+                subcommands::vault_list(sub_matches)?;
+            }
+            Some("get") => {
+                subcommands::vault_get(sub_matches)?;
+            }
+            Some("rm") => {
+                subcommands::vault_remove(sub_matches)?;
+            }
+            Some("compact") => {
+                subcommands::vault_compact(sub_matches)?;
+            }
+            _ => (),
+        },
         _ => (),
     }
     Ok(())
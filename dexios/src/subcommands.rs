@@ -1,4 +1,4 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
 use clap::ArgMatches;
 
 // this is called from main.rs
@@ -6,24 +6,33 @@ use clap::ArgMatches;
 
 use crate::global::{
     parameters::{
-        algorithm, erase_params, forcemode, get_param, get_params, key_manipulation_params,
-        pack_params, parameter_handler,
+        algorithm, erase_params, erase_scheme, forcemode, get_param, get_params, hash_algorithm,
+        hashing_algorithm, key_manipulation_params, mac_key, pack_params, parameter_handler,
+        split_size, unpack_limits, wipe_limit,
     },
-    states::{Key, KeyParams},
+    states::{Key, KeyParams, PrintMode},
 };
+use domain::hasher::HashAlgorithm;
 
 pub mod decrypt;
+pub mod dir;
 pub mod encrypt;
 pub mod erase;
 pub mod hashing;
 pub mod header;
 pub mod key;
 pub mod pack;
+pub mod remote;
+pub mod repo;
+pub mod signature;
 pub mod unpack;
+pub mod vault;
+pub mod volumes;
 
 pub fn encrypt(sub_matches: &ArgMatches) -> Result<()> {
     let params = parameter_handler(sub_matches)?;
     let algorithm = algorithm(sub_matches);
+    let split = split_size(sub_matches)?;
 
     // stream mode is the only mode to encrypt (v8.5.0+)
     encrypt::stream_mode(
@@ -31,24 +40,41 @@ pub fn encrypt(sub_matches: &ArgMatches) -> Result<()> {
         &get_param("output", sub_matches)?,
         &params,
         algorithm,
+        split,
     )
 }
 
 pub fn decrypt(sub_matches: &ArgMatches) -> Result<()> {
     let params = parameter_handler(sub_matches)?;
+    let input = get_param("input", sub_matches)?;
 
     // stream decrypt is the default as it will redirect to memory mode if the header says so (for backwards-compat)
     decrypt::stream_mode(
-        &get_param("input", sub_matches)?,
+        &input,
         &get_param("output", sub_matches)?,
         &params,
+        sub_matches.value_of("require-signer"),
     )
 }
 
 pub fn erase(sub_matches: &ArgMatches) -> Result<()> {
-    let (passes, force) = erase_params(sub_matches)?;
+    let (params, force) = erase_params(sub_matches)?;
+    let input = get_param("input", sub_matches)?;
+
+    if sub_matches.is_present("device") {
+        erase::erase_device(&input, &params, force)
+    } else {
+        erase::secure_erase(&input, &params, force)
+    }
+}
 
-    erase::secure_erase(&get_param("input", sub_matches)?, passes, force)
+pub fn wipe_free(sub_matches: &ArgMatches) -> Result<()> {
+    erase::wipe_free(
+        &get_param("mountpoint", sub_matches)?,
+        erase_scheme(sub_matches)?,
+        wipe_limit(sub_matches)?,
+        forcemode(sub_matches),
+    )
 }
 
 pub fn pack(sub_matches: &ArgMatches) -> Result<()> {
@@ -68,6 +94,7 @@ pub fn unpack(sub_matches: &ArgMatches) -> Result<()> {
     use super::global::states::PrintMode;
 
     let crypto_params = parameter_handler(sub_matches)?;
+    let limits = unpack_limits(sub_matches)?;
 
     let print_mode = if sub_matches.is_present("verbose") {
         PrintMode::Verbose
@@ -76,10 +103,67 @@ pub fn unpack(sub_matches: &ArgMatches) -> Result<()> {
     };
 
     unpack::unpack(
-        &get_param("input", sub_matches)?,
+        &get_params("input", sub_matches)?,
         &get_param("output", sub_matches)?,
         print_mode,
         crypto_params,
+        limits,
+    )
+}
+
+pub fn encrypt_dir(sub_matches: &ArgMatches) -> Result<()> {
+    let key = Key::init(sub_matches, &KeyParams::default(), "keyfile")?;
+
+    let print_mode = if sub_matches.is_present("verbose") {
+        PrintMode::Verbose
+    } else {
+        PrintMode::Quiet
+    };
+
+    dir::encrypt(
+        &get_param("input", sub_matches)?,
+        &get_param("output", sub_matches)?,
+        &key,
+        hashing_algorithm(sub_matches),
+        algorithm(sub_matches),
+        print_mode,
+    )
+}
+
+pub fn decrypt_dir(sub_matches: &ArgMatches) -> Result<()> {
+    let key = Key::init(sub_matches, &KeyParams::default(), "keyfile")?;
+
+    let print_mode = if sub_matches.is_present("verbose") {
+        PrintMode::Verbose
+    } else {
+        PrintMode::Quiet
+    };
+
+    dir::decrypt(
+        &get_param("input", sub_matches)?,
+        &get_param("output", sub_matches)?,
+        &key,
+        print_mode,
+        forcemode(sub_matches),
+    )
+}
+
+pub fn sync(sub_matches: &ArgMatches) -> Result<()> {
+    let key = Key::init(sub_matches, &KeyParams::default(), "keyfile")?;
+
+    let print_mode = if sub_matches.is_present("verbose") {
+        PrintMode::Verbose
+    } else {
+        PrintMode::Quiet
+    };
+
+    dir::sync(
+        &get_param("input", sub_matches)?,
+        &get_param("output", sub_matches)?,
+        &key,
+        hashing_algorithm(sub_matches),
+        algorithm(sub_matches),
+        print_mode,
     )
 }
 
@@ -91,7 +175,51 @@ pub fn hash_stream(sub_matches: &ArgMatches) -> Result<()> {
         Vec::new()
     };
 
-    hashing::hash_stream(&files)
+    let algorithm = hash_algorithm(sub_matches)?;
+
+    if sub_matches.is_present("key") {
+        if algorithm != HashAlgorithm::Blake3 {
+            return Err(anyhow::anyhow!("Keyed MACs are only available with BLAKE3"));
+        }
+        let key = mac_key(sub_matches)?;
+        hashing::mac(&files, &key, sub_matches.value_of("verify"))
+    } else if let Some(context) = sub_matches.value_of("derive-key") {
+        if algorithm != HashAlgorithm::Blake3 {
+            return Err(anyhow::anyhow!(
+                "Key derivation is only available with BLAKE3"
+            ));
+        }
+        hashing::derive_keys(&files, context)
+    } else if sub_matches.is_present("check") {
+        hashing::check(&get_param("check", sub_matches)?, algorithm)
+    } else if sub_matches.is_present("recursive") {
+        hashing::checksums(&files, algorithm)
+    } else {
+        hashing::hash_stream(&files, algorithm)
+    }
+}
+
+pub fn sign(sub_matches: &ArgMatches) -> Result<()> {
+    let force = forcemode(sub_matches);
+
+    if sub_matches.is_present("generate") {
+        return signature::generate(&get_param("generate", sub_matches)?, force);
+    }
+
+    signature::sign(
+        &get_param("input", sub_matches)?,
+        &get_param("key", sub_matches)?,
+        sub_matches.is_present("detached"),
+        force,
+    )
+}
+
+pub fn verify_sig(sub_matches: &ArgMatches) -> Result<()> {
+    signature::verify(
+        &get_param("input", sub_matches)?,
+        &get_param("signer", sub_matches)?,
+        sub_matches.value_of("sig"),
+    )
 }
 
 pub fn header_dump(sub_matches: &ArgMatches) -> Result<()> {
@@ -155,3 +283,154 @@ pub fn key_verify(sub_matches: &ArgMatches) -> Result<()> {
 
     key::verify(&get_param("input", sub_matches_verify_key)?, &key)
 }
+
+pub fn repo_init(sub_matches: &ArgMatches) -> Result<()> {
+    let sub_matches_init = sub_matches.subcommand_matches("init").unwrap();
+    let key = Key::init(sub_matches_init, &KeyParams::default(), "keyfile")?;
+
+    repo::init(
+        &get_param("repo", sub_matches_init)?,
+        &key,
+        hashing_algorithm(sub_matches_init),
+        algorithm(sub_matches_init),
+    )
+}
+
+pub fn repo_backup(sub_matches: &ArgMatches) -> Result<()> {
+    let sub_matches_backup = sub_matches.subcommand_matches("backup").unwrap();
+    let key = Key::init(sub_matches_backup, &KeyParams::default(), "keyfile")?;
+
+    let print_mode = if sub_matches_backup.is_present("verbose") {
+        PrintMode::Verbose
+    } else {
+        PrintMode::Quiet
+    };
+
+    repo::backup(
+        &get_param("repo", sub_matches_backup)?,
+        &key,
+        &get_params("input", sub_matches_backup)?,
+        print_mode,
+    )
+}
+
+pub fn repo_list(sub_matches: &ArgMatches) -> Result<()> {
+    let sub_matches_list = sub_matches.subcommand_matches("list").unwrap();
+    let key = Key::init(sub_matches_list, &KeyParams::default(), "keyfile")?;
+
+    repo::list(&get_param("repo", sub_matches_list)?, &key)
+}
+
+pub fn repo_restore(sub_matches: &ArgMatches) -> Result<()> {
+    let sub_matches_restore = sub_matches.subcommand_matches("restore").unwrap();
+    let key = Key::init(sub_matches_restore, &KeyParams::default(), "keyfile")?;
+
+    let print_mode = if sub_matches_restore.is_present("verbose") {
+        PrintMode::Verbose
+    } else {
+        PrintMode::Quiet
+    };
+
+    repo::restore(
+        &get_param("repo", sub_matches_restore)?,
+        &key,
+        &get_param("snapshot", sub_matches_restore)?,
+        &get_param("output", sub_matches_restore)?,
+        print_mode,
+        forcemode(sub_matches_restore),
+    )
+}
+
+pub fn repo_prune(sub_matches: &ArgMatches) -> Result<()> {
+    let sub_matches_prune = sub_matches.subcommand_matches("prune").unwrap();
+    let key = Key::init(sub_matches_prune, &KeyParams::default(), "keyfile")?;
+
+    let keep = get_param("keep", sub_matches_prune)?
+        .parse::<usize>()
+        .context("The number of snapshots to keep must be a number")?;
+
+    repo::prune(&get_param("repo", sub_matches_prune)?, &key, keep)
+}
+
+pub fn vault_create(sub_matches: &ArgMatches) -> Result<()> {
+    let sub_matches_create = sub_matches.subcommand_matches("create").unwrap();
+    let key = Key::init(sub_matches_create, &KeyParams::default(), "keyfile")?;
+
+    vault::create(
+        &get_param("vault", sub_matches_create)?,
+        &key,
+        hashing_algorithm(sub_matches_create),
+        algorithm(sub_matches_create),
+        forcemode(sub_matches_create),
+    )
+}
+
+pub fn vault_add(sub_matches: &ArgMatches) -> Result<()> {
+    let sub_matches_add = sub_matches.subcommand_matches("add").unwrap();
+    let key = Key::init(sub_matches_add, &KeyParams::default(), "keyfile")?;
+
+    let print_mode = if sub_matches_add.is_present("verbose") {
+        PrintMode::Verbose
+    } else {
+        PrintMode::Quiet
+    };
+
+    vault::add(
+        &get_param("vault", sub_matches_add)?,
+        &key,
+        &get_params("input", sub_matches_add)?,
+        print_mode,
+    )
+}
+
+pub fn vault_list(sub_matches: &ArgMatches) -> Result<()> {
+    let sub_matches_list = sub_matches.subcommand_matches("ls").unwrap();
+    let key = Key::init(sub_matches_list, &KeyParams::default(), "keyfile")?;
+
+    vault::list(&get_param("vault", sub_matches_list)?, &key)
+}
+
+pub fn vault_get(sub_matches: &ArgMatches) -> Result<()> {
+    let sub_matches_get = sub_matches.subcommand_matches("get").unwrap();
+    let key = Key::init(sub_matches_get, &KeyParams::default(), "keyfile")?;
+
+    let print_mode = if sub_matches_get.is_present("verbose") {
+        PrintMode::Verbose
+    } else {
+        PrintMode::Quiet
+    };
+
+    // every file is extracted if none are provided
+    let files = if sub_matches_get.is_present("file") {
+        get_params("file", sub_matches_get)?
+    } else {
+        Vec::new()
+    };
+
+    vault::get(
+        &get_param("vault", sub_matches_get)?,
+        &key,
+        &files,
+        &get_param("output", sub_matches_get)?,
+        print_mode,
+        forcemode(sub_matches_get),
+    )
+}
+
+pub fn vault_remove(sub_matches: &ArgMatches) -> Result<()> {
+    let sub_matches_remove = sub_matches.subcommand_matches("rm").unwrap();
+    let key = Key::init(sub_matches_remove, &KeyParams::default(), "keyfile")?;
+
+    vault::remove(
+        &get_param("vault", sub_matches_remove)?,
+        &key,
+        &get_params("file", sub_matches_remove)?,
+    )
+}
+
+pub fn vault_compact(sub_matches: &ArgMatches) -> Result<()> {
+    let sub_matches_compact = sub_matches.subcommand_matches("compact").unwrap();
+    let key = Key::init(sub_matches_compact, &KeyParams::default(), "keyfile")?;
+
+    vault::compact(&get_param("vault", sub_matches_compact)?, &key)
+}
@@ -1,20 +1,35 @@
+use std::cell::RefCell;
+use std::fs::File;
 use std::process::exit;
 use std::sync::Arc;
 
+use crate::cli::cancel::ctrl_c_token;
+use crate::cli::progress::ProgressBar;
 use crate::cli::prompt::overwrite_check;
 use crate::global::states::{EraseMode, HashMode, HeaderLocation, PasswordState};
-use crate::global::structs::CryptoParams;
+use crate::global::structs::{CryptoParams, EraseParams};
+use crate::success;
 
-use anyhow::Result;
+use anyhow::{Context, Result};
 
-use domain::storage::Storage;
+use domain::hasher::HashAlgorithm;
+use domain::overwrite::Scheme;
+use domain::signature::DigestReader;
+use domain::storage::{Entry, FileStorage, Storage};
 
 // this function is for decrypting a file in stream mode
 // it handles any user-facing interactiveness, opening files, or redirecting to memory mode if
 // the header says so (backwards-compat)
 // it also manages using a detached header file if selected
 // it creates the stream object and uses the convenience function provided by dexios-core
-pub fn stream_mode(input: &str, output: &str, params: &CryptoParams) -> Result<()> {
+// if a signer is required, the file is decrypted to a temporary file, which only replaces the
+// output once the signature matches
+pub fn stream_mode(
+    input: &str,
+    output: &str,
+    params: &CryptoParams,
+    signer: Option<&str>,
+) -> Result<()> {
     // TODO: It is necessary to raise it to a higher level
     let stor = Arc::new(domain::storage::FileStorage);
 
@@ -29,7 +44,66 @@ pub fn stream_mode(input: &str, output: &str, params: &CryptoParams) -> Result<(
         exit(0);
     }
 
+    if signer.is_some() {
+        if let HeaderLocation::Detached(_) = params.header_location {
+            return Err(anyhow::anyhow!(
+                "Signatures can't be required with a detached header, as they're only read from the file itself (restore the header first)."
+            ));
+        }
+    }
+
+    // split volumes are detected by their header, and the rest are found from the first volume
+    if super::volumes::is_split(input)? {
+        if signer.is_some() {
+            return Err(anyhow::anyhow!(
+                "Signatures can't be required for split volumes."
+            ));
+        }
+
+        if let HeaderLocation::Detached(_) = params.header_location {
+            return Err(anyhow::anyhow!(
+                "Detached headers can't be used with split volumes."
+            ));
+        }
+
+        let (reader, inputs) = super::volumes::open_reader(input)?;
+        let reader = RefCell::new(reader);
+
+        let raw_key = params.key.get_secret(&PasswordState::Direct)?;
+        let output_file = stor
+            .create_file(output)
+            .or_else(|_| stor.write_file(output))?;
+
+        // 2. decrypt volumes
+        if let Err(err) = domain::decrypt::execute(domain::decrypt::Request {
+            header_reader: None,
+            reader: &reader,
+            writer: output_file.try_writer()?,
+            raw_key,
+            on_decrypted_header: None,
+            progress: Some(ProgressBar::create()),
+            cancel: Some(ctrl_c_token()),
+        }) {
+            if let domain::decrypt::Error::Cancelled = err {
+                // the domain has already erased what was written
+                stor.remove_file(output_file)?;
+            }
+            return Err(err.into());
+        }
+
+        // 3. flush result
+        stor.flush_file(&output_file)?;
+
+        return finish(&inputs, params);
+    }
+
     let input_file = stor.read_file(input)?;
+
+    if let Some(signer) = signer {
+        decrypt_signed(&stor, input, &input_file, output, signer, params)?;
+        return finish(&[input.to_string()], params);
+    }
+
     let header_file = match &params.header_location {
         HeaderLocation::Embedded => None,
         HeaderLocation::Detached(path) => Some(stor.read_file(path)?),
@@ -41,23 +115,106 @@ pub fn stream_mode(input: &str, output: &str, params: &CryptoParams) -> Result<(
         .or_else(|_| stor.write_file(output))?;
 
     // 2. decrypt file
-    domain::decrypt::execute(domain::decrypt::Request {
+    if let Err(err) = domain::decrypt::execute(domain::decrypt::Request {
         header_reader: header_file.as_ref().and_then(|h| h.try_reader().ok()),
         reader: input_file.try_reader()?,
         writer: output_file.try_writer()?,
         raw_key,
         on_decrypted_header: None,
-    })?;
+        progress: Some(ProgressBar::create()),
+        cancel: Some(ctrl_c_token()),
+    }) {
+        if let domain::decrypt::Error::Cancelled = err {
+            // the domain has already erased what was written
+            stor.remove_file(output_file)?;
+        }
+        return Err(err.into());
+    }
 
     // 3. flush result
     stor.flush_file(&output_file)?;
 
+    finish(&[input.to_string()], params)
+}
+
+// the signature is checked against the bytes that were decrypted, as they're hashed while they're
+// read (verifying the file separately would read it twice, and it could be swapped in between)
+fn decrypt_signed(
+    stor: &Arc<FileStorage>,
+    input: &str,
+    input_file: &Entry<File>,
+    output: &str,
+    signer: &str,
+    params: &CryptoParams,
+) -> Result<()> {
+    let key = super::signature::public_key(signer)?;
+    let raw_key = params.key.get_secret(&PasswordState::Direct)?;
+
+    let temp_path = format!("{}.unverified", output);
+    let temp_file = stor
+        .create_file(&temp_path)
+        .or_else(|_| stor.write_file(&temp_path))?;
+
+    let mut input_reader = input_file.try_reader()?.borrow_mut();
+    let reader = RefCell::new(DigestReader::new(&mut *input_reader));
+
+    let res = domain::decrypt::execute(domain::decrypt::Request {
+        header_reader: None,
+        reader: &reader,
+        writer: temp_file.try_writer()?,
+        raw_key,
+        on_decrypted_header: None,
+        progress: Some(ProgressBar::create()),
+        cancel: Some(ctrl_c_token()),
+    })
+    .map_err(anyhow::Error::from)
+    .and_then(|()| {
+        let reader = reader.into_inner();
+        let signature = match reader.embedded() {
+            Some(_) => None,
+            None => Some(super::signature::detached_signature(input)?),
+        };
+
+        domain::signature::verify::finish(reader, &key, signature)
+            .with_context(|| format!("Unable to verify the signature of {}", input))
+    })
+    .and_then(|()| Ok(stor.flush_file(&temp_file)?));
+
+    // nothing that was decrypted is kept unless the signature matches
+    if let Err(err) = res {
+        stor.remove_file(temp_file)?;
+        return Err(err);
+    }
+
+    drop(temp_file);
+    std::fs::rename(&temp_path, output)
+        .with_context(|| format!("Unable to move {} to {}", temp_path, output))?;
+
+    success!(
+        "{} is signed by {}",
+        input,
+        domain::signature::encode(key.as_bytes())
+    );
+
+    Ok(())
+}
+
+// hashes and erases the input file(s), if requested
+fn finish(inputs: &[String], params: &CryptoParams) -> Result<()> {
     if params.hash_mode == HashMode::CalculateHash {
-        super::hashing::hash_stream(&[input.to_string()])?;
+        super::hashing::hash_stream(inputs, HashAlgorithm::Blake3)?;
     }
 
     if let EraseMode::EraseFile(passes) = params.erase {
-        super::erase::secure_erase(input, passes, params.force)?;
+        for input in inputs {
+            let erase_params = EraseParams {
+                scheme: Scheme::Random(passes),
+                verify: false,
+                scrub: false,
+                cross_filesystems: false,
+            };
+            super::erase::secure_erase(input, &erase_params, params.force)?;
+        }
     }
 
     Ok(())
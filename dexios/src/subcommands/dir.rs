@@ -0,0 +1,174 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use core::header::{HashingAlgorithm, HeaderType, HEADER_VERSION};
+use core::primitives::{Algorithm, Mode};
+use domain::encrypt_dir::DIR_HEADER_NAME;
+use domain::storage::FileStorage;
+use domain::sync::{Change, OnChangeFn};
+
+use crate::cli::prompt::get_answer;
+use crate::global::states::{ForceMode, Key, PasswordState, PrintMode};
+use crate::{info, success, warn};
+
+// every file is encrypted separately, and the names are encrypted too
+// the master key is shared by every file, and its keyslots are stored at the root of the output
+pub fn encrypt(
+    input: &str,
+    output: &str,
+    key: &Key,
+    hashing_algorithm: HashingAlgorithm,
+    algorithm: Algorithm,
+    print_mode: PrintMode,
+) -> Result<()> {
+    if Path::new(input) == Path::new(output) {
+        return Err(anyhow::anyhow!(
+            "Input and output directories cannot be the same."
+        ));
+    }
+
+    // existing encrypted directories are updated with their own key
+    let password_state = if Path::new(output).join(DIR_HEADER_NAME).exists() {
+        PasswordState::Direct
+    } else {
+        PasswordState::Validate
+    };
+    let raw_key = key.get_secret(&password_state)?;
+
+    let on_file: Box<dyn Fn(&Path)> = Box::new(move |path| {
+        if print_mode == PrintMode::Verbose {
+            info!("Encrypting {}", path.display());
+        }
+    });
+
+    domain::encrypt_dir::execute(
+        &FileStorage,
+        domain::encrypt_dir::Request {
+            input_dir_path: PathBuf::from(input),
+            output_dir_path: PathBuf::from(output),
+            raw_key,
+            header_type: HeaderType {
+                version: HEADER_VERSION,
+                algorithm,
+                mode: Mode::StreamMode,
+            },
+            hashing_algorithm,
+            on_file: Some(&on_file),
+        },
+    )?;
+
+    success!("Encrypted {} to {}", input, output);
+
+    Ok(())
+}
+
+pub fn decrypt(
+    input: &str,
+    output: &str,
+    key: &Key,
+    print_mode: PrintMode,
+    force: ForceMode,
+) -> Result<()> {
+    let raw_key = key.get_secret(&PasswordState::Direct)?;
+    let output_dir = PathBuf::from(output);
+
+    let on_file: Box<dyn Fn(&Path) -> bool> = Box::new(move |path| {
+        let output_path = output_dir.join(path);
+        if output_path.exists() {
+            let answer = get_answer(
+                &format!(
+                    "{} already exists, would you like to overwrite?",
+                    output_path.display()
+                ),
+                true,
+                force,
+            )
+            .expect("Unable to read answer");
+            if !answer {
+                warn!("Skipping {}", output_path.display());
+                return false;
+            }
+        }
+
+        if print_mode == PrintMode::Verbose {
+            info!("Decrypting {}", path.display());
+        }
+
+        true
+    });
+
+    domain::decrypt_dir::execute(
+        &FileStorage,
+        domain::decrypt_dir::Request {
+            input_dir_path: PathBuf::from(input),
+            output_dir_path: PathBuf::from(output),
+            raw_key,
+            on_file: Some(&on_file),
+        },
+    )?;
+
+    success!("Decrypted {} to {}", input, output);
+
+    Ok(())
+}
+
+// only new or changed files are encrypted, and files that were deleted are removed from the output
+pub fn sync(
+    input: &str,
+    output: &str,
+    key: &Key,
+    hashing_algorithm: HashingAlgorithm,
+    algorithm: Algorithm,
+    print_mode: PrintMode,
+) -> Result<()> {
+    if Path::new(input) == Path::new(output) {
+        return Err(anyhow::anyhow!(
+            "Input and output directories cannot be the same."
+        ));
+    }
+
+    let password_state = if Path::new(output).join(DIR_HEADER_NAME).exists() {
+        PasswordState::Direct
+    } else {
+        PasswordState::Validate
+    };
+    let raw_key = key.get_secret(&password_state)?;
+
+    let on_change: OnChangeFn = Box::new(move |path, change| {
+        if print_mode == PrintMode::Verbose {
+            match change {
+                Change::Added => info!("Adding {}", path.display()),
+                Change::Updated => info!("Updating {}", path.display()),
+                Change::Removed => info!("Removing {}", path.display()),
+            }
+        }
+    });
+
+    let summary = domain::sync::execute(
+        &FileStorage,
+        domain::sync::Request {
+            input_dir_path: PathBuf::from(input),
+            output_dir_path: PathBuf::from(output),
+            raw_key,
+            header_type: HeaderType {
+                version: HEADER_VERSION,
+                algorithm,
+                mode: Mode::StreamMode,
+            },
+            hashing_algorithm,
+            on_change: Some(&on_change),
+        },
+    )?;
+
+    success!(
+        "Synchronised {} to {} ({} added, {} updated, {} removed, {} unchanged)",
+        input,
+        output,
+        summary.added,
+        summary.updated,
+        summary.removed,
+        summary.unchanged
+    );
+
+    Ok(())
+}
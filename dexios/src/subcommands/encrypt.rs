@@ -1,12 +1,17 @@
+use crate::cli::cancel::ctrl_c_token;
+use crate::cli::progress::ProgressBar;
 use crate::cli::prompt::overwrite_check;
 use crate::global::states::{EraseMode, HashMode, HeaderLocation, PasswordState};
-use crate::global::structs::CryptoParams;
+use crate::global::structs::{CryptoParams, EraseParams};
 use anyhow::Result;
 use core::header::{HeaderType, HEADER_VERSION};
 use core::primitives::{Algorithm, Mode};
+use std::cell::RefCell;
 use std::process::exit;
 use std::sync::Arc;
 
+use domain::hasher::HashAlgorithm;
+use domain::overwrite::Scheme;
 use domain::storage::Storage;
 
 // this function is for encrypting a file in stream mode
@@ -17,6 +22,7 @@ pub fn stream_mode(
     output: &str,
     params: &CryptoParams,
     algorithm: Algorithm,
+    split: Option<u64>,
 ) -> Result<()> {
     // TODO: It is necessary to raise it to a higher level
     let stor = Arc::new(domain::storage::FileStorage);
@@ -28,54 +34,118 @@ pub fn stream_mode(
         ));
     }
 
-    if !overwrite_check(output, params.force)? {
+    if split.is_some() && super::remote::is_remote(output) {
+        return Err(anyhow::anyhow!("Split volumes can't be stored in S3."));
+    }
+
+    // split outputs are written to `output.001`, `output.002`, ...
+    let overwrite = match split {
+        Some(_) => super::volumes::overwrite_check(output, params.force)?,
+        None => overwrite_check(output, params.force)?,
+    };
+
+    if !overwrite {
         exit(0);
     }
 
     let input_file = stor.read_file(input)?;
     let raw_key = params.key.get_secret(&PasswordState::Validate)?;
-    let output_file = stor
-        .create_file(output)
-        .or_else(|_| stor.write_file(output))?;
-
-    let header_file = match &params.header_location {
-        HeaderLocation::Embedded => None,
-        HeaderLocation::Detached(path) => {
-            if !overwrite_check(path, params.force)? {
-                exit(0);
-            }
 
-            Some(stor.create_file(path).or_else(|_| stor.write_file(path))?)
-        }
+    let header_type = HeaderType {
+        version: HEADER_VERSION,
+        mode: Mode::StreamMode,
+        algorithm,
     };
 
     // 2. encrypt file
-    let req = domain::encrypt::Request {
-        reader: input_file.try_reader()?,
-        writer: output_file.try_writer()?,
-        header_writer: header_file.as_ref().and_then(|f| f.try_writer().ok()),
-        raw_key,
-        header_type: HeaderType {
-            version: HEADER_VERSION,
-            mode: Mode::StreamMode,
-            algorithm,
-        },
-        hashing_algorithm: params.hashing_algorithm,
-    };
-    domain::encrypt::execute(req)?;
+    let outputs = if let Some(volume_size) = split {
+        let writer = RefCell::new(super::volumes::create_writer(output, volume_size)?);
 
-    // 3. flush result
-    if let Some(header_file) = header_file {
-        stor.flush_file(&header_file)?;
-    }
-    stor.flush_file(&output_file)?;
+        let res = domain::encrypt::execute(domain::encrypt::Request {
+            reader: input_file.try_reader()?,
+            writer: &writer,
+            header_writer: None,
+            raw_key,
+            header_type,
+            hashing_algorithm: params.hashing_algorithm,
+            progress: Some(ProgressBar::create()),
+            cancel: Some(ctrl_c_token()),
+        });
+
+        if let Err(err) = res {
+            if let domain::encrypt::Error::Cancelled = err {
+                // volumes can't be erased through the writer, as it only writes sequentially
+                let volumes = writer.into_inner().volumes();
+                super::volumes::remove_volumes(output, volumes)?;
+            }
+            return Err(err.into());
+        }
+
+        // 3. mark the last volume
+        let volumes = writer.borrow_mut().finish()?;
+        super::volumes::volume_paths(output, volumes as usize)
+    } else {
+        // outputs may be local files or S3 objects, so their storage is chosen at runtime
+        let (output_stor, output_path) = super::remote::storage(output)?;
+        let output_file = output_stor
+            .create_file(&output_path)
+            .or_else(|_| output_stor.write_file(&output_path))?;
+
+        let header_file = match &params.header_location {
+            HeaderLocation::Embedded => None,
+            HeaderLocation::Detached(path) => {
+                if !overwrite_check(path, params.force)? {
+                    exit(0);
+                }
+
+                let (header_stor, path) = super::remote::storage(path)?;
+                let header_file = header_stor
+                    .create_file(&path)
+                    .or_else(|_| header_stor.write_file(&path))?;
+                Some((header_stor, header_file))
+            }
+        };
+
+        let req = domain::encrypt::Request {
+            reader: input_file.try_reader()?,
+            writer: output_file.try_writer()?,
+            header_writer: header_file.as_ref().and_then(|(_, f)| f.try_writer().ok()),
+            raw_key,
+            header_type,
+            hashing_algorithm: params.hashing_algorithm,
+            progress: Some(ProgressBar::create()),
+            cancel: Some(ctrl_c_token()),
+        };
+
+        if let Err(err) = domain::encrypt::execute(req) {
+            if let domain::encrypt::Error::Cancelled = err {
+                // the domain has already erased what was written
+                output_stor.remove_file(output_file)?;
+            }
+            return Err(err.into());
+        }
+
+        // 3. flush result
+        if let Some((header_stor, header_file)) = header_file {
+            header_stor.flush_file(&header_file)?;
+        }
+        output_stor.flush_file(&output_file)?;
+
+        vec![output.to_string()]
+    };
 
     if params.hash_mode == HashMode::CalculateHash {
-        super::hashing::hash_stream(&[output.to_string()])?;
+        super::hashing::hash_stream(&outputs, HashAlgorithm::Blake3)?;
     }
 
     if let EraseMode::EraseFile(passes) = params.erase {
-        super::erase::secure_erase(input, passes, params.force)?;
+        let erase_params = EraseParams {
+            scheme: Scheme::Random(passes),
+            verify: false,
+            scrub: false,
+            cross_filesystems: false,
+        };
+        super::erase::secure_erase(input, &erase_params, params.force)?;
     }
 
     Ok(())
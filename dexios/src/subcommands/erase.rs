@@ -1,16 +1,23 @@
 use anyhow::Result;
+use domain::erase_dir::Outcome;
+use domain::overwrite::{Report, Scheme};
 use domain::storage::Storage;
+use std::num::NonZeroUsize;
 use std::sync::Arc;
 
 use crate::global::states::ForceMode;
+use crate::global::structs::EraseParams;
 
+use crate::cli::cancel::ctrl_c_token;
+use crate::cli::progress::ProgressBar;
 use crate::cli::prompt::get_answer;
+use crate::{error, info, success, warn};
 
 // this function securely erases a file
 // read the docs for some caveats with file-erasure on flash storage
-// it takes the file name/relative path, and the number of times to go over the file's contents with random bytes
+// it takes the file name/relative path, and how the file should be overwritten and removed
 #[allow(clippy::module_name_repetitions)]
-pub fn secure_erase(input: &str, passes: i32, force: ForceMode) -> Result<()> {
+pub fn secure_erase(input: &str, params: &EraseParams, force: ForceMode) -> Result<()> {
     // TODO: It is necessary to raise it to a higher level
     let stor = Arc::new(domain::storage::FileStorage);
 
@@ -26,21 +33,171 @@ pub fn secure_erase(input: &str, passes: i32, force: ForceMode) -> Result<()> {
     }
 
     if file.is_dir() {
-        domain::erase_dir::execute(
+        let report = domain::erase_dir::execute(
             stor,
             domain::erase_dir::Request {
                 entry: file,
-                passes,
+                scheme: params.scheme.clone(),
+                verify: params.verify,
+                scrub: params.scrub,
+                cross_filesystems: params.cross_filesystems,
+                workers: std::thread::available_parallelism().map_or(1, NonZeroUsize::get),
+                progress: Some(ProgressBar::create()),
             },
         )?;
+
+        for file in report.skipped() {
+            warn!("Skipped {}", file.path.display());
+        }
+
+        let failed = report.failed().count();
+        for file in report.failed() {
+            if let Outcome::Failed(err) = &file.outcome {
+                error!("Unable to erase {}: {}", file.path.display(), err);
+            }
+        }
+
+        if failed > 0 {
+            return Err(anyhow::anyhow!(
+                "{} of {} files couldn't be erased, so {} was kept",
+                failed,
+                report.files.len(),
+                input
+            ));
+        }
+
+        if report.removed {
+            success!("Erased {}", input);
+        } else {
+            success!(
+                "Erased the files within {} (it was kept, as some entries were skipped)",
+                input
+            );
+        }
     } else {
-        domain::erase::execute(
+        let report = domain::erase::execute(
             stor,
             domain::erase::Request {
                 path: input,
-                passes,
+                scheme: params.scheme.clone(),
+                verify: params.verify,
+                scrub: params.scrub,
+                progress: Some(ProgressBar::create()),
             },
-        )?;
+        )
+        .map_err(|err| match err {
+            domain::erase::Error::NotRegularFile => anyhow::anyhow!(
+                "{} isn't a regular file (use --device to overwrite a block device)",
+                input
+            ),
+            err => err.into(),
+        })?;
+
+        print_passes(&report);
+
+        if params.verify {
+            success!("Erased {} (the last pass was read back)", input);
+        } else {
+            success!("Erased {}", input);
+        }
+    }
+
+    Ok(())
+}
+
+// this overwrites a whole block device (or anything else whose size isn't in its metadata), and leaves it in place
+// the domain refuses devices that are in use (and anything on platforms other than Linux), but it still asks first (unless forced)
+pub fn erase_device(input: &str, params: &EraseParams, force: ForceMode) -> Result<()> {
+    let stor = Arc::new(domain::storage::FileStorage);
+
+    if !get_answer(
+        &format!("This will overwrite all of {input}, would you like to continue?"),
+        false,
+        force,
+    )? {
+        std::process::exit(0);
+    }
+
+    let report = domain::erase_device::execute(
+        stor,
+        domain::erase_device::Request {
+            path: input,
+            scheme: params.scheme.clone(),
+            verify: params.verify,
+            progress: Some(ProgressBar::create()),
+            cancel: Some(ctrl_c_token()),
+        },
+    )?;
+
+    print_passes(&report);
+
+    if params.verify {
+        success!("Overwrote {} (the last pass was read back)", input);
+    } else {
+        success!("Overwrote {}", input);
+    }
+
+    Ok(())
+}
+
+fn print_passes(report: &Report) {
+    for (i, pass) in report.passes.iter().enumerate() {
+        info!(
+            "Pass {} ({}): {} bytes overwritten",
+            i + 1,
+            pass.pattern,
+            pass.bytes
+        );
+    }
+}
+
+// this fills the free space of the filesystem that `mountpoint` is on, and then removes what it wrote
+// other programs may fail to write while it runs, so it asks first (unless forced)
+pub fn wipe_free(
+    mountpoint: &str,
+    scheme: Scheme,
+    limit: Option<u64>,
+    force: ForceMode,
+) -> Result<()> {
+    let stor = Arc::new(domain::storage::FileStorage);
+
+    if !stor.read_file(mountpoint)?.is_dir() {
+        return Err(anyhow::anyhow!("{} is not a directory", mountpoint));
+    }
+
+    if !get_answer(
+        &format!("This will fill the free space of the filesystem that {mountpoint} is on, would you like to continue?"),
+        false,
+        force,
+    )? {
+        std::process::exit(0);
+    }
+
+    let report = domain::wipe_free::execute(
+        stor,
+        domain::wipe_free::Request {
+            dir: mountpoint,
+            scheme,
+            limit,
+            file_size: domain::wipe_free::DEFAULT_FILE_SIZE,
+            progress: Some(ProgressBar::create()),
+            cancel: Some(ctrl_c_token()),
+        },
+    )?;
+
+    info!(
+        "{} bytes of free space were overwritten ({} temporary files)",
+        report.bytes, report.files
+    );
+
+    if report.full {
+        success!("Wiped the free space of {}", mountpoint);
+    } else {
+        success!(
+            "Wiped {} bytes of the free space of {} (the limit was reached)",
+            report.bytes,
+            mountpoint
+        );
     }
 
     Ok(())
@@ -1,21 +1,46 @@
 use anyhow::Context;
 use anyhow::Result;
 use std::cell::RefCell;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
-use crate::success;
+use domain::checksums::{Checksum, Status};
+use domain::hasher::{Blake3Hasher, HashAlgorithm};
+use domain::mac::MacKey;
+use domain::storage::{FileStorage, Storage};
+
+use crate::cli::progress::ProgressBar;
+use crate::global::states::{Key, PasswordState};
+use crate::{success, warn};
 
 // this hashes the input file
 // it reads it in blocks, updates the hasher, and finalises/displays the hash
-// it's used by hash-standalone mode
-pub fn hash_stream(files: &[String]) -> Result<()> {
+// it's used by hash-standalone mode, and other modes always use BLAKE3
+// local files are hashed on every core with BLAKE3, which gives the same hash
+pub fn hash_stream(files: &[String], algorithm: HashAlgorithm) -> Result<()> {
     for input in files {
-        let mut input_file = std::fs::File::open(input)
+        if algorithm == HashAlgorithm::Blake3 && !super::remote::is_remote(input) {
+            let hash = domain::hash::execute_parallel(
+                Blake3Hasher::default(),
+                input,
+                Some(ProgressBar::create()),
+            )
+            .with_context(|| format!("Unable to hash file: {}", input))?;
+            success!("{}: {}", input, hash);
+            continue;
+        }
+
+        let (stor, path) = super::remote::storage(input)?;
+        let input_file = stor
+            .read_file(path)
             .with_context(|| format!("Unable to open file: {}", input))?;
+        let mut reader = input_file.try_reader()?.borrow_mut();
 
         let hash = domain::hash::execute(
-            domain::hasher::Blake3Hasher::default(),
+            algorithm.hasher(),
             domain::hash::Request {
-                reader: RefCell::new(&mut input_file),
+                reader: RefCell::new(&mut *reader),
+                progress: Some(ProgressBar::create()),
             },
         )?;
 
@@ -24,3 +49,182 @@ pub fn hash_stream(files: &[String]) -> Result<()> {
 
     Ok(())
 }
+
+// this makes a keyed BLAKE3 MAC of each file, so changes can't be hidden by recomputing the hash
+// if a MAC is given, it's compared with the file's MAC (in constant time) and this fails if they don't match
+pub fn mac(files: &[String], key: &Key, expected: Option<&str>) -> Result<()> {
+    if expected.is_some() && files.len() != 1 {
+        return Err(anyhow::anyhow!(
+            "Only one file can be verified against a MAC"
+        ));
+    }
+
+    // the password is only confirmed when making a new MAC, as a typo would make it unverifiable
+    let pass_state = if expected.is_some() {
+        PasswordState::Direct
+    } else {
+        PasswordState::Validate
+    };
+    let mac_key = MacKey::derive(key.get_secret(&pass_state)?)?;
+
+    for input in files {
+        let mac = blake3_hash(input, mac_key.hasher())?;
+
+        match expected {
+            Some(expected) => {
+                domain::mac::verify(&mac, expected)
+                    .with_context(|| format!("Unable to verify the MAC of {}", input))?;
+                success!("{}: MAC verified", input);
+            }
+            None => success!("{}: {}", input, mac),
+        }
+    }
+
+    Ok(())
+}
+
+// this derives a key from each file with BLAKE3's derive-key mode (like `b3sum --derive-key`)
+// the file is the key material, and the context string should be unique to the application
+pub fn derive_keys(files: &[String], context: &str) -> Result<()> {
+    for input in files {
+        let key = blake3_hash(input, Blake3Hasher::derive_key(context))?;
+        success!("{}: {}", input, key);
+    }
+
+    Ok(())
+}
+
+// hashes a file with an already-initialised BLAKE3 hasher
+// local files are hashed on every core, and remote files are streamed
+fn blake3_hash(input: &str, hasher: Blake3Hasher) -> Result<String> {
+    if !super::remote::is_remote(input) {
+        return domain::hash::execute_parallel(hasher, input, Some(ProgressBar::create()))
+            .with_context(|| format!("Unable to hash file: {}", input));
+    }
+
+    let (stor, path) = super::remote::storage(input)?;
+    let input_file = stor
+        .read_file(path)
+        .with_context(|| format!("Unable to open file: {}", input))?;
+    let mut reader = input_file.try_reader()?.borrow_mut();
+
+    Ok(domain::hash::execute(
+        hasher,
+        domain::hash::Request {
+            reader: RefCell::new(&mut *reader),
+            progress: Some(ProgressBar::create()),
+        },
+    )?)
+}
+
+// this prints a checksum line for every file, walking any directories that it's given
+// the output can be checked with `dexios hash --check`, or with sha256sum/b3sum (etc.) if the algorithm matches
+pub fn checksums(inputs: &[String], algorithm: HashAlgorithm) -> Result<()> {
+    let stor = Arc::new(FileStorage);
+
+    let mut paths = Vec::new();
+    for input in inputs {
+        let entry = stor
+            .read_file(input)
+            .with_context(|| format!("Unable to open file: {}", input))?;
+
+        if entry.is_dir() {
+            for path in stor.walk_dir(&entry)? {
+                let path = path?;
+                if !path.is_dir() {
+                    paths.push(path);
+                }
+            }
+        } else {
+            paths.push(PathBuf::from(input));
+        }
+    }
+
+    paths.sort();
+
+    for path in paths {
+        let checksum = Checksum {
+            hash: hash_file(&stor, &path, algorithm)?,
+            path,
+        };
+        println!("{checksum}");
+    }
+
+    Ok(())
+}
+
+// this hashes every file within a checksum file again, and prints whether each of them is OK, FAILED or MISSING
+// it fails if any of them aren't OK, so it can be used within scripts
+pub fn check(checksum_file: &str, algorithm: HashAlgorithm) -> Result<()> {
+    let stor = Arc::new(FileStorage);
+
+    let checksums = std::fs::read_to_string(checksum_file)
+        .with_context(|| format!("Unable to read checksum file: {}", checksum_file))?;
+    let checksums = domain::checksums::parse(&checksums)?;
+
+    let results = domain::checksums::execute(
+        stor,
+        domain::checksums::Request {
+            checksums: &checksums,
+            algorithm,
+            progress: Some(ProgressBar::create()),
+        },
+    );
+
+    for result in &results {
+        println!("{}: {}", result.path.display(), result.status);
+    }
+
+    let failed = results
+        .iter()
+        .filter(|r| r.status == Status::Failed)
+        .count();
+    let missing = results
+        .iter()
+        .filter(|r| r.status == Status::Missing)
+        .count();
+
+    if failed + missing > 0 {
+        if failed > 0 {
+            warn!("{} computed checksums did not match", failed);
+        }
+        if missing > 0 {
+            warn!("{} listed files could not be found", missing);
+        }
+        return Err(anyhow::anyhow!(
+            "{} of {} files failed the check",
+            failed + missing,
+            results.len()
+        ));
+    }
+
+    success!("All {} files are OK", results.len());
+
+    Ok(())
+}
+
+fn hash_file(stor: &Arc<FileStorage>, path: &Path, algorithm: HashAlgorithm) -> Result<String> {
+    if algorithm == HashAlgorithm::Blake3 {
+        return domain::hash::execute_parallel(
+            Blake3Hasher::default(),
+            path,
+            Some(ProgressBar::create()),
+        )
+        .with_context(|| format!("Unable to hash file: {}", path.display()));
+    }
+
+    let file = stor
+        .read_file(path)
+        .with_context(|| format!("Unable to open file: {}", path.display()))?;
+    let mut reader = file.try_reader()?.borrow_mut();
+
+    let hash = domain::hash::execute(
+        algorithm.hasher(),
+        domain::hash::Request {
+            reader: RefCell::new(&mut *reader),
+            progress: Some(ProgressBar::create()),
+        },
+    )?;
+
+    Ok(hash)
+}
@@ -58,6 +58,13 @@ pub fn details(input: &str) -> Result<()> {
         }
     }
 
+    if let Some(signature) = domain::signature::embedded(&RefCell::new(input_file))? {
+        println!(
+            "Signature: {} (hex, embedded)",
+            hex_encode(&signature.to_bytes())
+        );
+    }
+
     Ok(())
 }
 
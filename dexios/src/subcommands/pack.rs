@@ -1,20 +1,29 @@
+use std::cell::RefCell;
 use std::path::PathBuf;
 use std::process::exit;
 use std::sync::Arc;
 
-use anyhow::Result;
+use anyhow::{Context, Result};
 use core::header::{HeaderType, HEADER_VERSION};
 use core::primitives::{Algorithm, Mode};
 
-use crate::global::states::{HashMode, HeaderLocation, PasswordState};
+use crate::cli::cancel::ctrl_c_token;
+use crate::cli::progress::ProgressBar;
+use crate::global::states::{HashMode, HeaderLocation, PasswordState, PrintMode};
+use crate::info;
 use crate::{
     global::states::EraseSourceDir,
     global::{
         states::Compression,
-        structs::{CryptoParams, PackParams},
+        structs::{CryptoParams, EraseParams, PackParams},
     },
 };
-use domain::storage::Storage;
+use core::protected::Protected;
+use domain::hasher::HashAlgorithm;
+use domain::manifest::Manifest;
+use domain::overwrite::Scheme;
+use domain::pack::{Backup, CompressionMethod};
+use domain::storage::{FileStorage, Paths, Storage};
 
 use crate::cli::prompt::overwrite_check;
 
@@ -33,7 +42,7 @@ pub struct Request<'a> {
 // it erases the temporary archive afterwards, to stop any residual data from remaining
 pub fn execute(req: &Request) -> Result<()> {
     // TODO: It is necessary to raise it to a higher level
-    let stor = Arc::new(domain::storage::FileStorage);
+    let stor = Arc::new(FileStorage);
 
     // 1. validate and prepare options
     if req.input_file.iter().any(|f| f == req.output_file) {
@@ -46,7 +55,17 @@ pub fn execute(req: &Request) -> Result<()> {
         return Err(anyhow::anyhow!("Input path cannot be a file."));
     }
 
-    if !overwrite_check(req.output_file, req.crypto_params.force)? {
+    if req.pack_params.split.is_some() && super::remote::is_remote(req.output_file) {
+        return Err(anyhow::anyhow!("Split volumes can't be stored in S3."));
+    }
+
+    // split outputs are written to `output.001`, `output.002`, ...
+    let overwrite = match req.pack_params.split {
+        Some(_) => super::volumes::overwrite_check(req.output_file, req.crypto_params.force)?,
+        None => overwrite_check(req.output_file, req.crypto_params.force)?,
+    };
+
+    if !overwrite {
         exit(0);
     }
 
@@ -56,74 +75,179 @@ pub fn execute(req: &Request) -> Result<()> {
         .map(|file_name| stor.read_file(file_name))
         .collect::<Result<Vec<_>, _>>()?;
     let raw_key = req.crypto_params.key.get_secret(&PasswordState::Validate)?;
-    let output_file = stor
-        .create_file(req.output_file)
-        .or_else(|_| stor.write_file(req.output_file))?;
-
-    let header_file = match &req.crypto_params.header_location {
-        HeaderLocation::Embedded => None,
-        HeaderLocation::Detached(path) => {
-            if !overwrite_check(path, req.crypto_params.force)? {
-                exit(0);
-            }
 
-            Some(stor.create_file(path).or_else(|_| stor.write_file(path))?)
+    // incremental archives only contain files that changed since the previous archive
+    // only full and incremental archives store a manifest, so a plain archive is just the files
+    let backup = match &req.pack_params.since {
+        Some(previous) => {
+            let raw_key = Protected::new(raw_key.expose().clone());
+            Backup::Incremental(previous_manifest(&stor, previous, raw_key)?)
         }
+        None if req.pack_params.full => Backup::Full,
+        None => Backup::None,
     };
 
-    let compress_files = input_files
-        .into_iter()
-        .flat_map(|file| {
+    // paths are yielded lazily, so we never hold more than one file open at once
+    let compress_paths = input_files
+        .iter()
+        .flat_map(|file| -> Paths<'_> {
             if file.is_dir() {
-                // TODO(pleshevskiy): use iterator instead of vec!
-                match stor.read_dir(&file) {
-                    Ok(files) => files.into_iter().map(Ok).collect(),
-                    Err(err) => vec![Err(err)],
+                match stor.walk_dir(file) {
+                    Ok(paths) => paths,
+                    Err(err) => Box::new(std::iter::once(Err(err))),
                 }
             } else {
-                vec![Ok(file)]
+                Box::new(std::iter::once(Ok(file.path().to_path_buf())))
             }
         })
-        .collect::<Result<Vec<_>, _>>()?;
+        .inspect(|path| {
+            if let (Ok(path), PrintMode::Verbose) = (path, &req.pack_params.print_mode) {
+                info!("Compressing {}", path.display());
+            }
+        });
+
+    let zip_method = |method, level| (CompressionMethod::Zip(method), level);
+    let (compression_method, compression_level) = match req.pack_params.compression {
+        Compression::None => zip_method(zip::CompressionMethod::Stored, None),
+        Compression::Deflate(level) => zip_method(zip::CompressionMethod::Deflated, level),
+        Compression::Bzip2(level) => zip_method(zip::CompressionMethod::Bzip2, level),
+        Compression::Zstd(level) => zip_method(zip::CompressionMethod::Zstd, level),
+        Compression::Xz(level) => zip_method(zip::CompressionMethod::Xz, level),
+        Compression::Lz4 => (CompressionMethod::Lz4, None),
+    };
 
-    let compression_method = match req.pack_params.compression {
-        Compression::None => zip::CompressionMethod::Stored,
-        Compression::Zstd => zip::CompressionMethod::Zstd,
+    let header_type = HeaderType {
+        version: HEADER_VERSION,
+        mode: Mode::StreamMode,
+        algorithm: req.algorithm,
     };
 
     // 2. compress and encrypt files
-    domain::pack::execute(
-        stor.clone(),
-        domain::pack::Request {
-            compress_files,
-            compression_method,
-            writer: output_file.try_writer()?,
-            header_writer: header_file.as_ref().and_then(|f| f.try_writer().ok()),
-            raw_key,
-            header_type: HeaderType {
-                version: HEADER_VERSION,
-                mode: Mode::StreamMode,
-                algorithm: req.algorithm,
+    let outputs = if let Some(volume_size) = req.pack_params.split {
+        let writer = RefCell::new(super::volumes::create_writer(req.output_file, volume_size)?);
+
+        let res = domain::pack::execute(
+            stor.clone(),
+            domain::pack::Request {
+                compress_paths: Box::new(compress_paths),
+                compression_method,
+                compression_level,
+                backup,
+                writer: &writer,
+                header_writer: None,
+                raw_key,
+                header_type,
+                hashing_algorithm: req.crypto_params.hashing_algorithm,
+                progress: Some(ProgressBar::create()),
+                cancel: Some(ctrl_c_token()),
             },
-            hashing_algorithm: req.crypto_params.hashing_algorithm,
-        },
-    )?;
+        );
 
-    // 3. flush result
-    if let Some(header_file) = header_file {
-        stor.flush_file(&header_file)?;
-    }
-    stor.flush_file(&output_file)?;
+        if let Err(err) = res {
+            if let domain::pack::Error::Cancelled = err {
+                // volumes can't be erased through the writer, as it only writes sequentially
+                let volumes = writer.into_inner().volumes();
+                super::volumes::remove_volumes(req.output_file, volumes)?;
+            }
+            return Err(err.into());
+        }
+
+        // 3. mark the last volume
+        let volumes = writer.borrow_mut().finish()?;
+        super::volumes::volume_paths(req.output_file, volumes as usize)
+    } else {
+        // outputs may be local files or S3 objects, so their storage is chosen at runtime
+        let (output_stor, output_path) = super::remote::storage(req.output_file)?;
+        let output_file = output_stor
+            .create_file(&output_path)
+            .or_else(|_| output_stor.write_file(&output_path))?;
+
+        let header_file = match &req.crypto_params.header_location {
+            HeaderLocation::Embedded => None,
+            HeaderLocation::Detached(path) => {
+                if !overwrite_check(path, req.crypto_params.force)? {
+                    exit(0);
+                }
+
+                let (header_stor, path) = super::remote::storage(path)?;
+                let header_file = header_stor
+                    .create_file(&path)
+                    .or_else(|_| header_stor.write_file(&path))?;
+                Some((header_stor, header_file))
+            }
+        };
+
+        let res = domain::pack::execute(
+            stor.clone(),
+            domain::pack::Request {
+                compress_paths: Box::new(compress_paths),
+                compression_method,
+                compression_level,
+                backup,
+                writer: output_file.try_writer()?,
+                header_writer: header_file.as_ref().and_then(|(_, f)| f.try_writer().ok()),
+                raw_key,
+                header_type,
+                hashing_algorithm: req.crypto_params.hashing_algorithm,
+                progress: Some(ProgressBar::create()),
+                cancel: Some(ctrl_c_token()),
+            },
+        );
+
+        if let Err(err) = res {
+            if let domain::pack::Error::Cancelled = err {
+                // the domain has already erased what was written
+                output_stor.remove_file(output_file)?;
+            }
+            return Err(err.into());
+        }
+
+        // 3. flush result
+        if let Some((header_stor, header_file)) = header_file {
+            header_stor.flush_file(&header_file)?;
+        }
+        output_stor.flush_file(&output_file)?;
+
+        vec![req.output_file.to_string()]
+    };
 
     if req.crypto_params.hash_mode == HashMode::CalculateHash {
-        super::hashing::hash_stream(&[req.output_file.to_string()])?;
+        super::hashing::hash_stream(&outputs, HashAlgorithm::Blake3)?;
     }
 
     if req.pack_params.erase_source == EraseSourceDir::Erase {
         req.input_file.iter().try_for_each(|file_name| {
-            super::erase::secure_erase(file_name, 1, req.crypto_params.force)
+            super::erase::secure_erase(
+                file_name,
+                &EraseParams {
+                    scheme: Scheme::Random(1),
+                    verify: false,
+                    scrub: false,
+                    cross_filesystems: false,
+                },
+                req.crypto_params.force,
+            )
         })?;
     }
 
     Ok(())
 }
+
+// reads the manifest from a previous archive, which may be split into volumes
+fn previous_manifest(
+    stor: &Arc<FileStorage>,
+    path: &str,
+    raw_key: Protected<Vec<u8>>,
+) -> Result<Manifest> {
+    let manifest = if super::volumes::is_split(path)? {
+        let (reader, _) = super::volumes::open_reader(path)?;
+        domain::unpack::read_manifest(stor.clone(), &RefCell::new(reader), None, raw_key)?
+    } else {
+        let file = stor.read_file(path)?;
+        domain::unpack::read_manifest(stor.clone(), file.try_reader()?, None, raw_key)?
+    };
+
+    manifest.with_context(|| {
+        format!("{path} doesn't contain a manifest (only archives made with --full or --since do)")
+    })
+}
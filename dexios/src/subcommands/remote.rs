@@ -0,0 +1,62 @@
+use anyhow::Result;
+use domain::storage::{DynStorage, Erased, FileStorage, Storage};
+
+// outputs may be stored in an S3-compatible object store by using `s3://bucket/key` as the path
+pub fn is_remote(path: &str) -> bool {
+    path.starts_with("s3://")
+}
+
+// this returns the storage that the path belongs to, along with the path within it
+pub fn storage(path: &str) -> Result<(Box<dyn DynStorage>, String)> {
+    match path.strip_prefix("s3://") {
+        Some(url) => s3_storage(url),
+        None => Ok((Box::new(Erased::new(FileStorage)), path.to_string())),
+    }
+}
+
+pub fn exists(path: &str) -> Result<bool> {
+    let (stor, path) = storage(path)?;
+    Ok(stor.read_file(path).is_ok())
+}
+
+// the credentials and endpoint are read from the same environment variables as the AWS CLI
+// `AWS_ENDPOINT_URL` should be set to use a local MinIO server (e.g. http://localhost:9000)
+#[cfg(feature = "s3")]
+fn s3_storage(url: &str) -> Result<(Box<dyn DynStorage>, String)> {
+    use anyhow::Context;
+    use core::protected::Protected;
+    use domain::storage::s3::{Config, S3Storage, DEFAULT_PART_SIZE};
+    use std::env;
+
+    let (bucket, key) = url
+        .split_once('/')
+        .filter(|(bucket, key)| !bucket.is_empty() && !key.is_empty())
+        .context("S3 paths must contain a bucket and a key (e.g. s3://bucket/key)")?;
+
+    let region = env::var("AWS_REGION").unwrap_or_else(|_| "us-east-1".to_string());
+    let config = Config {
+        endpoint: env::var("AWS_ENDPOINT_URL")
+            .unwrap_or_else(|_| format!("https://s3.{region}.amazonaws.com")),
+        region,
+        bucket: bucket.to_string(),
+        access_key: env::var("AWS_ACCESS_KEY_ID")
+            .context("AWS_ACCESS_KEY_ID must be set to use S3")?,
+        secret_key: Protected::new(
+            env::var("AWS_SECRET_ACCESS_KEY")
+                .context("AWS_SECRET_ACCESS_KEY must be set to use S3")?,
+        ),
+        part_size: DEFAULT_PART_SIZE,
+    };
+
+    Ok((
+        Box::new(Erased::new(S3Storage::new(config))),
+        key.to_string(),
+    ))
+}
+
+#[cfg(not(feature = "s3"))]
+fn s3_storage(_url: &str) -> Result<(Box<dyn DynStorage>, String)> {
+    Err(anyhow::anyhow!(
+        "S3 paths require Dexios to be built with the `s3` feature"
+    ))
+}
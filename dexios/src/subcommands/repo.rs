@@ -0,0 +1,198 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use core::header::{HashingAlgorithm, HeaderType, HEADER_VERSION};
+use core::primitives::{Algorithm, Mode};
+use domain::repo::{self, chunker::ChunkSizes, Repository};
+use domain::storage::{FileStorage, Paths, Storage};
+
+use crate::cli::prompt::get_answer;
+use crate::global::states::{ForceMode, Key, PasswordState, PrintMode};
+use crate::{info, success, warn};
+
+pub fn init(
+    path: &str,
+    key: &Key,
+    hashing_algorithm: HashingAlgorithm,
+    algorithm: Algorithm,
+) -> Result<()> {
+    let stor = FileStorage;
+    let raw_key = key.get_secret(&PasswordState::Validate)?;
+
+    repo::init::execute(
+        &stor,
+        repo::init::Request {
+            path: PathBuf::from(path),
+            raw_key,
+            header_type: HeaderType {
+                version: HEADER_VERSION,
+                mode: Mode::StreamMode,
+                algorithm,
+            },
+            hashing_algorithm,
+        },
+    )?;
+
+    success!("Created a new repository at {}", path);
+
+    Ok(())
+}
+
+fn open(stor: &FileStorage, path: &str, key: &Key) -> Result<Repository> {
+    let raw_key = key.get_secret(&PasswordState::Direct)?;
+    Ok(Repository::open(stor, Path::new(path), raw_key)?)
+}
+
+// each file is split into chunks, and only new chunks are written to the repository
+pub fn backup(path: &str, key: &Key, inputs: &[String], print_mode: PrintMode) -> Result<()> {
+    let stor = FileStorage;
+
+    let input_files = inputs
+        .iter()
+        .map(|file_name| stor.read_file(file_name))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let repository = open(&stor, path, key)?;
+
+    let paths = input_files
+        .iter()
+        .flat_map(|file| -> Paths<'_> {
+            if file.is_dir() {
+                match stor.walk_dir(file) {
+                    Ok(paths) => paths,
+                    Err(err) => Box::new(std::iter::once(Err(err))),
+                }
+            } else {
+                Box::new(std::iter::once(Ok(file.path().to_path_buf())))
+            }
+        })
+        .inspect(|path| {
+            if let (Ok(path), PrintMode::Verbose) = (path, print_mode) {
+                info!("Backing up {}", path.display());
+            }
+        });
+
+    let summary = repo::backup::execute(
+        &stor,
+        repo::backup::Request {
+            repository: &repository,
+            paths: Box::new(paths),
+            chunk_sizes: ChunkSizes::default(),
+        },
+    )?;
+
+    success!(
+        "Created snapshot {} ({} files, {} of {} chunks were new, {} bytes added)",
+        summary.snapshot_id,
+        summary.files,
+        summary.new_chunks,
+        summary.chunks,
+        summary.new_bytes
+    );
+
+    Ok(())
+}
+
+pub fn list(path: &str, key: &Key) -> Result<()> {
+    let stor = FileStorage;
+    let repository = open(&stor, path, key)?;
+
+    let snapshots = repo::list::execute(
+        &stor,
+        repo::list::Request {
+            repository: &repository,
+        },
+    )?;
+
+    for snapshot in snapshots {
+        let files = snapshot
+            .entries
+            .iter()
+            .filter(|entry| matches!(entry, repo::SnapshotEntry::File { .. }))
+            .count();
+
+        println!(
+            "{} ({} files, {} bytes)",
+            snapshot.id,
+            files,
+            snapshot.size()
+        );
+    }
+
+    Ok(())
+}
+
+pub fn restore(
+    path: &str,
+    key: &Key,
+    snapshot: &str,
+    output: &str,
+    print_mode: PrintMode,
+    force: ForceMode,
+) -> Result<()> {
+    let stor = FileStorage;
+    let repository = open(&stor, path, key)?;
+
+    let on_file: Box<dyn Fn(PathBuf) -> bool> = Box::new(move |file_path| {
+        if std::fs::metadata(&file_path).is_ok() {
+            let answer = get_answer(
+                &format!(
+                    "{} already exists, would you like to overwrite?",
+                    file_path.display()
+                ),
+                true,
+                force,
+            )
+            .expect("Unable to read answer");
+            if !answer {
+                warn!("Skipping {}", file_path.display());
+                return false;
+            }
+        }
+
+        if print_mode == PrintMode::Verbose {
+            info!("Restoring {}", file_path.display());
+        }
+
+        true
+    });
+
+    let snapshot = repo::restore::execute(
+        &stor,
+        repo::restore::Request {
+            repository: &repository,
+            snapshot,
+            output_dir_path: PathBuf::from(output),
+            on_file: Some(on_file),
+        },
+    )?;
+
+    success!("Restored snapshot {} to {}", snapshot.id, output);
+
+    Ok(())
+}
+
+pub fn prune(path: &str, key: &Key, keep: usize) -> Result<()> {
+    let stor = FileStorage;
+    let repository = open(&stor, path, key)?;
+
+    let summary = repo::prune::execute(
+        &stor,
+        repo::prune::Request {
+            repository: &repository,
+            keep,
+        },
+    )?;
+
+    for id in &summary.removed_snapshots {
+        info!("Removed snapshot {}", id);
+    }
+
+    success!(
+        "Removed {} snapshots and {} unused chunks",
+        summary.removed_snapshots.len(),
+        summary.removed_chunks
+    );
+
+    Ok(())
+}
@@ -0,0 +1,151 @@
+use std::cell::RefCell;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::process::exit;
+
+use anyhow::{Context, Result};
+use domain::signature::{Signature, VerifyingKey};
+
+use crate::cli::progress::ProgressBar;
+use crate::cli::prompt::overwrite_check;
+use crate::global::states::ForceMode;
+use crate::{success, warn};
+
+// this generates a new signing key, and stores it as hex
+// the public key is stored alongside it in file.pub, and that's what gets shared with anyone who verifies signatures
+pub fn generate(path: &str, force: ForceMode) -> Result<()> {
+    let public_path = format!("{}.pub", path);
+    if !overwrite_check(path, force)? || !overwrite_check(&public_path, force)? {
+        exit(0);
+    }
+
+    let key = domain::signature::generate_key();
+    let public_key = domain::signature::encode(key.verifying_key().as_bytes());
+
+    // only the owner should be able to read the signing key
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+    let mut key_file = options
+        .open(path)
+        .with_context(|| format!("Unable to create file: {}", path))?;
+    writeln!(key_file, "{}", domain::signature::encode(&key.to_bytes()))
+        .with_context(|| format!("Unable to write to file: {}", path))?;
+
+    std::fs::write(&public_path, format!("{}\n", public_key))
+        .with_context(|| format!("Unable to write to file: {}", public_path))?;
+
+    success!("Your public key is: {}", public_key);
+    warn!(
+        "Keep {} secret, as anyone who has it can sign files as you",
+        path
+    );
+
+    Ok(())
+}
+
+// this signs the header and ciphertext of an encrypted file
+// the signature is embedded within the header by default, or written to input.sig if it's detached
+pub fn sign(input: &str, keyfile: &str, detached: bool, force: ForceMode) -> Result<()> {
+    let key = std::fs::read_to_string(keyfile)
+        .with_context(|| format!("Unable to read signing key: {}", keyfile))?;
+    let key = domain::signature::decode_signing_key(&key)?;
+
+    let sig_path = format!("{}.sig", input);
+    if detached && !overwrite_check(&sig_path, force)? {
+        exit(0);
+    }
+
+    let file = OpenOptions::new()
+        .read(true)
+        .write(!detached)
+        .open(input)
+        .with_context(|| format!("Unable to open input file: {}", input))?;
+
+    let signature = domain::signature::sign::execute(domain::signature::sign::Request {
+        handle: &RefCell::new(file),
+        key: &key,
+        embed: !detached,
+        progress: Some(ProgressBar::create()),
+    })?;
+
+    if detached {
+        std::fs::write(
+            &sig_path,
+            format!("{}\n", domain::signature::encode(&signature.to_bytes())),
+        )
+        .with_context(|| format!("Unable to write to file: {}", sig_path))?;
+        success!("Signature written to {}", sig_path);
+    } else {
+        success!("Signature embedded within the header of {}", input);
+    }
+
+    Ok(())
+}
+
+// this checks that the file was signed by the signer, and fails if it wasn't
+// without a detached signature, the embedded signature is used, and then input.sig if there isn't one
+pub fn verify(input: &str, signer: &str, sig: Option<&str>) -> Result<()> {
+    let reader = RefCell::new(
+        File::open(input).with_context(|| format!("Unable to open input file: {}", input))?,
+    );
+    let key = public_key(signer)?;
+
+    let signature = match sig {
+        Some(path) => Some(read_signature(path)?),
+        None if domain::signature::embedded(&reader)?.is_some() => None,
+        None => Some(detached_signature(input)?),
+    };
+
+    domain::signature::verify::execute(domain::signature::verify::Request {
+        reader: &reader,
+        key: &key,
+        signature,
+        progress: Some(ProgressBar::create()),
+    })
+    .with_context(|| format!("Unable to verify the signature of {}", input))?;
+
+    success!(
+        "{} is signed by {}",
+        input,
+        domain::signature::encode(key.as_bytes())
+    );
+
+    Ok(())
+}
+
+// this is used when a file doesn't have an embedded signature
+pub fn detached_signature(input: &str) -> Result<Signature> {
+    let path = format!("{}.sig", input);
+    if !Path::new(&path).exists() {
+        return Err(anyhow::anyhow!(
+            "{} isn't signed (there's no embedded signature, or {})",
+            input,
+            path
+        ));
+    }
+
+    read_signature(&path)
+}
+
+// public keys may be given directly as hex, or as a .pub file
+pub fn public_key(signer: &str) -> Result<VerifyingKey> {
+    let key = if Path::new(signer).is_file() {
+        std::fs::read_to_string(signer)
+            .with_context(|| format!("Unable to read public key: {}", signer))?
+    } else {
+        signer.to_string()
+    };
+
+    Ok(domain::signature::decode_verifying_key(&key)?)
+}
+
+fn read_signature(path: &str) -> Result<Signature> {
+    let signature = std::fs::read_to_string(path)
+        .with_context(|| format!("Unable to read signature: {}", path))?;
+
+    Ok(domain::signature::decode_signature(&signature)?)
+}
@@ -1,9 +1,15 @@
+use crate::cli::cancel::ctrl_c_token;
+use crate::cli::progress::ProgressBar;
 use crate::{cli::prompt::get_answer, global::states::HashMode};
+use std::cell::RefCell;
 use std::sync::Arc;
 
 use anyhow::Result;
 
-use domain::storage::Storage;
+use core::protected::Protected;
+use domain::hasher::HashAlgorithm;
+use domain::manifest::Manifest;
+use domain::storage::{FileStorage, Storage};
 
 use crate::global::{
     states::{HeaderLocation, PasswordState, PrintMode},
@@ -15,67 +21,148 @@ use std::path::PathBuf;
 // this first decrypts the input file to a temporary zip file
 // it then unpacks that temporary zip file to the target directory
 // once finished, it erases the temporary file to avoid any residual data
+// a chain of incremental archives is unpacked in order, each on top of the previous one
 #[allow(clippy::module_name_repetitions)]
 #[allow(clippy::needless_pass_by_value)]
 pub fn unpack(
-    input: &str,  // encrypted zip file
-    output: &str, // directory
+    inputs: &[String], // encrypted zip file(s)
+    output: &str,      // directory
     print_mode: PrintMode,
     params: CryptoParams, // params for decrypt function
+    limits: domain::unpack::Limits,
 ) -> Result<()> {
     // TODO: It is necessary to raise it to a higher level
-    let stor = Arc::new(domain::storage::FileStorage);
+    let stor = Arc::new(FileStorage);
 
-    let input_file = stor.read_file(input)?;
-    let header_file = match &params.header_location {
-        HeaderLocation::Embedded => None,
-        HeaderLocation::Detached(path) => Some(stor.read_file(path)?),
-    };
+    if inputs.len() > 1 {
+        if let HeaderLocation::Detached(_) = params.header_location {
+            return Err(anyhow::anyhow!(
+                "Detached headers can only be used when unpacking a single archive."
+            ));
+        }
+    }
 
     let raw_key = params.key.get_secret(&PasswordState::Direct)?;
 
-    domain::unpack::execute(
-        stor,
-        domain::unpack::Request {
-            header_reader: header_file.as_ref().and_then(|h| h.try_reader().ok()),
-            reader: input_file.try_reader()?,
-            output_dir_path: PathBuf::from(output),
-            raw_key,
-            on_decrypted_header: None,
-            on_archive_info: None,
-            on_zip_file: Some(Box::new(move |file_path| {
-                let file_name = file_path
-                    .file_name()
-                    .expect("Unable to convert file name to OsStr")
-                    .to_str()
-                    .expect("Unable to convert file name's OsStr to &str")
-                    .to_string();
-
-                if std::fs::metadata(file_path).is_ok() {
-                    let answer = get_answer(
-                        &format!("{} already exists, would you like to overwrite?", file_name),
-                        true,
-                        params.force,
-                    )
-                    .expect("Unable to read answer");
-                    if !answer {
-                        warn!("Skipping {}", file_name);
-                        return false;
-                    }
-                }
-
-                if print_mode == PrintMode::Verbose {
-                    info!("Extracting {}", file_name);
-                }
-
-                true
-            })),
-        },
-    )?;
+    let mut base = None;
+    for input in inputs {
+        if inputs.len() > 1 {
+            info!("Unpacking {}", input);
+        }
 
-    if params.hash_mode == HashMode::CalculateHash {
-        super::hashing::hash_stream(&[input.to_string()])?;
+        base = unpack_archive(
+            &stor,
+            input,
+            output,
+            print_mode,
+            &params,
+            Protected::new(raw_key.expose().clone()),
+            limits,
+            base.as_ref(),
+        )?;
     }
 
     Ok(())
 }
+
+// unpacks a single archive, and returns its manifest
+#[allow(clippy::too_many_arguments)]
+fn unpack_archive(
+    stor: &Arc<FileStorage>,
+    input: &str,
+    output: &str,
+    print_mode: PrintMode,
+    params: &CryptoParams,
+    raw_key: Protected<Vec<u8>>,
+    limits: domain::unpack::Limits,
+    base: Option<&Manifest>,
+) -> Result<Option<Manifest>> {
+    let force = params.force;
+    let on_zip_file: Box<dyn Fn(PathBuf) -> bool> = Box::new(move |file_path| {
+        let file_name = file_path
+            .file_name()
+            .expect("Unable to convert file name to OsStr")
+            .to_string_lossy()
+            .into_owned();
+
+        if std::fs::metadata(file_path).is_ok() {
+            let answer = get_answer(
+                &format!("{} already exists, would you like to overwrite?", file_name),
+                true,
+                force,
+            )
+            .expect("Unable to read answer");
+            if !answer {
+                warn!("Skipping {}", file_name);
+                return false;
+            }
+        }
+
+        if print_mode == PrintMode::Verbose {
+            info!("Extracting {}", file_name);
+        }
+
+        true
+    });
+
+    // split volumes are detected by their header, and the rest are found from the first volume
+    let (manifest, inputs) = if super::volumes::is_split(input)? {
+        if let HeaderLocation::Detached(_) = params.header_location {
+            return Err(anyhow::anyhow!(
+                "Detached headers can't be used with split volumes."
+            ));
+        }
+
+        let (reader, inputs) = super::volumes::open_reader(input)?;
+
+        let manifest = domain::unpack::execute(
+            stor.clone(),
+            domain::unpack::Request {
+                header_reader: None,
+                reader: &RefCell::new(reader),
+                output_dir_path: PathBuf::from(output),
+                raw_key,
+                on_decrypted_header: None,
+                on_archive_info: None,
+                on_zip_file: Some(on_zip_file),
+                limits,
+                base,
+                progress: Some(ProgressBar::create()),
+                cancel: Some(ctrl_c_token()),
+            },
+        )?;
+
+        (manifest, inputs)
+    } else {
+        let input_file = stor.read_file(input)?;
+        let header_file = match &params.header_location {
+            HeaderLocation::Embedded => None,
+            HeaderLocation::Detached(path) => Some(stor.read_file(path)?),
+        };
+
+        let manifest = domain::unpack::execute(
+            stor.clone(),
+            domain::unpack::Request {
+                header_reader: header_file.as_ref().and_then(|h| h.try_reader().ok()),
+                reader: input_file.try_reader()?,
+                output_dir_path: PathBuf::from(output),
+                raw_key,
+                on_decrypted_header: None,
+                on_archive_info: None,
+                on_zip_file: Some(on_zip_file),
+                limits,
+                base,
+                progress: Some(ProgressBar::create()),
+                cancel: Some(ctrl_c_token()),
+            },
+        )?;
+
+        (manifest, vec![input.to_string()])
+    };
+
+    if params.hash_mode == HashMode::CalculateHash {
+        super::hashing::hash_stream(&inputs, HashAlgorithm::Blake3)?;
+    }
+
+    Ok(manifest)
+}
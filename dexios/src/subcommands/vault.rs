@@ -0,0 +1,236 @@
+use std::cell::RefCell;
+use std::fs::{File, OpenOptions};
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use core::header::{HashingAlgorithm, HeaderType, HEADER_VERSION};
+use core::primitives::{Algorithm, Mode};
+use domain::storage::{FileStorage, Paths, Storage};
+use domain::vault::{self, Vault};
+
+use crate::cli::prompt::{get_answer, overwrite_check};
+use crate::global::states::{ForceMode, Key, PasswordState, PrintMode};
+use crate::{info, success, warn};
+
+pub fn create(
+    path: &str,
+    key: &Key,
+    hashing_algorithm: HashingAlgorithm,
+    algorithm: Algorithm,
+    force: ForceMode,
+) -> Result<()> {
+    if !overwrite_check(path, force)? {
+        std::process::exit(0);
+    }
+
+    let raw_key = key.get_secret(&PasswordState::Validate)?;
+
+    let handle = RefCell::new(
+        File::create(path).with_context(|| format!("Unable to create vault: {}", path))?,
+    );
+
+    vault::create::execute(vault::create::Request {
+        handle: &handle,
+        raw_key,
+        header_type: HeaderType {
+            version: HEADER_VERSION,
+            mode: Mode::MemoryMode,
+            algorithm,
+        },
+        hashing_algorithm,
+    })?;
+
+    handle
+        .borrow()
+        .sync_all()
+        .context("Unable to flush the vault")?;
+
+    success!("Created a new vault at {}", path);
+
+    Ok(())
+}
+
+fn open(path: &str, key: &Key) -> Result<(RefCell<File>, Vault)> {
+    let handle = RefCell::new(
+        OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(path)
+            .with_context(|| format!("Unable to open vault: {}", path))?,
+    );
+
+    let raw_key = key.get_secret(&PasswordState::Direct)?;
+    let vault = Vault::open(&handle, raw_key)?;
+
+    Ok((handle, vault))
+}
+
+// the files are appended to the vault, and then a new index is written
+pub fn add(path: &str, key: &Key, inputs: &[String], print_mode: PrintMode) -> Result<()> {
+    let stor = FileStorage;
+
+    let input_files = inputs
+        .iter()
+        .map(|file_name| stor.read_file(file_name))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let (handle, mut vault) = open(path, key)?;
+
+    let paths = input_files
+        .iter()
+        .flat_map(|file| -> Paths<'_> {
+            if file.is_dir() {
+                match stor.walk_dir(file) {
+                    Ok(paths) => paths,
+                    Err(err) => Box::new(std::iter::once(Err(err))),
+                }
+            } else {
+                Box::new(std::iter::once(Ok(file.path().to_path_buf())))
+            }
+        })
+        .inspect(|path| {
+            if let (Ok(path), PrintMode::Verbose) = (path, print_mode) {
+                info!("Adding {}", path.display());
+            }
+        });
+
+    let added = vault::add::execute(
+        &stor,
+        vault::add::Request {
+            handle: &handle,
+            vault: &mut vault,
+            paths: Box::new(paths),
+        },
+    )?;
+
+    handle
+        .borrow()
+        .sync_all()
+        .context("Unable to flush the vault")?;
+
+    success!("Added {} files to {}", added, path);
+
+    Ok(())
+}
+
+pub fn list(path: &str, key: &Key) -> Result<()> {
+    let (_, vault) = open(path, key)?;
+
+    for entry in vault.entries() {
+        println!("{} ({} bytes)", entry.path.display(), entry.size);
+    }
+
+    Ok(())
+}
+
+pub fn get(
+    path: &str,
+    key: &Key,
+    files: &[String],
+    output: &str,
+    print_mode: PrintMode,
+    force: ForceMode,
+) -> Result<()> {
+    let stor = FileStorage;
+    let (handle, vault) = open(path, key)?;
+
+    let on_file: Box<dyn Fn(PathBuf) -> bool> = Box::new(move |file_path| {
+        if std::fs::metadata(&file_path).is_ok() {
+            let answer = get_answer(
+                &format!(
+                    "{} already exists, would you like to overwrite?",
+                    file_path.display()
+                ),
+                true,
+                force,
+            )
+            .expect("Unable to read answer");
+            if !answer {
+                warn!("Skipping {}", file_path.display());
+                return false;
+            }
+        }
+
+        if print_mode == PrintMode::Verbose {
+            info!("Extracting {}", file_path.display());
+        }
+
+        true
+    });
+
+    let extracted = vault::get::execute(
+        &stor,
+        vault::get::Request {
+            handle: &handle,
+            vault: &vault,
+            paths: files.iter().map(PathBuf::from).collect(),
+            output_dir_path: PathBuf::from(output),
+            on_file: Some(on_file),
+        },
+    )?;
+
+    success!("Extracted {} files to {}", extracted, output);
+
+    Ok(())
+}
+
+pub fn remove(path: &str, key: &Key, files: &[String]) -> Result<()> {
+    let (handle, mut vault) = open(path, key)?;
+
+    vault::remove::execute(vault::remove::Request {
+        handle: &handle,
+        vault: &mut vault,
+        paths: files.iter().map(PathBuf::from).collect(),
+    })?;
+
+    handle
+        .borrow()
+        .sync_all()
+        .context("Unable to flush the vault")?;
+
+    success!("Removed {} files from {}", files.len(), path);
+
+    Ok(())
+}
+
+// the vault is rewritten to a temporary file, which then replaces the original
+pub fn compact(path: &str, key: &Key) -> Result<()> {
+    let (handle, mut vault) = open(path, key)?;
+    let old_size = handle.borrow().metadata()?.len();
+
+    let temp_path = format!("{}.compact", path);
+    let temp = RefCell::new(
+        File::create(&temp_path)
+            .with_context(|| format!("Unable to create temporary file: {}", temp_path))?,
+    );
+
+    let result = vault::compact::execute(vault::compact::Request {
+        reader: &handle,
+        writer: &temp,
+        vault: &mut vault,
+    })
+    .map_err(anyhow::Error::from)
+    .and_then(|_| {
+        temp.borrow()
+            .sync_all()
+            .context("Unable to flush the vault")
+    });
+
+    if let Err(err) = result {
+        drop(temp);
+        std::fs::remove_file(&temp_path).ok();
+        return Err(err);
+    }
+
+    let new_size = temp.borrow().metadata()?.len();
+    std::fs::rename(&temp_path, path)
+        .with_context(|| format!("Unable to replace vault: {}", path))?;
+
+    success!(
+        "Compacted {} ({} bytes reclaimed)",
+        path,
+        old_size.saturating_sub(new_size)
+    );
+
+    Ok(())
+}
@@ -0,0 +1,137 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use domain::split::{SplitReader, SplitWriter};
+
+use crate::cli::prompt::get_answer;
+use crate::global::states::ForceMode;
+
+// volumes are named after the output file, e.g. `out.dx.001`, `out.dx.002`, ...
+pub fn volume_path(base: &str, index: u32) -> String {
+    format!("{base}.{index:03}")
+}
+
+pub fn volume_paths(base: &str, count: usize) -> Vec<String> {
+    (1..=count)
+        .map(|index| volume_path(base, u32::try_from(index).unwrap_or(u32::MAX)))
+        .collect()
+}
+
+// this finds every volume of the output that already exists, including any past the first gap
+fn existing_volumes(output: &str) -> Result<Vec<String>> {
+    let path = Path::new(output);
+    let dir = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let prefix = match path.file_name() {
+        Some(name) => format!("{}.", name.to_string_lossy()),
+        None => return Ok(Vec::new()),
+    };
+
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return Ok(Vec::new()),
+    };
+
+    let mut volumes = Vec::new();
+    for entry in entries {
+        let entry = entry.with_context(|| format!("Unable to read {}", dir.display()))?;
+        let name = entry.file_name().to_string_lossy().to_string();
+        if let Some(index) = name.strip_prefix(&prefix) {
+            if index.len() >= 3 && index.chars().all(|c| c.is_ascii_digit()) {
+                volumes.push(format!("{output}.{index}"));
+            }
+        }
+    }
+
+    volumes.sort();
+    Ok(volumes)
+}
+
+// this asks before overwriting any existing volumes, and then removes all of them
+// otherwise, stale volumes from a larger output would be left behind, after the new output's last volume
+pub fn overwrite_check(output: &str, force: ForceMode) -> Result<bool> {
+    let existing = existing_volumes(output)?;
+    if existing.is_empty() {
+        return Ok(true);
+    }
+
+    let prompt = format!(
+        "{} already exists (along with {} other volume(s)), would you like to overwrite?",
+        existing[0],
+        existing.len() - 1
+    );
+    if !get_answer(&prompt, true, force)? {
+        return Ok(false);
+    }
+
+    for path in existing {
+        std::fs::remove_file(&path).with_context(|| format!("Unable to remove {path}"))?;
+    }
+
+    Ok(true)
+}
+
+pub fn create_writer(output: &str, volume_size: u64) -> Result<SplitWriter<File>> {
+    let base = output.to_string();
+    let writer = SplitWriter::new(
+        volume_size,
+        Box::new(move |index| {
+            File::options()
+                .read(true)
+                .write(true)
+                .create(true)
+                .truncate(true)
+                .open(volume_path(&base, index))
+        }),
+    )?;
+
+    Ok(writer)
+}
+
+// this erases and removes every volume that was written, once writing has been cancelled
+// the writer must be dropped first, so the last volume is closed
+pub fn remove_volumes(output: &str, volumes: u32) -> Result<()> {
+    let base = output.to_string();
+    domain::split::erase_volumes(
+        volumes,
+        Box::new(move |index| File::options().write(true).open(volume_path(&base, index))),
+    )?;
+
+    for path in volume_paths(output, volumes as usize) {
+        std::fs::remove_file(&path).with_context(|| format!("Unable to remove {path}"))?;
+    }
+
+    Ok(())
+}
+
+// this checks for the volume header, so volumes are found regardless of their name
+pub fn is_split(input: &str) -> Result<bool> {
+    let file = File::open(input).with_context(|| format!("Unable to open {input}"))?;
+    Ok(domain::split::is_volume(&mut BufReader::new(file)))
+}
+
+// any volume may be provided - the rest are found by stripping the `.001` extension
+pub fn base_path(volume: &str) -> Result<&str> {
+    volume
+        .rsplit_once('.')
+        .filter(|(_, ext)| ext.len() >= 3 && ext.chars().all(|c| c.is_ascii_digit()))
+        .map(|(base, _)| base)
+        .context("Split volumes must end with a volume number (e.g. .001)")
+}
+
+// returns the reader, along with the paths of every volume
+pub fn open_reader(volume: &str) -> Result<(SplitReader<File>, Vec<String>)> {
+    let base = base_path(volume)?.to_string();
+    let reader_base = base.clone();
+
+    let reader = SplitReader::new(Box::new(move |index| {
+        File::open(volume_path(&reader_base, index))
+    }))?;
+    let paths = volume_paths(&base, reader.volumes());
+
+    Ok((reader, paths))
+}